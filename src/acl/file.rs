@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use super::{AclError, User};
+
+/// 每行是user <name> <rule>...，空行和#开头的行忽略。任何一行出错时整个文件都不生效
+pub fn load(path: &Path) -> Result<Vec<User>, AclError> {
+    let content = fs::read_to_string(path)?;
+    let error = |line: usize, reason: String| AclError::File {
+        path: path.display().to_string(),
+        line,
+        reason,
+    };
+    let mut users = Vec::new();
+    let mut names = HashSet::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let name = match (parts.next(), parts.next()) {
+            (Some("user"), Some(name)) => name,
+            _ => return Err(error(i + 1, "should start with user keyword".into())),
+        };
+        if !names.insert(name) {
+            return Err(error(i + 1, format!("Duplicate user '{name}' found")));
+        }
+        let mut user = User::new(name);
+        for rule in parts {
+            user.apply(rule).map_err(|e| error(i + 1, e.to_string()))?;
+        }
+        users.push(user);
+    }
+    Ok(users)
+}
+
+/// 先写临时文件再rename，写到一半崩溃也不会破坏原来的ACL文件
+pub fn save(path: &Path, users: &[User]) -> Result<(), AclError> {
+    let content = users
+        .iter()
+        .map(|user| format!("{}\n", user.describe()))
+        .collect::<String>();
+    let tmp = path.with_extension("acl.tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acl_file() {
+        let path =
+            std::env::temp_dir().join(format!("simple-redis-acl-{}.acl", std::process::id()));
+        let mut alice = User::new("alice");
+        for rule in ["on", ">pw", "%R~cache:*", "+@read", "-hgetall"] {
+            alice.apply(rule).unwrap();
+        }
+        let users = vec![User::new_default("default"), alice];
+        save(&path, &users).unwrap();
+        assert_eq!(load(&path).unwrap(), users);
+
+        fs::write(&path, "user alice on\n\nuser alice off\n").unwrap();
+        let e = load(&path).unwrap_err();
+        assert!(e.to_string().ends_with(":3: Duplicate user 'alice' found"));
+        fs::write(&path, "user bob on +nosuchcommand\n").unwrap();
+        assert!(matches!(load(&path), Err(AclError::File { line: 1, .. })));
+        fs::write(&path, "bob on\n").unwrap();
+        assert!(load(&path).is_err());
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::backend::now_ms;
+
+/// 同一个原因的拒绝在这个时间内合并成一条
+const GROUPING_WINDOW_MS: u64 = 60_000;
+
+/// ACL LOG里的一条记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct AclLogEntry {
+    pub id: u64,
+    pub count: u64,
+    /// auth、command、key或者channel
+    pub reason: &'static str,
+    /// toplevel或者multi
+    pub context: &'static str,
+    /// 被拒绝的命令、key、频道，认证失败时是AUTH
+    pub object: String,
+    pub username: String,
+    pub client_info: String,
+    pub created: u64,
+    pub updated: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct AclLog {
+    /// 最新的在前面
+    entries: Mutex<VecDeque<AclLogEntry>>,
+    next_id: Mutex<u64>,
+}
+
+impl AclLog {
+    /// 记一条拒绝，和最近一分钟内相同的记录合并
+    pub fn add(
+        &self,
+        reason: &'static str,
+        context: &'static str,
+        object: String,
+        username: String,
+        client_info: String,
+        max_len: usize,
+    ) {
+        let now = now_ms();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let same = entries.iter().position(|entry| {
+            entry.reason == reason
+                && entry.context == context
+                && entry.object == object
+                && entry.username == username
+                && now.saturating_sub(entry.updated) < GROUPING_WINDOW_MS
+        });
+        if let Some(i) = same {
+            let mut entry = entries.remove(i).unwrap_or_else(|| unreachable!());
+            entry.count += 1;
+            entry.updated = now;
+            entry.client_info = client_info;
+            entries.push_front(entry);
+            return;
+        }
+        let mut next_id = self.next_id.lock().unwrap_or_else(|e| e.into_inner());
+        entries.push_front(AclLogEntry {
+            id: *next_id,
+            count: 1,
+            reason,
+            context,
+            object,
+            username,
+            client_info,
+            created: now,
+            updated: now,
+        });
+        *next_id += 1;
+        entries.truncate(max_len);
+    }
+
+    /// 最新的count条
+    pub fn get(&self, count: usize) -> Vec<AclLogEntry> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().take(count).cloned().collect()
+    }
+
+    pub fn reset(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acl_log() {
+        let log = AclLog::default();
+        let add = |object: &str, max_len| {
+            log.add(
+                "key",
+                "toplevel",
+                object.into(),
+                "alice".into(),
+                "id=1".into(),
+                max_len,
+            )
+        };
+        add("a", 10);
+        add("b", 10);
+        add("a", 10);
+        let entries = log.get(10);
+        assert_eq!(entries.len(), 2);
+        //相同的拒绝合并到一条里并移到最前面
+        assert_eq!((entries[0].object.as_str(), entries[0].count), ("a", 2));
+        assert_eq!((entries[1].object.as_str(), entries[1].id), ("b", 1));
+        assert_eq!(log.get(1).len(), 1);
+
+        add("c", 2);
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.get(10)[0].object, "c");
+        log.reset();
+        assert!(log.is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+/*
+ACL：
+    每个连接以某个用户的身份执行命令，新连接是default用户。default用户默认是on nopass ~* &* +@all，
+    这时不需要认证；设置了requirepass或者用ACL SETUSER改掉nopass之后，连接必须先AUTH
+    用户的规则：
+        on/off、>password、<password、#hash、!hash、nopass、resetpass
+        +command、-command、+command|subcommand、+@category、-@category，按顺序生效，后面的覆盖前面的
+        ~pattern(读写)、%R~pattern(只读)、%W~pattern(只写)、resetkeys
+        &pattern、resetchannels
+    命令的分类来自命令表(cmd/table.rs)，不在命令表里的命令不检查
+    密码只保存SHA-256，ACL LIST和ACL文件里也只有hash
+    被拒绝的命令和失败的认证记进ACL LOG
+    aclfile里每行是一条ACL LIST格式的user规则，ACL LOAD要么全部成功要么不生效
+*/
+mod file;
+mod log;
+mod user;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use thiserror::Error;
+
+pub use log::{AclLog, AclLogEntry};
+pub use user::{hash_password, User};
+
+use crate::cmd::command_spec;
+
+/// 新连接的用户
+pub const DEFAULT_USER: &str = "default";
+
+#[derive(Error, Debug)]
+pub enum AclError {
+    #[error("Error in ACL SETUSER modifier '{rule}': {reason}")]
+    InvalidRule { rule: String, reason: String },
+    #[error("User '{0}' not found")]
+    UserNotFound(String),
+    #[error("The 'default' user cannot be removed")]
+    CannotRemoveDefault,
+    #[error("This Redis instance is not configured to use an ACL file. You may want to specify users via the ACL SETUSER command and then issue a CONFIG REWRITE (assuming you have a Redis configuration file set) in order to store users in the Redis configuration.")]
+    NoAclFile,
+    #[error("{path}:{line}: {reason}")]
+    File {
+        path: String,
+        line: usize,
+        reason: String,
+    },
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("Unknown category '{0}'")]
+    UnknownCategory(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct AclConfig {
+    pub file: Option<PathBuf>,
+    pub log_max_len: usize,
+    /// CONFIG GET requirepass返回的明文，只有通过requirepass设置时才有
+    pub requirepass: String,
+}
+
+impl Default for AclConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            log_max_len: 128,
+            requirepass: String::new(),
+        }
+    }
+}
+
+/// 权限检查没通过的原因
+#[derive(Debug, PartialEq)]
+pub enum Denied {
+    Command,
+    Key(String),
+}
+
+#[derive(Debug)]
+pub struct AclState {
+    users: RwLock<BTreeMap<String, User>>,
+    pub log: AclLog,
+    config: RwLock<AclConfig>,
+}
+
+impl Default for AclState {
+    fn default() -> Self {
+        Self {
+            users: RwLock::new(default_users()),
+            log: AclLog::default(),
+            config: RwLock::default(),
+        }
+    }
+}
+
+fn default_users() -> BTreeMap<String, User> {
+    BTreeMap::from([(DEFAULT_USER.to_string(), User::new_default(DEFAULT_USER))])
+}
+
+impl AclState {
+    pub fn config(&self) -> AclConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_config(&self, config: AclConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    /// 用户存在、启用并且密码正确
+    pub fn authenticate(&self, username: &str, password: &str) -> bool {
+        self.get_user(username)
+            .is_some_and(|user| user.enabled && user.check_password(password))
+    }
+
+    /// default用户启用并且nopass时新连接不需要AUTH
+    pub fn no_auth_required(&self) -> bool {
+        self.get_user(DEFAULT_USER)
+            .is_some_and(|user| user.enabled && user.nopass)
+    }
+
+    pub fn get_user(&self, username: &str) -> Option<User> {
+        let users = self.users.read().unwrap_or_else(|e| e.into_inner());
+        users.get(username).cloned()
+    }
+
+    /// 按名字排序的所有用户
+    pub fn users(&self) -> Vec<User> {
+        let users = self.users.read().unwrap_or_else(|e| e.into_inner());
+        users.values().cloned().collect()
+    }
+
+    /// 用户不存在时先创建，任何一条规则出错时用户不变
+    pub fn set_user(&self, username: &str, rules: &[String]) -> Result<(), AclError> {
+        let mut users = self.users.write().unwrap_or_else(|e| e.into_inner());
+        let mut user = users
+            .get(username)
+            .cloned()
+            .unwrap_or_else(|| User::new(username));
+        for rule in rules {
+            user.apply(rule)?;
+        }
+        users.insert(username.to_string(), user);
+        Ok(())
+    }
+
+    /// 返回删除的用户数
+    pub fn del_users(&self, usernames: &[String]) -> Result<usize, AclError> {
+        if usernames.iter().any(|name| name == DEFAULT_USER) {
+            return Err(AclError::CannotRemoveDefault);
+        }
+        let mut users = self.users.write().unwrap_or_else(|e| e.into_inner());
+        Ok(usernames
+            .iter()
+            .filter(|name| users.remove(name.as_str()).is_some())
+            .count())
+    }
+
+    /// requirepass就是给default用户设置唯一的密码，空字符串表示nopass
+    pub fn set_requirepass(&self, password: &str) {
+        let rules: Vec<String> = match password {
+            "" => vec!["nopass".into()],
+            password => vec!["resetpass".into(), format!(">{password}")],
+        };
+        //这两条规则不会出错
+        let _ = self.set_user(DEFAULT_USER, &rules);
+        self.set_config(AclConfig {
+            requirepass: password.to_string(),
+            ..self.config()
+        });
+    }
+
+    /// label是带子命令的命令名，不在命令表里的命令不检查
+    pub fn check_command(&self, username: &str, label: &str) -> Result<(), Denied> {
+        let name = label.split('|').next().unwrap_or_default();
+        let Some(spec) = command_spec(name) else {
+            return Ok(());
+        };
+        match self.get_user(username) {
+            Some(user) if user.can_run(spec, label) => Ok(()),
+            _ => Err(Denied::Command),
+        }
+    }
+
+    /// write表示命令会修改这些key
+    pub fn check_keys(&self, username: &str, keys: &[&str], write: bool) -> Result<(), Denied> {
+        let user = self.get_user(username);
+        for key in keys {
+            if !user
+                .as_ref()
+                .is_some_and(|user| user.can_access_key(key, write))
+            {
+                return Err(Denied::Key(key.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_channel(&self, username: &str, channel: &str) -> bool {
+        self.get_user(username)
+            .is_some_and(|user| user.can_access_channel(channel))
+    }
+
+    /// 记一条拒绝，reason是auth、command、key或channel
+    pub fn log_denied(
+        &self,
+        reason: &'static str,
+        context: &'static str,
+        object: String,
+        username: String,
+        client_info: String,
+    ) {
+        let max_len = self.config().log_max_len;
+        self.log
+            .add(reason, context, object, username, client_info, max_len);
+    }
+
+    /// ACL LOAD：重新读取aclfile，文件里没有default用户时使用默认的default用户
+    pub fn load(&self) -> Result<(), AclError> {
+        let path = self.config().file.ok_or(AclError::NoAclFile)?;
+        let mut users = default_users();
+        for user in file::load(&path)? {
+            users.insert(user.name.clone(), user);
+        }
+        *self.users.write().unwrap_or_else(|e| e.into_inner()) = users;
+        Ok(())
+    }
+
+    /// ACL SAVE：把所有用户写进aclfile
+    pub fn save(&self) -> Result<(), AclError> {
+        let path = self.config().file.ok_or(AclError::NoAclFile)?;
+        file::save(&path, &self.users())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acl_state() {
+        let acl = AclState::default();
+        assert!(acl.no_auth_required());
+        assert!(acl.authenticate("default", "anything"));
+
+        acl.set_requirepass("secret");
+        assert!(!acl.no_auth_required());
+        assert!(!acl.authenticate("default", "anything"));
+        assert!(acl.authenticate("default", "secret"));
+        assert_eq!(acl.config().requirepass, "secret");
+        acl.set_requirepass("");
+        assert!(acl.no_auth_required());
+
+        let rules = ["on", ">pw", "~cache:*", "+@read"].map(String::from);
+        acl.set_user("alice", &rules).unwrap();
+        assert!(acl.authenticate("alice", "pw"));
+        assert_eq!(acl.check_command("alice", "get"), Ok(()));
+        assert_eq!(acl.check_command("alice", "set"), Err(Denied::Command));
+        assert_eq!(acl.check_keys("alice", &["cache:1"], false), Ok(()));
+        assert_eq!(
+            acl.check_keys("alice", &["cache:1", "db"], false),
+            Err(Denied::Key("db".into()))
+        );
+        //不在命令表里的命令不检查
+        assert_eq!(acl.check_command("alice", "nosuchcommand"), Ok(()));
+
+        //出错时用户不变
+        let rules = ["+set", "bogus"].map(String::from);
+        assert!(acl.set_user("alice", &rules).is_err());
+        assert_eq!(acl.check_command("alice", "set"), Err(Denied::Command));
+
+        assert!(matches!(
+            acl.del_users(&["default".into()]),
+            Err(AclError::CannotRemoveDefault)
+        ));
+        assert_eq!(acl.del_users(&["alice".into(), "bob".into()]).unwrap(), 1);
+        assert_eq!(acl.check_command("alice", "get"), Err(Denied::Command));
+        assert!(matches!(acl.load(), Err(AclError::NoAclFile)));
+    }
+}
@@ -0,0 +1,424 @@
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cmd::{command_spec, CommandSpec, CATEGORIES},
+    glob::glob_match,
+};
+
+use super::AclError;
+
+/// 一个ACL用户，规则按ACL SETUSER的写法逐条生效
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub enabled: bool,
+    /// 任意密码都能通过认证
+    pub nopass: bool,
+    /// 密码的SHA-256，十六进制小写
+    pub passwords: Vec<String>,
+    /// 按顺序生效的命令规则，后面的规则覆盖前面的，没有匹配的规则时不允许执行
+    commands: Vec<CommandRule>,
+    keys: Vec<KeyPattern>,
+    /// pub/sub频道的pattern
+    channels: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct CommandRule {
+    allow: bool,
+    target: CommandTarget,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CommandTarget {
+    /// @all也是一个分类
+    Category(String),
+    Command(String),
+    /// config|get
+    Subcommand(String, String),
+}
+
+/// ~pattern、%R~pattern、%W~pattern、%RW~pattern
+#[derive(Debug, Clone, PartialEq)]
+struct KeyPattern {
+    pattern: String,
+    read: bool,
+    write: bool,
+}
+
+impl User {
+    /// ACL SETUSER新建的用户：禁用、没有密码、不能执行任何命令
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            enabled: false,
+            nopass: false,
+            passwords: vec![],
+            commands: vec![],
+            keys: vec![],
+            channels: vec![],
+        }
+    }
+
+    /// 启动时的default用户：on nopass ~* &* +@all
+    pub fn new_default(name: impl Into<String>) -> Self {
+        let mut user = Self::new(name);
+        for rule in ["on", "nopass", "~*", "&*", "+@all"] {
+            let _ = user.apply(rule);
+        }
+        user
+    }
+
+    /// 应用一条规则，失败时用户不变
+    pub fn apply(&mut self, rule: &str) -> Result<(), AclError> {
+        let invalid = |reason: &str| AclError::InvalidRule {
+            rule: rule.to_string(),
+            reason: reason.to_string(),
+        };
+        match rule.to_ascii_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => return self.apply("~*"),
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => return self.apply("&*"),
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => return self.apply("+@all"),
+            "nocommands" => return self.apply("-@all"),
+            "reset" => {
+                for rule in ["resetpass", "resetkeys", "resetchannels", "-@all", "off"] {
+                    self.apply(rule)?;
+                }
+            }
+            _ => match rule.as_bytes().first() {
+                Some(b'>') => {
+                    let hash = hash_password(&rule[1..]);
+                    if !self.passwords.contains(&hash) {
+                        self.passwords.push(hash);
+                    }
+                    self.nopass = false;
+                }
+                Some(b'<') => self.remove_password(&hash_password(&rule[1..]), rule)?,
+                Some(b'#') => {
+                    let hash = &rule[1..];
+                    if !is_valid_hash(hash) {
+                        return Err(invalid("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters"));
+                    }
+                    if !self.passwords.iter().any(|p| p == hash) {
+                        self.passwords.push(hash.to_string());
+                    }
+                    self.nopass = false;
+                }
+                Some(b'!') => self.remove_password(&rule[1..], rule)?,
+                Some(b'~') | Some(b'%') => {
+                    let pattern = parse_key_pattern(rule).ok_or_else(|| invalid("Syntax error"))?;
+                    if !self.keys.contains(&pattern) {
+                        self.keys.push(pattern);
+                    }
+                }
+                Some(b'&') => {
+                    let pattern = rule[1..].to_string();
+                    if !self.channels.contains(&pattern) {
+                        self.channels.push(pattern);
+                    }
+                }
+                Some(b'+') | Some(b'-') => {
+                    let target = parse_command_target(&rule[1..])
+                        .ok_or_else(|| invalid("Unknown command or category name in ACL"))?;
+                    let allow = rule.starts_with('+');
+                    //+@all和-@all之前的规则都被覆盖了，没有规则时本来就什么都不能执行
+                    if target == CommandTarget::Category("all".into()) {
+                        self.commands.clear();
+                        if !allow {
+                            return Ok(());
+                        }
+                    }
+                    self.commands.push(CommandRule { allow, target });
+                }
+                _ => return Err(invalid("Syntax error")),
+            },
+        }
+        Ok(())
+    }
+
+    fn remove_password(&mut self, hash: &str, rule: &str) -> Result<(), AclError> {
+        match self.passwords.iter().position(|p| p == hash) {
+            Some(i) => {
+                self.passwords.remove(i);
+                Ok(())
+            }
+            None => Err(AclError::InvalidRule {
+                rule: rule.to_string(),
+                reason: "The password you are trying to remove from the user does not exist".into(),
+            }),
+        }
+    }
+
+    pub fn check_password(&self, password: &str) -> bool {
+        self.nopass || self.passwords.contains(&hash_password(password))
+    }
+
+    /// label是带子命令的命令名，比如config|get
+    pub fn can_run(&self, spec: &CommandSpec, label: &str) -> bool {
+        self.commands
+            .iter()
+            .rev()
+            .find(|rule| match &rule.target {
+                CommandTarget::Category(category) => spec.in_category(category),
+                CommandTarget::Command(name) => name == spec.name,
+                CommandTarget::Subcommand(name, sub) => {
+                    label.split_once('|') == Some((name.as_str(), sub.as_str()))
+                }
+            })
+            .is_some_and(|rule| rule.allow)
+    }
+
+    pub fn can_access_key(&self, key: &str, write: bool) -> bool {
+        self.keys.iter().any(|p| {
+            (if write { p.write } else { p.read })
+                && glob_match(p.pattern.as_bytes(), key.as_bytes(), false)
+        })
+    }
+
+    pub fn can_access_channel(&self, channel: &str) -> bool {
+        self.channels
+            .iter()
+            .any(|p| glob_match(p.as_bytes(), channel.as_bytes(), false))
+    }
+
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    /// +@all -config|set
+    pub fn describe_commands(&self) -> String {
+        let mut rules = self
+            .commands
+            .iter()
+            .map(|rule| {
+                let sign = if rule.allow { '+' } else { '-' };
+                match &rule.target {
+                    CommandTarget::Category(category) => format!("{sign}@{category}"),
+                    CommandTarget::Command(name) => format!("{sign}{name}"),
+                    CommandTarget::Subcommand(name, sub) => format!("{sign}{name}|{sub}"),
+                }
+            })
+            .collect::<Vec<_>>();
+        if rules.first().is_none_or(|rule| rule != "+@all") {
+            rules.insert(0, "-@all".into());
+        }
+        rules.join(" ")
+    }
+
+    /// ~* %R~cache:*
+    pub fn describe_keys(&self) -> String {
+        self.keys
+            .iter()
+            .map(|p| match (p.read, p.write) {
+                (true, true) => format!("~{}", p.pattern),
+                (true, false) => format!("%R~{}", p.pattern),
+                _ => format!("%W~{}", p.pattern),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// &*
+    pub fn describe_channels(&self) -> String {
+        self.channels
+            .iter()
+            .map(|p| format!("&{p}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// ACL LIST和ACL文件里的一行：user alice on #<hash> ~cache:* resetchannels -@all +get
+    pub fn describe(&self) -> String {
+        let mut parts = vec!["user".to_string(), self.name.clone()];
+        parts.extend(self.flags().into_iter().map(String::from));
+        parts.extend(self.passwords.iter().map(|p| format!("#{p}")));
+        if !self.keys.is_empty() {
+            parts.push(self.describe_keys());
+        }
+        parts.push(match self.channels.is_empty() {
+            true => "resetchannels".into(),
+            false => self.describe_channels(),
+        });
+        parts.push(self.describe_commands());
+        parts.join(" ")
+    }
+}
+
+pub fn hash_password(password: &str) -> String {
+    Sha256::digest(password.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn parse_key_pattern(rule: &str) -> Option<KeyPattern> {
+    if let Some(pattern) = rule.strip_prefix('~') {
+        return Some(KeyPattern {
+            pattern: pattern.into(),
+            read: true,
+            write: true,
+        });
+    }
+    let (perms, pattern) = rule.strip_prefix('%')?.split_once('~')?;
+    let perms = perms.to_ascii_uppercase();
+    if perms.is_empty() || !perms.chars().all(|c| c == 'R' || c == 'W') {
+        return None;
+    }
+    Some(KeyPattern {
+        pattern: pattern.into(),
+        read: perms.contains('R'),
+        write: perms.contains('W'),
+    })
+}
+
+/// @category、command或者command|subcommand
+fn parse_command_target(target: &str) -> Option<CommandTarget> {
+    let target = target.to_ascii_lowercase();
+    if let Some(category) = target.strip_prefix('@') {
+        return (category == "all" || CATEGORIES.contains(&category))
+            .then(|| CommandTarget::Category(category.into()));
+    }
+    match target.split_once('|') {
+        Some((name, sub)) if !sub.is_empty() => {
+            command_spec(name).map(|_| CommandTarget::Subcommand(name.into(), sub.into()))
+        }
+        Some(_) => None,
+        None => command_spec(&target).map(|_| CommandTarget::Command(target.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(rules: &[&str]) -> User {
+        let mut user = User::new("alice");
+        for rule in rules {
+            user.apply(rule).unwrap();
+        }
+        user
+    }
+
+    #[test]
+    fn test_passwords() {
+        let mut alice = user(&["on", ">secret"]);
+        assert!(alice.check_password("secret"));
+        assert!(!alice.check_password("other"));
+        assert_eq!(alice.passwords, vec![hash_password("secret")]);
+        assert_eq!(
+            hash_password("secret"),
+            "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+        );
+        assert!(alice.apply("<other").is_err());
+        alice.apply("<secret").unwrap();
+        assert!(!alice.check_password("secret"));
+
+        alice.apply(&format!("#{}", hash_password("pw"))).unwrap();
+        assert!(alice.check_password("pw"));
+        assert!(alice.apply("#abc").is_err());
+        alice.apply("nopass").unwrap();
+        assert!(alice.check_password("anything"));
+        alice.apply("resetpass").unwrap();
+        assert!(!alice.check_password("anything"));
+    }
+
+    #[test]
+    fn test_command_rules() {
+        let get = command_spec("get").unwrap();
+        let set = command_spec("set").unwrap();
+        let config = command_spec("config").unwrap();
+
+        let alice = user(&["+@read", "-get"]);
+        assert!(!alice.can_run(get, "get"));
+        assert!(!alice.can_run(set, "set"));
+        assert!(alice.can_run(command_spec("hget").unwrap(), "hget"));
+
+        let alice = user(&["+@all", "-@dangerous", "+config|get"]);
+        assert!(alice.can_run(set, "set"));
+        assert!(alice.can_run(config, "config|get"));
+        assert!(!alice.can_run(config, "config|set"));
+        assert_eq!(alice.describe_commands(), "+@all -@dangerous +config|get");
+
+        //+@all之前的规则不再保留
+        let alice = user(&["+get", "-@all", "+set"]);
+        assert_eq!(alice.describe_commands(), "-@all +set");
+        assert!(!alice.can_run(get, "get"));
+
+        let mut alice = User::new("alice");
+        assert!(alice.apply("+nosuchcommand").is_err());
+        assert!(alice.apply("+@nosuchcategory").is_err());
+        assert!(alice.apply("+config|").is_err());
+        assert!(alice.apply("bogus").is_err());
+        assert_eq!(alice.describe_commands(), "-@all");
+    }
+
+    #[test]
+    fn test_key_and_channel_patterns() {
+        let alice = user(&["~cache:*", "%R~ro:*", "%W~log:*", "&news.*"]);
+        assert!(alice.can_access_key("cache:1", true));
+        assert!(alice.can_access_key("ro:1", false));
+        assert!(!alice.can_access_key("ro:1", true));
+        assert!(alice.can_access_key("log:1", true));
+        assert!(!alice.can_access_key("log:1", false));
+        assert!(!alice.can_access_key("other", false));
+        assert!(alice.can_access_channel("news.tech"));
+        assert!(!alice.can_access_channel("sports"));
+        assert_eq!(alice.describe_keys(), "~cache:* %R~ro:* %W~log:*");
+
+        let mut alice = alice;
+        assert!(alice.apply("%X~a").is_err());
+        alice.apply("resetkeys").unwrap();
+        assert!(!alice.can_access_key("cache:1", true));
+    }
+
+    #[test]
+    fn test_describe() {
+        let default = User::new_default("default");
+        assert_eq!(default.describe(), "user default on nopass ~* &* +@all");
+
+        let mut alice = User::new("alice");
+        assert_eq!(alice.describe(), "user alice off resetchannels -@all");
+        alice.apply("on").unwrap();
+        alice.apply(">pw").unwrap();
+        alice.apply("%R~cache:*").unwrap();
+        alice.apply("+get").unwrap();
+        let line = alice.describe();
+        assert_eq!(
+            line,
+            format!(
+                "user alice on #{} %R~cache:* resetchannels -@all +get",
+                hash_password("pw")
+            )
+        );
+
+        //ACL LIST的输出可以原样作为规则重新生成同样的用户
+        let mut copy = User::new("alice");
+        for rule in line.split_whitespace().skip(2) {
+            copy.apply(rule).unwrap();
+        }
+        assert_eq!(copy, alice);
+
+        alice.apply("reset").unwrap();
+        assert_eq!(alice.describe(), "user alice off resetchannels -@all");
+    }
+}
@@ -0,0 +1,205 @@
+/*
+Redis 7的multi part AOF：appenddirname目录下有一个base文件、若干个incr文件，以及一个描述它们的manifest，
+manifest每行描述一个文件：
+    file appendonly.aof.1.base.rdb seq 1 type b
+    file appendonly.aof.1.incr.aof seq 1 type i
+type为h的是重写之后已经没用、等待删除的文件
+*/
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::AofError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AofFileType {
+    Base,
+    Incr,
+    History,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AofFile {
+    pub name: String,
+    pub seq: u64,
+    pub file_type: AofFileType,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub base: Option<AofFile>,
+    /// 按seq从小到大排列，加载时按这个顺序重放
+    pub incrs: Vec<AofFile>,
+    pub history: Vec<AofFile>,
+}
+
+impl Manifest {
+    pub fn parse(content: &str) -> Result<Self, AofError> {
+        let mut manifest = Manifest::default();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let file = parse_line(line)?;
+            match file.file_type {
+                AofFileType::Base if manifest.base.is_some() => {
+                    return Err(invalid("found duplicate base file information"))
+                }
+                AofFileType::Base => manifest.base = Some(file),
+                AofFileType::Incr => {
+                    if manifest
+                        .incrs
+                        .last()
+                        .is_some_and(|last| last.seq >= file.seq)
+                    {
+                        return Err(invalid("found a non-monotonic sequence number"));
+                    }
+                    manifest.incrs.push(file)
+                }
+                AofFileType::History => manifest.history.push(file),
+            }
+        }
+        Ok(manifest)
+    }
+
+    pub fn encode(&self) -> String {
+        self.base
+            .iter()
+            .chain(&self.history)
+            .chain(&self.incrs)
+            .map(|file| {
+                let file_type = match file.file_type {
+                    AofFileType::Base => 'b',
+                    AofFileType::Incr => 'i',
+                    AofFileType::History => 'h',
+                };
+                format!("file {} seq {} type {}\n", file.name, file.seq, file_type)
+            })
+            .collect()
+    }
+
+    /// 目录里没有manifest时返回None
+    pub fn read(dir: &Path, filename: &str) -> Result<Option<Self>, AofError> {
+        match fs::read_to_string(dir.join(manifest_name(filename))) {
+            Ok(content) => Self::parse(&content).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 先写临时文件再rename，manifest在任何时刻都是完整的
+    pub fn write(&self, dir: &Path, filename: &str) -> Result<(), AofError> {
+        let name = manifest_name(filename);
+        let tmp = dir.join(format!("temp-{name}"));
+        let mut file = File::create(&tmp)?;
+        file.write_all(self.encode().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, dir.join(name))?;
+        Ok(())
+    }
+
+    /// 按加载顺序排列的文件：base在最前面，然后是所有incr
+    pub fn files(&self) -> impl Iterator<Item = &AofFile> {
+        self.base.iter().chain(&self.incrs)
+    }
+
+    /// 追加一个新的incr文件，序号接着最后一个incr
+    pub fn next_incr(&mut self, filename: &str) -> AofFile {
+        let seq = self.incrs.last().map_or(1, |last| last.seq + 1);
+        let file = AofFile {
+            name: format!("{filename}.{seq}.incr.aof"),
+            seq,
+            file_type: AofFileType::Incr,
+        };
+        self.incrs.push(file.clone());
+        file
+    }
+
+    /// 下一个base文件的描述，重写完成之前不会放进manifest
+    pub fn next_base(&self, filename: &str, rdb_preamble: bool) -> AofFile {
+        let seq = self.base.as_ref().map_or(1, |base| base.seq + 1);
+        let ext = if rdb_preamble { "rdb" } else { "aof" };
+        AofFile {
+            name: format!("{filename}.{seq}.base.{ext}"),
+            seq,
+            file_type: AofFileType::Base,
+        }
+    }
+
+    /// 所有文件的总大小，auto-aof-rewrite-percentage用它判断AOF增长了多少
+    pub fn total_size(&self, dir: &Path) -> u64 {
+        self.files()
+            .filter_map(|file| fs::metadata(dir.join(&file.name)).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+}
+
+pub fn manifest_name(filename: &str) -> String {
+    format!("{filename}.manifest")
+}
+
+fn parse_line(line: &str) -> Result<AofFile, AofError> {
+    let tokens = line.split_whitespace().collect::<Vec<_>>();
+    if !tokens.len().is_multiple_of(2) {
+        return Err(invalid("the number of arguments must be even"));
+    }
+    let (mut name, mut seq, mut file_type) = (None, None, None);
+    for pair in tokens.chunks_exact(2) {
+        match pair[0] {
+            "file" => name = Some(pair[1].to_string()),
+            "seq" => seq = Some(pair[1].parse().map_err(|_| invalid("invalid seq"))?),
+            "type" => {
+                file_type = Some(match pair[1] {
+                    "b" => AofFileType::Base,
+                    "i" => AofFileType::Incr,
+                    "h" => AofFileType::History,
+                    _ => return Err(invalid("unknown AOF file type")),
+                })
+            }
+            //不认识的字段留给以后的版本，这里直接忽略
+            _ => {}
+        }
+    }
+    match (name, seq, file_type) {
+        (Some(name), Some(seq), Some(file_type)) => Ok(AofFile {
+            name,
+            seq,
+            file_type,
+        }),
+        _ => Err(invalid("missing file, seq or type")),
+    }
+}
+
+fn invalid(msg: &str) -> AofError {
+    AofError::InvalidManifest(msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_parse_and_encode() {
+        let content = "file appendonly.aof.1.base.rdb seq 1 type b\n\
+                       # comment\n\
+                       file appendonly.aof.1.incr.aof seq 1 type i\n\
+                       file appendonly.aof.2.incr.aof seq 2 type i\n";
+        let mut manifest = Manifest::parse(content).unwrap();
+        assert_eq!(
+            manifest.base.as_ref().unwrap().name,
+            "appendonly.aof.1.base.rdb"
+        );
+        assert_eq!(manifest.incrs.len(), 2);
+        assert_eq!(manifest.encode(), content.replace("# comment\n", ""));
+
+        let incr = manifest.next_incr("appendonly.aof");
+        assert_eq!(incr.name, "appendonly.aof.3.incr.aof");
+        let base = manifest.next_base("appendonly.aof", false);
+        assert_eq!(base.name, "appendonly.aof.2.base.aof");
+
+        assert!(Manifest::parse("file a seq 2 type i\nfile b seq 1 type i\n").is_err());
+        assert!(Manifest::parse("file a seq 1 type x\n").is_err());
+        assert!(Manifest::parse("file a seq 1\n").is_err());
+    }
+}
@@ -0,0 +1,927 @@
+/*
+AOF持久化：每条执行成功的写命令按RESP数组编码追加到文件末尾，启动时按顺序重放。
+    appendfsync always   每次追加后立即fsync
+    appendfsync everysec 后台每秒fsync一次
+    appendfsync no       交给操作系统决定什么时候落盘
+文件按Redis 7的multi part布局组织，见manifest.rs；BGREWRITEAOF见rewrite.rs
+*/
+mod manifest;
+mod rewrite;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, RwLock};
+use std::time::Duration;
+
+use bytes::BytesMut;
+use thiserror::Error;
+use tokio::sync::{futures::Notified, Notify};
+use tracing::{info, warn};
+
+use crate::{
+    network::{request_handler, Session},
+    rdb::{self, RdbError, RDB_MAGIC},
+    Backend, DecodeResp, EncodeResp, RespArray, RespBulkString, RespError, RespFrame,
+};
+
+pub use manifest::{AofFile, AofFileType, Manifest};
+pub use rewrite::{bgrewriteaof, rewrite_commands, should_rewrite};
+
+#[derive(Error, Debug)]
+pub enum AofError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+
+    #[error("Bad file format reading the append only file: {0}")]
+    BadFormat(String),
+
+    #[error("Unexpected end of file reading the append only file at offset {0}, set aof-load-truncated to yes to load it anyway")]
+    Truncated(usize),
+
+    #[error("invalid appendfsync policy '{0}'")]
+    InvalidFsyncPolicy(String),
+
+    #[error("Invalid AOF manifest file format: {0}")]
+    InvalidManifest(String),
+
+    #[error("The AOF file {0} doesn't exist")]
+    MissingFile(String),
+
+    #[error("Background append only file rewriting already in progress")]
+    RewriteInProgress,
+
+    #[error("{0}")]
+    Rdb(#[from] RdbError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AppendFsync {
+    Always,
+    #[default]
+    EverySec,
+    No,
+}
+
+impl FromStr for AppendFsync {
+    type Err = AofError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "always" => Ok(AppendFsync::Always),
+            "everysec" => Ok(AppendFsync::EverySec),
+            "no" => Ok(AppendFsync::No),
+            _ => Err(AofError::InvalidFsyncPolicy(s.into())),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AofConfig {
+    pub enabled: bool,
+    pub dir: PathBuf,
+    /// base、incr和manifest文件所在的目录，相对于dir
+    pub dirname: String,
+    /// 各个AOF文件名的前缀
+    pub filename: String,
+    pub fsync: AppendFsync,
+    /// 文件末尾不完整时是截掉坏掉的部分继续加载，还是拒绝启动
+    pub load_truncated: bool,
+    /// 重写时base文件写成RDB格式，否则写成重建数据的命令
+    pub use_rdb_preamble: bool,
+    /// AOF比上次重写之后增长超过这个百分比时自动重写，0表示不自动重写
+    pub auto_rewrite_percentage: u64,
+    /// AOF小于这个字节数时不自动重写
+    pub auto_rewrite_min_size: u64,
+}
+
+impl Default for AofConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: PathBuf::from("."),
+            dirname: "appendonlydir".into(),
+            filename: "appendonly.aof".into(),
+            fsync: AppendFsync::default(),
+            load_truncated: true,
+            use_rdb_preamble: true,
+            auto_rewrite_percentage: 100,
+            auto_rewrite_min_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl AofConfig {
+    /// 存放multi part AOF的目录
+    pub fn dir_path(&self) -> PathBuf {
+        self.dir.join(&self.dirname)
+    }
+
+    /// Redis 7之前的单文件AOF，启动时会被升级成multi part布局
+    pub fn legacy_path(&self) -> PathBuf {
+        self.dir.join(&self.filename)
+    }
+}
+
+/// AOF的运行时状态，挂在Backend上；文件打开之后写命令才会被追加
+#[derive(Debug)]
+pub struct AofState {
+    config: RwLock<AofConfig>,
+    manifest: Mutex<Manifest>,
+    /// 当前正在追加的incr文件
+    file: Mutex<Option<File>>,
+    enabled: AtomicBool,
+    /// everysec模式下上次fsync之后有没有新的写入
+    pending_fsync: AtomicBool,
+    last_write_ok: AtomicBool,
+    rewrite_in_progress: AtomicBool,
+    last_rewrite_ok: AtomicBool,
+    /// base和所有incr文件的总大小
+    current_size: AtomicU64,
+    /// 上次重写完成(或者启动)时的总大小，自动重写以它为基准计算增长比例
+    base_size: AtomicU64,
+    /// 最后一次追加的命令在复制流里的offset，以及其中已经fsync的部分，WAITAOF靠它们判断是否落盘
+    written_reploff: AtomicU64,
+    fsynced_reploff: AtomicU64,
+    fsynced: Notify,
+}
+
+impl Default for AofState {
+    fn default() -> Self {
+        Self {
+            config: RwLock::default(),
+            manifest: Mutex::default(),
+            file: Mutex::default(),
+            enabled: AtomicBool::new(false),
+            pending_fsync: AtomicBool::new(false),
+            last_write_ok: AtomicBool::new(true),
+            rewrite_in_progress: AtomicBool::new(false),
+            last_rewrite_ok: AtomicBool::new(true),
+            current_size: AtomicU64::new(0),
+            base_size: AtomicU64::new(0),
+            written_reploff: AtomicU64::new(0),
+            fsynced_reploff: AtomicU64::new(0),
+            fsynced: Notify::new(),
+        }
+    }
+}
+
+impl AofState {
+    pub fn config(&self) -> AofConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_config(&self, config: AofConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    /// 文件已经打开，写命令需要被追加
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    pub fn last_write_ok(&self) -> bool {
+        self.last_write_ok.load(Ordering::SeqCst)
+    }
+
+    pub fn rewrite_in_progress(&self) -> bool {
+        self.rewrite_in_progress.load(Ordering::SeqCst)
+    }
+
+    pub fn last_rewrite_ok(&self) -> bool {
+        self.last_rewrite_ok.load(Ordering::SeqCst)
+    }
+
+    pub fn current_size(&self) -> u64 {
+        self.current_size.load(Ordering::SeqCst)
+    }
+
+    pub fn base_size(&self) -> u64 {
+        self.base_size.load(Ordering::SeqCst)
+    }
+
+    /// 已经fsync到磁盘的写命令在复制流里的offset
+    pub fn fsynced_reploff(&self) -> u64 {
+        self.fsynced_reploff.load(Ordering::SeqCst)
+    }
+
+    /// fsync_reploff前进时被唤醒
+    pub fn fsync_notified(&self) -> Notified<'_> {
+        self.fsynced.notified()
+    }
+
+    pub fn manifest(&self) -> Manifest {
+        self.manifest_guard().clone()
+    }
+
+    /// 读取(必要时创建或者从单文件AOF升级)manifest，以追加模式打开最后一个incr文件，
+    /// 之后执行的写命令都会写进去
+    pub fn open(&self) -> Result<(), AofError> {
+        let config = self.config();
+        let dir = config.dir_path();
+        fs::create_dir_all(&dir)?;
+
+        let mut manifest = self.manifest_guard();
+        let mut next = match Manifest::read(&dir, &config.filename)? {
+            Some(manifest) => manifest,
+            None => upgrade_legacy(&config)?,
+        };
+        //上次重写完成后没来得及删掉的文件
+        for file in next.history.drain(..) {
+            let _ = fs::remove_file(dir.join(&file.name));
+        }
+        let incr = match next.incrs.last() {
+            Some(incr) => incr.clone(),
+            None => next.next_incr(&config.filename),
+        };
+        next.write(&dir, &config.filename)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(&incr.name))?;
+        let size = next.total_size(&dir);
+        self.current_size.store(size, Ordering::SeqCst);
+        self.base_size.store(size, Ordering::SeqCst);
+        *manifest = next;
+        *self.file_guard() = Some(file);
+        self.enabled.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn close(&self) {
+        self.enabled.store(false, Ordering::SeqCst);
+        if let Some(file) = self.file_guard().take() {
+            let _ = file.sync_data();
+        }
+    }
+
+    /// 把若干条命令作为一个整体追加到文件，reploff是这些命令之后复制流的offset。
+    /// 调用方负责保证调用顺序和执行顺序一致
+    pub fn append(&self, commands: &[RespArray], reploff: u64) {
+        let mut buf = Vec::new();
+        for cmd in commands {
+            buf.extend(cmd.clone().encode());
+        }
+
+        let mut guard = self.file_guard();
+        let Some(file) = guard.as_mut() else {
+            return;
+        };
+        let ret = file.write_all(&buf).and_then(|_| {
+            self.current_size
+                .fetch_add(buf.len() as u64, Ordering::SeqCst);
+            self.written_reploff.store(reploff, Ordering::SeqCst);
+            match self.config().fsync {
+                AppendFsync::Always => {
+                    file.sync_data()?;
+                    self.set_fsynced(reploff);
+                }
+                //不主动fsync时写进操作系统就算落盘
+                AppendFsync::No => self.set_fsynced(reploff),
+                AppendFsync::EverySec => self.pending_fsync.store(true, Ordering::SeqCst),
+            }
+            Ok(())
+        });
+        if let Err(e) = &ret {
+            warn!("Error writing to the AOF file: {}", e);
+        }
+        self.last_write_ok.store(ret.is_ok(), Ordering::SeqCst);
+    }
+
+    /// everysec模式下由后台任务调用，WAITAOF也会调用它尽快落盘。
+    /// fsync用复制出来的fd做，不挡住追加
+    pub fn fsync_if_pending(&self) -> io::Result<()> {
+        if !self.pending_fsync.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let (file, reploff) = match self.file_guard().as_ref() {
+            Some(file) => (
+                file.try_clone()?,
+                self.written_reploff.load(Ordering::SeqCst),
+            ),
+            None => return Ok(()),
+        };
+        file.sync_data()?;
+        self.set_fsynced(reploff);
+        Ok(())
+    }
+
+    fn set_fsynced(&self, reploff: u64) {
+        self.fsynced_reploff.fetch_max(reploff, Ordering::SeqCst);
+        self.fsynced.notify_waiters();
+    }
+
+    /// 新建一个incr文件并把之后的追加切换过去，返回新文件的序号。
+    /// 调用方需要持有Backend的独占锁，保证切换时没有写命令正在执行
+    fn rotate_incr(&self) -> Result<u64, AofError> {
+        let config = self.config();
+        let dir = config.dir_path();
+        let mut manifest = self.manifest_guard();
+        let mut next = manifest.clone();
+        let incr = next.next_incr(&config.filename);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(&incr.name))?;
+        next.write(&dir, &config.filename)?;
+        *manifest = next;
+
+        if let Some(old) = self.file_guard().replace(file) {
+            let _ = old.sync_data();
+        }
+        Ok(incr.seq)
+    }
+
+    /// 重写完成：manifest换成新的base加上重写开始之后的incr，再删掉旧文件
+    fn finish_rewrite(&self, base: AofFile, first_incr: Option<u64>) -> Result<(), AofError> {
+        let config = self.config();
+        let dir = config.dir_path();
+        let mut manifest = self.manifest_guard();
+        let mut next = manifest.clone();
+        let mut obsolete = next.base.replace(base).into_iter().collect::<Vec<_>>();
+        let (incrs, old): (Vec<_>, Vec<_>) = next
+            .incrs
+            .into_iter()
+            .partition(|file| first_incr.is_some_and(|seq| file.seq >= seq));
+        next.incrs = incrs;
+        obsolete.extend(old);
+        next.write(&dir, &config.filename)?;
+
+        for file in obsolete {
+            let _ = fs::remove_file(dir.join(&file.name));
+        }
+        let size = next.total_size(&dir);
+        self.current_size.store(size, Ordering::SeqCst);
+        self.base_size.store(size, Ordering::SeqCst);
+        *manifest = next;
+        Ok(())
+    }
+
+    fn file_guard(&self) -> MutexGuard<'_, Option<File>> {
+        self.file.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn manifest_guard(&self) -> MutexGuard<'_, Manifest> {
+        self.manifest.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 没有manifest时，把dir下的单文件AOF移进AOF目录当作base，和Redis 7升级旧AOF的做法一样
+fn upgrade_legacy(config: &AofConfig) -> Result<Manifest, AofError> {
+    let legacy = config.legacy_path();
+    if !legacy.is_file() {
+        return Ok(Manifest::default());
+    }
+    fs::rename(&legacy, config.dir_path().join(&config.filename))?;
+    info!(
+        "Upgraded the old-style AOF {} to the multi part layout",
+        legacy.display()
+    );
+    Ok(Manifest {
+        base: Some(AofFile {
+            name: config.filename.clone(),
+            seq: 1,
+            file_type: AofFileType::Base,
+        }),
+        ..Default::default()
+    })
+}
+
+/// 运行中通过CONFIG SET appendonly yes开启AOF：文件里没有开启之前的数据，
+/// 和Redis的startAppendOnly一样打开之后马上重写一次。调用方需要持有Backend的独占锁
+pub fn start(backend: &Backend) -> Result<(), AofError> {
+    if backend.aof.rewrite_in_progress() {
+        return Err(AofError::RewriteInProgress);
+    }
+    backend.aof.open()?;
+    if let Err(e) = bgrewriteaof(backend) {
+        backend.aof.close();
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// AOF的后台任务：appendfsync everysec每秒fsync一次，以及检查是否需要自动重写。
+/// 没有开启AOF时什么都不做，运行中开启之后才开始工作
+pub async fn cron(backend: Backend) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        if backend.aof.config().fsync == AppendFsync::EverySec {
+            let inner = backend.clone();
+            let ret = tokio::task::spawn_blocking(move || inner.aof.fsync_if_pending()).await;
+            if let Ok(Err(e)) = ret {
+                warn!("Error syncing the AOF file: {}", e);
+            }
+        }
+
+        if should_rewrite(&backend) {
+            info!(
+                "Starting automatic rewriting of AOF on {}% growth",
+                backend.aof.config().auto_rewrite_percentage
+            );
+            //和fsync一样，等锁和切换incr文件都放在阻塞线程里
+            let inner = backend.clone();
+            let started = tokio::task::spawn_blocking(move || {
+                let _guard = inner.exclusive_lock();
+                bgrewriteaof(&inner)
+            })
+            .await;
+            if let Ok(Err(e)) = started {
+                warn!(
+                    "Background append only file rewriting failed to start: {}",
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// 启动时按manifest的顺序加载base和所有incr文件，返回从RDB格式的base恢复的key数量加上重放的命令数量。
+/// 需要在AofState::open之前调用，否则重放的命令会被再次追加到文件里
+pub fn load(backend: &Backend) -> Result<usize, AofError> {
+    let config = backend.aof.config();
+    let dir = config.dir_path();
+    let manifest = match Manifest::read(&dir, &config.filename)? {
+        Some(manifest) => manifest,
+        //还没升级的单文件AOF
+        None if config.legacy_path().is_file() => {
+            return load_file(backend, &config.legacy_path(), config.load_truncated)
+        }
+        None => return Ok(0),
+    };
+
+    let files = manifest.files().collect::<Vec<_>>();
+    let mut loaded = 0;
+    for (i, file) in files.iter().enumerate() {
+        let path = dir.join(&file.name);
+        if !path.is_file() {
+            return Err(AofError::MissingFile(file.name.clone()));
+        }
+        //只有最后一个文件允许末尾不完整
+        let allow_truncated = i + 1 == files.len() && config.load_truncated;
+        loaded += load_file(backend, &path, allow_truncated)?;
+    }
+    Ok(loaded)
+}
+
+/// 加载单个AOF文件，以REDIS开头的是RDB格式的base
+fn load_file(backend: &Backend, path: &Path, allow_truncated: bool) -> Result<usize, AofError> {
+    let data = fs::read(path)?;
+    if data.starts_with(RDB_MAGIC) {
+        let report = rdb::load_data(backend, &data)?;
+        if let Some(e) = report.unsupported() {
+            warn!("{}", e);
+        }
+        return Ok(report.loaded);
+    }
+
+    let mut buf = BytesMut::from(&data[..]);
+    //和master的命令流一样，重放的命令不受认证、ACL、集群重定向和maxmemory的限制
+    let mut session = Session::internal();
+    let mut commands = 0;
+    let mut failed = 0;
+    //最后一条完整的、不在MULTI里的命令结束的位置
+    let mut valid_len = 0;
+    while !buf.is_empty() {
+        match RespFrame::decode(&mut buf) {
+            Ok(frame @ RespFrame::Arrays(_)) => {
+                match request_handler(frame, &mut session, backend) {
+                    RespFrame::SimpleError(e) => {
+                        warn!("error replaying AOF {}: {}", path.display(), e.0);
+                        failed += 1;
+                    }
+                    _ => commands += 1,
+                }
+                if !session.transaction.in_multi() {
+                    valid_len = data.len() - buf.len();
+                }
+            }
+            Ok(frame) => return Err(AofError::BadFormat(format!("{frame:?}"))),
+            Err(RespError::NotComplete) => break,
+            Err(e) => return Err(AofError::BadFormat(e.to_string())),
+        }
+    }
+
+    if failed > 0 {
+        warn!(
+            "{} commands in AOF {} failed to replay",
+            failed,
+            path.display()
+        );
+    }
+
+    //末尾是写了一半的命令，或者是没有EXEC的MULTI
+    if valid_len < data.len() {
+        if !allow_truncated {
+            return Err(AofError::Truncated(valid_len));
+        }
+        warn!(
+            "AOF {} was truncated, discarding {} bytes at the end",
+            path.display(),
+            data.len() - valid_len
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(valid_len as u64)?;
+    }
+    Ok(commands)
+}
+
+/// 由若干参数组成的命令，写进AOF的都是这种全BulkString的数组
+pub fn command_array(args: impl IntoIterator<Item = impl Into<Vec<u8>>>) -> RespArray {
+    RespArray::new(
+        args.into_iter()
+            .map(|arg| RespBulkString::new(arg.into()).into())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::now_ms;
+
+    fn backend_in_tmp(name: &str) -> Backend {
+        let backend = Backend::new();
+        let dir =
+            std::env::temp_dir().join(format!("simple-redis-aof-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        backend.aof.set_config(AofConfig {
+            enabled: true,
+            dir,
+            fsync: AppendFsync::Always,
+            ..Default::default()
+        });
+        backend
+    }
+
+    fn cmd(args: &[&str]) -> RespFrame {
+        command_array(args.iter().map(|arg| arg.as_bytes())).into()
+    }
+
+    fn aof_file(backend: &Backend, name: &str) -> PathBuf {
+        backend.aof.config().dir_path().join(name)
+    }
+
+    fn wait_rewrite(backend: &Backend) {
+        let start = std::time::Instant::now();
+        while backend.aof.rewrite_in_progress() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(backend.aof.last_rewrite_ok());
+    }
+
+    #[test]
+    fn test_fsync_policy_from_str() {
+        assert_eq!(
+            "Always".parse::<AppendFsync>().unwrap(),
+            AppendFsync::Always
+        );
+        assert_eq!("no".parse::<AppendFsync>().unwrap(), AppendFsync::No);
+        assert!("sometimes".parse::<AppendFsync>().is_err());
+    }
+
+    #[test]
+    fn test_append_and_replay() {
+        let backend = backend_in_tmp("replay");
+        backend.aof.open().unwrap();
+        let mut session = Session::new();
+        for args in [
+            &["set", "a", "1"][..],
+            &["get", "a"],
+            &["hset", "h", "f", "v"],
+            &["set", "gone", "1"],
+            &["expire", "gone", "-1"],
+            &["set", "ttl", "1"],
+            &["expire", "ttl", "100"],
+            &["multi"],
+            &["set", "b", "2"],
+            &["del", "a"],
+            &["exec"],
+        ] {
+            request_handler(cmd(args), &mut session, &backend);
+        }
+        backend.aof.close();
+
+        let content = fs::read_to_string(aof_file(&backend, "appendonly.aof.1.incr.aof")).unwrap();
+        assert!(!content.contains("get"));
+        assert!(!content.to_ascii_lowercase().contains("$6\r\nexpire\r\n"));
+        assert!(content.contains("$9\r\nPEXPIREAT\r\n"));
+        assert!(content.contains("*1\r\n$5\r\nMULTI\r\n"));
+
+        let loaded = Backend::new();
+        loaded.aof.set_config(backend.aof.config());
+        assert_eq!(load(&loaded).unwrap(), 10);
+        assert_eq!(loaded.get("a"), None);
+        assert_eq!(loaded.get("b"), Some(RespBulkString::from("2").into()));
+        assert_eq!(loaded.get("gone"), None);
+        //两次pttl之间可能跨过一毫秒
+        assert!((loaded.pttl("ttl") - backend.pttl("ttl")).abs() <= 1);
+        assert!(loaded.pttl("ttl") > 90_000);
+        assert_eq!(
+            loaded.hget("h", "f"),
+            Some(RespBulkString::from("v").into())
+        );
+        fs::remove_dir_all(backend.aof.config().dir).unwrap();
+    }
+
+    #[test]
+    fn test_expire_overflow_not_propagated() {
+        let backend = backend_in_tmp("expire-overflow");
+        backend.aof.open().unwrap();
+        let mut session = Session::new();
+        request_handler(cmd(&["set", "k", "v"]), &mut session, &backend);
+        for (args, error) in [
+            (["expire", "k", "9223372036854775807"], "'expire'"),
+            (["pexpire", "k", "9223372036854775807"], "'pexpire'"),
+        ] {
+            let reply = request_handler(cmd(&args), &mut session, &backend);
+            let RespFrame::SimpleError(e) = reply else {
+                panic!("overflowing expire should fail");
+            };
+            assert_eq!(e.0, format!("ERR invalid expire time in {error} command"));
+        }
+        backend.aof.close();
+
+        assert!(backend.get("k").is_some());
+        let content = fs::read_to_string(aof_file(&backend, "appendonly.aof.1.incr.aof")).unwrap();
+        assert!(!content.contains("PEXPIREAT"));
+        assert!(!content.contains("DEL"));
+        fs::remove_dir_all(backend.aof.config().dir).unwrap();
+    }
+
+    #[test]
+    fn test_replay_with_requirepass() {
+        let backend = backend_in_tmp("requirepass");
+        backend.aof.open().unwrap();
+        let mut session = Session::new();
+        for args in [&["set", "a", "1"][..], &["hset", "h", "f", "v"]] {
+            request_handler(cmd(args), &mut session, &backend);
+        }
+        backend.aof.close();
+
+        //重启之后先设置了密码和ACL用户，重放不需要认证
+        let loaded = Backend::new();
+        loaded.aof.set_config(backend.aof.config());
+        loaded.acl.set_requirepass("secret");
+        assert_eq!(load(&loaded).unwrap(), 2);
+        assert_eq!(loaded.get("a"), Some(RespBulkString::from("1").into()));
+        assert_eq!(
+            loaded.hget("h", "f"),
+            Some(RespBulkString::from("v").into())
+        );
+        fs::remove_dir_all(backend.aof.config().dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_truncated() {
+        let backend = backend_in_tmp("truncated");
+        let path = backend.aof.config().legacy_path();
+        let mut data = cmd(&["set", "a", "1"]).encode();
+        let valid_len = data.len();
+        data.extend(cmd(&["multi"]).encode());
+        data.extend(cmd(&["set", "b", "1"]).encode());
+        data.extend(b"*3\r\n$3\r\nset\r\n$1\r\nc");
+        fs::write(&path, &data).unwrap();
+
+        backend.aof.set_config(AofConfig {
+            load_truncated: false,
+            ..backend.aof.config()
+        });
+        assert!(matches!(load(&backend), Err(AofError::Truncated(n)) if n == valid_len));
+
+        backend.aof.set_config(AofConfig {
+            load_truncated: true,
+            ..backend.aof.config()
+        });
+        let loaded = Backend::new();
+        loaded.aof.set_config(backend.aof.config());
+        load(&loaded).unwrap();
+        assert_eq!(loaded.get("a"), Some(RespBulkString::from("1").into()));
+        assert_eq!(loaded.get("b"), None);
+        assert_eq!(fs::metadata(&path).unwrap().len(), valid_len as u64);
+        fs::remove_dir_all(backend.aof.config().dir).unwrap();
+    }
+
+    #[test]
+    fn test_absolute_expire_is_deterministic() {
+        let backend = backend_in_tmp("expire");
+        backend.aof.open().unwrap();
+        let mut session = Session::new();
+        request_handler(cmd(&["set", "k", "v"]), &mut session, &backend);
+        request_handler(cmd(&["pexpire", "k", "50000"]), &mut session, &backend);
+        backend.aof.close();
+
+        let content = fs::read_to_string(aof_file(&backend, "appendonly.aof.1.incr.aof")).unwrap();
+        let when = content
+            .split("\r\n")
+            .filter_map(|s| s.parse::<u64>().ok())
+            .max()
+            .unwrap();
+        assert!(when > now_ms() + 40_000);
+        fs::remove_dir_all(backend.aof.config().dir).unwrap();
+    }
+
+    #[test]
+    fn test_rewrite_commands() {
+        let backend = Backend::new();
+        backend.set("s".into(), RespBulkString::from("v").into());
+        backend.rpush(
+            "l".into(),
+            (0..100)
+                .map(|i| RespBulkString::new(i.to_string().into_bytes()).into())
+                .collect(),
+        );
+        backend.zadd("z".into(), vec![(RespBulkString::from("m").into(), 1.5)]);
+        backend.expire_at("s", now_ms() + 10_000);
+
+        let commands = rewrite_commands(&backend.snapshot());
+        let names = commands
+            .iter()
+            .map(|cmd| match &cmd[0] {
+                RespFrame::BulkString(name) => String::from_utf8_lossy(name).to_string(),
+                _ => panic!("command name should be a bulk string"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names.iter().filter(|name| *name == "RPUSH").count(), 2);
+        assert!(names.contains(&"PEXPIREAT".to_string()));
+        assert!(commands.contains(&command_array(["ZADD", "z", "1.5", "m"])));
+    }
+
+    fn rewrite_and_reload(name: &str, use_rdb_preamble: bool) {
+        let backend = backend_in_tmp(name);
+        backend.aof.set_config(AofConfig {
+            use_rdb_preamble,
+            ..backend.aof.config()
+        });
+        backend.aof.open().unwrap();
+        let mut session = Session::new();
+        for args in [
+            &["set", "a", "1"][..],
+            &["set", "a", "2"],
+            &["hset", "h", "f", "v"],
+            &["rpush", "l", "x", "y"],
+            &["sadd", "set", "m"],
+            &["zadd", "z", "2", "m"],
+            &["set", "ttl", "1"],
+            &["pexpire", "ttl", "100000"],
+        ] {
+            request_handler(cmd(args), &mut session, &backend);
+        }
+
+        {
+            let _guard = backend.exclusive_lock();
+            bgrewriteaof(&backend).unwrap();
+        }
+        //重写期间的写入进入新的incr文件
+        request_handler(cmd(&["set", "b", "3"]), &mut session, &backend);
+        wait_rewrite(&backend);
+        backend.aof.close();
+
+        let manifest = backend.aof.manifest();
+        let base = manifest.base.clone().unwrap();
+        let ext = if use_rdb_preamble { "rdb" } else { "aof" };
+        assert_eq!(base.name, format!("appendonly.aof.1.base.{ext}"));
+        assert_eq!(manifest.incrs.len(), 1);
+        assert_eq!(manifest.incrs[0].name, "appendonly.aof.2.incr.aof");
+        assert!(!aof_file(&backend, "appendonly.aof.1.incr.aof").exists());
+        let on_disk = Manifest::read(&backend.aof.config().dir_path(), "appendonly.aof")
+            .unwrap()
+            .unwrap();
+        assert_eq!(on_disk, manifest);
+
+        let loaded = Backend::new();
+        loaded.aof.set_config(backend.aof.config());
+        load(&loaded).unwrap();
+        assert_eq!(loaded.snapshot().len(), backend.snapshot().len());
+        for key in ["a", "b", "h", "l", "set", "z", "ttl"] {
+            assert_eq!(loaded.key_type(key), backend.key_type(key), "{key}");
+        }
+        assert_eq!(loaded.get("a"), Some(RespBulkString::from("2").into()));
+        assert_eq!(loaded.get("b"), Some(RespBulkString::from("3").into()));
+        assert_eq!(loaded.pttl("ttl") / 1000, backend.pttl("ttl") / 1000);
+        fs::remove_dir_all(backend.aof.config().dir).unwrap();
+    }
+
+    #[test]
+    fn test_rewrite_with_command_base() {
+        rewrite_and_reload("rewrite-cmd", false);
+    }
+
+    #[test]
+    fn test_rewrite_with_rdb_base() {
+        rewrite_and_reload("rewrite-rdb", true);
+    }
+
+    #[test]
+    fn test_config_set_appendonly() {
+        let backend = backend_in_tmp("config-set");
+        backend.aof.set_config(AofConfig {
+            enabled: false,
+            ..backend.aof.config()
+        });
+        let mut session = Session::new();
+        request_handler(cmd(&["set", "a", "1"]), &mut session, &backend);
+
+        //开启之前已有的数据通过重写进入base，之后的写命令追加到incr
+        let set =
+            |value: &str| crate::config::set(&backend, &[("appendonly".into(), value.into())]);
+        set("yes").unwrap();
+        assert!(backend.aof.is_enabled());
+        wait_rewrite(&backend);
+        request_handler(cmd(&["set", "b", "2"]), &mut session, &backend);
+        set("no").unwrap();
+        assert!(!backend.aof.is_enabled());
+        request_handler(cmd(&["set", "c", "3"]), &mut session, &backend);
+
+        let reloaded = Backend::new();
+        reloaded.aof.set_config(backend.aof.config());
+        assert_eq!(load(&reloaded).unwrap(), 2);
+        assert!(reloaded.get("a").is_some());
+        assert!(reloaded.get("b").is_some());
+        assert!(reloaded.get("c").is_none());
+    }
+
+    #[test]
+    fn test_upgrade_legacy_aof() {
+        let backend = backend_in_tmp("legacy");
+        let legacy = backend.aof.config().legacy_path();
+        fs::write(&legacy, cmd(&["set", "a", "1"]).encode()).unwrap();
+        assert_eq!(load(&backend).unwrap(), 1);
+
+        backend.aof.open().unwrap();
+        assert!(!legacy.exists());
+        let manifest = backend.aof.manifest();
+        assert_eq!(manifest.base.unwrap().name, "appendonly.aof");
+        assert_eq!(manifest.incrs[0].name, "appendonly.aof.1.incr.aof");
+        backend.aof.close();
+
+        let loaded = Backend::new();
+        loaded.aof.set_config(backend.aof.config());
+        assert_eq!(load(&loaded).unwrap(), 1);
+        assert_eq!(loaded.get("a"), Some(RespBulkString::from("1").into()));
+        fs::remove_dir_all(backend.aof.config().dir).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_file_in_the_middle() {
+        let backend = backend_in_tmp("middle");
+        let config = backend.aof.config();
+        fs::create_dir_all(config.dir_path()).unwrap();
+        let mut manifest = Manifest::default();
+        let first = manifest.next_incr(&config.filename);
+        let second = manifest.next_incr(&config.filename);
+        manifest
+            .write(&config.dir_path(), &config.filename)
+            .unwrap();
+        fs::write(aof_file(&backend, &first.name), b"*2\r\n$3\r\ndel").unwrap();
+        fs::write(
+            aof_file(&backend, &second.name),
+            cmd(&["set", "a", "1"]).encode(),
+        )
+        .unwrap();
+        assert!(matches!(load(&backend), Err(AofError::Truncated(0))));
+
+        fs::remove_file(aof_file(&backend, &second.name)).unwrap();
+        fs::write(aof_file(&backend, &first.name), b"").unwrap();
+        assert!(matches!(load(&backend), Err(AofError::MissingFile(_))));
+        fs::remove_dir_all(config.dir).unwrap();
+    }
+
+    #[test]
+    fn test_auto_rewrite_threshold() {
+        let backend = backend_in_tmp("auto");
+        backend.aof.set_config(AofConfig {
+            auto_rewrite_min_size: 100,
+            ..backend.aof.config()
+        });
+        backend.aof.open().unwrap();
+        assert!(!should_rewrite(&backend));
+
+        let mut session = Session::new();
+        request_handler(cmd(&["set", "a", "1"]), &mut session, &backend);
+        //小于auto-aof-rewrite-min-size
+        assert!(!should_rewrite(&backend));
+        for _ in 0..10 {
+            request_handler(cmd(&["set", "a", "1"]), &mut session, &backend);
+        }
+        assert!(should_rewrite(&backend));
+
+        backend.aof.set_config(AofConfig {
+            auto_rewrite_percentage: 0,
+            ..backend.aof.config()
+        });
+        assert!(!should_rewrite(&backend));
+        backend.aof.close();
+        fs::remove_dir_all(backend.aof.config().dir).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+/*
+BGREWRITEAOF：用重建当前数据所需的最少命令(或者一份RDB)生成新的base文件，替换掉越来越长的incr。
+    1. 持有Backend的独占锁登记快照，同时新建一个incr文件，之后的写命令都追加到新incr里
+    2. 后台线程拷贝快照里的数据，写成新的base
+    3. manifest换成新base加上新incr，删除旧的base和incr
+重写期间的写入就缓存在新incr里，不需要像Redis 7之前那样在内存里维护重写缓冲区
+*/
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use tracing::{info, warn};
+
+use crate::{rdb::dump_aof_preamble, Backend, EncodeResp, RespArray, RespBulkString, Value};
+
+use super::{command_array, AofError};
+
+/// 每条RPUSH/SADD/ZADD最多带的元素数量，和Redis的AOF_REWRITE_ITEMS_PER_CMD一致
+const ITEMS_PER_CMD: usize = 64;
+
+type Entry = (String, Value, Option<u64>);
+
+/// 调用方需要持有Backend的独占锁，快照的登记和incr文件的切换在同一时刻完成
+pub fn bgrewriteaof(backend: &Backend) -> Result<(), AofError> {
+    let aof = &backend.aof;
+    if aof
+        .rewrite_in_progress
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return Err(AofError::RewriteInProgress);
+    }
+
+    let config = aof.config();
+    let base = aof
+        .manifest()
+        .next_base(&config.filename, config.use_rdb_preamble);
+    //没开启AOF时只生成base，没有需要保留的incr
+    let first_incr = if aof.is_enabled() {
+        match aof.rotate_incr() {
+            Ok(seq) => Some(seq),
+            Err(e) => {
+                aof.rewrite_in_progress.store(false, Ordering::SeqCst);
+                return Err(e);
+            }
+        }
+    } else {
+        None
+    };
+    let snapshot = backend.begin_snapshot();
+
+    let backend = backend.clone();
+    thread::spawn(move || {
+        let aof = &backend.aof;
+        let dir = config.dir_path();
+        let snapshot = snapshot.collect();
+        let data = if config.use_rdb_preamble {
+            dump_aof_preamble(&snapshot)
+        } else {
+            rewrite_commands(&snapshot)
+                .into_iter()
+                .flat_map(|cmd| cmd.encode())
+                .collect()
+        };
+        let ret =
+            write_base(&dir, &base.name, &data).and_then(|_| aof.finish_rewrite(base, first_incr));
+        match &ret {
+            Ok(()) => info!("Background AOF rewrite terminated with success"),
+            Err(e) => warn!("Background AOF rewrite failed: {}", e),
+        }
+        aof.last_rewrite_ok.store(ret.is_ok(), Ordering::SeqCst);
+        aof.rewrite_in_progress.store(false, Ordering::SeqCst);
+    });
+    Ok(())
+}
+
+/// auto-aof-rewrite-percentage和auto-aof-rewrite-min-size都满足时返回true
+pub fn should_rewrite(backend: &Backend) -> bool {
+    let aof = &backend.aof;
+    let config = aof.config();
+    if !aof.is_enabled() || aof.rewrite_in_progress() || config.auto_rewrite_percentage == 0 {
+        return false;
+    }
+    let current = aof.current_size();
+    let base = aof.base_size().max(1);
+    let growth = (current * 100 / base).saturating_sub(100);
+    current > config.auto_rewrite_min_size && growth >= config.auto_rewrite_percentage
+}
+
+/// 重建快照里所有key需要的命令，过期时间统一用PEXPIREAT表示
+pub fn rewrite_commands(entries: &[Entry]) -> Vec<RespArray> {
+    let mut commands = Vec::new();
+    for (key, value, expire_at) in entries {
+        match value {
+            Value::String(frame) => {
+                let mut cmd = command_array(["SET", key]);
+                cmd.0.push(frame.clone());
+                commands.push(cmd);
+            }
+            //HSET一次只能设置一个field
+            Value::Hash(fields) => {
+                for (field, frame) in fields {
+                    let mut cmd = command_array(["HSET", key, field]);
+                    cmd.0.push(frame.clone());
+                    commands.push(cmd);
+                }
+            }
+            Value::List(elements) => {
+                for chunk in elements.chunks(ITEMS_PER_CMD) {
+                    let mut cmd = command_array(["RPUSH", key]);
+                    cmd.0.extend(chunk.iter().cloned());
+                    commands.push(cmd);
+                }
+            }
+            Value::Set(members) => {
+                for chunk in members.chunks(ITEMS_PER_CMD) {
+                    let mut cmd = command_array(["SADD", key]);
+                    cmd.0.extend(chunk.iter().cloned());
+                    commands.push(cmd);
+                }
+            }
+            Value::ZSet(members) => {
+                for chunk in members.chunks(ITEMS_PER_CMD) {
+                    let mut cmd = command_array(["ZADD", key]);
+                    for (member, score) in chunk {
+                        cmd.0
+                            .push(RespBulkString::new(score.to_string().into_bytes()).into());
+                        cmd.0.push(member.clone());
+                    }
+                    commands.push(cmd);
+                }
+            }
+        }
+        if let Some(when) = expire_at {
+            commands.push(command_array(["PEXPIREAT", key, &when.to_string()]));
+        }
+    }
+    commands
+}
+
+/// 先写临时文件再rename，base文件在出现在目录里时就是完整的
+fn write_base(dir: &Path, name: &str, data: &[u8]) -> Result<(), AofError> {
+    fs::create_dir_all(dir)?;
+    let tmp = dir.join(format!("temp-rewriteaof-bg-{}.aof", std::process::id()));
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp, dir.join(name))?;
+    Ok(())
+}
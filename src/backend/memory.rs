@@ -0,0 +1,385 @@
+use std::cell::Cell;
+use std::sync::atomic::Ordering;
+
+use dashmap::DashMap;
+use rand::Rng;
+
+use crate::{EncodeResp, RespFrame, Value};
+
+use super::{now_ms, Backend};
+
+/// dictEntry + redisObject + sds头，和Redis里一个key的固定开销大致相当
+const KEY_OVERHEAD: usize = 56;
+/// hash/list/set/zset里每个元素的固定开销
+const ENTRY_OVERHEAD: usize = 24;
+/// 每个值对象的固定开销
+const FRAME_OVERHEAD: usize = 16;
+
+/// 和Redis的默认配置一致：元素数量和大小都不超过这些值时使用紧凑编码
+const LISTPACK_MAX_ENTRIES: usize = 128;
+const LISTPACK_MAX_VALUE: usize = 64;
+const INTSET_MAX_ENTRIES: usize = 512;
+/// 不超过这个长度的字符串和对象头分配在一起
+const EMBSTR_MAX_LEN: usize = 44;
+
+/// 新key的LFU计数从5开始，避免刚写入就被淘汰
+const LFU_INIT_VAL: u8 = 5;
+/// 计数越大增长越慢，factor为10时大约一百万次访问达到255
+const LFU_LOG_FACTOR: f64 = 10.0;
+/// 每过这么多分钟没有访问，计数减一
+const LFU_DECAY_TIME: u64 = 1;
+
+thread_local! {
+    /// CLIENT NO-TOUCH的连接执行命令期间为true，命令在同一个线程里同步执行完
+    static NO_TOUCH: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 执行f期间读命令不更新key的LRU/LFU，用于CLIENT NO-TOUCH
+pub fn without_touch<T>(f: impl FnOnce() -> T) -> T {
+    let old = NO_TOUCH.with(|flag| flag.replace(true));
+    let ret = f();
+    NO_TOUCH.with(|flag| flag.set(old));
+    ret
+}
+
+/// 每个key的内存占用估算和访问信息，淘汰和MEMORY/OBJECT命令都用它
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMeta {
+    /// 估算的字节数，包括key本身
+    pub size: usize,
+    /// 最后一次访问的unix毫秒
+    lru: u64,
+    /// Redis的对数访问计数
+    lfu: u8,
+    /// 上次更新lfu的unix分钟
+    lfu_time: u64,
+}
+
+impl KeyMeta {
+    fn new(size: usize) -> Self {
+        let now = now_ms();
+        Self {
+            size,
+            lru: now,
+            lfu: LFU_INIT_VAL,
+            lfu_time: now / 60_000,
+        }
+    }
+
+    /// 多久没有被访问了，毫秒
+    pub fn idle_ms(&self, now: u64) -> u64 {
+        now.saturating_sub(self.lru)
+    }
+
+    /// 衰减之后的LFU计数
+    pub fn freq(&self, now: u64) -> u8 {
+        let periods = (now / 60_000).saturating_sub(self.lfu_time) / LFU_DECAY_TIME;
+        self.lfu.saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+
+    fn touch(&mut self, now: u64) {
+        self.lfu = lfu_log_incr(self.freq(now));
+        self.lfu_time = now / 60_000;
+        self.lru = now;
+    }
+}
+
+/// 计数越大，加一的概率越小
+fn lfu_log_incr(counter: u8) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+    let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    let p = 1.0 / (base * LFU_LOG_FACTOR + 1.0);
+    if rand::thread_rng().gen::<f64>() < p {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+pub(super) fn key_size(key: &str) -> usize {
+    KEY_OVERHEAD + key.len()
+}
+
+pub(super) fn frame_size(frame: &RespFrame) -> usize {
+    FRAME_OVERHEAD
+        + match frame {
+            RespFrame::BulkString(s) => s.as_ref().len(),
+            RespFrame::SimpleString(s) => s.0.len(),
+            //整数直接存在对象里
+            RespFrame::Integer(_) => 0,
+            frame => frame.clone().encode().len(),
+        }
+}
+
+pub(super) fn field_size(field: &str, value: &RespFrame) -> usize {
+    ENTRY_OVERHEAD + field.len() + frame_size(value)
+}
+
+pub(super) fn element_size(element: &RespFrame) -> usize {
+    ENTRY_OVERHEAD + frame_size(element)
+}
+
+pub(super) fn member_size(member: &RespFrame) -> usize {
+    //再加上8字节的score
+    ENTRY_OVERHEAD + frame_size(member) + 8
+}
+
+/// value的估算大小，不包括key
+pub fn value_size(value: &Value) -> usize {
+    match value {
+        Value::String(frame) => frame_size(frame),
+        Value::Hash(fields) => fields.iter().map(|(f, v)| field_size(f, v)).sum(),
+        Value::List(elements) | Value::Set(elements) => elements.iter().map(element_size).sum(),
+        Value::ZSet(members) => members.iter().map(|(m, _)| member_size(m)).sum(),
+    }
+}
+
+/// MEMORY STATS的内容
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryStats {
+    pub peak: usize,
+    pub total: usize,
+    pub keys: usize,
+    /// key本身的固定开销
+    pub overhead: usize,
+    /// 除去固定开销之后key和value的数据
+    pub dataset: usize,
+}
+
+impl Value {
+    /// OBJECT ENCODING返回的编码名。Backend里的数据结构只有一种，这里按Redis的默认阈值
+    /// 报告同样的数据在Redis里会用哪种编码
+    pub fn encoding(&self) -> &'static str {
+        let small = |frame: &RespFrame| frame_size(frame) - FRAME_OVERHEAD <= LISTPACK_MAX_VALUE;
+        match self {
+            Value::String(RespFrame::Integer(_)) => "int",
+            Value::String(frame) if as_integer(frame).is_some() => "int",
+            Value::String(frame) if frame_size(frame) - FRAME_OVERHEAD <= EMBSTR_MAX_LEN => {
+                "embstr"
+            }
+            Value::String(_) => "raw",
+            Value::Hash(fields)
+                if fields.len() <= LISTPACK_MAX_ENTRIES
+                    && fields
+                        .iter()
+                        .all(|(f, v)| f.len() <= LISTPACK_MAX_VALUE && small(v)) =>
+            {
+                "listpack"
+            }
+            Value::Hash(_) => "hashtable",
+            Value::List(elements) if elements.len() <= LISTPACK_MAX_ENTRIES => "listpack",
+            Value::List(_) => "quicklist",
+            Value::Set(members)
+                if members.len() <= INTSET_MAX_ENTRIES
+                    && members.iter().all(|m| as_integer(m).is_some()) =>
+            {
+                "intset"
+            }
+            Value::Set(members)
+                if members.len() <= LISTPACK_MAX_ENTRIES && members.iter().all(small) =>
+            {
+                "listpack"
+            }
+            Value::Set(_) => "hashtable",
+            Value::ZSet(members)
+                if members.len() <= LISTPACK_MAX_ENTRIES
+                    && members.iter().all(|(m, _)| small(m)) =>
+            {
+                "listpack"
+            }
+            Value::ZSet(_) => "skiplist",
+        }
+    }
+}
+
+/// 能表示成i64的值，Redis会把它们存成整数
+pub(crate) fn as_integer(frame: &RespFrame) -> Option<i64> {
+    match frame {
+        RespFrame::Integer(i) => Some(i.0),
+        RespFrame::BulkString(s) => {
+            let s = std::str::from_utf8(s.as_ref()).ok()?;
+            //"007"和"+1"转成整数之后就不是原来的字符串了
+            s.parse::<i64>().ok().filter(|i| i.to_string() == s)
+        }
+        _ => None,
+    }
+}
+
+impl Backend {
+    /// 所有key估算的总字节数，maxmemory限制的就是它
+    pub fn used_memory(&self) -> usize {
+        self.used_memory.load(Ordering::SeqCst)
+    }
+
+    /// 启动以来used_memory的最大值
+    pub fn used_memory_peak(&self) -> usize {
+        self.peak_memory.load(Ordering::SeqCst)
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+        let total = self.used_memory();
+        let keys = self.meta.len();
+        let overhead = keys * KEY_OVERHEAD;
+        MemoryStats {
+            peak: self.used_memory_peak().max(total),
+            total,
+            keys,
+            overhead,
+            dataset: total.saturating_sub(overhead),
+        }
+    }
+
+    pub fn key_meta(&self, key: &str) -> Option<KeyMeta> {
+        self.meta.get(key).map(|meta| meta.clone())
+    }
+
+    /// key的数据增加(或减少)了delta字节，同时算作一次访问。第一次出现的key先计入key本身的开销
+    pub(super) fn grow(&self, key: &str, delta: isize) {
+        let mut meta = self.meta.entry(key.to_string()).or_insert_with(|| {
+            let size = key_size(key);
+            self.used_memory.fetch_add(size, Ordering::SeqCst);
+            KeyMeta::new(size)
+        });
+        meta.size = meta.size.saturating_add_signed(delta);
+        if delta >= 0 {
+            let used = self.used_memory.fetch_add(delta as usize, Ordering::SeqCst);
+            self.peak_memory
+                .fetch_max(used + delta as usize, Ordering::SeqCst);
+        } else {
+            self.used_memory
+                .fetch_sub(delta.unsigned_abs(), Ordering::SeqCst);
+        }
+        meta.touch(now_ms());
+    }
+
+    /// key被删除
+    pub(super) fn forget(&self, key: &str) {
+        if let Some((_, meta)) = self.meta.remove(key) {
+            self.used_memory.fetch_sub(meta.size, Ordering::SeqCst);
+        }
+    }
+
+    pub(super) fn forget_all(&self) {
+        self.meta.clear();
+        self.used_memory.store(0, Ordering::SeqCst);
+    }
+
+    /// 读命令访问了key，更新LRU时间和LFU计数
+    pub(super) fn record_access(&self, key: &str) {
+        if NO_TOUCH.with(Cell::get) {
+            return;
+        }
+        if let Some(mut meta) = self.meta.get_mut(key) {
+            meta.touch(now_ms());
+        }
+    }
+
+    /// RESTORE ... IDLETIME
+    pub fn set_idle(&self, key: &str, seconds: u64) {
+        if let Some(mut meta) = self.meta.get_mut(key) {
+            meta.lru = now_ms().saturating_sub(seconds.saturating_mul(1000));
+        }
+    }
+
+    /// RESTORE ... FREQ
+    pub fn set_freq(&self, key: &str, freq: u8) {
+        if let Some(mut meta) = self.meta.get_mut(key) {
+            meta.lfu = freq;
+            meta.lfu_time = now_ms() / 60_000;
+        }
+    }
+
+    /// 随机取最多count个key和它们的访问信息，volatile时只从设置了过期时间的key里取。
+    /// 和Redis的dictGetSomeKeys一样，从随机位置开始取连续的一段，不保证均匀
+    pub fn sample_keys(&self, count: usize, volatile: bool) -> Vec<(String, KeyMeta)> {
+        let keys = if volatile {
+            sample(&self.expires, count)
+        } else {
+            sample(&self.meta, count)
+        };
+        keys.into_iter()
+            .filter_map(|key| self.key_meta(&key).map(|meta| (key, meta)))
+            .collect()
+    }
+}
+
+fn sample<V>(map: &DashMap<String, V>, count: usize) -> Vec<String> {
+    let len = map.len();
+    if len == 0 || count == 0 {
+        return vec![];
+    }
+    let count = count.min(len);
+    let start = rand::thread_rng().gen_range(0..len);
+    let mut keys = map
+        .iter()
+        .skip(start)
+        .take(count)
+        .map(|e| e.key().clone())
+        .collect::<Vec<_>>();
+    //不够时从头接着取
+    let rest = count - keys.len();
+    keys.extend(map.iter().take(rest).map(|e| e.key().clone()));
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RespBulkString;
+
+    #[test]
+    fn test_memory_accounting() {
+        let backend = Backend::new();
+        let frame = |s: &'static str| RespFrame::from(RespBulkString::from(s));
+        backend.set("s".into(), frame("hello"));
+        assert_eq!(
+            backend.used_memory(),
+            key_size("s") + frame_size(&frame("hello"))
+        );
+        backend.set("s".into(), frame("hi"));
+        assert_eq!(
+            backend.used_memory(),
+            key_size("s") + frame_size(&frame("hi"))
+        );
+
+        backend.hset("h".into(), "f".into(), frame("1"));
+        backend.hset("h".into(), "f".into(), frame("22"));
+        backend.rpush("l".into(), vec![frame("a"), frame("b")]);
+        backend.sadd("set".into(), vec![frame("a"), frame("a")]);
+        backend.zadd("z".into(), vec![(frame("m"), 1.0)]);
+        let expected = backend
+            .snapshot()
+            .iter()
+            .map(|(key, value, _)| key_size(key) + value_size(value))
+            .sum::<usize>();
+        assert_eq!(backend.used_memory(), expected);
+        assert_eq!(
+            backend.key_meta("h").unwrap().size,
+            key_size("h") + field_size("f", &frame("22"))
+        );
+
+        backend.del("h");
+        backend.restore("h".into(), Value::String(frame("v")), None);
+        backend.del("s");
+        backend.flushdb();
+        assert_eq!(backend.used_memory(), 0);
+        assert!(backend.key_meta("l").is_none());
+    }
+
+    #[test]
+    fn test_lfu() {
+        let mut meta = KeyMeta::new(0);
+        let now = now_ms();
+        assert_eq!(meta.freq(now), LFU_INIT_VAL);
+        for _ in 0..1000 {
+            meta.touch(now);
+        }
+        //对数增长：1000次访问远远到不了255
+        assert!(meta.freq(now) > LFU_INIT_VAL && meta.freq(now) < 100);
+        let counter = meta.freq(now);
+        assert_eq!(meta.freq(now + 3 * 60_000), counter - 3);
+        assert_eq!(meta.idle_ms(now + 500), 500);
+    }
+}
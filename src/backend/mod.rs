@@ -1,9 +1,25 @@
-use std::sync::Arc;
+mod memory;
+mod snapshot;
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use dashmap::DashMap;
 use derive_more::derive::Deref;
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    acl::AclState, aof::AofState, clients::ClientRegistry, cluster::ClusterState,
+    config::ConfigState, eviction::EvictionState, latency::LatencyState, monitor::MonitorState,
+    rdb::RdbState, replication::ReplicationState, slowlog::SlowlogState, stats::ServerStats,
+    tls::TlsState, tracking::TrackingState, RespFrame,
+};
 
-use crate::RespFrame;
+pub(crate) use memory::as_integer;
+pub use memory::{value_size, without_touch, KeyMeta, MemoryStats};
+pub use snapshot::Snapshot;
 
 #[derive(Debug, Clone, Deref, Default)]
 pub struct Backend(Arc<BackendInner>);
@@ -12,6 +28,69 @@ pub struct Backend(Arc<BackendInner>);
 pub struct BackendInner {
     pub map: DashMap<String, RespFrame>,
     pub hmap: DashMap<String, DashMap<String, RespFrame>>,
+    pub lists: DashMap<String, VecDeque<RespFrame>>,
+    pub sets: DashMap<String, IndexSet<RespFrame>>,
+    /// member -> score
+    pub zsets: DashMap<String, IndexMap<RespFrame, f64>>,
+    /// key -> 过期时间(unix毫秒)
+    pub expires: DashMap<String, u64>,
+    /// key -> 最后一次被修改时的版本号，WATCH靠它判断key有没有被改过
+    versions: DashMap<String, u64>,
+    version_counter: AtomicU64,
+    /// key -> 估算的内存占用和访问信息
+    meta: DashMap<String, KeyMeta>,
+    used_memory: AtomicUsize,
+    peak_memory: AtomicUsize,
+    /// 普通命令持有读锁，EXEC持有写锁，保证事务执行期间没有其他连接的命令插进来
+    exec_lock: RwLock<()>,
+    /// 写命令从执行到追加AOF期间持有，保证AOF的顺序和执行顺序一致
+    write_lock: Mutex<()>,
+    /// 正在进行的快照，写操作修改key之前先把旧数据交给它们
+    snapshots: snapshot::Snapshots,
+    pub rdb: RdbState,
+    pub aof: AofState,
+    pub repl: ReplicationState,
+    pub cluster: ClusterState,
+    pub eviction: EvictionState,
+    pub stats: ServerStats,
+    pub config: ConfigState,
+    pub slowlog: SlowlogState,
+    pub latency: LatencyState,
+    pub monitor: MonitorState,
+    pub clients: ClientRegistry,
+    pub tracking: TrackingState,
+    pub acl: AclState,
+    pub tls: TlsState,
+}
+
+/// 一个key的完整数据，持久化时用它在Backend和磁盘格式之间转换
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(RespFrame),
+    Hash(Vec<(String, RespFrame)>),
+    List(Vec<RespFrame>),
+    Set(Vec<RespFrame>),
+    ZSet(Vec<(RespFrame, f64)>),
+}
+
+impl Value {
+    /// TYPE命令返回的类型名
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Hash(_) => "hash",
+            Value::List(_) => "list",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+        }
+    }
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
 }
 
 impl Backend {
@@ -20,28 +99,360 @@ impl Backend {
     }
 
     pub fn set(&self, key: String, value: RespFrame) {
+        //SET会覆盖任意类型的旧值，并且清除过期时间
+        self.preserve(&key);
+        self.remove_value(&key);
+        self.expires.remove(&key);
+        self.touch(&key);
+        self.grow(&key, memory::frame_size(&value) as isize);
         self.map.insert(key, value);
     }
 
     pub fn get(&self, key: &str) -> Option<RespFrame> {
-        self.map.get(key).map(|v| v.value().clone())
+        self.expire_if_needed(key);
+        let value = self.map.get(key).map(|v| v.value().clone());
+        if value.is_some() {
+            self.record_access(key);
+        }
+        self.tracking.remember(key);
+        self.stats.keyspace_hit(value.is_some());
+        value
     }
 
-    pub fn hset(&self, table_name: String, key: String, value: RespFrame) {
-        let target_table = self.hmap.entry(table_name).or_default();
-        target_table.insert(key, value);
+    /// 返回true表示新增了一个field，false表示覆盖了已有的field
+    pub fn hset(&self, table_name: String, key: String, value: RespFrame) -> bool {
+        self.expire_if_needed(&table_name);
+        self.preserve(&table_name);
+        self.touch(&table_name);
+        let size = memory::field_size(&key, &value) as isize;
+        let target_table = self.hmap.entry(table_name.clone()).or_default();
+        let old = target_table.insert(key.clone(), value);
+        drop(target_table);
+        let delta = old
+            .as_ref()
+            .map_or(size, |old| size - memory::field_size(&key, old) as isize);
+        self.grow(&table_name, delta);
+        old.is_none()
     }
 
     pub fn hget(&self, table_name: &str, key: &str) -> Option<RespFrame> {
-        self.hmap
-            .get(table_name)
-            .and_then(|v| v.get(key).map(|v| v.value().clone()))
+        self.expire_if_needed(table_name);
+        self.record_access(table_name);
+        self.tracking.remember(table_name);
+        let table = self.hmap.get(table_name);
+        //和Redis一样按key是否存在计算命中，不看field
+        self.stats.keyspace_hit(table.is_some());
+        table.and_then(|v| v.get(key).map(|v| v.value().clone()))
     }
 
     pub fn hgetall(&self, table_name: &str) -> Option<DashMap<String, RespFrame>> {
+        self.expire_if_needed(table_name);
+        self.record_access(table_name);
         self.hmap
             .get(table_name)
             // .and_then(|target_table| Some(target_table.clone())) //and_then方法也可行，但是需要手动用Some包装起来成为Option类型
             .map(|target_table| target_table.clone()) //map方法更简洁，会自动包装为Option类型
     }
+
+    /// 追加到list末尾，返回追加之后list的长度
+    pub fn rpush(&self, key: String, values: Vec<RespFrame>) -> usize {
+        self.expire_if_needed(&key);
+        self.preserve(&key);
+        self.touch(&key);
+        let size = values.iter().map(memory::element_size).sum::<usize>();
+        let mut list = self.lists.entry(key.clone()).or_default();
+        list.extend(values);
+        let len = list.len();
+        drop(list);
+        self.grow(&key, size as isize);
+        len
+    }
+
+    /// 返回新加入set的成员数量，已经存在的成员不计入
+    pub fn sadd(&self, key: String, members: Vec<RespFrame>) -> usize {
+        self.expire_if_needed(&key);
+        self.preserve(&key);
+        self.touch(&key);
+        let mut set = self.sets.entry(key.clone()).or_default();
+        let (mut added, mut size) = (0, 0);
+        for member in members {
+            let member_size = memory::element_size(&member);
+            if set.insert(member) {
+                added += 1;
+                size += member_size;
+            }
+        }
+        drop(set);
+        self.grow(&key, size as isize);
+        added
+    }
+
+    /// 已经存在的成员只更新分数，返回新加入的成员数量
+    pub fn zadd(&self, key: String, members: Vec<(RespFrame, f64)>) -> usize {
+        self.expire_if_needed(&key);
+        self.preserve(&key);
+        self.touch(&key);
+        let mut zset = self.zsets.entry(key.clone()).or_default();
+        let (mut added, mut size) = (0, 0);
+        for (member, score) in members {
+            let member_size = memory::member_size(&member);
+            if zset.insert(member, score).is_none() {
+                added += 1;
+                size += member_size;
+            }
+        }
+        drop(zset);
+        self.grow(&key, size as isize);
+        added
+    }
+
+    pub fn exists(&self, key: &str) -> bool {
+        self.expire_if_needed(key);
+        self.key_type(key).is_some()
+    }
+
+    /// key的类型，不存在时返回None
+    pub fn key_type(&self, key: &str) -> Option<&'static str> {
+        self.expire_if_needed(key);
+        if self.map.contains_key(key) {
+            Some("string")
+        } else if self.hmap.contains_key(key) {
+            Some("hash")
+        } else if self.lists.contains_key(key) {
+            Some("list")
+        } else if self.sets.contains_key(key) {
+            Some("set")
+        } else if self.zsets.contains_key(key) {
+            Some("zset")
+        } else {
+            None
+        }
+    }
+
+    /// 删除key，返回key之前是否存在
+    pub fn del(&self, key: &str) -> bool {
+        self.preserve(key);
+        self.expires.remove(key);
+        let existed = self.remove_value(key);
+        if existed {
+            self.touch(key);
+        }
+        existed
+    }
+
+    /// 设置绝对过期时间(unix毫秒)，key不存在时返回false，时间已过去则直接删除
+    pub fn expire_at(&self, key: &str, when_ms: u64) -> bool {
+        if !self.exists(key) {
+            return false;
+        }
+        if when_ms <= now_ms() {
+            self.del(key);
+        } else {
+            self.preserve(key);
+            self.expires.insert(key.to_string(), when_ms);
+            self.touch(key);
+        }
+        true
+    }
+
+    /// 剩余的毫秒数，-2表示key不存在，-1表示没有设置过期时间
+    pub fn pttl(&self, key: &str) -> i64 {
+        if !self.exists(key) {
+            return -2;
+        }
+        match self.expires.get(key).map(|v| *v) {
+            Some(when) => when.saturating_sub(now_ms()) as i64,
+            None => -1,
+        }
+    }
+
+    /// 所有类型的key，包括已经过期但还没有被删除的
+    pub fn keys(&self) -> Vec<String> {
+        self.map
+            .iter()
+            .map(|e| e.key().clone())
+            .chain(self.hmap.iter().map(|e| e.key().clone()))
+            .chain(self.lists.iter().map(|e| e.key().clone()))
+            .chain(self.sets.iter().map(|e| e.key().clone()))
+            .chain(self.zsets.iter().map(|e| e.key().clone()))
+            .collect()
+    }
+
+    pub fn flushdb(&self) {
+        let keys = self.keys();
+        for key in keys.iter() {
+            self.preserve(key);
+            self.touch(key);
+        }
+        self.map.clear();
+        self.hmap.clear();
+        self.lists.clear();
+        self.sets.clear();
+        self.zsets.clear();
+        self.expires.clear();
+        self.forget_all();
+    }
+
+    pub fn dbsize(&self) -> usize {
+        self.map.len() + self.hmap.len() + self.lists.len() + self.sets.len() + self.zsets.len()
+    }
+
+    /// 从所有类型的存储里删掉key，不处理过期时间和版本号
+    fn remove_value(&self, key: &str) -> bool {
+        self.forget(key);
+        //用|而不是||，保证每种类型都会被删除
+        self.map.remove(key).is_some()
+            | self.hmap.remove(key).is_some()
+            | self.lists.remove(key).is_some()
+            | self.sets.remove(key).is_some()
+            | self.zsets.remove(key).is_some()
+    }
+
+    /// key过期了就删掉它，返回是否发生了删除
+    pub fn expire_if_needed(&self, key: &str) -> bool {
+        let when = self.expires.get(key).map(|v| *v);
+        match when {
+            Some(when) if when <= now_ms() => {
+                let deleted = self.del(key);
+                if deleted {
+                    self.stats.key_expired();
+                }
+                deleted
+            }
+            _ => false,
+        }
+    }
+
+    /// 拷贝出单个key的完整数据和过期时间，key不存在时返回None
+    pub fn value(&self, key: &str) -> Option<(Value, Option<u64>)> {
+        self.expire_if_needed(key);
+        self.stored_value(key)
+    }
+
+    /// 和value一样，但不删除已经过期的key，快照里不能有修改数据的副作用
+    fn stored_value(&self, key: &str) -> Option<(Value, Option<u64>)> {
+        let value = if let Some(frame) = self.map.get(key) {
+            Value::String(frame.clone())
+        } else if let Some(fields) = self.hmap.get(key) {
+            let fields = fields
+                .iter()
+                .map(|f| (f.key().clone(), f.value().clone()))
+                .collect();
+            Value::Hash(fields)
+        } else if let Some(list) = self.lists.get(key) {
+            Value::List(list.iter().cloned().collect())
+        } else if let Some(set) = self.sets.get(key) {
+            Value::Set(set.iter().cloned().collect())
+        } else if let Some(zset) = self.zsets.get(key) {
+            Value::ZSet(zset.iter().map(|(m, s)| (m.clone(), *s)).collect())
+        } else {
+            return None;
+        };
+        Some((value, self.expires.get(key).map(|v| *v)))
+    }
+
+    /// 拷贝出所有未过期的key，在当前线程里完成，见begin_snapshot
+    pub fn snapshot(&self) -> Vec<(String, Value, Option<u64>)> {
+        self.begin_snapshot().collect()
+    }
+
+    /// 用完整的value覆盖一个key，加载RDB时使用
+    pub fn restore(&self, key: String, value: Value, expire_at: Option<u64>) {
+        self.del(&key);
+        self.touch(&key);
+        self.grow(&key, value_size(&value) as isize);
+        match value {
+            Value::String(frame) => {
+                self.map.insert(key.clone(), frame);
+            }
+            Value::Hash(fields) => {
+                self.hmap.insert(key.clone(), fields.into_iter().collect());
+            }
+            Value::List(list) => {
+                self.lists.insert(key.clone(), list.into());
+            }
+            Value::Set(set) => {
+                self.sets.insert(key.clone(), set.into_iter().collect());
+            }
+            Value::ZSet(zset) => {
+                self.zsets.insert(key.clone(), zset.into_iter().collect());
+            }
+        }
+        if let Some(when) = expire_at {
+            self.expire_at(&key, when);
+        }
+    }
+
+    /// 启动以来的修改次数，和上次保存时的值相减就是还没落盘的修改数
+    pub fn changes(&self) -> u64 {
+        self.version_counter.load(Ordering::SeqCst)
+    }
+
+    /// key当前的版本号，从来没被修改过的key版本号为0
+    pub fn version(&self, key: &str) -> u64 {
+        self.versions.get(key).map(|v| *v).unwrap_or_default()
+    }
+
+    fn touch(&self, key: &str) {
+        let version = self.version_counter.fetch_add(1, Ordering::SeqCst) + 1;
+        self.versions.insert(key.to_string(), version);
+        //打开了CLIENT TRACKING的连接缓存的key失效了
+        self.tracking.invalidate(key, &self.clients);
+    }
+
+    pub fn shared_lock(&self) -> RwLockReadGuard<'_, ()> {
+        self.exec_lock.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn exclusive_lock(&self) -> RwLockWriteGuard<'_, ()> {
+        self.exec_lock.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn write_lock(&self) -> MutexGuard<'_, ()> {
+        self.write_lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RespBulkString;
+
+    #[test]
+    fn test_versions_change_on_write() {
+        let backend = Backend::new();
+        assert_eq!(backend.version("a"), 0);
+
+        backend.set("a".into(), RespBulkString::from("1").into());
+        let v1 = backend.version("a");
+        assert!(v1 > 0);
+
+        backend.get("a");
+        assert_eq!(backend.version("a"), v1);
+
+        backend.hset("a".into(), "f".into(), RespBulkString::from("1").into());
+        let v2 = backend.version("a");
+        assert!(v2 > v1);
+
+        backend.flushdb();
+        assert!(backend.version("a") > v2);
+        assert_eq!(backend.dbsize(), 0);
+    }
+
+    #[test]
+    fn test_expire() {
+        let backend = Backend::new();
+        assert!(!backend.expire_at("a", now_ms() + 1000));
+        assert_eq!(backend.pttl("a"), -2);
+
+        backend.set("a".into(), RespBulkString::from("1").into());
+        assert_eq!(backend.pttl("a"), -1);
+        assert!(backend.expire_at("a", now_ms() + 10_000));
+        assert!(backend.pttl("a") > 9_000);
+
+        let version = backend.version("a");
+        backend.expires.insert("a".into(), now_ms() - 1);
+        assert_eq!(backend.get("a"), None);
+        assert!(backend.version("a") > version);
+    }
 }
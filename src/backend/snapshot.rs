@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::{now_ms, Backend, Value};
+
+/// 一个key在快照开始那一刻的数据和过期时间，None表示那时key不存在
+type Saved = Option<(Value, Option<u64>)>;
+
+/// 正在进行的快照。和Redis fork之后的写时复制类似：
+/// 开始之后某个key第一次被修改之前，先把它的旧数据记在这里
+#[derive(Debug, Default)]
+struct Overlay {
+    saved: Mutex<HashMap<String, Saved>>,
+}
+
+impl Overlay {
+    fn saved(&self) -> MutexGuard<'_, HashMap<String, Saved>> {
+        self.saved.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 所有正在进行的快照，BGSAVE、AOF重写和全量同步可能同时进行
+#[derive(Debug, Default)]
+pub(super) struct Snapshots {
+    active: AtomicUsize,
+    overlays: Mutex<Vec<Arc<Overlay>>>,
+}
+
+impl Snapshots {
+    fn overlays(&self) -> MutexGuard<'_, Vec<Arc<Overlay>>> {
+        self.overlays.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 某一时刻的快照，begin_snapshot只登记，collect时才拷贝数据，
+/// 拷贝期间其他连接照常读写，drop时注销
+#[derive(Debug)]
+pub struct Snapshot {
+    backend: Backend,
+    overlay: Arc<Overlay>,
+    /// 开始时已经过期的key不放进快照
+    time: u64,
+}
+
+impl Backend {
+    /// 开始一个快照，调用方持有写锁时快照就是这一刻的完整数据，开销和数据量无关
+    pub fn begin_snapshot(&self) -> Snapshot {
+        let overlay = Arc::new(Overlay::default());
+        let mut overlays = self.snapshots.overlays();
+        overlays.push(overlay.clone());
+        self.snapshots
+            .active
+            .store(overlays.len(), Ordering::SeqCst);
+        Snapshot {
+            backend: self.clone(),
+            overlay,
+            time: now_ms(),
+        }
+    }
+
+    /// 写操作修改key之前调用，没有快照在进行时只有一次原子读
+    pub(super) fn preserve(&self, key: &str) {
+        if self.snapshots.active.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let overlays = self.snapshots.overlays().clone();
+        for overlay in overlays {
+            let mut saved = overlay.saved();
+            if !saved.contains_key(key) {
+                saved.insert(key.to_string(), self.stored_value(key));
+            }
+        }
+    }
+}
+
+impl Snapshot {
+    /// 拷贝出开始那一刻所有未过期的key，在后台线程里调用，不需要持有任何锁
+    pub fn collect(self) -> Vec<(String, Value, Option<u64>)> {
+        let backend = &self.backend;
+        let mut keys = backend.keys();
+        keys.extend(self.overlay.saved().keys().cloned());
+        keys.sort_unstable();
+        keys.dedup();
+        keys.into_iter()
+            .filter_map(|key| {
+                //持有saved的锁读取当前的数据，期间写操作会停在preserve，
+                //没有被记下旧数据的key一定还没被修改过
+                let mut saved = self.overlay.saved();
+                let (value, expire) = match saved.remove(&key) {
+                    Some(entry) => entry,
+                    None => backend.stored_value(&key),
+                }?;
+                drop(saved);
+                expire
+                    .is_none_or(|when| when > self.time)
+                    .then_some((key, value, expire))
+            })
+            .collect()
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let mut overlays = self.backend.snapshots.overlays();
+        overlays.retain(|overlay| !Arc::ptr_eq(overlay, &self.overlay));
+        self.backend
+            .snapshots
+            .active
+            .store(overlays.len(), Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RespBulkString, RespFrame};
+
+    fn frame(s: &'static str) -> RespFrame {
+        RespBulkString::from(s).into()
+    }
+
+    #[test]
+    fn test_snapshot_keeps_point_in_time() {
+        let backend = Backend::new();
+        backend.set("a".into(), frame("1"));
+        backend.set("b".into(), frame("1"));
+        backend.rpush("l".into(), vec![frame("x")]);
+        backend.expire_at("b", now_ms() + 10_000);
+
+        let snapshot = backend.begin_snapshot();
+        //开始之后的修改、删除和新增都不影响快照
+        backend.set("a".into(), frame("2"));
+        backend.del("b");
+        backend.rpush("l".into(), vec![frame("y")]);
+        backend.set("c".into(), frame("3"));
+        let mut entries = snapshot.collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let keys = entries.iter().map(|e| e.0.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, ["a", "b", "l"]);
+        assert_eq!(entries[0].1, Value::String(frame("1")));
+        assert!(entries[1].2.is_some());
+        assert_eq!(entries[2].1, Value::List(vec![frame("x")]));
+
+        //快照结束之后不再记录旧数据
+        assert_eq!(backend.snapshots.active.load(Ordering::SeqCst), 0);
+        backend.set("a".into(), frame("3"));
+        assert_eq!(backend.snapshot().len(), 3);
+    }
+
+    #[test]
+    fn test_snapshot_concurrent_writes() {
+        let backend = Backend::new();
+        for i in 0..1000 {
+            backend.set(format!("k{i}"), frame("old"));
+        }
+        let snapshot = backend.begin_snapshot();
+        let writer = {
+            let backend = backend.clone();
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    backend.set(format!("k{i}"), frame("new"));
+                    backend.del(&format!("k{}", 999 - i));
+                }
+            })
+        };
+        let entries = snapshot.collect();
+        writer.join().unwrap();
+        assert_eq!(entries.len(), 1000);
+        assert!(entries
+            .iter()
+            .all(|(_, value, _)| *value == Value::String(frame("old"))));
+    }
+}
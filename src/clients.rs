@@ -0,0 +1,483 @@
+/*
+连接登记表：
+    每个客户端连接建立时登记，断开时注销，CLIENT LIST/INFO/KILL通过它看到其他连接。
+    连接状态本身仍然在各自的Session里，连接循环在每条命令前后把需要展示的字段同步到登记表
+    CLIENT KILL通过每个连接的Notify通知它的连接循环退出
+CLIENT PAUSE：
+    暂停期间普通客户端的命令(WRITE模式下只有写命令)在连接循环里等待，直到超时或者CLIENT UNPAUSE，
+    replica和master的复制连接不受影响
+*/
+use std::sync::{Arc, RwLock};
+
+use dashmap::DashMap;
+use tokio::sync::{mpsc::UnboundedSender, Notify};
+
+use crate::{
+    backend::now_ms,
+    network::{ConnAddr, Session},
+    RespFrame,
+};
+
+/// CLIENT LIST/INFO里的一个连接
+#[derive(Debug, Clone)]
+pub struct ClientEntry {
+    pub id: u64,
+    pub addr: Option<ConnAddr>,
+    pub laddr: Option<ConnAddr>,
+    pub name: String,
+    /// 连接建立时的unix毫秒
+    pub created: u64,
+    /// 最后一次收到命令的unix毫秒
+    pub last_interaction: u64,
+    /// 最后一条命令，带子命令时写成client|list
+    pub cmd: String,
+    pub user: String,
+    pub resp: i64,
+    /// 事务里排队的命令个数，不在事务里时为None
+    pub multi: Option<usize>,
+    pub flags: ClientFlags,
+    /// 读缓冲区里还没有解析的字节数和剩余容量
+    pub qbuf: usize,
+    pub qbuf_free: usize,
+    /// 写缓冲区里还没有发出去的字节数
+    pub omem: usize,
+    /// CLIENT TRACKING REDIRECT的目标，没有打开TRACKING时为-1，没有REDIRECT时为0
+    pub redir: i64,
+    kill: Arc<Notify>,
+    push: UnboundedSender<RespFrame>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientFlags {
+    pub master: bool,
+    pub replica: bool,
+    pub monitor: bool,
+    pub blocked: bool,
+    pub no_evict: bool,
+    pub no_touch: bool,
+    pub tracking: bool,
+}
+
+/// CLIENT LIST TYPE和CLIENT KILL TYPE的取值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientType {
+    Normal,
+    Master,
+    Replica,
+    PubSub,
+}
+
+impl std::str::FromStr for ClientType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "normal" => Ok(ClientType::Normal),
+            "master" => Ok(ClientType::Master),
+            "replica" | "slave" => Ok(ClientType::Replica),
+            "pubsub" => Ok(ClientType::PubSub),
+            _ => Err(format!("Unknown client type '{s}'")),
+        }
+    }
+}
+
+impl ClientEntry {
+    pub fn new(session: &Session) -> Self {
+        let now = now_ms();
+        let mut entry = Self {
+            id: session.id,
+            addr: session.addr.clone(),
+            laddr: session.laddr.clone(),
+            name: String::new(),
+            created: now,
+            last_interaction: now,
+            cmd: "NULL".into(),
+            user: session.user.clone(),
+            resp: 2,
+            multi: None,
+            flags: ClientFlags::default(),
+            qbuf: 0,
+            qbuf_free: 0,
+            omem: 0,
+            redir: -1,
+            kill: session.kill.clone(),
+            push: session.push.clone(),
+        };
+        entry.update(session);
+        entry
+    }
+
+    /// 用连接当前的状态刷新名字、用户、协议、MULTI和标志
+    pub fn update(&mut self, session: &Session) {
+        self.name = session.name.clone().unwrap_or_default();
+        self.user = session.user.clone();
+        self.resp = session.protocol.version();
+        self.multi = session.transaction.queued_len();
+        self.flags = ClientFlags {
+            master: session.is_master,
+            replica: self.flags.replica,
+            monitor: session.monitor.is_some(),
+            blocked: session.blocked.is_some(),
+            no_evict: session.no_evict,
+            no_touch: session.no_touch,
+            tracking: self.flags.tracking,
+        };
+    }
+
+    pub fn client_type(&self) -> ClientType {
+        match &self.flags {
+            flags if flags.master => ClientType::Master,
+            flags if flags.replica => ClientType::Replica,
+            _ => ClientType::Normal,
+        }
+    }
+
+    /// CLIENT LIST/INFO的一行，字段顺序和Redis一致
+    pub fn info(&self) -> String {
+        let now = now_ms();
+        let addr =
+            |addr: &Option<ConnAddr>| addr.as_ref().map(|a| a.to_string()).unwrap_or_default();
+        let mut flags = String::new();
+        for (set, flag) in [
+            (self.flags.replica, 'S'),
+            (self.flags.master, 'M'),
+            (self.flags.monitor, 'O'),
+            (self.multi.is_some(), 'x'),
+            (self.flags.blocked, 'b'),
+            (self.flags.no_evict, 'e'),
+            (self.flags.tracking, 't'),
+            (self.flags.no_touch, 'T'),
+        ] {
+            if set {
+                flags.push(flag);
+            }
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        format!(
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db=0 sub=0 psub=0 ssub=0 \
+             multi={} qbuf={} qbuf-free={} obl=0 oll=0 omem={} cmd={} user={} redir={} resp={}",
+            self.id,
+            addr(&self.addr),
+            addr(&self.laddr),
+            self.name,
+            now.saturating_sub(self.created) / 1000,
+            now.saturating_sub(self.last_interaction) / 1000,
+            flags,
+            self.multi.map_or(-1, |n| n as i64),
+            self.qbuf,
+            self.qbuf_free,
+            self.omem,
+            self.cmd,
+            self.user,
+            self.redir,
+            self.resp
+        )
+    }
+}
+
+/// CLIENT KILL的过滤条件，所有给出的条件都满足的连接才会被断开
+#[derive(Debug, Clone, PartialEq)]
+pub struct KillFilter {
+    pub id: Option<u64>,
+    pub addr: Option<String>,
+    pub laddr: Option<String>,
+    pub user: Option<String>,
+    pub client_type: Option<ClientType>,
+    /// 只断开连接时间不少于这么多秒的连接
+    pub max_age: Option<u64>,
+    pub skip_me: bool,
+}
+
+impl Default for KillFilter {
+    fn default() -> Self {
+        Self {
+            id: None,
+            addr: None,
+            laddr: None,
+            user: None,
+            client_type: None,
+            max_age: None,
+            skip_me: true,
+        }
+    }
+}
+
+impl KillFilter {
+    fn matches(&self, entry: &ClientEntry, me: u64, now: u64) -> bool {
+        let addr = |addr: &Option<ConnAddr>| addr.as_ref().map(|a| a.to_string());
+        !(self.skip_me && entry.id == me)
+            && self.id.is_none_or(|id| id == entry.id)
+            && self
+                .addr
+                .as_ref()
+                .is_none_or(|a| Some(a) == addr(&entry.addr).as_ref())
+            && self
+                .laddr
+                .as_ref()
+                .is_none_or(|a| Some(a) == addr(&entry.laddr).as_ref())
+            && self.user.as_ref().is_none_or(|user| *user == entry.user)
+            && self.client_type.is_none_or(|t| t == entry.client_type())
+            && self
+                .max_age
+                .is_none_or(|age| now.saturating_sub(entry.created) / 1000 >= age)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseMode {
+    Write,
+    All,
+}
+
+#[derive(Debug, Default)]
+pub struct ClientRegistry {
+    clients: DashMap<u64, ClientEntry>,
+    /// (暂停到的unix毫秒, 模式)
+    pause: RwLock<Option<(u64, PauseMode)>>,
+    unpaused: Notify,
+}
+
+impl ClientRegistry {
+    pub fn register(&self, session: &Session) {
+        self.clients.insert(session.id, ClientEntry::new(session));
+    }
+
+    pub fn unregister(&self, id: u64) {
+        self.clients.remove(&id);
+    }
+
+    /// 复制连接在PSYNC之后变成replica
+    pub fn set_replica(&self, id: u64) {
+        if let Some(mut entry) = self.clients.get_mut(&id) {
+            entry.flags.replica = true;
+            entry.cmd = "psync".into();
+        }
+    }
+
+    /// 收到一条命令，在执行之前记下命令名和时间
+    pub fn command_received(&self, id: u64, cmd: String) {
+        if let Some(mut entry) = self.clients.get_mut(&id) {
+            entry.cmd = cmd;
+            entry.last_interaction = now_ms();
+        }
+    }
+
+    /// 命令执行之后同步连接状态和缓冲区大小
+    pub fn sync(&self, session: &Session, qbuf: usize, qbuf_free: usize, omem: usize) {
+        if let Some(mut entry) = self.clients.get_mut(&session.id) {
+            entry.update(session);
+            entry.qbuf = qbuf;
+            entry.qbuf_free = qbuf_free;
+            entry.omem = omem;
+        }
+    }
+
+    /// CLIENT TRACKING打开(Some(redirect))或者关闭(None)
+    pub fn set_tracking(&self, id: u64, tracking: Option<Option<u64>>) {
+        if let Some(mut entry) = self.clients.get_mut(&id) {
+            entry.flags.tracking = tracking.is_some();
+            entry.redir = tracking.map_or(-1, |redirect| redirect.unwrap_or(0) as i64);
+        }
+    }
+
+    /// 给连接推送一条消息，build按连接的协议版本生成消息，返回None表示不发送。
+    /// 连接不存在时返回false
+    pub fn push(&self, id: u64, build: impl FnOnce(i64) -> Option<RespFrame>) -> bool {
+        let Some(entry) = self.clients.get(&id) else {
+            return false;
+        };
+        if let Some(frame) = build(entry.resp) {
+            let _ = entry.push.send(frame);
+        }
+        true
+    }
+
+    pub fn get(&self, id: u64) -> Option<ClientEntry> {
+        self.clients.get(&id).map(|entry| entry.clone())
+    }
+
+    /// 按id排序的所有连接
+    pub fn list(&self) -> Vec<ClientEntry> {
+        let mut clients = self
+            .clients
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect::<Vec<_>>();
+        clients.sort_by_key(|entry| entry.id);
+        clients
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// 通知所有满足条件的连接退出，返回个数。me是执行CLIENT KILL的连接
+    pub fn kill(&self, filter: &KillFilter, me: u64) -> usize {
+        let now = now_ms();
+        let mut killed = 0;
+        for entry in self.clients.iter() {
+            if filter.matches(&entry, me, now) {
+                entry.kill.notify_one();
+                killed += 1;
+            }
+        }
+        killed
+    }
+
+    pub fn pause(&self, timeout_ms: u64, mode: PauseMode) {
+        let until = now_ms().saturating_add(timeout_ms);
+        let mut pause = self.pause.write().unwrap_or_else(|e| e.into_inner());
+        //和Redis一样，重复暂停时取更长的时间和更严格的模式
+        let (until, mode) = match *pause {
+            Some((old_until, old_mode)) if old_until > now_ms() => (
+                until.max(old_until),
+                if old_mode == PauseMode::All {
+                    PauseMode::All
+                } else {
+                    mode
+                },
+            ),
+            _ => (until, mode),
+        };
+        *pause = Some((until, mode));
+    }
+
+    pub fn unpause(&self) {
+        *self.pause.write().unwrap_or_else(|e| e.into_inner()) = None;
+        self.unpaused.notify_waiters();
+    }
+
+    /// 当前生效的暂停：(暂停到的unix毫秒, 模式)
+    pub fn paused(&self) -> Option<(u64, PauseMode)> {
+        let pause = *self.pause.read().unwrap_or_else(|e| e.into_inner());
+        pause.filter(|(until, _)| *until > now_ms())
+    }
+
+    /// 暂停期间等待，write表示这条命令是不是写命令
+    pub async fn wait_unpaused(&self, write: bool) {
+        loop {
+            //先创建等待的future再检查，避免错过两者之间的UNPAUSE
+            let unpaused = self.unpaused.notified();
+            let Some((until, mode)) = self.paused() else {
+                return;
+            };
+            if mode == PauseMode::Write && !write {
+                return;
+            }
+            let remaining = until.saturating_sub(now_ms());
+            tokio::select! {
+                _ = unpaused => {}
+                _ = tokio::time::sleep(std::time::Duration::from_millis(remaining)) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn register(registry: &ClientRegistry, addr: &str) -> Session {
+        let mut session = Session::new();
+        session.addr = addr.parse().ok();
+        registry.register(&session);
+        session
+    }
+
+    #[test]
+    fn test_client_list() {
+        let registry = ClientRegistry::default();
+        let mut first = register(&registry, "127.0.0.1:5000");
+        let second = register(&registry, "127.0.0.1:5001");
+        first.name = Some("app".into());
+        first.no_touch = true;
+        registry.command_received(first.id, "client|list".into());
+        registry.sync(&first, 10, 100, 0);
+
+        let clients = registry.list();
+        assert_eq!(clients.len(), 2);
+        let info = clients[0].info();
+        assert!(info.starts_with(&format!(
+            "id={} addr=127.0.0.1:5000 laddr= name=app age=0 idle=0 flags=T db=0",
+            first.id
+        )));
+        assert!(info.contains(" multi=-1 qbuf=10 qbuf-free=100 "));
+        assert!(info.ends_with(" cmd=client|list user=default redir=-1 resp=2"));
+        assert!(clients[1].info().contains(" flags=N "));
+
+        registry.unregister(second.id);
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_client_kill() {
+        let registry = ClientRegistry::default();
+        let me = register(&registry, "127.0.0.1:5000");
+        let other = register(&registry, "127.0.0.1:5001");
+        registry.set_replica(other.id);
+
+        let filter = KillFilter {
+            addr: Some("127.0.0.1:5000".into()),
+            ..Default::default()
+        };
+        assert_eq!(registry.kill(&filter, me.id), 0);
+        let filter = KillFilter {
+            skip_me: false,
+            ..filter
+        };
+        assert_eq!(registry.kill(&filter, me.id), 1);
+        me.kill.notified().await;
+
+        let filter = KillFilter {
+            client_type: Some(ClientType::Replica),
+            ..Default::default()
+        };
+        assert_eq!(registry.kill(&filter, me.id), 1);
+        other.kill.notified().await;
+        let filter = KillFilter {
+            user: Some("nobody".into()),
+            ..Default::default()
+        };
+        assert_eq!(registry.kill(&filter, me.id), 0);
+    }
+
+    #[tokio::test]
+    async fn test_client_pause() {
+        let registry = Arc::new(ClientRegistry::default());
+        registry.pause(10_000, PauseMode::Write);
+        //WRITE模式下读命令不等待
+        tokio::time::timeout(Duration::from_millis(100), registry.wait_unpaused(false))
+            .await
+            .unwrap();
+        let waiting = tokio::spawn({
+            let registry = registry.clone();
+            async move { registry.wait_unpaused(true).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+        registry.unpause();
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+
+        //超时之后自动恢复
+        registry.pause(20, PauseMode::All);
+        assert_eq!(
+            registry.paused().map(|(_, mode)| mode),
+            Some(PauseMode::All)
+        );
+        tokio::time::timeout(Duration::from_secs(1), registry.wait_unpaused(false))
+            .await
+            .unwrap();
+        assert!(registry.paused().is_none());
+    }
+}
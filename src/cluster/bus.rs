@@ -0,0 +1,599 @@
+/*
+cluster bus上的消息和客户端请求一样编码成RESP数组：
+    type sender port bus_port current_epoch config_epoch slots failing [id ip port bus_port flags]...
+slots是sender负责的slot位图(16384位)，failing只在FAIL消息里有值，最后是若干个随机挑选的其他节点(gossip)
+每个节点主动连接所有已知节点并定期发送PING，对方在同一个连接上回复PONG；
+PING超过node_timeout没有回复的节点被标记为PFAIL，多数master都报告PFAIL时标记为FAIL并广播
+*/
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use futures::SinkExt;
+use rand::seq::SliceRandom;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio_stream::StreamExt;
+use tokio_util::codec::Framed;
+use tracing::{info, warn};
+
+use crate::{
+    aof::command_array, backend::now_ms, network::RespFrameCodec, Backend, RespArray, RespFrame,
+};
+
+use super::{Cluster, ClusterState, Node, NodeFlags, CLUSTER_SLOTS};
+
+/// 每条消息最多附带几个随机节点，PFAIL的节点总是会附带
+const GOSSIP_NODES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MessageType {
+    Ping,
+    Pong,
+    Meet,
+    Fail,
+}
+
+impl MessageType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MessageType::Ping => "PING",
+            MessageType::Pong => "PONG",
+            MessageType::Meet => "MEET",
+            MessageType::Fail => "FAIL",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "PING" => Some(MessageType::Ping),
+            "PONG" => Some(MessageType::Pong),
+            "MEET" => Some(MessageType::Meet),
+            "FAIL" => Some(MessageType::Fail),
+            _ => None,
+        }
+    }
+}
+
+/// 消息里附带的一个其他节点
+#[derive(Debug, Clone, PartialEq)]
+struct Gossip {
+    id: String,
+    ip: String,
+    port: u16,
+    bus_port: u16,
+    flags: NodeFlags,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    kind: MessageType,
+    sender: String,
+    port: u16,
+    bus_port: u16,
+    current_epoch: u64,
+    config_epoch: u64,
+    slots: Vec<u8>,
+    failing: Option<String>,
+    gossip: Vec<Gossip>,
+}
+
+impl Message {
+    fn encode(&self) -> RespArray {
+        let mut args: Vec<Vec<u8>> = vec![
+            self.kind.as_str().into(),
+            self.sender.clone().into(),
+            self.port.to_string().into(),
+            self.bus_port.to_string().into(),
+            self.current_epoch.to_string().into(),
+            self.config_epoch.to_string().into(),
+            self.slots.clone(),
+            self.failing.clone().unwrap_or_default().into(),
+        ];
+        for g in &self.gossip {
+            args.extend([
+                g.id.clone().into(),
+                g.ip.clone().into(),
+                g.port.to_string().into(),
+                g.bus_port.to_string().into(),
+                g.flags.encode().into(),
+            ]);
+        }
+        command_array(args)
+    }
+
+    fn decode(array: RespArray) -> Result<Self> {
+        let args = array
+            .0
+            .into_iter()
+            .map(|frame| match frame {
+                RespFrame::BulkString(s) => Ok(s.0),
+                frame => Err(anyhow!("unexpected frame on cluster bus: {:?}", frame)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if args.len() < 8 || (args.len() - 8) % 5 != 0 || args[6].len() != CLUSTER_SLOTS / 8 {
+            bail!("malformed cluster bus message");
+        }
+        let text = |i: usize| String::from_utf8_lossy(&args[i]).into_owned();
+        let port = |i: usize| text(i).parse::<u16>().map_err(|_| anyhow!("invalid port"));
+        let epoch = |i: usize| text(i).parse::<u64>().map_err(|_| anyhow!("invalid epoch"));
+
+        let kind = MessageType::parse(&text(0)).ok_or_else(|| anyhow!("unknown message type"))?;
+        let gossip = (8..args.len())
+            .step_by(5)
+            .map(|i| {
+                Ok(Gossip {
+                    id: text(i),
+                    ip: text(i + 1),
+                    port: port(i + 2)?,
+                    bus_port: port(i + 3)?,
+                    flags: NodeFlags::parse(&text(i + 4)),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Message {
+            kind,
+            sender: text(1),
+            port: port(2)?,
+            bus_port: port(3)?,
+            current_epoch: epoch(4)?,
+            config_epoch: epoch(5)?,
+            slots: args[6].clone(),
+            failing: Some(text(7)).filter(|id| !id.is_empty()),
+            gossip,
+        })
+    }
+
+    fn claims(&self, slot: usize) -> bool {
+        self.slots[slot / 8] & (1 << (slot % 8)) != 0
+    }
+}
+
+impl Cluster {
+    fn message(&self, kind: MessageType) -> Message {
+        let myself = self.myself();
+        let mut slots = vec![0u8; CLUSTER_SLOTS / 8];
+        for slot in self.slots_of(&self.myself) {
+            slots[slot as usize / 8] |= 1 << (slot % 8);
+        }
+
+        let others = self
+            .nodes
+            .values()
+            .filter(|n| !n.flags.myself && !n.flags.handshake)
+            .collect::<Vec<_>>();
+        let mut gossip = others
+            .choose_multiple(&mut rand::thread_rng(), GOSSIP_NODES)
+            .copied()
+            .collect::<Vec<_>>();
+        for node in others.iter().filter(|n| n.is_failing()) {
+            if !gossip.iter().any(|g| g.id == node.id) {
+                gossip.push(node);
+            }
+        }
+
+        Message {
+            kind,
+            sender: myself.id.clone(),
+            port: myself.port,
+            bus_port: myself.bus_port,
+            current_epoch: self.current_epoch,
+            config_epoch: myself.config_epoch,
+            slots,
+            failing: None,
+            gossip: gossip
+                .into_iter()
+                .map(|node| Gossip {
+                    id: node.id.clone(),
+                    ip: node.ip.clone(),
+                    port: node.port,
+                    bus_port: node.bus_port,
+                    flags: node.flags,
+                })
+                .collect(),
+        }
+    }
+
+    /// 处理收到的消息，PING和MEET需要回复PONG。
+    /// link_node是消息所在的主动连接对应的节点，被动接受的连接是None
+    fn handle(
+        &mut self,
+        msg: Message,
+        peer_ip: IpAddr,
+        link_node: Option<&str>,
+    ) -> Option<Message> {
+        let now = now_ms();
+        //握手中的节点回复了PONG，用它真正的id替换掉临时id，之后由cron重新建立连接
+        if let Some(id) = link_node.filter(|_| msg.kind == MessageType::Pong) {
+            if let Some(node) = self.nodes.get(id).filter(|n| n.flags.handshake) {
+                let ip = node.ip.clone();
+                self.nodes.remove(id);
+                if msg.sender != self.myself && !self.nodes.contains_key(&msg.sender) {
+                    let node = Node::new(msg.sender.clone(), ip, msg.port, msg.bus_port);
+                    self.nodes.insert(msg.sender.clone(), node);
+                }
+            }
+        }
+        if msg.kind == MessageType::Meet && !self.nodes.contains_key(&msg.sender) {
+            info!("Node {} joined the cluster via MEET", msg.sender);
+            let node = Node::new(
+                msg.sender.clone(),
+                peer_ip.to_string(),
+                msg.port,
+                msg.bus_port,
+            );
+            self.nodes.insert(msg.sender.clone(), node);
+        }
+
+        //只相信已知节点发来的信息
+        if msg.sender != self.myself && self.nodes.contains_key(&msg.sender) {
+            self.current_epoch = self.current_epoch.max(msg.current_epoch);
+            let node = self.nodes.get_mut(&msg.sender).expect("checked above");
+            node.port = msg.port;
+            node.bus_port = msg.bus_port;
+            node.config_epoch = msg.config_epoch;
+            if msg.kind == MessageType::Pong {
+                node.ping_sent = 0;
+                node.pong_received = now;
+                if node.is_failing() {
+                    info!("Clear FAIL state for node {}: is reachable again", node.id);
+                    node.flags.pfail = false;
+                    node.flags.fail = false;
+                    node.fail_reports.clear();
+                }
+            }
+            match msg.kind {
+                MessageType::Fail => self.mark_failed_by(&msg),
+                _ => {
+                    self.update_slots(&msg);
+                    self.process_gossip(&msg, now);
+                }
+            }
+        }
+
+        matches!(msg.kind, MessageType::Ping | MessageType::Meet)
+            .then(|| self.message(MessageType::Pong))
+    }
+
+    /// sender声明的slot：没有负责人或者原来负责人的config epoch更小时归sender；
+    /// 原来属于sender但它不再声明的slot变成没有负责人
+    fn update_slots(&mut self, msg: &Message) {
+        for slot in 0..CLUSTER_SLOTS {
+            let owner = self.slots[slot].as_deref();
+            if !msg.claims(slot) {
+                if owner == Some(msg.sender.as_str()) {
+                    self.slots[slot] = None;
+                }
+                continue;
+            }
+            let takeover = match owner {
+                Some(id) if id == msg.sender => false,
+                Some(id) => self
+                    .nodes
+                    .get(id)
+                    .is_none_or(|owner| owner.config_epoch < msg.config_epoch),
+                None => true,
+            };
+            if takeover {
+                //本节点负责的slot被拿走了，说明迁移已经完成
+                if owner == Some(self.myself.as_str()) {
+                    self.migrating.remove(&(slot as u16));
+                }
+                self.slots[slot] = Some(msg.sender.clone());
+            }
+        }
+    }
+
+    /// gossip里不认识的节点直接加进来，cron会去连接它；PFAIL/FAIL的节点记一次sender的故障报告
+    fn process_gossip(&mut self, msg: &Message, now: u64) {
+        for g in &msg.gossip {
+            if g.id == self.myself {
+                continue;
+            }
+            let failing = g.flags.pfail || g.flags.fail;
+            match self.nodes.get_mut(&g.id) {
+                Some(node) if failing => {
+                    node.fail_reports.insert(msg.sender.clone(), now);
+                }
+                Some(node) => {
+                    node.fail_reports.remove(&msg.sender);
+                }
+                None if !failing && !g.flags.handshake => {
+                    let node = Node::new(g.id.clone(), g.ip.clone(), g.port, g.bus_port);
+                    self.nodes.insert(g.id.clone(), node);
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn mark_failed_by(&mut self, msg: &Message) {
+        let Some(node) = msg.failing.as_ref().and_then(|id| self.nodes.get_mut(id)) else {
+            return;
+        };
+        if !node.flags.myself && !node.flags.fail {
+            info!(
+                "FAIL message received from {} about {}",
+                msg.sender, node.id
+            );
+            node.flags.fail = true;
+            node.flags.pfail = false;
+        }
+    }
+
+    /// 给节点发一个PING，还在等上一个PONG时不重置ping_sent
+    fn ping(&mut self, id: &str, now: u64) {
+        let msg = self.message(MessageType::Ping).encode();
+        if let Some(node) = self.nodes.get_mut(id) {
+            if let Some(link) = &node.link {
+                let _ = link.send(msg);
+                if node.ping_sent == 0 {
+                    node.ping_sent = now;
+                }
+            }
+        }
+    }
+
+    /// 为还没有连接的节点创建连接，第一条消息是MEET(握手中)或者PING
+    fn open_links(&mut self, now: u64) -> Vec<(String, String, u16, UnboundedReceiver<RespArray>)> {
+        let ids = self
+            .nodes
+            .values()
+            .filter(|n| !n.flags.myself && n.link.is_none() && now - n.link_attempt >= 1000)
+            .map(|n| n.id.clone())
+            .collect::<Vec<_>>();
+        let mut links = Vec::new();
+        for id in ids {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let node = self.nodes.get_mut(&id).expect("collected above");
+            node.link = Some(sender);
+            node.link_attempt = now;
+            links.push((id.clone(), node.ip.clone(), node.bus_port, receiver));
+            if node.flags.handshake {
+                let msg = self.message(MessageType::Meet).encode();
+                if let Some(link) = &self.nodes[&id].link {
+                    let _ = link.send(msg);
+                }
+            } else {
+                self.ping(&id, now);
+            }
+        }
+        links
+    }
+
+    /// 定期PING：超过node_timeout/2没有收到PONG的节点，每秒再随机挑一个
+    fn send_pings(&mut self, now: u64, node_timeout: u64, random: bool) {
+        let idle = self
+            .nodes
+            .values()
+            .filter(|n| {
+                !n.flags.myself && !n.flags.handshake && n.link.is_some() && n.ping_sent == 0
+            })
+            .collect::<Vec<_>>();
+        let mut targets = idle
+            .iter()
+            .filter(|n| now.saturating_sub(n.pong_received) > node_timeout / 2)
+            .map(|n| n.id.clone())
+            .collect::<Vec<_>>();
+        if random {
+            if let Some(node) = idle.choose(&mut rand::thread_rng()) {
+                targets.push(node.id.clone());
+            }
+        }
+        for id in targets {
+            self.ping(&id, now);
+        }
+    }
+
+    /// PING超时的节点标记为PFAIL，加上其他master的报告超过半数时标记为FAIL，返回需要广播的FAIL消息
+    fn detect_failures(&mut self, now: u64, node_timeout: u64) -> Vec<RespArray> {
+        //握手一直没有完成的节点
+        self.nodes
+            .retain(|_, n| !n.flags.handshake || now.saturating_sub(n.created) < node_timeout);
+
+        let needed = self.size() / 2 + 1;
+        let mut failed = Vec::new();
+        for node in self.nodes.values_mut().filter(|n| !n.flags.myself) {
+            node.fail_reports
+                .retain(|_, &mut when| now.saturating_sub(when) <= node_timeout * 2);
+            if !node.is_failing()
+                && node.ping_sent > 0
+                && now.saturating_sub(node.ping_sent) > node_timeout
+            {
+                info!("*** NODE {} possibly failing", node.id);
+                node.flags.pfail = true;
+            }
+            //本节点也是master，算一票
+            if node.flags.pfail && !node.flags.fail && node.fail_reports.len() + 1 >= needed {
+                info!("Marking node {} as failing (quorum reached).", node.id);
+                node.flags.pfail = false;
+                node.flags.fail = true;
+                failed.push(node.id.clone());
+            }
+        }
+
+        failed
+            .into_iter()
+            .map(|id| {
+                let mut msg = self.message(MessageType::Fail);
+                msg.failing = Some(id);
+                msg.gossip.clear();
+                msg.encode()
+            })
+            .collect()
+    }
+
+    fn broadcast(&self, msg: RespArray) {
+        for link in self.nodes.values().filter_map(|n| n.link.as_ref()) {
+            let _ = link.send(msg.clone());
+        }
+    }
+}
+
+impl ClusterState {
+    fn handle(
+        &self,
+        msg: Message,
+        peer_ip: IpAddr,
+        local_ip: Option<IpAddr>,
+        link_node: Option<&str>,
+    ) -> Option<Message> {
+        let learn_ip = self.config().announce_ip.is_none();
+        let mut cluster = self.guard();
+        //没有配置announce ip时，别人MEET本节点用的地址就是本节点的地址
+        if let Some(ip) = local_ip.filter(|_| learn_ip && msg.kind == MessageType::Meet) {
+            cluster.myself_mut().ip = ip.to_string();
+        }
+        cluster.handle(msg, peer_ip, link_node)
+    }
+
+    fn link_closed(&self, id: &str) {
+        if let Some(node) = self.guard().nodes.get_mut(id) {
+            node.link = None;
+        }
+    }
+}
+
+/// 接受其他节点的连接，在这些连接上回复PONG
+pub async fn serve_bus(listener: TcpListener, backend: Backend) -> Result<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let backend = backend.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_inbound(stream, &backend).await {
+                warn!("Cluster bus connection from {} closed: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn handle_inbound(stream: TcpStream, backend: &Backend) -> Result<()> {
+    let peer_ip = stream.peer_addr()?.ip();
+    let local_ip = stream.local_addr()?.ip();
+    let mut framed = Framed::new(stream, RespFrameCodec::default());
+    while let Some(frame) = framed.next().await {
+        let RespFrame::Arrays(array) = frame? else {
+            bail!("cluster bus message should be a RespArray");
+        };
+        let msg = Message::decode(array)?;
+        if let Some(reply) = backend.cluster.handle(msg, peer_ip, Some(local_ip), None) {
+            framed.send(reply.encode().into()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 到一个节点的主动连接：发送channel里的消息，处理对方回复的PONG。
+/// 节点被删除时channel关闭，连接随之结束
+async fn connect(
+    backend: Backend,
+    id: String,
+    ip: String,
+    bus_port: u16,
+    mut receiver: UnboundedReceiver<RespArray>,
+) {
+    let node_timeout = Duration::from_millis(backend.cluster.config().node_timeout);
+    let ret: Result<()> = async {
+        let stream =
+            tokio::time::timeout(node_timeout, TcpStream::connect((ip.as_str(), bus_port)))
+                .await??;
+        let peer_ip = stream.peer_addr()?.ip();
+        let mut framed = Framed::new(stream, RespFrameCodec::default());
+        loop {
+            tokio::select! {
+                msg = receiver.recv() => match msg {
+                    Some(msg) => framed.send(msg.into()).await?,
+                    None => return Ok(()),
+                },
+                frame = framed.next() => match frame {
+                    Some(Ok(RespFrame::Arrays(array))) => {
+                        let msg = Message::decode(array)?;
+                        backend.cluster.handle(msg, peer_ip, None, Some(&id));
+                    }
+                    Some(Ok(frame)) => bail!("unexpected frame on cluster bus: {:?}", frame),
+                    Some(Err(e)) => return Err(e),
+                    None => bail!("connection closed"),
+                },
+            }
+        }
+    }
+    .await;
+    if let Err(e) = ret {
+        warn!("Cluster bus link to {}:{} lost: {}", ip, bus_port, e);
+    }
+    backend.cluster.link_closed(&id);
+}
+
+/// 每100毫秒：建立缺少的连接、发送PING、检测故障节点
+pub async fn cron(backend: Backend) {
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+    let mut ticks = 0u64;
+    loop {
+        interval.tick().await;
+        ticks += 1;
+        let now = now_ms();
+        let node_timeout = backend.cluster.config().node_timeout;
+        let links = {
+            let mut cluster = backend.cluster.guard();
+            let links = cluster.open_links(now);
+            cluster.send_pings(now, node_timeout, ticks.is_multiple_of(10));
+            for msg in cluster.detect_failures(now, node_timeout) {
+                cluster.broadcast(msg);
+            }
+            links
+        };
+        for (id, ip, bus_port, receiver) in links {
+            tokio::spawn(connect(backend.clone(), id, ip, bus_port, receiver));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_roundtrip() {
+        let mut cluster = Cluster::default();
+        let mut myself = Node::new(cluster.myself.clone(), "127.0.0.1".into(), 7000, 17000);
+        myself.flags.myself = true;
+        cluster.nodes.insert(myself.id.clone(), myself);
+        let mut other = Node::new("b".repeat(40), "127.0.0.2".into(), 7001, 17001);
+        other.flags.pfail = true;
+        cluster.nodes.insert(other.id.clone(), other);
+        cluster.slots[100] = Some(cluster.myself.clone());
+
+        let msg = cluster.message(MessageType::Ping);
+        assert!(msg.claims(100) && !msg.claims(101));
+        assert_eq!(msg.gossip.len(), 1);
+        assert!(msg.gossip[0].flags.pfail);
+        assert_eq!(Message::decode(msg.encode()).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_handle_meet_and_slots() {
+        let mut a = Cluster::default();
+        let mut myself = Node::new(a.myself.clone(), "127.0.0.1".into(), 7000, 17000);
+        myself.flags.myself = true;
+        a.nodes.insert(myself.id.clone(), myself);
+
+        let mut b = Cluster::default();
+        let mut myself = Node::new(b.myself.clone(), "127.0.0.1".into(), 7001, 17001);
+        myself.flags.myself = true;
+        b.nodes.insert(myself.id.clone(), myself);
+        b.slots[5] = Some(b.myself.clone());
+
+        let ip = "127.0.0.1".parse().unwrap();
+        let reply = a.handle(b.message(MessageType::Meet), ip, None).unwrap();
+        assert_eq!(reply.kind, MessageType::Pong);
+        assert_eq!(a.nodes[&b.myself].port, 7001);
+        assert_eq!(a.slots[5].as_deref(), Some(b.myself.as_str()));
+
+        //不认识的节点发来的PING只回复PONG，不会被加进来
+        let c = Cluster::default();
+        let mut msg = b.message(MessageType::Ping);
+        msg.sender = c.myself.clone();
+        assert!(a.handle(msg, ip, None).is_some());
+        assert!(!a.nodes.contains_key(&c.myself));
+    }
+}
@@ -0,0 +1,786 @@
+/*
+Cluster模式：
+    key按CRC16(key) % 16384分到slot上，key里有{tag}时只用tag计算，相关的key可以放进同一个slot
+    每个slot由一个master负责，key不属于本节点时回复MOVED slot ip:port，客户端据此更新自己的路由表
+    slot正在迁出时本节点已经没有的key回复ASK slot ip:port，客户端只把这一次请求发到目标节点
+节点之间通过cluster bus(默认是客户端端口+10000)互相PING/PONG，消息里带着各自负责的slot和认识的
+其他节点，新节点只要MEET集群中的任意一个节点就能被所有节点发现，见bus.rs
+*/
+mod bus;
+mod node;
+mod slot;
+
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, RwLock};
+
+use rand::Rng;
+use thiserror::Error;
+use tokio::net::TcpListener;
+use tracing::info;
+
+use crate::Backend;
+
+pub use bus::{cron, serve_bus};
+pub use node::{Node, NodeFlags};
+pub use slot::{key_hash_slot, slot_ranges, CLUSTER_SLOTS};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ClusterError {
+    #[error("This instance has cluster support disabled")]
+    Disabled,
+    #[error("Invalid or out of range slot")]
+    InvalidSlot,
+    #[error("Slot {0} is already busy")]
+    SlotBusy(u16),
+    #[error("Slot {0} is already unassigned")]
+    SlotUnassigned(u16),
+    #[error("Invalid node address specified: {0}")]
+    InvalidAddress(String),
+    #[error("I don't know about node {0}")]
+    UnknownNode(String),
+    #[error("I'm not the owner of hash slot {0}")]
+    NotOwner(u16),
+    #[error("I'm already the owner of hash slot {0}")]
+    AlreadyOwner(u16),
+    #[error(
+        "Can't assign hashslot {0} to a different node while I still hold keys for this hash slot."
+    )]
+    SlotNotEmpty(u16),
+}
+
+/// 请求不能在本节点执行时的回复
+#[derive(Error, Debug, PartialEq)]
+pub enum Redirect {
+    #[error("MOVED {0} {1}")]
+    Moved(u16, String),
+    #[error("ASK {0} {1}")]
+    Ask(u16, String),
+    #[error("CROSSSLOT Keys in request don't hash to the same slot")]
+    CrossSlot,
+    #[error("TRYAGAIN Multiple keys request during rehashing of slot")]
+    TryAgain,
+    #[error("CLUSTERDOWN Hash slot not served")]
+    Unbound,
+    #[error("CLUSTERDOWN The cluster is down")]
+    Down,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+    pub enabled: bool,
+    /// cluster bus的端口，0表示客户端端口+10000
+    pub port: u16,
+    /// 超过这么多毫秒没有回复PONG的节点会被标记为PFAIL
+    pub node_timeout: u64,
+    /// 告诉其他节点和客户端的ip，没有配置时从其他节点连过来的地址得知
+    pub announce_ip: Option<String>,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 0,
+            node_timeout: 15000,
+            announce_ip: None,
+        }
+    }
+}
+
+impl ClusterConfig {
+    pub fn bus_port(&self, port: u16) -> u16 {
+        match self.port {
+            0 => port.wrapping_add(10000),
+            bus_port => bus_port,
+        }
+    }
+}
+
+/// CLUSTER INFO里的统计
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterInfo {
+    pub state_ok: bool,
+    pub slots_assigned: usize,
+    pub slots_ok: usize,
+    pub slots_pfail: usize,
+    pub slots_fail: usize,
+    pub known_nodes: usize,
+    pub size: usize,
+    pub current_epoch: u64,
+    pub my_epoch: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ClusterState {
+    config: RwLock<ClusterConfig>,
+    enabled: AtomicBool,
+    inner: Mutex<Cluster>,
+}
+
+/// 本节点看到的整个集群
+#[derive(Debug)]
+struct Cluster {
+    myself: String,
+    current_epoch: u64,
+    nodes: HashMap<String, Node>,
+    /// 每个slot由哪个节点负责
+    slots: Vec<Option<String>>,
+    /// 正在迁出的slot -> 目标节点
+    migrating: HashMap<u16, String>,
+    /// 正在迁入的slot -> 来源节点
+    importing: HashMap<u16, String>,
+}
+
+impl Default for Cluster {
+    fn default() -> Self {
+        Self {
+            myself: random_node_id(),
+            current_epoch: 0,
+            nodes: HashMap::new(),
+            slots: vec![None; CLUSTER_SLOTS],
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+        }
+    }
+}
+
+impl Cluster {
+    fn myself(&self) -> &Node {
+        &self.nodes[&self.myself]
+    }
+
+    fn myself_mut(&mut self) -> &mut Node {
+        self.nodes
+            .get_mut(&self.myself)
+            .expect("myself is always known")
+    }
+
+    fn slots_of(&self, id: &str) -> Vec<u16> {
+        (0..CLUSTER_SLOTS as u16)
+            .filter(|&slot| self.slots[slot as usize].as_deref() == Some(id))
+            .collect()
+    }
+
+    fn owner(&self, slot: u16) -> Option<&Node> {
+        self.slots[slot as usize]
+            .as_ref()
+            .and_then(|id| self.nodes.get(id))
+    }
+
+    /// 至少负责一个slot的master数量，判断FAIL需要其中的多数同意
+    fn size(&self) -> usize {
+        self.slots.iter().flatten().collect::<HashSet<_>>().len()
+    }
+
+    /// 所有slot都有节点负责，并且这些节点都没有FAIL
+    fn state_ok(&self) -> bool {
+        (0..CLUSTER_SLOTS as u16).all(|slot| self.owner(slot).is_some_and(|n| !n.flags.fail))
+    }
+}
+
+impl ClusterState {
+    pub fn config(&self) -> ClusterConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_config(&self, config: ClusterConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    /// init之后才算开启，在那之前(比如加载AOF时)不做重定向
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// 以port/bus_port作为本节点的地址开启cluster模式
+    pub fn init(&self, port: u16, bus_port: u16) {
+        let ip = self
+            .config()
+            .announce_ip
+            .unwrap_or_else(|| "127.0.0.1".into());
+        let mut cluster = self.guard();
+        let mut myself = Node::new(cluster.myself.clone(), ip, port, bus_port);
+        myself.flags.myself = true;
+        cluster.nodes.insert(myself.id.clone(), myself);
+        self.enabled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn myid(&self) -> String {
+        self.guard().myself.clone()
+    }
+
+    pub fn myself(&self) -> Node {
+        self.guard().myself().clone()
+    }
+
+    /// 所有已知的节点，按id排序
+    pub fn nodes(&self) -> Vec<Node> {
+        let mut nodes = self.guard().nodes.values().cloned().collect::<Vec<_>>();
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        nodes
+    }
+
+    pub fn slot_owner(&self, slot: u16) -> Option<Node> {
+        self.guard().owner(slot).cloned()
+    }
+
+    /// 节点负责的slot区间
+    pub fn slot_ranges(&self, id: &str) -> Vec<(u16, u16)> {
+        slot_ranges(self.guard().slots_of(id))
+    }
+
+    pub fn info(&self) -> ClusterInfo {
+        let cluster = self.guard();
+        let mut info = ClusterInfo {
+            state_ok: cluster.state_ok(),
+            slots_assigned: 0,
+            slots_ok: 0,
+            slots_pfail: 0,
+            slots_fail: 0,
+            known_nodes: cluster.nodes.len(),
+            size: cluster.size(),
+            current_epoch: cluster.current_epoch,
+            my_epoch: cluster.myself().config_epoch,
+        };
+        for node in (0..CLUSTER_SLOTS as u16).filter_map(|slot| cluster.owner(slot)) {
+            info.slots_assigned += 1;
+            match (node.flags.fail, node.flags.pfail) {
+                (true, _) => info.slots_fail += 1,
+                (false, true) => info.slots_pfail += 1,
+                _ => info.slots_ok += 1,
+            }
+        }
+        info
+    }
+
+    /// CLUSTER MEET：先用一个临时id记下对方，握手成功之后换成它真正的id
+    pub fn meet(&self, ip: &str, port: u16, bus_port: u16) -> Result<(), ClusterError> {
+        let ip = ip
+            .parse::<IpAddr>()
+            .map_err(|_| ClusterError::InvalidAddress(format!("{ip}:{port}")))?;
+        let mut node = Node::new(random_node_id(), ip.to_string(), port, bus_port);
+        node.flags.handshake = true;
+        self.guard().nodes.insert(node.id.clone(), node);
+        Ok(())
+    }
+
+    /// CLUSTER ADDSLOTS：slot会通过之后的PING/PONG告诉其他节点
+    pub fn add_slots(&self, slots: &[u16]) -> Result<(), ClusterError> {
+        let mut cluster = self.guard();
+        if let Some(&slot) = slots.iter().find(|&&s| cluster.slots[s as usize].is_some()) {
+            return Err(ClusterError::SlotBusy(slot));
+        }
+        let myself = cluster.myself.clone();
+        for &slot in slots {
+            cluster.slots[slot as usize] = Some(myself.clone());
+        }
+        Ok(())
+    }
+
+    /// CLUSTER DELSLOTS：本节点忘掉这些slot的负责人，不影响其他节点
+    pub fn del_slots(&self, slots: &[u16]) -> Result<(), ClusterError> {
+        let mut cluster = self.guard();
+        if let Some(&slot) = slots.iter().find(|&&s| cluster.slots[s as usize].is_none()) {
+            return Err(ClusterError::SlotUnassigned(slot));
+        }
+        for &slot in slots {
+            cluster.slots[slot as usize] = None;
+        }
+        Ok(())
+    }
+
+    /// CLUSTER SETSLOT MIGRATING：本节点已经没有的key开始回复ASK
+    pub fn set_slot_migrating(&self, slot: u16, id: &str) -> Result<(), ClusterError> {
+        let mut cluster = self.guard();
+        if cluster.slots[slot as usize].as_deref() != Some(cluster.myself.as_str()) {
+            return Err(ClusterError::NotOwner(slot));
+        }
+        if !cluster.nodes.contains_key(id) || id == cluster.myself {
+            return Err(ClusterError::UnknownNode(id.into()));
+        }
+        cluster.migrating.insert(slot, id.into());
+        Ok(())
+    }
+
+    /// CLUSTER SETSLOT IMPORTING：带着ASKING的请求在slot迁移完成之前就可以在本节点执行
+    pub fn set_slot_importing(&self, slot: u16, id: &str) -> Result<(), ClusterError> {
+        let mut cluster = self.guard();
+        if cluster.slots[slot as usize].as_deref() == Some(cluster.myself.as_str()) {
+            return Err(ClusterError::AlreadyOwner(slot));
+        }
+        if !cluster.nodes.contains_key(id) || id == cluster.myself {
+            return Err(ClusterError::UnknownNode(id.into()));
+        }
+        cluster.importing.insert(slot, id.into());
+        Ok(())
+    }
+
+    /// CLUSTER SETSLOT STABLE：放弃正在进行的迁移
+    pub fn set_slot_stable(&self, slot: u16) {
+        let mut cluster = self.guard();
+        cluster.migrating.remove(&slot);
+        cluster.importing.remove(&slot);
+    }
+
+    /// CLUSTER SETSLOT NODE：迁移完成，slot交给id负责。
+    /// 迁入方拿到slot时增加自己的config epoch，这样原来的负责人和其他节点都会接受新的归属
+    pub fn set_slot_node(
+        &self,
+        backend: &Backend,
+        slot: u16,
+        id: &str,
+    ) -> Result<(), ClusterError> {
+        let mut cluster = self.guard();
+        if !cluster.nodes.contains_key(id) {
+            return Err(ClusterError::UnknownNode(id.into()));
+        }
+        let myself = cluster.myself.clone();
+        if cluster.slots[slot as usize].as_deref() == Some(myself.as_str())
+            && id != myself
+            && !keys_in_slot(backend, slot, 1).is_empty()
+        {
+            return Err(ClusterError::SlotNotEmpty(slot));
+        }
+        cluster.migrating.remove(&slot);
+        if id == myself && cluster.importing.remove(&slot).is_some() {
+            cluster.current_epoch += 1;
+            let epoch = cluster.current_epoch;
+            cluster.myself_mut().config_epoch = epoch;
+            info!("Slot {} imported, config epoch set to {}", slot, epoch);
+        }
+        cluster.slots[slot as usize] = Some(id.into());
+        Ok(())
+    }
+
+    /// 本节点正在迁出的slot和目标节点
+    pub fn migrating(&self) -> Vec<(u16, String)> {
+        sorted_slots(&self.guard().migrating)
+    }
+
+    /// 本节点正在迁入的slot和来源节点
+    pub fn importing(&self) -> Vec<(u16, String)> {
+        sorted_slots(&self.guard().importing)
+    }
+
+    /// 判断keys能不能在本节点执行，cluster模式没有开启或者命令没有key时总是可以。
+    /// asking表示客户端在这之前发送了ASKING，正在迁入的slot也可以在本节点执行
+    pub fn route(&self, backend: &Backend, keys: &[&str], asking: bool) -> Result<(), Redirect> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let mut slots = keys.iter().map(|key| key_hash_slot(key.as_bytes()));
+        let Some(slot) = slots.next() else {
+            return Ok(());
+        };
+        if slots.any(|s| s != slot) {
+            return Err(Redirect::CrossSlot);
+        }
+
+        let cluster = self.guard();
+        if !cluster.state_ok() {
+            return Err(Redirect::Down);
+        }
+        let owner = cluster.owner(slot).ok_or(Redirect::Unbound)?;
+        if owner.id != cluster.myself {
+            if asking && cluster.importing.contains_key(&slot) {
+                //多个key时必须都已经迁移过来了
+                if keys.len() > 1 && keys.iter().any(|key| !backend.exists(key)) {
+                    return Err(Redirect::TryAgain);
+                }
+                return Ok(());
+            }
+            return Err(Redirect::Moved(slot, owner.addr()));
+        }
+        //正在迁出的slot：key已经不在本节点时让客户端去目标节点问一下
+        if let Some(target) = cluster.migrating.get(&slot) {
+            let missing = keys.iter().filter(|key| !backend.exists(key)).count();
+            if missing == keys.len() {
+                let target = cluster.nodes.get(target).ok_or(Redirect::Unbound)?;
+                return Err(Redirect::Ask(slot, target.addr()));
+            }
+            if missing > 0 {
+                return Err(Redirect::TryAgain);
+            }
+        }
+        Ok(())
+    }
+
+    fn guard(&self) -> MutexGuard<'_, Cluster> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 开启cluster模式：监听cluster bus并启动后台的PING/故障检测任务
+pub fn start(backend: &Backend, port: u16, bus: TcpListener) -> std::io::Result<()> {
+    let bus_port = bus.local_addr()?.port();
+    backend.cluster.init(port, bus_port);
+    tokio::spawn(serve_bus(bus, backend.clone()));
+    tokio::spawn(cron(backend.clone()));
+    Ok(())
+}
+
+/// slot里的key，最多count个
+pub fn keys_in_slot(backend: &Backend, slot: u16, count: usize) -> Vec<String> {
+    let mut keys = backend
+        .keys()
+        .into_iter()
+        .filter(|key| key_hash_slot(key.as_bytes()) == slot)
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.truncate(count);
+    keys
+}
+
+fn sorted_slots(slots: &HashMap<u16, String>) -> Vec<(u16, String)> {
+    let mut slots = slots
+        .iter()
+        .map(|(slot, id)| (*slot, id.clone()))
+        .collect::<Vec<_>>();
+    slots.sort();
+    slots
+}
+
+fn random_node_id() -> String {
+    let bytes = rand::thread_rng().gen::<[u8; 20]>();
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aof::command_array,
+        network::{request_handler, Session},
+        test_util::{call, calls, wait_for},
+        EncodeResp, RespBulkString, RespFrame,
+    };
+    use std::time::Duration;
+
+    /// 本节点负责0-8191，另一个节点负责其余的slot
+    fn two_node_cluster() -> (Backend, String) {
+        let backend = Backend::new();
+        backend.cluster.init(7000, 17000);
+        backend
+            .cluster
+            .add_slots(&(0..8192).collect::<Vec<_>>())
+            .unwrap();
+        let other = Node::new(random_node_id(), "10.0.0.2".into(), 7001, 17001);
+        let id = other.id.clone();
+        let mut cluster = backend.cluster.guard();
+        for slot in 8192..CLUSTER_SLOTS {
+            cluster.slots[slot] = Some(id.clone());
+        }
+        cluster.nodes.insert(id.clone(), other);
+        drop(cluster);
+        (backend, id)
+    }
+
+    #[test]
+    fn test_route() {
+        let (backend, other) = two_node_cluster();
+        let cluster = &backend.cluster;
+        assert_eq!(key_hash_slot(b"bar"), 5061);
+        assert_eq!(cluster.route(&backend, &["bar"], false), Ok(()));
+        assert_eq!(
+            cluster.route(&backend, &["foo"], false),
+            Err(Redirect::Moved(12182, "10.0.0.2:7001".into()))
+        );
+        assert_eq!(
+            cluster.route(&backend, &["bar", "foo"], false),
+            Err(Redirect::CrossSlot)
+        );
+        assert_eq!(
+            cluster.route(&backend, &["{foo}a", "{foo}b"], false),
+            Err(Redirect::Moved(12182, "10.0.0.2:7001".into()))
+        );
+        assert_eq!(cluster.route(&backend, &[], false), Ok(()));
+
+        //迁出中的slot：本节点没有的key回复ASK
+        cluster.guard().migrating.insert(5061, other.clone());
+        assert_eq!(
+            cluster.route(&backend, &["bar"], false),
+            Err(Redirect::Ask(5061, "10.0.0.2:7001".into()))
+        );
+        backend.set("bar".into(), RespBulkString::from("1").into());
+        assert_eq!(cluster.route(&backend, &["bar"], false), Ok(()));
+
+        //正在迁入的slot：只有带着ASKING的请求可以在本节点执行
+        cluster.set_slot_importing(12182, &other).unwrap();
+        assert_eq!(cluster.route(&backend, &["foo"], true), Ok(()));
+        assert_eq!(
+            cluster.route(&backend, &["foo"], false),
+            Err(Redirect::Moved(12182, "10.0.0.2:7001".into()))
+        );
+        assert_eq!(
+            cluster.route(&backend, &["{foo}a", "{foo}b"], true),
+            Err(Redirect::TryAgain)
+        );
+
+        cluster.del_slots(&[0]).unwrap();
+        assert_eq!(
+            cluster.route(&backend, &["bar"], false),
+            Err(Redirect::Down)
+        );
+        assert!(!cluster.info().state_ok);
+        assert_eq!(cluster.info().slots_assigned, CLUSTER_SLOTS - 1);
+    }
+
+    #[test]
+    fn test_redirect_wire_format() {
+        //客户端按错误的第一个单词识别重定向，前面不能有其他前缀
+        let (backend, other) = two_node_cluster();
+        let mut session = Session::new();
+        let reply = request_handler(command_array(["GET", "foo"]).into(), &mut session, &backend);
+        assert_eq!(reply.encode(), b"-MOVED 12182 10.0.0.2:7001\r\n");
+
+        backend.cluster.guard().migrating.insert(5061, other);
+        let reply = request_handler(command_array(["GET", "bar"]).into(), &mut session, &backend);
+        assert_eq!(reply.encode(), b"-ASK 5061 10.0.0.2:7001\r\n");
+        let reply = request_handler(
+            command_array(["DEL", "bar", "foo"]).into(),
+            &mut session,
+            &backend,
+        );
+        assert_eq!(
+            reply.encode(),
+            b"-CROSSSLOT Keys in request don't hash to the same slot\r\n"
+        );
+    }
+
+    #[test]
+    fn test_set_slot() {
+        let (backend, other) = two_node_cluster();
+        let cluster = &backend.cluster;
+        let myid = cluster.myid();
+        assert_eq!(
+            cluster.set_slot_migrating(12182, &other),
+            Err(ClusterError::NotOwner(12182))
+        );
+        assert_eq!(
+            cluster.set_slot_importing(5061, &other),
+            Err(ClusterError::AlreadyOwner(5061))
+        );
+        assert_eq!(
+            cluster.set_slot_migrating(5061, "unknown"),
+            Err(ClusterError::UnknownNode("unknown".into()))
+        );
+
+        //迁出：slot里还有key时不能交给别的节点
+        cluster.set_slot_migrating(5061, &other).unwrap();
+        backend.set("bar".into(), RespBulkString::from("1").into());
+        assert_eq!(
+            cluster.set_slot_node(&backend, 5061, &other),
+            Err(ClusterError::SlotNotEmpty(5061))
+        );
+        backend.del("bar");
+        cluster.set_slot_node(&backend, 5061, &other).unwrap();
+        assert_eq!(cluster.slot_owner(5061).unwrap().id, other);
+        assert!(cluster.migrating().is_empty() && cluster.importing().is_empty());
+
+        //迁入：拿到slot之后config epoch变大
+        cluster.set_slot_importing(12182, &other).unwrap();
+        assert_eq!(cluster.importing(), vec![(12182, other.clone())]);
+        cluster.set_slot_node(&backend, 12182, &myid).unwrap();
+        assert_eq!(cluster.slot_owner(12182).unwrap().id, myid);
+        assert_eq!(cluster.myself().config_epoch, 1);
+        assert!(cluster.migrating().is_empty() && cluster.importing().is_empty());
+    }
+
+    #[test]
+    fn test_add_and_del_slots() {
+        let backend = Backend::new();
+        let cluster = &backend.cluster;
+        cluster.init(7000, 17000);
+        cluster.add_slots(&[1, 2, 3, 7]).unwrap();
+        assert_eq!(cluster.add_slots(&[3]), Err(ClusterError::SlotBusy(3)));
+        assert_eq!(cluster.slot_ranges(&cluster.myid()), vec![(1, 3), (7, 7)]);
+        assert_eq!(
+            cluster.del_slots(&[4]),
+            Err(ClusterError::SlotUnassigned(4))
+        );
+        cluster.del_slots(&[2]).unwrap();
+        assert_eq!(
+            cluster.slot_ranges(&cluster.myid()),
+            vec![(1, 1), (3, 3), (7, 7)]
+        );
+        assert_eq!(cluster.info().size, 1);
+    }
+
+    /// 在当前的runtime上启动一个cluster节点，返回客户端端口和bus端口
+    fn start_node() -> (Backend, u16, u16) {
+        let bind = || {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.set_nonblocking(true).unwrap();
+            TcpListener::from_std(listener).unwrap()
+        };
+        let (listener, bus) = (bind(), bind());
+        let port = listener.local_addr().unwrap().port();
+        let bus_port = bus.local_addr().unwrap().port();
+        let backend = Backend::new();
+        backend.cluster.set_config(ClusterConfig {
+            enabled: true,
+            node_timeout: 500,
+            ..Default::default()
+        });
+        start(&backend, port, bus).unwrap();
+        tokio::spawn(crate::network::serve(listener, backend.clone()));
+        (backend, port, bus_port)
+    }
+
+    fn is_error(reply: &RespFrame, prefix: &str) -> bool {
+        matches!(reply, RespFrame::SimpleError(e) if e.contains(prefix))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cluster_bus() {
+        let (node1, port1, _) = start_node();
+        let (node2, port2, bus2) = start_node();
+        //第三个节点跑在单独的runtime里，关掉这个runtime就相当于节点宕机
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let (node3, port3, bus3) = {
+            let _guard = rt.enter();
+            start_node()
+        };
+
+        //只需要MEET一次，node2和node3通过gossip互相认识
+        for (port, bus_port) in [(port2, bus2), (port3, bus3)] {
+            let reply = call(
+                port1,
+                &[
+                    "CLUSTER",
+                    "MEET",
+                    "127.0.0.1",
+                    &port.to_string(),
+                    &bus_port.to_string(),
+                ],
+            )
+            .await;
+            assert_eq!(reply, RespFrame::SimpleString("OK".into()));
+        }
+        for (port, range) in [
+            (port1, ["0", "5460"]),
+            (port2, ["5461", "10922"]),
+            (port3, ["10923", "16383"]),
+        ] {
+            let reply = call(port, &["CLUSTER", "ADDSLOTSRANGE", range[0], range[1]]).await;
+            assert_eq!(reply, RespFrame::SimpleString("OK".into()));
+        }
+        let nodes = [&node1, &node2, &node3];
+        wait_for(|| {
+            nodes.iter().all(|node| {
+                let info = node.cluster.info();
+                info.state_ok && info.known_nodes == 3
+            })
+        })
+        .await;
+        assert_eq!(node1.cluster.info().size, 3);
+        assert_eq!(
+            node2.cluster.slot_owner(0).unwrap().id,
+            node1.cluster.myid()
+        );
+
+        //foo在slot 12182，由node3负责
+        let reply = call(port1, &["SET", "foo", "1"]).await;
+        assert!(
+            matches!(&reply, RespFrame::SimpleError(e) if e.contains(&format!("MOVED 12182 127.0.0.1:{port3}")))
+        );
+        let reply = call(port3, &["SET", "foo", "1"]).await;
+        assert_eq!(reply, RespFrame::SimpleString("OK".into()));
+        let reply = call(port2, &["CLUSTER", "SLOTS"]).await;
+        assert!(matches!(reply, RespFrame::Arrays(slots) if slots.len() == 3));
+
+        //node3宕机：超过node_timeout之后被标记为PFAIL，多数master同意之后变成FAIL
+        let id3 = node3.cluster.myid();
+        rt.shutdown_background();
+        wait_for(|| {
+            [&node1, &node2].iter().all(|node| {
+                node.cluster
+                    .nodes()
+                    .iter()
+                    .any(|n| n.id == id3 && n.flags.fail)
+            })
+        })
+        .await;
+        assert!(!node1.cluster.info().state_ok);
+        let reply = call(port1, &["GET", "bar"]).await;
+        assert!(matches!(reply, RespFrame::SimpleError(e) if e.contains("CLUSTERDOWN")));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_slot_migration() {
+        let (node1, port1, _) = start_node();
+        let (node2, port2, bus2) = start_node();
+        let ok = RespFrame::SimpleString("OK".into());
+        let (p1, p2) = (port1.to_string(), port2.to_string());
+        let reply = call(
+            port1,
+            &["CLUSTER", "MEET", "127.0.0.1", &p2, &bus2.to_string()],
+        )
+        .await;
+        assert_eq!(reply, ok);
+        call(port1, &["CLUSTER", "ADDSLOTSRANGE", "0", "16383"]).await;
+        wait_for(|| {
+            [&node1, &node2].iter().all(|node| {
+                let info = node.cluster.info();
+                info.state_ok && info.known_nodes == 2
+            })
+        })
+        .await;
+        call(port1, &["SET", "foo", "1"]).await;
+        call(port1, &["SET", "{foo}x", "2"]).await;
+
+        //把foo所在的slot 12182从node1迁到node2
+        let (id1, id2) = (node1.cluster.myid(), node2.cluster.myid());
+        let reply = call(port2, &["CLUSTER", "SETSLOT", "12182", "IMPORTING", &id1]).await;
+        assert_eq!(reply, ok);
+        let reply = call(port1, &["CLUSTER", "SETSLOT", "12182", "MIGRATING", &id2]).await;
+        assert_eq!(reply, ok);
+        let reply = call(port1, &["MIGRATE", "127.0.0.1", &p2, "foo", "0", "1000"]).await;
+        assert_eq!(reply, ok);
+
+        //已经迁走的key回复ASK，还没迁走的照常执行
+        let reply = call(port1, &["GET", "foo"]).await;
+        assert!(is_error(&reply, &format!("ASK 12182 127.0.0.1:{p2}")));
+        let reply = call(port1, &["GET", "{foo}x"]).await;
+        assert_eq!(reply, RespBulkString::from("2").into());
+        let reply = call(port2, &["GET", "foo"]).await;
+        assert!(is_error(&reply, &format!("MOVED 12182 127.0.0.1:{p1}")));
+        let replies = calls(port2, &[&["ASKING"], &["GET", "foo"], &["GET", "foo"]]).await;
+        assert_eq!(replies[1], RespBulkString::from("1").into());
+        assert!(is_error(&replies[2], "MOVED"));
+
+        let reply = call(
+            port1,
+            &[
+                "MIGRATE",
+                "127.0.0.1",
+                &p2,
+                "",
+                "0",
+                "1000",
+                "KEYS",
+                "{foo}x",
+            ],
+        )
+        .await;
+        assert_eq!(reply, ok);
+        assert_eq!(node1.dbsize(), 0);
+        for port in [port2, port1] {
+            let reply = call(port, &["CLUSTER", "SETSLOT", "12182", "NODE", &id2]).await;
+            assert_eq!(reply, ok);
+        }
+        let reply = call(port1, &["GET", "foo"]).await;
+        assert!(is_error(&reply, &format!("MOVED 12182 127.0.0.1:{p2}")));
+        let reply = call(port2, &["GET", "{foo}x"]).await;
+        assert_eq!(reply, RespBulkString::from("2").into());
+        //node2的config epoch变大了，两边对slot的归属保持一致
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(node1.cluster.slot_owner(12182).unwrap().id, id2);
+        assert_eq!(node1.cluster.slot_ranges(&id2), vec![(12182, 12182)]);
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{backend::now_ms, RespArray};
+
+/// 这里的节点都是master，所以只记录和故障检测、握手有关的标志
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeFlags {
+    pub myself: bool,
+    /// 本节点认为它可能已经下线
+    pub pfail: bool,
+    /// 超过半数的master认为它已经下线
+    pub fail: bool,
+    /// CLUSTER MEET之后还没有收到PONG，还不知道它真正的id
+    pub handshake: bool,
+}
+
+impl NodeFlags {
+    /// CLUSTER NODES里的格式，比如myself,master或者master,fail?
+    pub fn encode(&self) -> String {
+        let mut flags = Vec::new();
+        if self.myself {
+            flags.push("myself");
+        }
+        flags.push("master");
+        if self.fail {
+            flags.push("fail");
+        } else if self.pfail {
+            flags.push("fail?");
+        }
+        if self.handshake {
+            flags.push("handshake");
+        }
+        flags.join(",")
+    }
+
+    pub fn parse(s: &str) -> Self {
+        let mut flags = NodeFlags::default();
+        for flag in s.split(',') {
+            match flag {
+                "myself" => flags.myself = true,
+                "fail?" => flags.pfail = true,
+                "fail" => flags.fail = true,
+                "handshake" => flags.handshake = true,
+                _ => {}
+            }
+        }
+        flags
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub id: String,
+    pub ip: String,
+    /// 客户端端口
+    pub port: u16,
+    pub bus_port: u16,
+    pub flags: NodeFlags,
+    pub config_epoch: u64,
+    /// 发出的PING还没有收到PONG时是发出的时间，否则是0
+    pub ping_sent: u64,
+    pub pong_received: u64,
+    pub(super) created: u64,
+    /// 其他master报告这个节点PFAIL或FAIL的时间
+    pub(super) fail_reports: HashMap<String, u64>,
+    /// 到这个节点的cluster bus连接，正在连接时也是Some
+    pub(super) link: Option<UnboundedSender<RespArray>>,
+    pub(super) link_attempt: u64,
+}
+
+impl Node {
+    pub fn new(id: String, ip: String, port: u16, bus_port: u16) -> Self {
+        Self {
+            id,
+            ip,
+            port,
+            bus_port,
+            flags: NodeFlags::default(),
+            config_epoch: 0,
+            ping_sent: 0,
+            pong_received: 0,
+            created: now_ms(),
+            fail_reports: HashMap::new(),
+            link: None,
+            link_attempt: 0,
+        }
+    }
+
+    /// MOVED/ASK里的ip:port
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.ip, self.port)
+    }
+
+    pub fn is_failing(&self) -> bool {
+        self.flags.pfail || self.flags.fail
+    }
+
+    pub fn link_connected(&self) -> bool {
+        self.flags.myself || self.link.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_flags() {
+        let flags = NodeFlags {
+            myself: true,
+            ..Default::default()
+        };
+        assert_eq!(flags.encode(), "myself,master");
+        let flags = NodeFlags::parse("master,fail?");
+        assert!(flags.pfail && !flags.fail);
+        assert_eq!(flags.encode(), "master,fail?");
+    }
+}
@@ -0,0 +1,65 @@
+use crc::{Crc, CRC_16_XMODEM};
+
+pub const CLUSTER_SLOTS: usize = 16384;
+
+/// Redis cluster使用的CRC16(XMODEM)
+const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_XMODEM);
+
+/// key所在的slot。key里第一个{和它之后第一个}之间不为空时只用这部分计算，
+/// 比如{user1000}.following和{user1000}.followers一定在同一个slot
+pub fn key_hash_slot(key: &[u8]) -> u16 {
+    let hashed = key
+        .iter()
+        .position(|&b| b == b'{')
+        .and_then(|start| {
+            let rest = &key[start + 1..];
+            rest.iter().position(|&b| b == b'}').map(|end| &rest[..end])
+        })
+        .filter(|tag| !tag.is_empty())
+        .unwrap_or(key);
+    CRC16.checksum(hashed) & (CLUSTER_SLOTS as u16 - 1)
+}
+
+/// 把升序排列的slot合并成连续的区间[start, end]
+pub fn slot_ranges(slots: impl IntoIterator<Item = u16>) -> Vec<(u16, u16)> {
+    let mut ranges: Vec<(u16, u16)> = Vec::new();
+    for slot in slots {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == slot => *end = slot,
+            _ => ranges.push((slot, slot)),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_hash_slot() {
+        assert_eq!(CRC16.checksum(b"123456789"), 0x31c3);
+        assert_eq!(key_hash_slot(b"foo"), 12182);
+        assert_eq!(key_hash_slot(b"bar"), 5061);
+        assert_eq!(
+            key_hash_slot(b"{user1000}.following"),
+            key_hash_slot(b"user1000")
+        );
+        //空的tag不算，整个key参与计算
+        assert_eq!(
+            key_hash_slot(b"foo{}{bar}"),
+            CRC16.checksum(b"foo{}{bar}") & 16383
+        );
+        assert_eq!(key_hash_slot(b"foo{{bar}}zap"), key_hash_slot(b"{bar"));
+        assert_eq!(key_hash_slot(b"foo{bar"), key_hash_slot(b"foo{bar"));
+    }
+
+    #[test]
+    fn test_slot_ranges() {
+        assert_eq!(
+            slot_ranges([0, 1, 2, 5, 7, 8]),
+            vec![(0, 2), (5, 5), (7, 8)]
+        );
+        assert!(slot_ranges([]).is_empty());
+    }
+}
@@ -0,0 +1,363 @@
+use rand::Rng;
+
+use crate::{
+    acl::{AclError, AclLogEntry, Denied},
+    backend::now_ms,
+    clients::KillFilter,
+    network::Session,
+    Backend, RespArray, RespBulkString, RespDoubles, RespFrame, RespInteger, RespMaps,
+    RespNullBulkString, SimpleError,
+};
+
+use super::{
+    command_label, command_spec, commands_in_category, extract_cmd_args, string_arg, Command,
+    CommandError, CATEGORIES, RESP_OK,
+};
+
+/// ACL LOG不带参数时返回的条数
+const DEFAULT_LOG_COUNT: usize = 10;
+
+/// ACL SETUSER username [rule ...] | GETUSER username | DELUSER username [username ...] |
+/// LIST | USERS | WHOAMI | CAT [category] | LOG [count|RESET] |
+/// DRYRUN username command [arg ...] | LOAD | SAVE | GENPASS [bits]
+#[derive(Debug, PartialEq)]
+pub enum Acl {
+    SetUser(String, Vec<String>),
+    GetUser(String),
+    DelUser(Vec<String>),
+    List,
+    Users,
+    WhoAmI,
+    Cat(Option<String>),
+    Log(usize),
+    LogReset,
+    DryRun(String, RespArray),
+    Load,
+    Save,
+    GenPass(usize),
+}
+
+impl Acl {
+    /// WHOAMI读连接的用户，DELUSER要断开被删除用户的连接，所以由network层直接调用
+    pub fn execute(self, session: &Session, backend: &Backend) -> Result<RespFrame, CommandError> {
+        let acl = &backend.acl;
+        let bulk = |s: String| RespFrame::from(RespBulkString::from(s));
+        match self {
+            Acl::SetUser(username, rules) => match acl.set_user(&username, &rules) {
+                Ok(()) => Ok(RESP_OK.clone()),
+                Err(e) => Ok(acl_error(e)),
+            },
+            Acl::GetUser(username) => Ok(match acl.get_user(&username) {
+                Some(user) => {
+                    let mut map = RespMaps::default();
+                    let strings = |items: Vec<String>| {
+                        RespFrame::from(RespArray::new(items.into_iter().map(bulk).collect()))
+                    };
+                    map.insert(
+                        bulk("flags".into()),
+                        strings(user.flags().into_iter().map(String::from).collect()),
+                    );
+                    map.insert(bulk("passwords".into()), strings(user.passwords.clone()));
+                    map.insert(bulk("commands".into()), bulk(user.describe_commands()));
+                    map.insert(bulk("keys".into()), bulk(user.describe_keys()));
+                    map.insert(bulk("channels".into()), bulk(user.describe_channels()));
+                    map.insert(bulk("selectors".into()), RespArray::new(vec![]).into());
+                    map.into()
+                }
+                None => RespNullBulkString.into(),
+            }),
+            Acl::DelUser(usernames) => match acl.del_users(&usernames) {
+                Ok(deleted) => {
+                    //被删除的用户的连接全部断开
+                    for username in usernames {
+                        let filter = KillFilter {
+                            user: Some(username),
+                            skip_me: false,
+                            ..Default::default()
+                        };
+                        backend.clients.kill(&filter, session.id);
+                    }
+                    Ok(RespInteger::from(deleted as i64).into())
+                }
+                Err(e) => Ok(acl_error(e)),
+            },
+            Acl::List => Ok(RespArray::new(
+                acl.users()
+                    .iter()
+                    .map(|user| bulk(user.describe()))
+                    .collect(),
+            )
+            .into()),
+            Acl::Users => Ok(RespArray::new(
+                acl.users()
+                    .into_iter()
+                    .map(|user| bulk(user.name))
+                    .collect(),
+            )
+            .into()),
+            Acl::WhoAmI => Ok(bulk(session.user.clone())),
+            Acl::Cat(None) => {
+                Ok(RespArray::new(CATEGORIES.iter().map(|c| bulk(c.to_string())).collect()).into())
+            }
+            Acl::Cat(Some(category)) => {
+                let category = category.to_ascii_lowercase();
+                if !CATEGORIES.contains(&category.as_str()) {
+                    return Ok(acl_error(AclError::UnknownCategory(category)));
+                }
+                Ok(RespArray::new(
+                    commands_in_category(&category)
+                        .into_iter()
+                        .map(|name| bulk(name.to_string()))
+                        .collect(),
+                )
+                .into())
+            }
+            Acl::Log(count) => {
+                Ok(RespArray::new(acl.log.get(count).iter().map(log_entry).collect()).into())
+            }
+            Acl::LogReset => {
+                acl.log.reset();
+                Ok(RESP_OK.clone())
+            }
+            Acl::DryRun(username, array) => dry_run(backend, &username, array),
+            Acl::Load => match acl.load() {
+                Ok(()) => Ok(RESP_OK.clone()),
+                Err(e) => Ok(acl_error(e)),
+            },
+            Acl::Save => match acl.save() {
+                Ok(()) => Ok(RESP_OK.clone()),
+                Err(e) => Ok(acl_error(e)),
+            },
+            Acl::GenPass(bits) => {
+                //每个十六进制字符4位，不足4位的向上取整
+                let mut rng = rand::thread_rng();
+                let password = (0..bits.div_ceil(4))
+                    .map(|_| format!("{:x}", rng.gen_range(0..16u8)))
+                    .collect();
+                Ok(bulk(password))
+            }
+        }
+    }
+}
+
+fn acl_error(e: AclError) -> RespFrame {
+    SimpleError::from(format!("ERR {e}")).into()
+}
+
+/// ACL LOG的一条记录
+fn log_entry(entry: &AclLogEntry) -> RespFrame {
+    let bulk = |s: &str| RespFrame::from(RespBulkString::from(s.to_string()));
+    let integer = |n: u64| RespFrame::from(RespInteger::from(n as i64));
+    let age = now_ms().saturating_sub(entry.created) as f64 / 1000.0;
+    let mut map = RespMaps::default();
+    map.insert(bulk("count"), integer(entry.count));
+    map.insert(bulk("reason"), bulk(entry.reason));
+    map.insert(bulk("context"), bulk(entry.context));
+    map.insert(bulk("object"), bulk(&entry.object));
+    map.insert(bulk("username"), bulk(&entry.username));
+    map.insert(bulk("age-seconds"), RespDoubles::new(age).into());
+    map.insert(bulk("client-info"), bulk(&entry.client_info));
+    map.insert(bulk("entry-id"), integer(entry.id));
+    map.insert(bulk("timestamp-created"), integer(entry.created));
+    map.insert(bulk("timestamp-last-updated"), integer(entry.updated));
+    map.into()
+}
+
+/// 不执行命令，只检查用户能不能执行，能执行时回复OK，否则回复原因
+fn dry_run(backend: &Backend, username: &str, array: RespArray) -> Result<RespFrame, CommandError> {
+    let acl = &backend.acl;
+    if acl.get_user(username).is_none() {
+        return Ok(acl_error(AclError::UserNotFound(username.into())));
+    }
+    let label = command_label(&array);
+    let name = label.split('|').next().unwrap_or_default().to_string();
+    if command_spec(&name).is_none() {
+        return Ok(SimpleError::from(format!("ERR Command '{name}' not found")).into());
+    }
+    if acl.check_command(username, &label).is_err() {
+        return Ok(RespBulkString::from(format!(
+            "User {username} has no permissions to run the '{label}' command"
+        ))
+        .into());
+    }
+    let cmd = Command::try_from(array)?;
+    if let Err(Denied::Key(key)) = acl.check_keys(username, &cmd.keys(), cmd.is_write()) {
+        return Ok(RespBulkString::from(format!(
+            "User {username} has no permissions to access the '{key}' key"
+        ))
+        .into());
+    }
+    Ok(RESP_OK.clone())
+}
+
+///*3\r\n$3\r\nacl\r\n$7\r\ngetuser\r\n$7\r\ndefault\r\n
+impl TryFrom<RespArray> for Acl {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let subcommand = match args.next() {
+            Some(RespFrame::BulkString(s)) => String::from_utf8(s.0)?.to_ascii_lowercase(),
+            _ => {
+                return Err(CommandError::InvalidArgument(
+                    "acl command should have a subcommand".into(),
+                ))
+            }
+        };
+        //DRYRUN的命令参数原样保留
+        if subcommand == "dryrun" {
+            let username = args.next().map(string_arg).transpose()?;
+            let command = args.collect::<Vec<_>>();
+            return match username {
+                Some(username) if !command.is_empty() => {
+                    Ok(Acl::DryRun(username, RespArray::new(command)))
+                }
+                _ => Err(wrong_args(&subcommand)),
+            };
+        }
+        let args = args.map(string_arg).collect::<Result<Vec<_>, _>>()?;
+
+        let acl = match (subcommand.as_str(), args.as_slice()) {
+            ("setuser", [username, rules @ ..]) => Acl::SetUser(username.clone(), rules.to_vec()),
+            ("getuser", [username]) => Acl::GetUser(username.clone()),
+            ("deluser", usernames) if !usernames.is_empty() => Acl::DelUser(usernames.to_vec()),
+            ("list", []) => Acl::List,
+            ("users", []) => Acl::Users,
+            ("whoami", []) => Acl::WhoAmI,
+            ("cat", []) => Acl::Cat(None),
+            ("cat", [category]) => Acl::Cat(Some(category.clone())),
+            ("log", []) => Acl::Log(DEFAULT_LOG_COUNT),
+            ("log", [reset]) if reset.eq_ignore_ascii_case("reset") => Acl::LogReset,
+            ("log", [count]) => Acl::Log(count.parse().map_err(|_| {
+                CommandError::InvalidArgument("value is out of range, must be positive".into())
+            })?),
+            ("load", []) => Acl::Load,
+            ("save", []) => Acl::Save,
+            ("genpass", []) => Acl::GenPass(256),
+            ("genpass", [bits]) => match bits.parse::<usize>() {
+                Ok(bits) if (1..=4096).contains(&bits) => Acl::GenPass(bits),
+                _ => {
+                    return Err(CommandError::InvalidArgument(
+                        "ACL GENPASS argument must be the number of bits for the output password, a positive number up to 4096".into(),
+                    ))
+                }
+            },
+            _ => return Err(wrong_args(&subcommand)),
+        };
+        Ok(acl)
+    }
+}
+
+fn wrong_args(subcommand: &str) -> CommandError {
+    CommandError::InvalidArgument(format!(
+        "unknown subcommand or wrong number of arguments for 'acl|{subcommand}'"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aof::command_array, EncodeResp};
+    use anyhow::Result;
+
+    fn parse(args: &[&str]) -> Result<Acl, CommandError> {
+        Acl::try_from(command_array(args.iter().copied()))
+    }
+
+    fn run(args: &[&str], session: &Session, backend: &Backend) -> Result<RespFrame> {
+        Ok(parse(args)?.execute(session, backend)?)
+    }
+
+    #[test]
+    fn test_acl_from_resp_array() -> Result<()> {
+        assert_eq!(
+            parse(&["ACL", "SETUSER", "alice", "on", ">pw"])?,
+            Acl::SetUser("alice".into(), vec!["on".into(), ">pw".into()])
+        );
+        assert_eq!(parse(&["acl", "log", "RESET"])?, Acl::LogReset);
+        assert_eq!(parse(&["acl", "log", "3"])?, Acl::Log(3));
+        assert_eq!(parse(&["acl", "genpass", "5"])?, Acl::GenPass(5));
+        assert!(parse(&["acl", "genpass", "0"]).is_err());
+        assert!(parse(&["acl", "deluser"]).is_err());
+        assert!(parse(&["acl", "dryrun", "alice"]).is_err());
+        assert!(parse(&["acl", "whoami", "x"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_acl_users() -> Result<()> {
+        let backend = Backend::new();
+        let session = Session::new();
+        let reply = run(
+            &["acl", "setuser", "alice", "on", ">pw", "~cache:*", "+@read"],
+            &session,
+            &backend,
+        )?;
+        assert_eq!(reply, RESP_OK.clone());
+        let reply = run(
+            &["acl", "setuser", "bob", "+nosuchcommand"],
+            &session,
+            &backend,
+        )?;
+        assert!(matches!(reply, RespFrame::SimpleError(e) if e.contains("Unknown command")));
+
+        let reply = run(&["acl", "users"], &session, &backend)?;
+        assert_eq!(reply.encode(), b"*2\r\n$5\r\nalice\r\n$7\r\ndefault\r\n");
+        let reply = run(&["acl", "whoami"], &session, &backend)?;
+        assert_eq!(reply.encode(), b"$7\r\ndefault\r\n");
+        let reply = run(&["acl", "getuser", "alice"], &session, &backend)?;
+        let RespFrame::Maps(map) = reply else {
+            panic!("getuser should reply a map");
+        };
+        assert_eq!(
+            map.get(&RespFrame::from(RespBulkString::from("keys"))),
+            Some(&RespBulkString::from("~cache:*").into())
+        );
+        let reply = run(&["acl", "getuser", "nobody"], &session, &backend)?;
+        assert_eq!(reply, RespNullBulkString.into());
+
+        let reply = run(
+            &["acl", "dryrun", "alice", "get", "cache:1"],
+            &session,
+            &backend,
+        )?;
+        assert_eq!(reply, RESP_OK.clone());
+        let reply = run(
+            &["acl", "dryrun", "alice", "get", "other"],
+            &session,
+            &backend,
+        )?;
+        assert_eq!(
+            reply,
+            RespBulkString::from("User alice has no permissions to access the 'other' key").into()
+        );
+        let reply = run(
+            &["acl", "dryrun", "alice", "set", "cache:1", "v"],
+            &session,
+            &backend,
+        )?;
+        assert_eq!(
+            reply,
+            RespBulkString::from("User alice has no permissions to run the 'set' command").into()
+        );
+
+        let reply = run(&["acl", "cat", "hash"], &session, &backend)?;
+        assert_eq!(
+            reply.encode(),
+            b"*3\r\n$4\r\nhget\r\n$4\r\nhset\r\n$7\r\nhgetall\r\n"
+        );
+        let reply = run(&["acl", "cat", "nope"], &session, &backend)?;
+        assert!(matches!(reply, RespFrame::SimpleError(_)));
+
+        let reply = run(&["acl", "deluser", "alice", "default"], &session, &backend)?;
+        assert!(matches!(reply, RespFrame::SimpleError(_)));
+        let reply = run(&["acl", "deluser", "alice", "nobody"], &session, &backend)?;
+        assert_eq!(reply, RespInteger::from(1).into());
+
+        let RespFrame::BulkString(password) = run(&["acl", "genpass"], &session, &backend)? else {
+            panic!("genpass should reply a bulk string");
+        };
+        assert_eq!(password.as_ref().len(), 64);
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{acl::DEFAULT_USER, network::Session, Backend, RespArray, RespFrame, SimpleError};
+
+use super::{current_entry, extract_cmd_args, string_arg, CommandError, RESP_OK};
+
+/// AUTH [username] password
+#[derive(Debug, PartialEq)]
+pub struct Auth {
+    pub username: Option<String>,
+    pub password: String,
+}
+
+impl Auth {
+    /// 认证成功后连接切换到这个用户，由network层直接调用
+    pub fn execute(
+        self,
+        session: &mut Session,
+        backend: &Backend,
+    ) -> Result<RespFrame, CommandError> {
+        //只有密码的AUTH认证的是default用户，default用户没有密码时这样调用一般是配置写错了
+        if self.username.is_none()
+            && backend
+                .acl
+                .get_user(DEFAULT_USER)
+                .is_some_and(|user| user.nopass)
+        {
+            return Ok(SimpleError::from(
+                "ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?",
+            )
+            .into());
+        }
+        let username = self.username.unwrap_or_else(|| DEFAULT_USER.into());
+        authenticate(session, backend, username, &self.password)?;
+        Ok(RESP_OK.clone())
+    }
+}
+
+/// AUTH和HELLO AUTH共用，失败时记进ACL LOG
+pub fn authenticate(
+    session: &mut Session,
+    backend: &Backend,
+    username: String,
+    password: &str,
+) -> Result<(), CommandError> {
+    if !backend.acl.authenticate(&username, password) {
+        let context = match session.transaction.in_multi() {
+            true => "multi",
+            false => "toplevel",
+        };
+        let info = current_entry(session, backend).info();
+        backend
+            .acl
+            .log_denied("auth", context, "AUTH".into(), username, info);
+        return Err(CommandError::WrongPass);
+    }
+    session.user = username;
+    session.authenticated = true;
+    Ok(())
+}
+
+///*2\r\n$4\r\nauth\r\n$6\r\nsecret\r\n
+impl TryFrom<RespArray> for Auth {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let args = extract_cmd_args(value, 1)?
+            .into_iter()
+            .map(string_arg)
+            .collect::<Result<Vec<_>, _>>()?;
+        match <[String; 1]>::try_from(args) {
+            Ok([password]) => Ok(Auth {
+                username: None,
+                password,
+            }),
+            Err(args) => match <[String; 2]>::try_from(args) {
+                Ok([username, password]) => Ok(Auth {
+                    username: Some(username),
+                    password,
+                }),
+                Err(_) => Err(CommandError::InvalidArgument("syntax error".into())),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aof::command_array;
+
+    fn parse(args: &[&str]) -> Result<Auth, CommandError> {
+        Auth::try_from(command_array(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_auth() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(&["auth", "alice", "pw"])?,
+            Auth {
+                username: Some("alice".into()),
+                password: "pw".into()
+            }
+        );
+        assert!(parse(&["auth"]).is_err());
+        assert!(parse(&["auth", "a", "b", "c"]).is_err());
+
+        let backend = Backend::new();
+        let mut session = Session::new();
+        let reply = parse(&["auth", "pw"])?.execute(&mut session, &backend)?;
+        assert!(matches!(reply, RespFrame::SimpleError(_)));
+
+        backend.acl.set_requirepass("secret");
+        assert!(matches!(
+            parse(&["auth", "wrong"])?.execute(&mut session, &backend),
+            Err(CommandError::WrongPass)
+        ));
+        assert_eq!(backend.acl.log.get(1)[0].reason, "auth");
+        assert!(!session.authenticated);
+        parse(&["auth", "secret"])?.execute(&mut session, &backend)?;
+        assert!(session.authenticated);
+        assert_eq!(session.user, "default");
+        Ok(())
+    }
+}
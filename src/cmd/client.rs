@@ -0,0 +1,591 @@
+use crate::{
+    clients::{ClientEntry, ClientType, KillFilter, PauseMode},
+    network::Session,
+    tracking::{TrackingError, TrackingOptions},
+    Backend, RespArray, RespBulkString, RespFrame, RespInteger, RespMaps, RespNullBulkString,
+    RespVerbatimString, SimpleError,
+};
+
+use super::{extract_cmd_args, hello::validate_client_name, string_arg, CommandError, RESP_OK};
+
+/// CLIENT ID | INFO | GETNAME | SETNAME name | LIST [TYPE type] [ID id ...] |
+/// KILL ip:port | KILL filter value ... | PAUSE timeout [WRITE|ALL] | UNPAUSE |
+/// NO-EVICT ON|OFF | NO-TOUCH ON|OFF |
+/// TRACKING ON|OFF [REDIRECT id] [PREFIX prefix ...] [BCAST] [OPTIN] [OPTOUT] [NOLOOP] |
+/// CACHING YES|NO | GETREDIR | TRACKINGINFO
+#[derive(Debug, PartialEq)]
+pub enum Client {
+    Id,
+    Info,
+    GetName,
+    SetName(String),
+    List {
+        client_type: Option<ClientType>,
+        ids: Vec<u64>,
+    },
+    /// 旧的写法CLIENT KILL ip:port，回复OK或者错误
+    KillAddr(String),
+    /// 新的写法，回复断开的连接个数
+    Kill(KillFilter),
+    Pause(u64, PauseMode),
+    Unpause,
+    NoEvict(bool),
+    NoTouch(bool),
+    /// None表示TRACKING OFF
+    Tracking(Option<TrackingOptions>),
+    Caching(bool),
+    GetRedir,
+    TrackingInfo,
+}
+
+impl Client {
+    /// CLIENT子命令读写的都是连接状态，由network层直接调用
+    pub fn execute(
+        self,
+        session: &mut Session,
+        backend: &Backend,
+    ) -> Result<RespFrame, CommandError> {
+        match self {
+            Client::Id => Ok(RespInteger::from(session.id as i64).into()),
+            Client::Info => {
+                let info = format!("{}\n", current_entry(session, backend).info());
+                Ok(RespVerbatimString::text(info).into())
+            }
+            Client::GetName => Ok(match &session.name {
+                Some(name) => RespBulkString::from(name.clone()).into(),
+                None => RespNullBulkString.into(),
+            }),
+            Client::SetName(name) => {
+                validate_client_name(&name)?;
+                //空字符串表示清除名字
+                session.name = if name.is_empty() { None } else { Some(name) };
+                Ok(RESP_OK.clone())
+            }
+            Client::List { client_type, ids } => {
+                let mut list = String::new();
+                for entry in backend.clients.list() {
+                    //自己的状态可能还没有同步到登记表
+                    let entry = match entry.id == session.id {
+                        true => current_entry(session, backend),
+                        false => entry,
+                    };
+                    if client_type.is_some_and(|t| t != entry.client_type())
+                        || (!ids.is_empty() && !ids.contains(&entry.id))
+                    {
+                        continue;
+                    }
+                    list.push_str(&entry.info());
+                    list.push('\n');
+                }
+                Ok(RespVerbatimString::text(list).into())
+            }
+            Client::KillAddr(addr) => {
+                let filter = KillFilter {
+                    addr: Some(addr),
+                    skip_me: false,
+                    ..Default::default()
+                };
+                match backend.clients.kill(&filter, session.id) {
+                    0 => Err(CommandError::InvalidArgument("No such client".into())),
+                    _ => Ok(RESP_OK.clone()),
+                }
+            }
+            Client::Kill(filter) => {
+                let killed = backend.clients.kill(&filter, session.id);
+                Ok(RespInteger::from(killed as i64).into())
+            }
+            Client::Pause(timeout, mode) => {
+                backend.clients.pause(timeout, mode);
+                Ok(RESP_OK.clone())
+            }
+            Client::Unpause => {
+                backend.clients.unpause();
+                Ok(RESP_OK.clone())
+            }
+            Client::NoEvict(on) => {
+                session.no_evict = on;
+                Ok(RESP_OK.clone())
+            }
+            Client::NoTouch(on) => {
+                session.no_touch = on;
+                Ok(RESP_OK.clone())
+            }
+            Client::Tracking(Some(options)) => {
+                let redirect = options.redirect;
+                if redirect.is_some_and(|id| backend.clients.get(id).is_none()) {
+                    return Ok(tracking_error(TrackingError::NoRedirectTarget));
+                }
+                if let Err(e) = backend.tracking.enable(session.id, options) {
+                    return Ok(tracking_error(e));
+                }
+                backend.clients.set_tracking(session.id, Some(redirect));
+                Ok(RESP_OK.clone())
+            }
+            Client::Tracking(None) => {
+                backend.tracking.disable(session.id);
+                backend.clients.set_tracking(session.id, None);
+                session.caching = None;
+                Ok(RESP_OK.clone())
+            }
+            Client::Caching(yes) => {
+                if let Err(e) = backend.tracking.check_caching(session.id, yes) {
+                    return Ok(tracking_error(e));
+                }
+                session.caching = Some(yes);
+                Ok(RESP_OK.clone())
+            }
+            Client::GetRedir => {
+                let redir = match backend.tracking.options(session.id) {
+                    Some(options) => options.redirect.map_or(0, |id| id as i64),
+                    None => -1,
+                };
+                Ok(RespInteger::from(redir).into())
+            }
+            Client::TrackingInfo => Ok(tracking_info(session, backend)),
+        }
+    }
+}
+
+fn tracking_error(e: TrackingError) -> RespFrame {
+    SimpleError::from(format!("ERR {e}")).into()
+}
+
+/// CLIENT TRACKINGINFO：flags、redirect、prefixes
+fn tracking_info(session: &Session, backend: &Backend) -> RespFrame {
+    let bulk = |s: &str| RespFrame::from(RespBulkString::from(s.to_string()));
+    let options = backend.tracking.options(session.id);
+    let mut flags = vec![];
+    let (redirect, prefixes) = match &options {
+        None => {
+            flags.push(bulk("off"));
+            (-1, vec![])
+        }
+        Some(options) => {
+            flags.push(bulk("on"));
+            for (set, flag) in [
+                (options.bcast, "bcast"),
+                (options.optin, "optin"),
+                (options.optout, "optout"),
+                (session.caching == Some(true), "caching-yes"),
+                (session.caching == Some(false), "caching-no"),
+                (options.noloop, "noloop"),
+            ] {
+                if set {
+                    flags.push(bulk(flag));
+                }
+            }
+            //REDIRECT的连接已经断开
+            if options
+                .redirect
+                .is_some_and(|id| backend.clients.get(id).is_none())
+            {
+                flags.push(bulk("broken_redirect"));
+            }
+            let prefixes = options.prefixes.iter().map(|p| bulk(p)).collect();
+            (options.redirect.map_or(0, |id| id as i64), prefixes)
+        }
+    };
+    let mut info = RespMaps::default();
+    info.insert(bulk("flags"), RespArray::new(flags).into());
+    info.insert(bulk("redirect"), RespInteger::from(redirect).into());
+    info.insert(bulk("prefixes"), RespArray::new(prefixes).into());
+    info.into()
+}
+
+/// 当前连接的最新状态，没有登记的连接(比如测试里)直接从Session生成
+pub fn current_entry(session: &Session, backend: &Backend) -> ClientEntry {
+    match backend.clients.get(session.id) {
+        Some(mut entry) => {
+            entry.update(session);
+            entry
+        }
+        None => ClientEntry::new(session),
+    }
+}
+
+///*2\r\n$6\r\nclient\r\n$4\r\ninfo\r\n
+impl TryFrom<RespArray> for Client {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let subcommand = match args.next() {
+            Some(RespFrame::BulkString(s)) => String::from_utf8(s.0)?.to_ascii_lowercase(),
+            _ => {
+                return Err(CommandError::InvalidArgument(
+                    "client command should have a subcommand".into(),
+                ))
+            }
+        };
+        let args = args.map(string_arg).collect::<Result<Vec<_>, _>>()?;
+        let syntax_error = || CommandError::InvalidArgument("syntax error".into());
+        let wrong_args = || {
+            CommandError::InvalidArgument(format!(
+                "unknown subcommand or wrong number of arguments for 'client|{subcommand}'"
+            ))
+        };
+
+        let client = match (subcommand.as_str(), args.as_slice()) {
+            ("id", []) => Client::Id,
+            ("info", []) => Client::Info,
+            ("getname", []) => Client::GetName,
+            ("setname", [name]) => Client::SetName(name.clone()),
+            ("list", options) => parse_list(options)?,
+            ("kill", [addr]) => Client::KillAddr(addr.clone()),
+            ("kill", filters) if !filters.is_empty() && filters.len().is_multiple_of(2) => {
+                Client::Kill(parse_kill_filter(filters)?)
+            }
+            ("kill", _) => return Err(syntax_error()),
+            ("pause", [timeout, mode @ ..]) if mode.len() <= 1 => {
+                let timeout = timeout.parse::<u64>().map_err(|_| {
+                    CommandError::InvalidArgument(
+                        "timeout is not an integer or out of range".into(),
+                    )
+                })?;
+                let mode = match mode.first().map(|m| m.to_ascii_lowercase()).as_deref() {
+                    None | Some("all") => PauseMode::All,
+                    Some("write") => PauseMode::Write,
+                    Some(_) => return Err(syntax_error()),
+                };
+                Client::Pause(timeout, mode)
+            }
+            ("unpause", []) => Client::Unpause,
+            ("no-evict", [on]) => Client::NoEvict(parse_on_off(on)?),
+            ("no-touch", [on]) => Client::NoTouch(parse_on_off(on)?),
+            ("tracking", [on, options @ ..]) => parse_tracking(on, options)?,
+            ("caching", [yes]) => match yes.to_ascii_lowercase().as_str() {
+                "yes" => Client::Caching(true),
+                "no" => Client::Caching(false),
+                _ => return Err(syntax_error()),
+            },
+            ("getredir", []) => Client::GetRedir,
+            ("trackinginfo", []) => Client::TrackingInfo,
+            _ => return Err(wrong_args()),
+        };
+        Ok(client)
+    }
+}
+
+/// CLIENT LIST [TYPE type] [ID id [id ...]]
+fn parse_list(options: &[String]) -> Result<Client, CommandError> {
+    let syntax_error = || CommandError::InvalidArgument("syntax error".into());
+    match options {
+        [] => Ok(Client::List {
+            client_type: None,
+            ids: vec![],
+        }),
+        [option, client_type] if option.eq_ignore_ascii_case("type") => Ok(Client::List {
+            client_type: Some(client_type.parse().map_err(CommandError::InvalidArgument)?),
+            ids: vec![],
+        }),
+        [option, ids @ ..] if option.eq_ignore_ascii_case("id") && !ids.is_empty() => {
+            let ids = ids
+                .iter()
+                .map(|id| parse_client_id(id))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Client::List {
+                client_type: None,
+                ids,
+            })
+        }
+        _ => Err(syntax_error()),
+    }
+}
+
+/// CLIENT KILL [ID id] [ADDR ip:port] [LADDR ip:port] [USER username] [TYPE type]
+/// [SKIPME yes|no] [MAXAGE seconds]
+fn parse_kill_filter(args: &[String]) -> Result<KillFilter, CommandError> {
+    let syntax_error = || CommandError::InvalidArgument("syntax error".into());
+    let mut filter = KillFilter::default();
+    for pair in args.chunks(2) {
+        let value = pair[1].clone();
+        match pair[0].to_ascii_lowercase().as_str() {
+            "id" => filter.id = Some(parse_client_id(&value)?),
+            "addr" => filter.addr = Some(value),
+            "laddr" => filter.laddr = Some(value),
+            "user" => filter.user = Some(value),
+            "type" => {
+                filter.client_type = Some(value.parse().map_err(CommandError::InvalidArgument)?)
+            }
+            "skipme" => {
+                filter.skip_me = match value.to_ascii_lowercase().as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err(syntax_error()),
+                }
+            }
+            "maxage" => filter.max_age = Some(value.parse().map_err(|_| syntax_error())?),
+            _ => return Err(syntax_error()),
+        }
+    }
+    Ok(filter)
+}
+
+/// CLIENT TRACKING ON|OFF [REDIRECT client-id] [PREFIX prefix [PREFIX prefix ...]]
+/// [BCAST] [OPTIN] [OPTOUT] [NOLOOP]
+fn parse_tracking(on: &str, args: &[String]) -> Result<Client, CommandError> {
+    let syntax_error = || CommandError::InvalidArgument("syntax error".into());
+    let mut options = TrackingOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.to_ascii_lowercase().as_str() {
+            "redirect" => {
+                let id = args.next().ok_or_else(syntax_error)?;
+                options.redirect = Some(parse_client_id(id)?);
+            }
+            "prefix" => options
+                .prefixes
+                .push(args.next().ok_or_else(syntax_error)?.clone()),
+            "bcast" => options.bcast = true,
+            "optin" => options.optin = true,
+            "optout" => options.optout = true,
+            "noloop" => options.noloop = true,
+            _ => return Err(syntax_error()),
+        }
+    }
+    match parse_on_off(on)? {
+        true => Ok(Client::Tracking(Some(options))),
+        false => Ok(Client::Tracking(None)),
+    }
+}
+
+fn parse_client_id(id: &str) -> Result<u64, CommandError> {
+    match id.parse::<u64>() {
+        Ok(id) if id > 0 => Ok(id),
+        _ => Err(CommandError::InvalidArgument(
+            "client-id should be greater than 0".into(),
+        )),
+    }
+}
+
+fn parse_on_off(value: &str) -> Result<bool, CommandError> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(CommandError::InvalidArgument("syntax error".into())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aof::command_array, DecodeResp, EncodeResp};
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    fn parse(args: &[&str]) -> Result<Client, CommandError> {
+        Client::try_from(command_array(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_client_from_resp_array() -> Result<()> {
+        let mut bytes_mut =
+            BytesMut::from(&b"*3\r\n$6\r\nclient\r\n$7\r\nSETNAME\r\n$5\r\nmyapp\r\n"[..]);
+        let frame = RespArray::decode(&mut bytes_mut)?;
+        assert_eq!(Client::try_from(frame)?, Client::SetName("myapp".into()));
+
+        let mut bytes_mut = BytesMut::from(&b"*3\r\n$6\r\nclient\r\n$4\r\ninfo\r\n$1\r\nx\r\n"[..]);
+        let frame = RespArray::decode(&mut bytes_mut)?;
+        assert!(Client::try_from(frame).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_list_and_kill_from_resp_array() -> Result<()> {
+        assert_eq!(
+            parse(&["CLIENT", "LIST", "TYPE", "replica"])?,
+            Client::List {
+                client_type: Some(ClientType::Replica),
+                ids: vec![]
+            }
+        );
+        assert_eq!(
+            parse(&["CLIENT", "LIST", "ID", "3", "5"])?,
+            Client::List {
+                client_type: None,
+                ids: vec![3, 5]
+            }
+        );
+        assert!(parse(&["CLIENT", "LIST", "TYPE", "nobody"]).is_err());
+        assert!(parse(&["CLIENT", "LIST", "ID", "0"]).is_err());
+
+        assert_eq!(
+            parse(&["CLIENT", "KILL", "127.0.0.1:5000"])?,
+            Client::KillAddr("127.0.0.1:5000".into())
+        );
+        assert_eq!(
+            parse(&["CLIENT", "KILL", "ID", "7", "SKIPME", "no", "TYPE", "normal"])?,
+            Client::Kill(KillFilter {
+                id: Some(7),
+                client_type: Some(ClientType::Normal),
+                skip_me: false,
+                ..Default::default()
+            })
+        );
+        assert!(parse(&["CLIENT", "KILL", "ID", "7", "SKIPME"]).is_err());
+        assert!(parse(&["CLIENT", "KILL", "NAME", "x"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_pause_and_flags_from_resp_array() -> Result<()> {
+        assert_eq!(
+            parse(&["CLIENT", "PAUSE", "100"])?,
+            Client::Pause(100, PauseMode::All)
+        );
+        assert_eq!(
+            parse(&["CLIENT", "PAUSE", "100", "write"])?,
+            Client::Pause(100, PauseMode::Write)
+        );
+        assert!(parse(&["CLIENT", "PAUSE", "-1"]).is_err());
+        assert!(parse(&["CLIENT", "PAUSE", "100", "read"]).is_err());
+        assert_eq!(parse(&["CLIENT", "UNPAUSE"])?, Client::Unpause);
+        assert_eq!(parse(&["CLIENT", "NO-EVICT", "on"])?, Client::NoEvict(true));
+        assert_eq!(
+            parse(&["CLIENT", "NO-TOUCH", "OFF"])?,
+            Client::NoTouch(false)
+        );
+        assert!(parse(&["CLIENT", "NO-TOUCH", "maybe"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_tracking_from_resp_array() -> Result<()> {
+        assert_eq!(
+            parse(&["CLIENT", "TRACKING", "on"])?,
+            Client::Tracking(Some(TrackingOptions::default()))
+        );
+        assert_eq!(
+            parse(&[
+                "CLIENT", "TRACKING", "ON", "REDIRECT", "7", "BCAST", "PREFIX", "a:", "PREFIX",
+                "b:", "NOLOOP"
+            ])?,
+            Client::Tracking(Some(TrackingOptions {
+                redirect: Some(7),
+                bcast: true,
+                prefixes: vec!["a:".into(), "b:".into()],
+                noloop: true,
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            parse(&["CLIENT", "TRACKING", "off"])?,
+            Client::Tracking(None)
+        );
+        assert!(parse(&["CLIENT", "TRACKING", "on", "PREFIX"]).is_err());
+        assert!(parse(&["CLIENT", "TRACKING", "on", "REDIRECT", "0"]).is_err());
+        assert_eq!(parse(&["CLIENT", "CACHING", "yes"])?, Client::Caching(true));
+        assert!(parse(&["CLIENT", "CACHING", "maybe"]).is_err());
+        assert_eq!(parse(&["CLIENT", "GETREDIR"])?, Client::GetRedir);
+        assert_eq!(parse(&["CLIENT", "TRACKINGINFO"])?, Client::TrackingInfo);
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_tracking() -> Result<()> {
+        let backend = Backend::new();
+        let mut session = Session::new();
+        let error = |frame: RespFrame| match frame {
+            RespFrame::SimpleError(e) => e.0,
+            frame => panic!("expected an error, got {frame:?}"),
+        };
+        assert_eq!(
+            Client::GetRedir.execute(&mut session, &backend)?,
+            RespInteger::from(-1).into()
+        );
+        let reply = Client::Caching(true).execute(&mut session, &backend)?;
+        assert!(error(reply).starts_with("ERR CLIENT CACHING can be called only"));
+        let redirect = TrackingOptions {
+            redirect: Some(12345),
+            ..Default::default()
+        };
+        let reply = Client::Tracking(Some(redirect)).execute(&mut session, &backend)?;
+        assert_eq!(
+            error(reply),
+            "ERR The client ID you want redirect to does not exist"
+        );
+
+        let optin = TrackingOptions {
+            optin: true,
+            ..Default::default()
+        };
+        Client::Tracking(Some(optin)).execute(&mut session, &backend)?;
+        assert_eq!(
+            Client::GetRedir.execute(&mut session, &backend)?,
+            RespInteger::from(0).into()
+        );
+        Client::Caching(true).execute(&mut session, &backend)?;
+        let info = Client::TrackingInfo
+            .execute(&mut session, &backend)?
+            .downgrade()
+            .encode();
+        assert_eq!(
+            String::from_utf8_lossy(&info),
+            "*6\r\n$5\r\nflags\r\n*3\r\n$2\r\non\r\n$5\r\noptin\r\n$11\r\ncaching-yes\r\n\
+             $8\r\nredirect\r\n:+0\r\n$8\r\nprefixes\r\n*0\r\n"
+        );
+
+        Client::Tracking(None).execute(&mut session, &backend)?;
+        assert!(backend.tracking.is_empty());
+        assert_eq!(session.caching, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_name_and_info() -> Result<()> {
+        let backend = Backend::new();
+        let mut session = Session::new();
+        assert_eq!(
+            Client::GetName.execute(&mut session, &backend)?,
+            RespNullBulkString.into()
+        );
+
+        Client::SetName("myapp".into()).execute(&mut session, &backend)?;
+        assert!(Client::SetName("my app".into())
+            .execute(&mut session, &backend)
+            .is_err());
+
+        let RespFrame::VerbatimString(info) = Client::Info.execute(&mut session, &backend)? else {
+            panic!("client info should reply a verbatim string");
+        };
+        let info = String::from_utf8_lossy(info.data());
+        assert!(info.starts_with(&format!("id={} ", session.id)));
+        assert!(info.contains(" name=myapp "));
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_list_and_kill() -> Result<()> {
+        let backend = Backend::new();
+        let mut me = Session::new();
+        let mut other = Session::new();
+        other.addr = "127.0.0.1:5001".parse().ok();
+        backend.clients.register(&me);
+        backend.clients.register(&other);
+
+        Client::NoTouch(true).execute(&mut me, &backend)?;
+        let list = parse(&["CLIENT", "LIST"])?.execute(&mut me, &backend)?;
+        let RespFrame::VerbatimString(list) = list else {
+            panic!("client list should reply a verbatim string");
+        };
+        let list = String::from_utf8_lossy(list.data()).to_string();
+        let lines = list.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(" flags=T "));
+        assert!(lines[1].contains(" addr=127.0.0.1:5001 "));
+
+        let id = other.id.to_string();
+        let list = parse(&["CLIENT", "LIST", "ID", &id])?.execute(&mut me, &backend)?;
+        let RespFrame::VerbatimString(list) = list else {
+            panic!("client list should reply a verbatim string");
+        };
+        assert_eq!(String::from_utf8_lossy(list.data()).lines().count(), 1);
+
+        assert!(Client::KillAddr("127.0.0.1:9".into())
+            .execute(&mut me, &backend)
+            .is_err());
+        let killed = parse(&["CLIENT", "KILL", "USER", "default"])?.execute(&mut me, &backend)?;
+        assert_eq!(killed, RespInteger::from(1).into());
+        Ok(())
+    }
+}
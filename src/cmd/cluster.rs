@@ -0,0 +1,453 @@
+use std::fmt::Write;
+
+use crate::{
+    cluster::{self, key_hash_slot, ClusterError, ClusterInfo, ClusterState, CLUSTER_SLOTS},
+    Backend, RespArray, RespBulkString, RespFrame, RespInteger, RespMaps, RespVerbatimString,
+    SimpleError,
+};
+
+use super::{extract_cmd_args, integer_arg, string_arg, CommandError, CommandExecutor, RESP_OK};
+
+/// CLUSTER INFO | MYID | NODES | SLOTS | SHARDS | KEYSLOT key | COUNTKEYSINSLOT slot |
+/// GETKEYSINSLOT slot count | MEET ip port [cport] | ADDSLOTS slot [slot ...] |
+/// ADDSLOTSRANGE start end [start end ...] | DELSLOTS slot [slot ...] | DELSLOTSRANGE start end [start end ...] |
+/// SETSLOT slot IMPORTING node-id | MIGRATING node-id | NODE node-id | STABLE
+#[derive(Debug, PartialEq)]
+pub enum Cluster {
+    Info,
+    MyId,
+    Nodes,
+    Slots,
+    Shards,
+    KeySlot(String),
+    CountKeysInSlot(u16),
+    GetKeysInSlot(u16, usize),
+    Meet(String, u16, Option<u16>),
+    AddSlots(Vec<u16>),
+    DelSlots(Vec<u16>),
+    SetSlot(u16, SlotAction),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SlotAction {
+    Importing(String),
+    Migrating(String),
+    Node(String),
+    Stable,
+}
+
+impl CommandExecutor for Cluster {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let state = &backend.cluster;
+        if !state.is_enabled() {
+            return error(ClusterError::Disabled);
+        }
+        let ret = match self {
+            Cluster::Info => Ok(RespVerbatimString::text(info_text(&state.info())).into()),
+            Cluster::MyId => Ok(RespBulkString::from(state.myid()).into()),
+            Cluster::Nodes => Ok(RespVerbatimString::text(nodes_text(state)).into()),
+            Cluster::Slots => Ok(slots_reply(state)),
+            Cluster::Shards => Ok(shards_reply(state)),
+            Cluster::KeySlot(key) => {
+                Ok(RespInteger::from(key_hash_slot(key.as_bytes()) as i64).into())
+            }
+            Cluster::CountKeysInSlot(slot) => {
+                let count = cluster::keys_in_slot(backend, slot, usize::MAX).len();
+                Ok(RespInteger::from(count as i64).into())
+            }
+            Cluster::GetKeysInSlot(slot, count) => {
+                let keys = cluster::keys_in_slot(backend, slot, count)
+                    .into_iter()
+                    .map(|key| RespBulkString::from(key).into())
+                    .collect();
+                Ok(RespArray::new(keys).into())
+            }
+            Cluster::Meet(ip, port, bus_port) => {
+                let bus_port = bus_port.unwrap_or_else(|| port.wrapping_add(10000));
+                state.meet(&ip, port, bus_port).map(|_| RESP_OK.clone())
+            }
+            Cluster::AddSlots(slots) => state.add_slots(&slots).map(|_| RESP_OK.clone()),
+            Cluster::DelSlots(slots) => state.del_slots(&slots).map(|_| RESP_OK.clone()),
+            Cluster::SetSlot(slot, action) => match action {
+                SlotAction::Importing(id) => state.set_slot_importing(slot, &id),
+                SlotAction::Migrating(id) => state.set_slot_migrating(slot, &id),
+                SlotAction::Node(id) => state.set_slot_node(backend, slot, &id),
+                SlotAction::Stable => {
+                    state.set_slot_stable(slot);
+                    Ok(())
+                }
+            }
+            .map(|_| RESP_OK.clone()),
+        };
+        ret.unwrap_or_else(error)
+    }
+}
+
+fn error(e: ClusterError) -> RespFrame {
+    SimpleError::from(format!("ERR {e}")).into()
+}
+
+fn info_text(info: &ClusterInfo) -> String {
+    let mut ret = String::new();
+    let state = if info.state_ok { "ok" } else { "fail" };
+    let _ = write!(ret, "cluster_state:{state}\r\n");
+    let _ = write!(ret, "cluster_slots_assigned:{}\r\n", info.slots_assigned);
+    let _ = write!(ret, "cluster_slots_ok:{}\r\n", info.slots_ok);
+    let _ = write!(ret, "cluster_slots_pfail:{}\r\n", info.slots_pfail);
+    let _ = write!(ret, "cluster_slots_fail:{}\r\n", info.slots_fail);
+    let _ = write!(ret, "cluster_known_nodes:{}\r\n", info.known_nodes);
+    let _ = write!(ret, "cluster_size:{}\r\n", info.size);
+    let _ = write!(ret, "cluster_current_epoch:{}\r\n", info.current_epoch);
+    let _ = write!(ret, "cluster_my_epoch:{}\r\n", info.my_epoch);
+    ret
+}
+
+/// 每个节点一行：id ip:port@cport flags master ping-sent pong-recv config-epoch link-state slot...
+/// 本节点那一行的最后是正在迁出的[slot->-id]和正在迁入的[slot-<-id]
+fn nodes_text(state: &ClusterState) -> String {
+    let mut ret = String::new();
+    let (migrating, importing) = (state.migrating(), state.importing());
+    for node in state.nodes() {
+        let link = if node.link_connected() {
+            "connected"
+        } else {
+            "disconnected"
+        };
+        let _ = write!(
+            ret,
+            "{} {}@{} {} - {} {} {} {}",
+            node.id,
+            node.addr(),
+            node.bus_port,
+            node.flags.encode(),
+            node.ping_sent,
+            node.pong_received,
+            node.config_epoch,
+            link
+        );
+        for (start, end) in state.slot_ranges(&node.id) {
+            match start == end {
+                true => {
+                    let _ = write!(ret, " {start}");
+                }
+                false => {
+                    let _ = write!(ret, " {start}-{end}");
+                }
+            }
+        }
+        if node.flags.myself {
+            for (slot, id) in &migrating {
+                let _ = write!(ret, " [{slot}->-{id}]");
+            }
+            for (slot, id) in &importing {
+                let _ = write!(ret, " [{slot}-<-{id}]");
+            }
+        }
+        ret.push('\n');
+    }
+    ret
+}
+
+/// 每个slot区间一项：start end [ip port id {}]
+fn slots_reply(state: &ClusterState) -> RespFrame {
+    let mut ranges = Vec::new();
+    for node in state.nodes() {
+        for (start, end) in state.slot_ranges(&node.id) {
+            ranges.push((start, end, node.clone()));
+        }
+    }
+    ranges.sort_by_key(|(start, _, _)| *start);
+    let ranges = ranges
+        .into_iter()
+        .map(|(start, end, node)| {
+            let endpoint = RespArray::new(vec![
+                RespBulkString::from(node.ip).into(),
+                RespInteger::from(node.port as i64).into(),
+                RespBulkString::from(node.id).into(),
+                RespMaps::default().into(),
+            ]);
+            RespArray::new(vec![
+                RespInteger::from(start as i64).into(),
+                RespInteger::from(end as i64).into(),
+                endpoint.into(),
+            ])
+            .into()
+        })
+        .collect();
+    RespArray::new(ranges).into()
+}
+
+/// 每个master一项：{slots: [start end ...], nodes: [{id port ip endpoint role replication-offset health}]}
+fn shards_reply(state: &ClusterState) -> RespFrame {
+    let shards = state
+        .nodes()
+        .into_iter()
+        .filter(|node| !node.flags.handshake)
+        .map(|node| {
+            let slots = state
+                .slot_ranges(&node.id)
+                .into_iter()
+                .flat_map(|(start, end)| [start, end])
+                .map(|slot| RespInteger::from(slot as i64).into())
+                .collect();
+            let health = if node.flags.fail { "fail" } else { "online" };
+            let mut info = RespMaps::default();
+            info.insert(
+                RespBulkString::from("id").into(),
+                RespBulkString::from(node.id.clone()).into(),
+            );
+            info.insert(
+                RespBulkString::from("port").into(),
+                RespInteger::from(node.port as i64).into(),
+            );
+            info.insert(
+                RespBulkString::from("ip").into(),
+                RespBulkString::from(node.ip.clone()).into(),
+            );
+            info.insert(
+                RespBulkString::from("endpoint").into(),
+                RespBulkString::from(node.ip.clone()).into(),
+            );
+            info.insert(
+                RespBulkString::from("role").into(),
+                RespBulkString::from("master").into(),
+            );
+            info.insert(
+                RespBulkString::from("replication-offset").into(),
+                RespInteger::from(0).into(),
+            );
+            info.insert(
+                RespBulkString::from("health").into(),
+                RespBulkString::from(health).into(),
+            );
+
+            let mut shard = RespMaps::default();
+            shard.insert(
+                RespBulkString::from("slots").into(),
+                RespArray::new(slots).into(),
+            );
+            shard.insert(
+                RespBulkString::from("nodes").into(),
+                RespArray::new(vec![info.into()]).into(),
+            );
+            shard.into()
+        })
+        .collect();
+    RespArray::new(shards).into()
+}
+
+///*3\r\n$7\r\ncluster\r\n$7\r\nkeyslot\r\n$3\r\nfoo\r\n
+impl TryFrom<RespArray> for Cluster {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let subcommand = match args.next() {
+            Some(arg) => string_arg(arg)?.to_ascii_lowercase(),
+            None => {
+                return Err(CommandError::InvalidArgument(
+                    "cluster command should have a subcommand".into(),
+                ))
+            }
+        };
+        let args = args.collect::<Vec<_>>();
+        let arity_error = || {
+            CommandError::InvalidArgument(format!(
+                "wrong number of arguments for 'cluster|{subcommand}' command"
+            ))
+        };
+        let check_arity = |n: usize| {
+            if args.len() == n {
+                Ok(())
+            } else {
+                Err(arity_error())
+            }
+        };
+
+        let cmd = match subcommand.as_str() {
+            "info" => check_arity(0).map(|_| Cluster::Info)?,
+            "myid" => check_arity(0).map(|_| Cluster::MyId)?,
+            "nodes" => check_arity(0).map(|_| Cluster::Nodes)?,
+            "slots" => check_arity(0).map(|_| Cluster::Slots)?,
+            "shards" => check_arity(0).map(|_| Cluster::Shards)?,
+            "keyslot" => {
+                check_arity(1)?;
+                let mut args = args.into_iter();
+                Cluster::KeySlot(string_arg(args.next().ok_or_else(arity_error)?)?)
+            }
+            "countkeysinslot" => {
+                check_arity(1)?;
+                let mut args = args.into_iter();
+                Cluster::CountKeysInSlot(slot_arg(args.next().ok_or_else(arity_error)?)?)
+            }
+            "getkeysinslot" => {
+                check_arity(2)?;
+                let mut args = args.into_iter();
+                let slot = slot_arg(args.next().ok_or_else(arity_error)?)?;
+                let count = usize::try_from(integer_arg(args.next().ok_or_else(arity_error)?)?)
+                    .map_err(|_| CommandError::InvalidArgument("Invalid number of keys".into()))?;
+                Cluster::GetKeysInSlot(slot, count)
+            }
+            "meet" => {
+                if !(2..=3).contains(&args.len()) {
+                    return Err(arity_error());
+                }
+                let mut args = args.into_iter();
+                let ip = string_arg(args.next().ok_or_else(arity_error)?)?;
+                let mut ports = args.map(|arg| {
+                    integer_arg(arg)
+                        .ok()
+                        .and_then(|port| u16::try_from(port).ok())
+                        .ok_or_else(|| {
+                            CommandError::InvalidArgument("Invalid base port specified".into())
+                        })
+                });
+                let port = ports.next().ok_or_else(arity_error)??;
+                let bus_port = ports.next().transpose()?;
+                Cluster::Meet(ip, port, bus_port)
+            }
+            "addslots" | "delslots" => {
+                if args.is_empty() {
+                    return Err(arity_error());
+                }
+                let slots = args.into_iter().map(slot_arg).collect::<Result<_, _>>()?;
+                match subcommand.as_str() {
+                    "addslots" => Cluster::AddSlots(slots),
+                    _ => Cluster::DelSlots(slots),
+                }
+            }
+            "addslotsrange" | "delslotsrange" => {
+                if args.is_empty() || !args.len().is_multiple_of(2) {
+                    return Err(arity_error());
+                }
+                let bounds = args
+                    .into_iter()
+                    .map(slot_arg)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut slots = Vec::new();
+                for pair in bounds.chunks_exact(2) {
+                    if pair[0] > pair[1] {
+                        return Err(CommandError::InvalidArgument(format!(
+                            "start slot number {} is greater than end slot number {}",
+                            pair[0], pair[1]
+                        )));
+                    }
+                    slots.extend(pair[0]..=pair[1]);
+                }
+                match subcommand.as_str() {
+                    "addslotsrange" => Cluster::AddSlots(slots),
+                    _ => Cluster::DelSlots(slots),
+                }
+            }
+            "setslot" => {
+                if !(2..=3).contains(&args.len()) {
+                    return Err(arity_error());
+                }
+                let mut args = args.into_iter();
+                let slot = slot_arg(args.next().ok_or_else(arity_error)?)?;
+                let action = string_arg(args.next().ok_or_else(arity_error)?)?.to_ascii_lowercase();
+                let id = args.next().map(string_arg).transpose()?;
+                let action = match (action.as_str(), id) {
+                    ("importing", Some(id)) => SlotAction::Importing(id),
+                    ("migrating", Some(id)) => SlotAction::Migrating(id),
+                    ("node", Some(id)) => SlotAction::Node(id),
+                    ("stable", None) => SlotAction::Stable,
+                    _ => return Err(CommandError::InvalidArgument(
+                        "Invalid CLUSTER SETSLOT action or number of arguments. Try CLUSTER HELP"
+                            .into(),
+                    )),
+                };
+                Cluster::SetSlot(slot, action)
+            }
+            _ => {
+                return Err(CommandError::InvalidArgument(format!(
+                    "unknown subcommand '{subcommand}'"
+                )))
+            }
+        };
+        Ok(cmd)
+    }
+}
+
+fn slot_arg(frame: RespFrame) -> Result<u16, CommandError> {
+    integer_arg(frame)
+        .ok()
+        .and_then(|slot| u16::try_from(slot).ok())
+        .filter(|&slot| (slot as usize) < CLUSTER_SLOTS)
+        .ok_or_else(|| CommandError::InvalidArgument(ClusterError::InvalidSlot.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aof::command_array;
+
+    #[test]
+    fn test_cluster_from_resp_array() {
+        let cmd = Cluster::try_from(command_array(["CLUSTER", "KEYSLOT", "foo"])).unwrap();
+        assert_eq!(cmd, Cluster::KeySlot("foo".into()));
+        let cmd = Cluster::try_from(command_array([
+            "cluster",
+            "addslotsrange",
+            "0",
+            "2",
+            "5",
+            "5",
+        ]))
+        .unwrap();
+        assert_eq!(cmd, Cluster::AddSlots(vec![0, 1, 2, 5]));
+        let cmd =
+            Cluster::try_from(command_array(["cluster", "meet", "127.0.0.1", "7001"])).unwrap();
+        assert_eq!(cmd, Cluster::Meet("127.0.0.1".into(), 7001, None));
+        let cmd = Cluster::try_from(command_array([
+            "cluster",
+            "setslot",
+            "5",
+            "IMPORTING",
+            "n1",
+        ]))
+        .unwrap();
+        assert_eq!(cmd, Cluster::SetSlot(5, SlotAction::Importing("n1".into())));
+        let cmd = Cluster::try_from(command_array(["cluster", "setslot", "5", "stable"])).unwrap();
+        assert_eq!(cmd, Cluster::SetSlot(5, SlotAction::Stable));
+        assert!(Cluster::try_from(command_array(["cluster", "setslot", "5", "node"])).is_err());
+        assert!(Cluster::try_from(command_array(["cluster", "countkeysinslot", "16384"])).is_err());
+        assert!(Cluster::try_from(command_array(["cluster", "addslotsrange", "5", "1"])).is_err());
+        assert!(Cluster::try_from(command_array(["cluster", "foo"])).is_err());
+    }
+
+    #[test]
+    fn test_cluster_execute() {
+        let backend = Backend::new();
+        let reply = Cluster::KeySlot("foo".into()).execute(&backend);
+        assert!(matches!(reply, RespFrame::SimpleError(_)));
+
+        backend.cluster.init(7000, 17000);
+        let reply = Cluster::KeySlot("foo".into()).execute(&backend);
+        assert_eq!(reply, RespInteger::from(12182).into());
+        Cluster::AddSlots((0..CLUSTER_SLOTS as u16).collect()).execute(&backend);
+        backend.set("foo".into(), RespBulkString::from("1").into());
+        backend.set("{foo}bar".into(), RespBulkString::from("2").into());
+        let reply = Cluster::CountKeysInSlot(12182).execute(&backend);
+        assert_eq!(reply, RespInteger::from(2).into());
+        let reply = Cluster::GetKeysInSlot(12182, 1).execute(&backend);
+        assert_eq!(
+            reply,
+            RespArray::new(vec![RespBulkString::from("foo").into()]).into()
+        );
+
+        let RespFrame::VerbatimString(text) = Cluster::Info.execute(&backend) else {
+            panic!("CLUSTER INFO should reply a verbatim string");
+        };
+        assert!(String::from_utf8_lossy(text.data()).contains("cluster_state:ok"));
+        let RespFrame::VerbatimString(text) = Cluster::Nodes.execute(&backend) else {
+            panic!("CLUSTER NODES should reply a verbatim string");
+        };
+        let nodes = String::from_utf8_lossy(text.data()).into_owned();
+        assert!(nodes.ends_with("myself,master - 0 0 0 connected 0-16383\n"));
+        let RespFrame::Arrays(slots) = Cluster::Slots.execute(&backend) else {
+            panic!("CLUSTER SLOTS should reply an array");
+        };
+        assert_eq!(slots.len(), 1);
+    }
+}
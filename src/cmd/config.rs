@@ -0,0 +1,133 @@
+use crate::{config, Backend, RespArray, RespBulkString, RespFrame, RespMaps, SimpleError};
+
+use super::{extract_cmd_args, string_arg, CommandError, CommandExecutor, RESP_OK};
+
+/// CONFIG GET parameter [parameter ...] | SET parameter value [parameter value ...] |
+/// RESETSTAT | REWRITE
+#[derive(Debug, PartialEq)]
+pub enum Config {
+    Get(Vec<String>),
+    Set(Vec<(String, String)>),
+    ResetStat,
+    Rewrite,
+}
+
+impl CommandExecutor for Config {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let ret = match self {
+            Config::Get(patterns) => {
+                //RESP2下会被降级成name value交替的数组
+                let mut map = RespMaps::default();
+                for (name, value) in config::get(backend, &patterns) {
+                    map.insert(
+                        RespBulkString::from(name).into(),
+                        RespBulkString::from(value).into(),
+                    );
+                }
+                Ok(map.into())
+            }
+            Config::Set(params) => config::set(backend, &params).map(|_| RESP_OK.clone()),
+            Config::ResetStat => {
+                config::reset_stats(backend);
+                Ok(RESP_OK.clone())
+            }
+            Config::Rewrite => config::rewrite(backend).map(|_| RESP_OK.clone()),
+        };
+        ret.unwrap_or_else(|e| SimpleError::from(format!("ERR {e}")).into())
+    }
+}
+
+impl TryFrom<RespArray> for Config {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let Some(sub) = args.next() else {
+            return Err(CommandError::InvalidArgument(
+                "config command should have a subcommand".into(),
+            ));
+        };
+        let sub = string_arg(sub)?.to_ascii_lowercase();
+        let args = args.map(string_arg).collect::<Result<Vec<_>, _>>()?;
+        let wrong_args = || {
+            CommandError::InvalidArgument(format!(
+                "wrong number of arguments for 'config|{sub}' command"
+            ))
+        };
+        match sub.as_str() {
+            "get" if !args.is_empty() => Ok(Config::Get(args)),
+            "set" if !args.is_empty() && args.len().is_multiple_of(2) => {
+                let params = args
+                    .chunks(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect();
+                Ok(Config::Set(params))
+            }
+            "resetstat" if args.is_empty() => Ok(Config::ResetStat),
+            "rewrite" if args.is_empty() => Ok(Config::Rewrite),
+            "get" | "set" | "resetstat" | "rewrite" => Err(wrong_args()),
+            _ => Err(CommandError::InvalidCommand(format!(
+                "unknown subcommand '{sub}'. Try CONFIG HELP."
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aof::command_array, EncodeResp, RespProtocol};
+
+    fn config(args: &[&str], backend: &Backend) -> RespFrame {
+        Config::try_from(command_array(args.iter().copied()))
+            .unwrap()
+            .execute(backend)
+    }
+
+    #[test]
+    fn test_config_from_resp_array() {
+        let cmd = Config::try_from(command_array(["CONFIG", "set", "a", "1", "b", "2"])).unwrap();
+        assert_eq!(
+            cmd,
+            Config::Set(vec![("a".into(), "1".into()), ("b".into(), "2".into())])
+        );
+        assert!(Config::try_from(command_array(["CONFIG", "set", "a"])).is_err());
+        assert!(Config::try_from(command_array(["CONFIG", "get"])).is_err());
+        assert!(Config::try_from(command_array(["CONFIG", "rewrite", "x"])).is_err());
+        assert!(Config::try_from(command_array(["CONFIG", "load"])).is_err());
+    }
+
+    #[test]
+    fn test_config_execute() {
+        let backend = Backend::new();
+        let reply = config(
+            &["CONFIG", "SET", "maxmemory-policy", "allkeys-lru"],
+            &backend,
+        );
+        assert_eq!(reply, RESP_OK.clone());
+
+        let reply = config(&["CONFIG", "GET", "maxmemory-p*"], &backend);
+        assert_eq!(
+            reply.with_protocol(RespProtocol::Resp2).encode(),
+            b"*2\r\n$16\r\nmaxmemory-policy\r\n$11\r\nallkeys-lru\r\n"
+        );
+
+        let reply = config(&["CONFIG", "SET", "maxmemory", "lots"], &backend);
+        assert_eq!(
+            reply,
+            SimpleError::from(
+                "ERR CONFIG SET failed (possibly related to argument 'maxmemory') - argument must be a memory value"
+            )
+            .into()
+        );
+
+        backend.get("missing");
+        assert_eq!(config(&["CONFIG", "RESETSTAT"], &backend), RESP_OK.clone());
+        assert_eq!(backend.stats.keyspace_misses(), 0);
+        let reply = config(&["CONFIG", "REWRITE"], &backend);
+        assert_eq!(
+            reply,
+            SimpleError::from("ERR The server is running without a config file").into()
+        );
+    }
+}
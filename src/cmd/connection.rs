@@ -0,0 +1,41 @@
+use crate::{Backend, RespArray, RespFrame, SimpleString};
+
+use super::{extract_cmd_args, CommandError, CommandExecutor, Ping};
+
+impl CommandExecutor for Ping {
+    fn execute(self, _backend: &Backend) -> RespFrame {
+        match self.message {
+            Some(message) => message,
+            None => SimpleString::from("PONG").into(),
+        }
+    }
+}
+
+impl TryFrom<RespArray> for Ping {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        if value.len() > 2 {
+            return Err(CommandError::InvalidArgument(
+                "ping command should have at most 1 argument(s)!".into(),
+            ));
+        }
+        let message = extract_cmd_args(value, 1)?.into_iter().next();
+        Ok(Ping::new(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aof::command_array, RespBulkString};
+
+    #[test]
+    fn test_ping_execute() {
+        let backend = Backend::new();
+        let ping = Ping::try_from(command_array(["PING"])).unwrap();
+        assert_eq!(ping.execute(&backend), SimpleString::from("PONG").into());
+        let ping = Ping::try_from(command_array(["ping", "hi"])).unwrap();
+        assert_eq!(ping.execute(&backend), RespBulkString::from("hi").into());
+    }
+}
@@ -0,0 +1,252 @@
+use crate::{
+    backend::now_ms,
+    rdb::{self, RdbError},
+    Backend, RespArray, RespBulkString, RespFrame, RespNull, SimpleError,
+};
+
+use super::{
+    extract_cmd_args, integer_arg, string_arg, validate_command, CommandError, CommandExecutor,
+    RESP_OK,
+};
+
+/// DUMP key
+#[derive(Debug, PartialEq)]
+pub struct Dump {
+    pub key: String,
+}
+
+/// RESTORE key ttl payload [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]，
+/// RESTORE-ASKING是MIGRATE在cluster模式下发给目标节点的版本
+#[derive(Debug, PartialEq)]
+pub struct Restore {
+    pub key: String,
+    /// 毫秒，0表示不过期
+    pub ttl: u64,
+    pub payload: Vec<u8>,
+    pub replace: bool,
+    /// ttl是unix毫秒时间戳而不是剩余时间
+    pub absttl: bool,
+    /// 秒
+    pub idletime: Option<u64>,
+    pub freq: Option<u8>,
+    /// 相当于在这条命令之前发送了ASKING
+    pub asking: bool,
+}
+
+impl CommandExecutor for Dump {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match backend.value(&self.key) {
+            Some((value, _)) => RespBulkString::new(rdb::dump_payload(&value)).into(),
+            None => RespFrame::Null(RespNull),
+        }
+    }
+}
+
+impl CommandExecutor for Restore {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if !self.replace && backend.exists(&self.key) {
+            return SimpleError::from("BUSYKEY Target key name already exists.").into();
+        }
+        let value = match rdb::parse_payload(&self.payload) {
+            Ok(value) => value,
+            Err(RdbError::UnsupportedVersion(_) | RdbError::Checksum { .. }) => {
+                return SimpleError::from("ERR DUMP payload version or checksum are wrong").into()
+            }
+            Err(_) => return SimpleError::from("ERR Bad data format").into(),
+        };
+        let expire_at = match (self.ttl, self.absttl) {
+            (0, _) => None,
+            (ttl, true) => Some(ttl),
+            (ttl, false) => Some(now_ms().saturating_add(ttl)),
+        };
+        //已经过期的key不会被创建，REPLACE时原来的key仍然被删除
+        if expire_at.is_some_and(|when| when <= now_ms()) {
+            backend.del(&self.key);
+            return RESP_OK.clone();
+        }
+        backend.restore(self.key.clone(), value, expire_at);
+        if let Some(idletime) = self.idletime {
+            backend.set_idle(&self.key, idletime);
+        }
+        if let Some(freq) = self.freq {
+            backend.set_freq(&self.key, freq);
+        }
+        RESP_OK.clone()
+    }
+}
+
+impl TryFrom<RespArray> for Restore {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let asking = super::command_name(&value)? == "restore-asking";
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let (Some(key), Some(ttl), Some(payload)) = (args.next(), args.next(), args.next()) else {
+            return Err(CommandError::InvalidArgument(
+                "restore command should have at least 3 argument(s)!".into(),
+            ));
+        };
+        let ttl = u64::try_from(integer_arg(ttl)?)
+            .map_err(|_| CommandError::InvalidArgument("Invalid TTL value, must be >= 0".into()))?;
+        let RespFrame::BulkString(payload) = payload else {
+            return Err(CommandError::InvalidArgument(
+                "argument should be a BulkString".into(),
+            ));
+        };
+        let mut cmd = Restore {
+            key: string_arg(key)?,
+            ttl,
+            payload: payload.0,
+            replace: false,
+            absttl: false,
+            idletime: None,
+            freq: None,
+            asking,
+        };
+        let syntax_error = || CommandError::InvalidArgument("syntax error".into());
+        while let Some(arg) = args.next() {
+            match string_arg(arg)?.to_ascii_lowercase().as_str() {
+                "replace" => cmd.replace = true,
+                "absttl" => cmd.absttl = true,
+                "idletime" if cmd.freq.is_none() => {
+                    let idletime = integer_arg(args.next().ok_or_else(syntax_error)?)?;
+                    cmd.idletime = Some(u64::try_from(idletime).map_err(|_| {
+                        CommandError::InvalidArgument("Invalid IDLETIME value, must be >= 0".into())
+                    })?);
+                }
+                "freq" if cmd.idletime.is_none() => {
+                    let freq = integer_arg(args.next().ok_or_else(syntax_error)?)?;
+                    cmd.freq = Some(u8::try_from(freq).map_err(|_| {
+                        CommandError::InvalidArgument(
+                            "Invalid FREQ value, must be >= 0 and <= 255".into(),
+                        )
+                    })?);
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(cmd)
+    }
+}
+
+impl TryFrom<RespArray> for Dump {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "dump", 1)?;
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        Ok(Dump {
+            key: string_arg(args.next().expect("validated"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aof::command_array, RespBulkString, Value};
+
+    fn restore(args: &[&str], payload: &[u8]) -> Result<Restore, CommandError> {
+        let mut array = command_array(args.iter().copied());
+        array
+            .0
+            .insert(3, RespBulkString::new(payload.to_vec()).into());
+        Restore::try_from(array)
+    }
+
+    #[test]
+    fn test_restore_from_resp_array() {
+        let cmd = restore(&["RESTORE-ASKING", "k", "100", "replace"], b"\x00").unwrap();
+        assert_eq!(
+            cmd,
+            Restore {
+                key: "k".into(),
+                ttl: 100,
+                payload: b"\x00".to_vec(),
+                replace: true,
+                absttl: false,
+                idletime: None,
+                freq: None,
+                asking: true,
+            }
+        );
+        let cmd = restore(&["RESTORE", "k", "0", "ABSTTL", "FREQ", "5"], b"").unwrap();
+        assert!(cmd.absttl);
+        assert_eq!(cmd.freq, Some(5));
+        assert!(restore(&["RESTORE", "k", "-1"], b"").is_err());
+        assert!(restore(&["RESTORE", "k", "0", "absent"], b"").is_err());
+        assert!(restore(&["RESTORE", "k", "0", "FREQ", "256"], b"").is_err());
+        assert!(restore(&["RESTORE", "k", "0", "IDLETIME", "1", "FREQ", "1"], b"").is_err());
+    }
+
+    #[test]
+    fn test_restore_execute() {
+        let backend = Backend::new();
+        let value = Value::List(vec![RespBulkString::from("a").into()]);
+        let payload = rdb::dump_payload(&value);
+
+        let reply = restore(&["RESTORE", "l", "0"], &payload)
+            .unwrap()
+            .execute(&backend);
+        assert_eq!(reply, RESP_OK.clone());
+        assert_eq!(backend.value("l"), Some((value, None)));
+
+        let reply = restore(&["RESTORE", "l", "0"], &payload)
+            .unwrap()
+            .execute(&backend);
+        assert!(matches!(reply, RespFrame::SimpleError(e) if e.starts_with("BUSYKEY")));
+        restore(&["RESTORE", "l", "10000", "REPLACE"], &payload)
+            .unwrap()
+            .execute(&backend);
+        assert!(backend.pttl("l") > 9000);
+
+        //ABSTTL：时间戳已经过去的key不会被创建
+        let past = (now_ms() - 1000).to_string();
+        let reply = restore(&["RESTORE", "l", &past, "REPLACE", "ABSTTL"], &payload)
+            .unwrap()
+            .execute(&backend);
+        assert_eq!(reply, RESP_OK.clone());
+        assert!(!backend.exists("l"));
+
+        let mut corrupted = payload.clone();
+        corrupted[0] = 2;
+        let reply = restore(&["RESTORE", "s", "0"], &corrupted)
+            .unwrap()
+            .execute(&backend);
+        assert_eq!(
+            reply,
+            SimpleError::from("ERR DUMP payload version or checksum are wrong").into()
+        );
+    }
+
+    #[test]
+    fn test_dump_restore_all_types() {
+        let backend = Backend::new();
+        let frame = |s: &'static str| RespFrame::from(RespBulkString::from(s));
+        let values = [
+            Value::String(frame("12345")),
+            Value::Hash(vec![("f".into(), frame("v"))]),
+            Value::List(vec![frame("a"), frame("-7")]),
+            Value::Set(vec![frame("m")]),
+            Value::ZSet(vec![(frame("z"), 2.5)]),
+        ];
+        for (i, value) in values.into_iter().enumerate() {
+            let key = format!("k{i}");
+            backend.restore(key.clone(), value.clone(), None);
+            let RespFrame::BulkString(payload) = (Dump { key: key.clone() }).execute(&backend)
+            else {
+                panic!("DUMP should reply a bulk string");
+            };
+            backend.del(&key);
+            restore(&["RESTORE", &key, "0"], payload.as_ref())
+                .unwrap()
+                .execute(&backend);
+            assert_eq!(backend.value(&key), Some((value, None)));
+        }
+        let reply = Dump {
+            key: "missing".into(),
+        }
+        .execute(&backend);
+        assert_eq!(reply, RespFrame::Null(RespNull));
+    }
+}
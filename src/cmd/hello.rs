@@ -0,0 +1,236 @@
+use crate::{
+    network::Session, Backend, RespArray, RespBulkString, RespFrame, RespInteger, RespMaps,
+    RespProtocol, SimpleError, SimpleString,
+};
+
+use super::{
+    auth::authenticate, extract_cmd_args, string_arg, CommandError, SERVER_NAME, SERVER_VERSION,
+};
+
+/// HELLO [protover [AUTH username password] [SETNAME clientname]]
+#[derive(Debug, PartialEq)]
+pub struct Hello {
+    pub protover: Option<i64>,
+    pub auth: Option<(String, String)>,
+    pub setname: Option<String>,
+}
+
+impl Hello {
+    /// HELLO需要修改连接状态，所以不走CommandExecutor，而是由network层直接调用
+    pub fn execute(
+        self,
+        session: &mut Session,
+        backend: &Backend,
+    ) -> Result<RespFrame, CommandError> {
+        let protocol = match self.protover {
+            Some(version) => {
+                Some(RespProtocol::try_from(version).map_err(|_| CommandError::NoProto)?)
+            }
+            None => None,
+        };
+
+        if let Some((username, password)) = self.auth {
+            authenticate(session, backend, username, &password)?;
+        }
+        //没有认证的连接只能用HELLO AUTH认证
+        if !session.authenticated && !backend.acl.no_auth_required() {
+            return Ok(SimpleError::from("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time").into());
+        }
+
+        if let Some(name) = &self.setname {
+            validate_client_name(name)?;
+        }
+
+        if let Some(protocol) = protocol {
+            session.protocol = protocol;
+        }
+        if let Some(name) = self.setname {
+            session.name = Some(name);
+        }
+
+        Ok(hello_reply(session))
+    }
+}
+
+pub fn validate_client_name(name: &str) -> Result<(), CommandError> {
+    if name.bytes().any(|b| !(b'!'..=b'~').contains(&b)) {
+        return Err(CommandError::InvalidArgument(
+            "Client names cannot contain spaces, newlines or special characters.".into(),
+        ));
+    }
+    Ok(())
+}
+
+fn hello_reply(session: &Session) -> RespFrame {
+    let mut map = RespMaps::default();
+    map.insert(
+        RespBulkString::from("server").into(),
+        SimpleString::from(SERVER_NAME).into(),
+    );
+    map.insert(
+        RespBulkString::from("version").into(),
+        SimpleString::from(SERVER_VERSION).into(),
+    );
+    map.insert(
+        RespBulkString::from("proto").into(),
+        RespInteger::from(session.protocol.version()).into(),
+    );
+    map.insert(
+        RespBulkString::from("id").into(),
+        RespInteger::from(session.id as i64).into(),
+    );
+    map.insert(
+        RespBulkString::from("mode").into(),
+        SimpleString::from("standalone").into(),
+    );
+    map.insert(
+        RespBulkString::from("role").into(),
+        SimpleString::from("master").into(),
+    );
+    map.insert(
+        RespBulkString::from("modules").into(),
+        RespArray::new(vec![]).into(),
+    );
+    map.into()
+}
+
+///*4\r\n$5\r\nhello\r\n$1\r\n3\r\n$7\r\nsetname\r\n$5\r\nmyapp\r\n
+impl TryFrom<RespArray> for Hello {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let mut hello = Hello {
+            protover: None,
+            auth: None,
+            setname: None,
+        };
+
+        let Some(protover) = args.next() else {
+            return Ok(hello);
+        };
+        let protover = string_arg(protover)?;
+        hello.protover = Some(protover.parse().map_err(|_| {
+            CommandError::InvalidArgument(
+                "Protocol version is not an integer or out of range".into(),
+            )
+        })?);
+
+        while let Some(option) = args.next() {
+            let option = string_arg(option)?;
+            match option.to_ascii_lowercase().as_str() {
+                "auth" => match (args.next(), args.next()) {
+                    (Some(username), Some(password)) => {
+                        hello.auth = Some((string_arg(username)?, string_arg(password)?));
+                    }
+                    _ => return Err(syntax_error(&option)),
+                },
+                "setname" => match args.next() {
+                    Some(name) => hello.setname = Some(string_arg(name)?),
+                    None => return Err(syntax_error(&option)),
+                },
+                _ => return Err(syntax_error(&option)),
+            }
+        }
+
+        Ok(hello)
+    }
+}
+
+fn syntax_error(option: &str) -> CommandError {
+    CommandError::InvalidArgument(format!("Syntax error in HELLO option '{option}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DecodeResp;
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    #[test]
+    fn test_hello_from_resp_array() -> Result<()> {
+        let mut bytes_mut = BytesMut::from(
+            &b"*7\r\n$5\r\nhello\r\n$1\r\n3\r\n$4\r\nAUTH\r\n$7\r\ndefault\r\n$3\r\npwd\r\n$7\r\nsetname\r\n$5\r\nmyapp\r\n"[..],
+        );
+        let frame = RespArray::decode(&mut bytes_mut)?;
+        let hello = Hello::try_from(frame)?;
+
+        assert_eq!(
+            hello,
+            Hello {
+                protover: Some(3),
+                auth: Some(("default".into(), "pwd".into())),
+                setname: Some("myapp".into()),
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_hello_switches_protocol() -> Result<()> {
+        let backend = Backend::new();
+        let mut session = Session::new();
+        let hello = Hello {
+            protover: Some(3),
+            auth: None,
+            setname: Some("myapp".into()),
+        };
+        let reply = hello.execute(&mut session, &backend)?;
+
+        assert_eq!(session.protocol, RespProtocol::Resp3);
+        assert_eq!(session.name.as_deref(), Some("myapp"));
+        let RespFrame::Maps(map) = reply else {
+            panic!("hello should reply a map");
+        };
+        assert_eq!(
+            map.get(&RespFrame::from(RespBulkString::from("proto"))),
+            Some(&RespInteger::from(3).into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_hello_rejects_bad_request() {
+        let backend = Backend::new();
+        let mut session = Session::new();
+        let hello = Hello {
+            protover: Some(4),
+            auth: None,
+            setname: None,
+        };
+        assert!(matches!(
+            hello.execute(&mut session, &backend),
+            Err(CommandError::NoProto)
+        ));
+
+        let hello = Hello {
+            protover: Some(3),
+            auth: Some(("admin".into(), "pwd".into())),
+            setname: None,
+        };
+        assert!(matches!(
+            hello.execute(&mut session, &backend),
+            Err(CommandError::WrongPass)
+        ));
+        assert_eq!(session.protocol, RespProtocol::Resp2);
+
+        //需要认证时只能用HELLO AUTH
+        backend.acl.set_requirepass("secret");
+        let hello = Hello {
+            protover: Some(3),
+            auth: None,
+            setname: None,
+        };
+        let reply = hello.execute(&mut session, &backend).unwrap();
+        assert!(matches!(reply, RespFrame::SimpleError(e) if e.starts_with("NOAUTH")));
+        let hello = Hello {
+            protover: Some(3),
+            auth: Some(("default".into(), "secret".into())),
+            setname: None,
+        };
+        assert!(hello.execute(&mut session, &backend).is_ok());
+        assert!(session.authenticated);
+        assert_eq!(session.protocol, RespProtocol::Resp3);
+    }
+}
@@ -1,10 +1,42 @@
 use crate::{
     cmd::{extract_cmd_args, validate_command},
-    RespArray,
+    Backend, RespArray, RespBulkString, RespFrame,
     RespFrame::BulkString,
+    RespInteger, RespMaps, RespNull,
 };
 
-use super::{CommandError, HGet, HGetAll, HSet};
+use super::{CommandError, CommandExecutor, HGet, HGetAll, HSet};
+
+impl CommandExecutor for HGet {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match backend.hget(&self.table_name, &self.key) {
+            Some(resp_frame) => resp_frame,
+            None => RespFrame::Null(RespNull),
+        }
+    }
+}
+
+impl CommandExecutor for HGetAll {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let mut map = RespMaps::default();
+        if let Some(table) = backend.hgetall(&self.table_name) {
+            for entry in table.iter() {
+                map.insert(
+                    RespBulkString::from(entry.key().clone()).into(),
+                    entry.value().clone(),
+                );
+            }
+        }
+        map.into()
+    }
+}
+
+impl CommandExecutor for HSet {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let created = backend.hset(self.table_name, self.key, self.value);
+        RespInteger::from(created as i64).into()
+    }
+}
 
 impl TryFrom<RespArray> for HGet {
     type Error = CommandError;
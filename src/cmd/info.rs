@@ -0,0 +1,422 @@
+use std::fmt::Write;
+
+use crate::{rdb, replication::Role, stats, Backend, RespArray, RespFrame, RespVerbatimString};
+
+use super::{extract_cmd_args, CommandError, CommandExecutor, SERVER_VERSION};
+
+/// INFO [section [section ...]]
+#[derive(Debug, PartialEq)]
+pub struct Info {
+    pub sections: Vec<String>,
+}
+
+/// 不带参数或者default时输出的section，commandstats和latencystats内容多，需要显式指定
+const DEFAULT_SECTIONS: [&str; 10] = [
+    "server",
+    "clients",
+    "memory",
+    "persistence",
+    "stats",
+    "replication",
+    "cpu",
+    "errorstats",
+    "cluster",
+    "keyspace",
+];
+
+type SectionFn = fn(&Backend) -> String;
+
+/// 所有section，按输出顺序排列
+const SECTIONS: [(&str, SectionFn); 12] = [
+    ("server", server_section),
+    ("clients", clients_section),
+    ("memory", memory_section),
+    ("persistence", persistence_section),
+    ("stats", stats_section),
+    ("replication", replication_section),
+    ("cpu", cpu_section),
+    ("commandstats", commandstats_section),
+    ("errorstats", errorstats_section),
+    ("latencystats", latencystats_section),
+    ("cluster", cluster_section),
+    ("keyspace", keyspace_section),
+];
+
+/// latencystats输出的百分位
+const LATENCY_PERCENTILES: [f64; 3] = [50.0, 99.0, 99.9];
+
+impl CommandExecutor for Info {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let all = self
+            .sections
+            .iter()
+            .any(|s| s == "all" || s == "everything");
+        let default = self.sections.is_empty() || self.sections.iter().any(|s| s == "default");
+        let wanted = |section: &str| {
+            all || (default && DEFAULT_SECTIONS.contains(&section))
+                || self.sections.iter().any(|s| s == section)
+        };
+
+        let mut sections = Vec::new();
+        for (name, generate) in SECTIONS {
+            if wanted(name) {
+                sections.push(generate(backend));
+            }
+        }
+
+        //RESP3下回复txt格式的verbatim string，RESP2会被降级为BulkString
+        RespVerbatimString::text(sections.join("\r\n")).into()
+    }
+}
+
+fn server_section(backend: &Backend) -> String {
+    let mut ret = String::from("# Server\r\n");
+    let _ = write!(ret, "redis_version:{SERVER_VERSION}\r\n");
+    let mode = if backend.cluster.is_enabled() {
+        "cluster"
+    } else {
+        "standalone"
+    };
+    let _ = write!(ret, "redis_mode:{mode}\r\n");
+    let _ = write!(ret, "arch_bits:{}\r\n", usize::BITS);
+    let _ = write!(ret, "process_id:{}\r\n", std::process::id());
+    let uptime = backend.stats.uptime().as_secs();
+    let _ = write!(ret, "uptime_in_seconds:{uptime}\r\n");
+    let _ = write!(ret, "uptime_in_days:{}\r\n", uptime / 86400);
+    ret
+}
+
+fn clients_section(backend: &Backend) -> String {
+    let stats = &backend.stats;
+    let mut ret = String::from("# Clients\r\n");
+    let _ = write!(ret, "connected_clients:{}\r\n", stats.connected_clients());
+    let _ = write!(ret, "maxclients:{}\r\n", stats.max_clients());
+    let _ = write!(ret, "blocked_clients:{}\r\n", stats.blocked_clients());
+    let _ = write!(ret, "tracking_clients:{}\r\n", backend.tracking.len());
+    ret
+}
+
+fn memory_section(backend: &Backend) -> String {
+    let mut ret = String::from("# Memory\r\n");
+    let config = backend.eviction.config();
+    let _ = write!(ret, "used_memory:{}\r\n", backend.used_memory());
+    let _ = write!(ret, "used_memory_peak:{}\r\n", backend.used_memory_peak());
+    let _ = write!(ret, "maxmemory:{}\r\n", config.maxmemory);
+    let _ = write!(ret, "maxmemory_policy:{}\r\n", config.policy.as_str());
+    ret
+}
+
+fn stats_section(backend: &Backend) -> String {
+    let stats = &backend.stats;
+    let mut ret = String::from("# Stats\r\n");
+    let _ = write!(
+        ret,
+        "total_connections_received:{}\r\n",
+        stats.total_connections()
+    );
+    let _ = write!(
+        ret,
+        "total_commands_processed:{}\r\n",
+        stats.total_commands()
+    );
+    let _ = write!(
+        ret,
+        "instantaneous_ops_per_sec:{}\r\n",
+        stats.instantaneous_ops_per_sec()
+    );
+    let _ = write!(
+        ret,
+        "rejected_connections:{}\r\n",
+        stats.rejected_connections()
+    );
+    let _ = write!(ret, "expired_keys:{}\r\n", stats.expired_keys());
+    let _ = write!(ret, "evicted_keys:{}\r\n", backend.eviction.evicted_keys());
+    let _ = write!(ret, "keyspace_hits:{}\r\n", stats.keyspace_hits());
+    let _ = write!(ret, "keyspace_misses:{}\r\n", stats.keyspace_misses());
+    let tracking = &backend.tracking;
+    let _ = write!(ret, "tracking_total_keys:{}\r\n", tracking.total_keys());
+    let _ = write!(ret, "tracking_total_items:{}\r\n", tracking.total_items());
+    let _ = write!(
+        ret,
+        "tracking_total_prefixes:{}\r\n",
+        tracking.total_prefixes()
+    );
+    let _ = write!(
+        ret,
+        "total_error_replies:{}\r\n",
+        stats.total_error_replies()
+    );
+    ret
+}
+
+fn cpu_section(_backend: &Backend) -> String {
+    let (user, sys) = stats::cpu_usage();
+    format!("# CPU\r\nused_cpu_sys:{sys:.6}\r\nused_cpu_user:{user:.6}\r\n")
+}
+
+fn commandstats_section(backend: &Backend) -> String {
+    let mut ret = String::from("# Commandstats\r\n");
+    for (name, cmd) in backend.stats.commands() {
+        let per_call = match cmd.calls {
+            0 => 0.0,
+            calls => cmd.usec as f64 / calls as f64,
+        };
+        let _ = write!(
+            ret,
+            "cmdstat_{name}:calls={},usec={},usec_per_call={per_call:.2},rejected_calls={},failed_calls={}\r\n",
+            cmd.calls, cmd.usec, cmd.rejected_calls, cmd.failed_calls
+        );
+    }
+    ret
+}
+
+fn errorstats_section(backend: &Backend) -> String {
+    let mut ret = String::from("# Errorstats\r\n");
+    for (prefix, count) in backend.stats.errors() {
+        let _ = write!(ret, "errorstat_{prefix}:count={count}\r\n");
+    }
+    ret
+}
+
+fn latencystats_section(backend: &Backend) -> String {
+    let mut ret = String::from("# Latencystats\r\n");
+    for (name, cmd) in backend.stats.commands() {
+        if cmd.latency.count() == 0 {
+            continue;
+        }
+        let percentiles = LATENCY_PERCENTILES
+            .iter()
+            .map(|p| format!("p{p}={:.3}", cmd.latency.percentile(*p) as f64))
+            .collect::<Vec<_>>();
+        let _ = write!(
+            ret,
+            "latency_percentiles_usec_{name}:{}\r\n",
+            percentiles.join(",")
+        );
+    }
+    ret
+}
+
+fn persistence_section(backend: &Backend) -> String {
+    let mut ret = String::from("# Persistence\r\n");
+    ret.push_str("loading:0\r\n");
+    let _ = write!(
+        ret,
+        "rdb_changes_since_last_save:{}\r\n",
+        rdb::changes_since_save(backend)
+    );
+    let _ = write!(
+        ret,
+        "rdb_bgsave_in_progress:{}\r\n",
+        backend.rdb.bgsave_in_progress() as u8
+    );
+    let _ = write!(ret, "rdb_last_save_time:{}\r\n", backend.rdb.last_save());
+    let status = if backend.rdb.last_bgsave_ok() {
+        "ok"
+    } else {
+        "err"
+    };
+    let _ = write!(ret, "rdb_last_bgsave_status:{status}\r\n");
+    let _ = write!(ret, "aof_enabled:{}\r\n", backend.aof.is_enabled() as u8);
+    let status = if backend.aof.last_write_ok() {
+        "ok"
+    } else {
+        "err"
+    };
+    let _ = write!(ret, "aof_last_write_status:{status}\r\n");
+    let _ = write!(
+        ret,
+        "aof_rewrite_in_progress:{}\r\n",
+        backend.aof.rewrite_in_progress() as u8
+    );
+    let status = if backend.aof.last_rewrite_ok() {
+        "ok"
+    } else {
+        "err"
+    };
+    let _ = write!(ret, "aof_last_bgrewrite_status:{status}\r\n");
+    if backend.aof.is_enabled() {
+        let _ = write!(ret, "aof_current_size:{}\r\n", backend.aof.current_size());
+        let _ = write!(ret, "aof_base_size:{}\r\n", backend.aof.base_size());
+    }
+    ret
+}
+
+fn replication_section(backend: &Backend) -> String {
+    let repl = &backend.repl;
+    let mut ret = String::from("# Replication\r\n");
+    match repl.role() {
+        Role::Master => {
+            ret.push_str("role:master\r\n");
+            let replicas = repl.replicas();
+            let _ = write!(ret, "connected_slaves:{}\r\n", replicas.len());
+            for (i, r) in replicas.iter().enumerate() {
+                let _ = write!(
+                    ret,
+                    "slave{i}:ip={},port={},state={},offset={},lag={}\r\n",
+                    r.ip, r.port, r.state, r.offset, r.lag
+                );
+            }
+        }
+        Role::Replica { host, port } => {
+            ret.push_str("role:slave\r\n");
+            let _ = write!(ret, "master_host:{host}\r\n");
+            let _ = write!(ret, "master_port:{port}\r\n");
+            let status = if repl.link_up() { "up" } else { "down" };
+            let _ = write!(ret, "master_link_status:{status}\r\n");
+            let last_io = repl.last_io_seconds_ago().map_or(-1, |secs| secs as i64);
+            let _ = write!(ret, "master_last_io_seconds_ago:{last_io}\r\n");
+            let _ = write!(
+                ret,
+                "master_sync_in_progress:{}\r\n",
+                repl.sync_in_progress() as u8
+            );
+            let _ = write!(ret, "slave_repl_offset:{}\r\n", repl.offset());
+            let _ = write!(ret, "slave_read_only:{}\r\n", repl.config().read_only as u8);
+            let _ = write!(ret, "connected_slaves:{}\r\n", repl.replicas().len());
+        }
+    }
+    let _ = write!(ret, "master_replid:{}\r\n", repl.replid());
+    let _ = write!(ret, "master_replid2:{}\r\n", repl.replid2());
+    let _ = write!(ret, "master_repl_offset:{}\r\n", repl.offset());
+    let _ = write!(
+        ret,
+        "second_repl_offset:{}\r\n",
+        repl.second_replid_offset()
+    );
+    match repl.backlog_info() {
+        Some((first_offset, histlen)) => {
+            ret.push_str("repl_backlog_active:1\r\n");
+            let _ = write!(ret, "repl_backlog_size:{}\r\n", repl.config().backlog_size);
+            let _ = write!(ret, "repl_backlog_first_byte_offset:{first_offset}\r\n");
+            let _ = write!(ret, "repl_backlog_histlen:{histlen}\r\n");
+        }
+        None => ret.push_str("repl_backlog_active:0\r\n"),
+    }
+    ret
+}
+
+fn cluster_section(backend: &Backend) -> String {
+    format!(
+        "# Cluster\r\ncluster_enabled:{}\r\n",
+        backend.cluster.is_enabled() as u8
+    )
+}
+
+fn keyspace_section(backend: &Backend) -> String {
+    let mut ret = String::from("# Keyspace\r\n");
+    let keys = backend.dbsize();
+    if keys > 0 {
+        let expires = backend.expires.len();
+        let _ = write!(ret, "db0:keys={keys},expires={expires},avg_ttl=0\r\n");
+    }
+    ret
+}
+
+impl TryFrom<RespArray> for Info {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let args = extract_cmd_args(value, 1)?;
+        let mut sections = Vec::with_capacity(args.len());
+        for arg in args {
+            match arg {
+                RespFrame::BulkString(section) => {
+                    sections.push(String::from_utf8(section.0)?.to_ascii_lowercase())
+                }
+                _ => {
+                    return Err(CommandError::InvalidArgument(
+                        "info sections should be BulkString".into(),
+                    ))
+                }
+            }
+        }
+        Ok(Info { sections })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{stats::CallOutcome, DecodeResp, RespBulkString, RespProtocol};
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    #[test]
+    fn test_info_from_resp_array() -> Result<()> {
+        let mut bytes_mut = BytesMut::from(&b"*2\r\n$4\r\ninfo\r\n$6\r\nServer\r\n"[..]);
+        let frame = RespArray::decode(&mut bytes_mut)?;
+        let info = Info::try_from(frame)?;
+        assert_eq!(info.sections, vec!["server".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_info_reply_is_verbatim_string() {
+        let backend = Backend::new();
+        backend.set("hello".into(), RespBulkString::from("world").into());
+
+        let reply = Info { sections: vec![] }.execute(&backend);
+        let RespFrame::VerbatimString(ref text) = reply else {
+            panic!("info should reply a verbatim string");
+        };
+        assert_eq!(text.encoding(), "txt");
+        let text = String::from_utf8_lossy(text.data());
+        assert!(text.starts_with("# Server\r\nredis_version:"));
+        assert!(text.contains("rdb_changes_since_last_save:1\r\n"));
+        assert!(text.contains("maxmemory_policy:noeviction\r\n"));
+        assert!(text.contains("# Clients\r\nconnected_clients:0\r\n"));
+        assert!(text.contains("# CPU\r\n"));
+        assert!(!text.contains("# Commandstats"));
+        assert!(text.contains("# Keyspace\r\ndb0:keys=1,expires=0,avg_ttl=0\r\n"));
+
+        let RespFrame::BulkString(_) = reply.with_protocol(RespProtocol::Resp2) else {
+            panic!("info should be a bulk string under RESP2");
+        };
+    }
+
+    #[test]
+    fn test_info_stats_sections() {
+        let backend = Backend::new();
+        let elapsed = std::time::Duration::from_micros(3);
+        backend
+            .stats
+            .record_call(Some("get"), elapsed, CallOutcome::Ok, None);
+        backend.stats.record_call(
+            Some("set"),
+            elapsed,
+            CallOutcome::Failed,
+            Some("WRONGTYPE Operation against a key holding the wrong kind of value"),
+        );
+        backend.get("missing");
+
+        let info = |sections: &[&str]| {
+            let sections = sections.iter().map(|s| s.to_string()).collect();
+            match (Info { sections }).execute(&backend) {
+                RespFrame::VerbatimString(text) => {
+                    String::from_utf8_lossy(text.data()).into_owned()
+                }
+                frame => panic!("unexpected reply {frame:?}"),
+            }
+        };
+        let text = info(&["commandstats", "errorstats"]);
+        assert!(text.starts_with("# Commandstats\r\n"));
+        assert!(text.contains(
+            "cmdstat_get:calls=1,usec=3,usec_per_call=3.00,rejected_calls=0,failed_calls=0\r\n"
+        ));
+        assert!(text.contains(
+            "cmdstat_set:calls=1,usec=3,usec_per_call=3.00,rejected_calls=0,failed_calls=1\r\n"
+        ));
+        assert!(text.contains("# Errorstats\r\nerrorstat_WRONGTYPE:count=1\r\n"));
+        assert!(!text.contains("# Server"));
+
+        let text = info(&["stats", "latencystats"]);
+        assert!(text.contains("total_commands_processed:2\r\n"));
+        assert!(text.contains("keyspace_misses:1\r\n"));
+        assert!(text.contains("total_error_replies:1\r\n"));
+        assert!(text.contains("latency_percentiles_usec_get:p50=3.000,p99=3.000,p99.9=3.000\r\n"));
+
+        let text = info(&["everything"]);
+        assert!(text.contains("# Latencystats") && text.contains("# Keyspace"));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{Backend, RespArray, RespFrame, RespInteger, SimpleError, SimpleString};
+
+use super::{
+    extract_cmd_args, validate_command, CommandError, CommandExecutor, Del, Expire, FlushDb,
+    PExpire, PExpireAt, Pttl, Ttl, Type, RESP_OK,
+};
+
+impl CommandExecutor for Del {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let deleted = self.keys.iter().filter(|key| backend.del(key)).count();
+        RespInteger::from(deleted as i64).into()
+    }
+}
+
+impl CommandExecutor for Expire {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match self.deadline() {
+            Some(when) => expire_at(backend, &self.key, when),
+            None => SimpleError::from("ERR invalid expire time in 'expire' command").into(),
+        }
+    }
+}
+
+impl CommandExecutor for PExpire {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match self.deadline() {
+            Some(when) => expire_at(backend, &self.key, when),
+            None => SimpleError::from("ERR invalid expire time in 'pexpire' command").into(),
+        }
+    }
+}
+
+impl CommandExecutor for PExpireAt {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        expire_at(backend, &self.key, self.timestamp_ms)
+    }
+}
+
+fn expire_at(backend: &Backend, key: &str, when: i64) -> RespFrame {
+    let ret = backend.expire_at(key, when.max(0) as u64);
+    RespInteger::from(ret as i64).into()
+}
+
+impl CommandExecutor for Ttl {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let pttl = backend.pttl(&self.key);
+        let ttl = if pttl < 0 { pttl } else { (pttl + 500) / 1000 };
+        RespInteger::from(ttl).into()
+    }
+}
+
+impl CommandExecutor for Pttl {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        RespInteger::from(backend.pttl(&self.key)).into()
+    }
+}
+
+impl CommandExecutor for FlushDb {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        backend.flushdb();
+        RESP_OK.clone()
+    }
+}
+
+impl CommandExecutor for Type {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let name = backend.key_type(&self.key).unwrap_or("none");
+        SimpleString::from(name).into()
+    }
+}
+
+///*3\r\n$3\r\ndel\r\n$1\r\na\r\n$1\r\nb\r\n
+impl TryFrom<RespArray> for Del {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        if value.len() < 2 {
+            return Err(CommandError::InvalidArgument(
+                "del command should have at least 1 argument(s)!".into(),
+            ));
+        }
+        let keys = extract_cmd_args(value, 1)?
+            .into_iter()
+            .map(string_arg)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Del::new(keys))
+    }
+}
+
+impl TryFrom<RespArray> for Expire {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "expire", 2)?;
+        let (key, seconds) = key_and_integer(value)?;
+        Ok(Expire::new(key, seconds))
+    }
+}
+
+impl TryFrom<RespArray> for PExpire {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "pexpire", 2)?;
+        let (key, millis) = key_and_integer(value)?;
+        Ok(PExpire::new(key, millis))
+    }
+}
+
+impl TryFrom<RespArray> for PExpireAt {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "pexpireat", 2)?;
+        let (key, timestamp_ms) = key_and_integer(value)?;
+        Ok(PExpireAt::new(key, timestamp_ms))
+    }
+}
+
+impl TryFrom<RespArray> for Ttl {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "ttl", 1)?;
+        let mut args = extract_cmd_args(value, 1)?;
+        Ok(Ttl::new(string_arg(args.remove(0))?))
+    }
+}
+
+impl TryFrom<RespArray> for Pttl {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "pttl", 1)?;
+        let mut args = extract_cmd_args(value, 1)?;
+        Ok(Pttl::new(string_arg(args.remove(0))?))
+    }
+}
+
+impl TryFrom<RespArray> for Type {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "type", 1)?;
+        let mut args = extract_cmd_args(value, 1)?;
+        Ok(Type::new(string_arg(args.remove(0))?))
+    }
+}
+
+impl TryFrom<RespArray> for FlushDb {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        //FLUSHDB [ASYNC|SYNC]，这里总是同步清空
+        if value.len() > 2 {
+            return Err(CommandError::InvalidArgument(
+                "flushdb command should have at most 1 argument(s)!".into(),
+            ));
+        }
+        if let Some(mode) = value.get(1) {
+            let mode = string_arg(mode.clone())?.to_ascii_lowercase();
+            if mode != "async" && mode != "sync" {
+                return Err(CommandError::InvalidArgument("syntax error".into()));
+            }
+        }
+        Ok(FlushDb)
+    }
+}
+
+fn key_and_integer(value: RespArray) -> Result<(String, i64), CommandError> {
+    let mut args = extract_cmd_args(value, 1)?.into_iter();
+    match (args.next(), args.next()) {
+        (Some(key), Some(n)) => Ok((string_arg(key)?, integer_arg(n)?)),
+        _ => Err(CommandError::InvalidArgument(
+            "command should have a key and an integer as arguments".into(),
+        )),
+    }
+}
+
+pub fn string_arg(frame: RespFrame) -> Result<String, CommandError> {
+    match frame {
+        RespFrame::BulkString(s) => Ok(String::from_utf8(s.0)?),
+        _ => Err(CommandError::InvalidArgument(
+            "argument should be a BulkString".into(),
+        )),
+    }
+}
+
+pub fn integer_arg(frame: RespFrame) -> Result<i64, CommandError> {
+    string_arg(frame)?.parse().map_err(|_| {
+        CommandError::InvalidArgument("value is not an integer or out of range".into())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DecodeResp, RespBulkString};
+    use anyhow::Result;
+    use bytes::BytesMut;
+
+    #[test]
+    fn test_expire_from_resp_array() -> Result<()> {
+        let mut bytes_mut =
+            BytesMut::from(&b"*3\r\n$6\r\nEXPIRE\r\n$5\r\nhello\r\n$2\r\n10\r\n"[..]);
+        let frame = RespArray::decode(&mut bytes_mut)?;
+        assert_eq!(Expire::try_from(frame)?, Expire::new("hello".into(), 10));
+
+        let mut bytes_mut =
+            BytesMut::from(&b"*3\r\n$6\r\nexpire\r\n$5\r\nhello\r\n$2\r\nxx\r\n"[..]);
+        let frame = RespArray::decode(&mut bytes_mut)?;
+        assert!(Expire::try_from(frame).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_del_expire_ttl_execute() {
+        let backend = Backend::new();
+        backend.set("a".into(), RespBulkString::from("1").into());
+        backend.set("b".into(), RespBulkString::from("2").into());
+
+        let ret = Ttl::new("a".into()).execute(&backend);
+        assert_eq!(ret, RespInteger::from(-1).into());
+
+        let ret = Expire::new("a".into(), 100).execute(&backend);
+        assert_eq!(ret, RespInteger::from(1).into());
+        let ret = Ttl::new("a".into()).execute(&backend);
+        assert_eq!(ret, RespInteger::from(100).into());
+
+        let ret = PExpire::new("b".into(), -1).execute(&backend);
+        assert_eq!(ret, RespInteger::from(1).into());
+        assert_eq!(backend.get("b"), None);
+
+        let ret = Del::new(vec!["a".into(), "b".into()]).execute(&backend);
+        assert_eq!(ret, RespInteger::from(1).into());
+        let ret = Pttl::new("a".into()).execute(&backend);
+        assert_eq!(ret, RespInteger::from(-2).into());
+    }
+
+    #[test]
+    fn test_type_execute() {
+        let backend = Backend::new();
+        backend.set("s".into(), RespBulkString::from("1").into());
+        backend.restore(
+            "l".into(),
+            crate::Value::List(vec![RespBulkString::from("1").into()]),
+            None,
+        );
+
+        let ret = Type::new("s".into()).execute(&backend);
+        assert_eq!(ret, SimpleString::from("string").into());
+        let ret = Type::new("l".into()).execute(&backend);
+        assert_eq!(ret, SimpleString::from("list").into());
+        let ret = Type::new("missing".into()).execute(&backend);
+        assert_eq!(ret, SimpleString::from("none").into());
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    latency, stats::LatencyHistogram, Backend, RespArray, RespBulkString, RespFrame, RespInteger,
+    RespMaps, RespVerbatimString,
+};
+
+use super::{extract_cmd_args, integer_arg, string_arg, CommandError, CommandExecutor, RESP_OK};
+
+/// SLOWLOG GET [count] | LEN | RESET
+#[derive(Debug, PartialEq)]
+pub enum Slowlog {
+    /// None表示返回全部
+    Get(Option<usize>),
+    Len,
+    Reset,
+}
+
+/// LATENCY LATEST | HISTORY event | RESET [event ...] | HISTOGRAM [command ...] | DOCTOR
+#[derive(Debug, PartialEq)]
+pub enum Latency {
+    Latest,
+    History(String),
+    Reset(Vec<String>),
+    Histogram(Vec<String>),
+    Doctor,
+}
+
+/// SLOWLOG GET不带count时返回的条数
+const SLOWLOG_DEFAULT_COUNT: usize = 10;
+
+impl CommandExecutor for Slowlog {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match self {
+            Slowlog::Get(count) => {
+                let entries = backend.slowlog.get(count).into_iter().map(|entry| {
+                    let args = entry
+                        .args
+                        .into_iter()
+                        .map(|arg| RespBulkString::from(arg).into())
+                        .collect::<Vec<_>>();
+                    RespArray::new(vec![
+                        RespInteger::from(entry.id as i64).into(),
+                        RespInteger::from(entry.time as i64).into(),
+                        RespInteger::from(entry.duration as i64).into(),
+                        RespArray::new(args).into(),
+                        RespBulkString::from(entry.addr).into(),
+                        RespBulkString::from(entry.name).into(),
+                    ])
+                    .into()
+                });
+                RespArray::new(entries.collect()).into()
+            }
+            Slowlog::Len => RespInteger::from(backend.slowlog.len() as i64).into(),
+            Slowlog::Reset => {
+                backend.slowlog.reset();
+                RESP_OK.clone()
+            }
+        }
+    }
+}
+
+impl CommandExecutor for Latency {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let integer = |n: u64| RespFrame::from(RespInteger::from(n as i64));
+        match self {
+            Latency::Latest => {
+                let events = backend
+                    .latency
+                    .latest()
+                    .into_iter()
+                    .map(|(name, last, max)| {
+                        RespArray::new(vec![
+                            RespBulkString::from(name).into(),
+                            integer(last.time),
+                            integer(last.latency),
+                            integer(max),
+                        ])
+                        .into()
+                    });
+                RespArray::new(events.collect()).into()
+            }
+            Latency::History(event) => {
+                let samples = backend.latency.history(&event).into_iter().map(|sample| {
+                    RespArray::new(vec![integer(sample.time), integer(sample.latency)]).into()
+                });
+                RespArray::new(samples.collect()).into()
+            }
+            Latency::Reset(events) => integer(backend.latency.reset(&events) as u64),
+            Latency::Histogram(commands) => histogram_reply(backend, &commands),
+            Latency::Doctor => RespVerbatimString::text(latency::doctor(backend)).into(),
+        }
+    }
+}
+
+/// 每个命令一项：调用次数和按2的幂分桶的累计次数，桶的key是耗时上界(微秒)。
+/// 不指定命令时返回所有执行过的命令
+fn histogram_reply(backend: &Backend, commands: &[String]) -> RespFrame {
+    let stats = match commands.is_empty() {
+        true => backend.stats.commands(),
+        false => commands
+            .iter()
+            .filter_map(|name| {
+                let name = name.to_ascii_lowercase();
+                backend.stats.command(&name).map(|stats| (name, stats))
+            })
+            .collect(),
+    };
+    let mut map = RespMaps::default();
+    for (name, stats) in stats.into_iter().filter(|(_, stats)| stats.calls > 0) {
+        let mut buckets = RespMaps::default();
+        for (upper, count) in power_of_two_buckets(&stats.latency) {
+            buckets.insert(
+                RespInteger::from(upper as i64).into(),
+                RespInteger::from(count as i64).into(),
+            );
+        }
+        let mut entry = RespMaps::default();
+        entry.insert(
+            RespBulkString::from("calls").into(),
+            RespInteger::from(stats.calls as i64).into(),
+        );
+        entry.insert(
+            RespBulkString::from("histogram_usec").into(),
+            buckets.into(),
+        );
+        map.insert(RespBulkString::from(name).into(), entry.into());
+    }
+    map.into()
+}
+
+/// 把直方图更细的区间合并到2的幂的区间里：(上界, 小于等于上界的累计次数)
+fn power_of_two_buckets(histogram: &LatencyHistogram) -> Vec<(u64, u64)> {
+    let mut buckets = BTreeMap::new();
+    for (upper, cumulative) in histogram.cumulative() {
+        //累计次数是递增的，同一个桶里保留最后一个
+        buckets.insert(
+            upper.max(1).checked_next_power_of_two().unwrap_or(u64::MAX),
+            cumulative,
+        );
+    }
+    buckets.into_iter().collect()
+}
+
+impl TryFrom<RespArray> for Slowlog {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let sub = string_arg(args.next().ok_or_else(|| {
+            CommandError::InvalidArgument("slowlog command should have a subcommand".into())
+        })?)?
+        .to_ascii_lowercase();
+        let cmd = match sub.as_str() {
+            "get" => match args.next().map(integer_arg).transpose()? {
+                None => Slowlog::Get(Some(SLOWLOG_DEFAULT_COUNT)),
+                //-1表示返回全部
+                Some(-1) => Slowlog::Get(None),
+                Some(count) => Slowlog::Get(Some(usize::try_from(count).map_err(|_| {
+                    CommandError::InvalidArgument(
+                        "count should be greater than or equal to -1".into(),
+                    )
+                })?)),
+            },
+            "len" => Slowlog::Len,
+            "reset" => Slowlog::Reset,
+            _ => {
+                return Err(CommandError::InvalidCommand(format!(
+                    "unknown subcommand '{sub}'. Try SLOWLOG HELP."
+                )))
+            }
+        };
+        if args.next().is_some() {
+            return Err(CommandError::InvalidArgument(format!(
+                "wrong number of arguments for 'slowlog|{sub}' command"
+            )));
+        }
+        Ok(cmd)
+    }
+}
+
+impl TryFrom<RespArray> for Latency {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let sub = string_arg(args.next().ok_or_else(|| {
+            CommandError::InvalidArgument("latency command should have a subcommand".into())
+        })?)?
+        .to_ascii_lowercase();
+        let args = args.map(string_arg).collect::<Result<Vec<_>, _>>()?;
+        let wrong_args = || {
+            CommandError::InvalidArgument(format!(
+                "wrong number of arguments for 'latency|{sub}' command"
+            ))
+        };
+        match sub.as_str() {
+            "latest" if args.is_empty() => Ok(Latency::Latest),
+            "history" => match <[String; 1]>::try_from(args) {
+                Ok([event]) => Ok(Latency::History(event)),
+                Err(_) => Err(wrong_args()),
+            },
+            "reset" => Ok(Latency::Reset(args)),
+            "histogram" => Ok(Latency::Histogram(args)),
+            "doctor" if args.is_empty() => Ok(Latency::Doctor),
+            "latest" | "doctor" => Err(wrong_args()),
+            _ => Err(CommandError::InvalidCommand(format!(
+                "unknown subcommand '{sub}'. Try LATENCY HELP."
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        aof::command_array, latency::LatencyConfig, slowlog::SlowlogConfig, stats::CallOutcome,
+        EncodeResp, RespProtocol,
+    };
+
+    fn run<T>(args: &[&str], backend: &Backend) -> RespFrame
+    where
+        T: CommandExecutor + TryFrom<RespArray, Error = CommandError>,
+    {
+        T::try_from(command_array(args.iter().copied()))
+            .unwrap()
+            .execute(backend)
+    }
+
+    #[test]
+    fn test_slowlog_from_resp_array() {
+        let parse = |args: &[&str]| Slowlog::try_from(command_array(args.iter().copied()));
+        assert_eq!(parse(&["SLOWLOG", "get"]).unwrap(), Slowlog::Get(Some(10)));
+        assert_eq!(
+            parse(&["SLOWLOG", "GET", "-1"]).unwrap(),
+            Slowlog::Get(None)
+        );
+        assert_eq!(
+            parse(&["SLOWLOG", "GET", "3"]).unwrap(),
+            Slowlog::Get(Some(3))
+        );
+        assert!(parse(&["SLOWLOG", "GET", "-2"]).is_err());
+        assert!(parse(&["SLOWLOG", "LEN", "1"]).is_err());
+        assert!(parse(&["SLOWLOG", "nothing"]).is_err());
+    }
+
+    #[test]
+    fn test_slowlog_execute() {
+        let backend = Backend::new();
+        backend.slowlog.set_config(SlowlogConfig {
+            slower_than: 0,
+            max_len: 10,
+        });
+        let args = backend
+            .slowlog
+            .capture(&command_array(["GET", "a"]))
+            .unwrap();
+        let addr = "127.0.0.1:6000".parse().ok();
+        backend
+            .slowlog
+            .record(args, Duration::from_micros(15), addr, Some("app"));
+        assert_eq!(
+            run::<Slowlog>(&["SLOWLOG", "LEN"], &backend),
+            RespInteger::from(1).into()
+        );
+
+        let RespFrame::Arrays(entries) = run::<Slowlog>(&["SLOWLOG", "GET"], &backend) else {
+            panic!("SLOWLOG GET should reply an array");
+        };
+        let RespFrame::Arrays(entry) = &entries[0] else {
+            panic!("slowlog entry should be an array");
+        };
+        assert_eq!(entry[0], RespInteger::from(0).into());
+        assert_eq!(entry[2], RespInteger::from(15).into());
+        assert_eq!(
+            entry[3],
+            RespArray::new(vec![
+                RespBulkString::from("GET").into(),
+                RespBulkString::from("a").into()
+            ])
+            .into()
+        );
+        assert_eq!(entry[4], RespBulkString::from("127.0.0.1:6000").into());
+        assert_eq!(entry[5], RespBulkString::from("app").into());
+
+        assert_eq!(
+            run::<Slowlog>(&["SLOWLOG", "RESET"], &backend),
+            RESP_OK.clone()
+        );
+        assert_eq!(
+            run::<Slowlog>(&["SLOWLOG", "LEN"], &backend),
+            RespInteger::from(0).into()
+        );
+    }
+
+    #[test]
+    fn test_latency_from_resp_array() {
+        let parse = |args: &[&str]| Latency::try_from(command_array(args.iter().copied()));
+        assert_eq!(parse(&["LATENCY", "latest"]).unwrap(), Latency::Latest);
+        assert_eq!(
+            parse(&["LATENCY", "HISTORY", "command"]).unwrap(),
+            Latency::History("command".into())
+        );
+        assert_eq!(
+            parse(&["LATENCY", "RESET"]).unwrap(),
+            Latency::Reset(vec![])
+        );
+        assert_eq!(
+            parse(&["LATENCY", "HISTOGRAM", "set", "get"]).unwrap(),
+            Latency::Histogram(vec!["set".into(), "get".into()])
+        );
+        assert!(parse(&["LATENCY", "HISTORY"]).is_err());
+        assert!(parse(&["LATENCY", "LATEST", "x"]).is_err());
+        assert!(parse(&["LATENCY", "GRAPH"]).is_err());
+    }
+
+    #[test]
+    fn test_latency_execute() {
+        let backend = Backend::new();
+        backend.latency.set_config(LatencyConfig { threshold: 10 });
+        backend.latency.record("command", Duration::from_millis(25));
+
+        let RespFrame::Arrays(latest) = run::<Latency>(&["LATENCY", "LATEST"], &backend) else {
+            panic!("LATENCY LATEST should reply an array");
+        };
+        let RespFrame::Arrays(event) = &latest[0] else {
+            panic!("latency event should be an array");
+        };
+        assert_eq!(event[0], RespBulkString::from("command").into());
+        assert_eq!(event[2], RespInteger::from(25).into());
+        assert_eq!(event[3], RespInteger::from(25).into());
+
+        let RespFrame::Arrays(history) =
+            run::<Latency>(&["LATENCY", "HISTORY", "command"], &backend)
+        else {
+            panic!("LATENCY HISTORY should reply an array");
+        };
+        assert_eq!(history.len(), 1);
+        assert_eq!(
+            run::<Latency>(&["LATENCY", "RESET", "command"], &backend),
+            RespInteger::from(1).into()
+        );
+
+        let RespFrame::VerbatimString(_) = run::<Latency>(&["LATENCY", "DOCTOR"], &backend) else {
+            panic!("LATENCY DOCTOR should reply a verbatim string");
+        };
+    }
+
+    #[test]
+    fn test_latency_histogram() {
+        let backend = Backend::new();
+        for usec in [1, 3, 3, 100] {
+            backend.stats.record_call(
+                Some("set"),
+                Duration::from_micros(usec),
+                CallOutcome::Ok,
+                None,
+            );
+        }
+        let reply = run::<Latency>(&["LATENCY", "HISTOGRAM", "SET", "get"], &backend);
+        let encoded = reply.with_protocol(RespProtocol::Resp3).encode();
+        assert_eq!(
+            String::from_utf8(encoded).unwrap(),
+            "%1\r\n$3\r\nset\r\n%2\r\n$5\r\ncalls\r\n:+4\r\n$14\r\nhistogram_usec\r\n%3\r\n:+1\r\n:+1\r\n:+4\r\n:+3\r\n:+128\r\n:+4\r\n"
+        );
+    }
+}
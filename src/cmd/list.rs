@@ -0,0 +1,53 @@
+use crate::{Backend, RespArray, RespFrame, RespInteger};
+
+use super::{check_type, extract_cmd_args, string_arg, CommandError, CommandExecutor, RPush};
+
+impl CommandExecutor for RPush {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if let Some(err) = check_type(backend, &self.key, "list") {
+            return err;
+        }
+        let len = backend.rpush(self.key, self.values);
+        RespInteger::from(len as i64).into()
+    }
+}
+
+///*4\r\n$5\r\nrpush\r\n$4\r\nlist\r\n$1\r\na\r\n$1\r\nb\r\n
+impl TryFrom<RespArray> for RPush {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        if value.len() < 3 {
+            return Err(CommandError::InvalidArgument(
+                "rpush command should have at least 2 argument(s)!".into(),
+            ));
+        }
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let key = args.next().map(string_arg).transpose()?.unwrap_or_default();
+        Ok(RPush::new(key, args.collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aof::command_array, RespBulkString};
+
+    #[test]
+    fn test_rpush_execute() {
+        let backend = Backend::new();
+        let cmd = RPush::try_from(command_array(["rpush", "l", "a", "b"])).unwrap();
+        assert_eq!(cmd.execute(&backend), RespInteger::from(2).into());
+        let cmd = RPush::try_from(command_array(["rpush", "l", "c"])).unwrap();
+        assert_eq!(cmd.execute(&backend), RespInteger::from(3).into());
+        assert_eq!(
+            backend.lists.get("l").unwrap()[2],
+            RespBulkString::from("c").into()
+        );
+
+        backend.set("s".into(), RespBulkString::from("v").into());
+        let cmd = RPush::try_from(command_array(["rpush", "s", "a"])).unwrap();
+        assert!(matches!(cmd.execute(&backend), RespFrame::SimpleError(_)));
+        assert!(RPush::try_from(command_array(["rpush", "l"])).is_err());
+    }
+}
@@ -0,0 +1,384 @@
+use crate::{
+    backend::{as_integer, now_ms},
+    eviction::EvictionPolicy,
+    Backend, RespArray, RespBulkString, RespDoubles, RespFrame, RespInteger, RespMaps, RespNull,
+    RespVerbatimString, SimpleError, Value,
+};
+
+use super::{
+    command_name, extract_cmd_args, integer_arg, string_arg, CommandError, CommandExecutor,
+};
+
+/// MEMORY USAGE key [SAMPLES count] | STATS | DOCTOR
+#[derive(Debug, PartialEq)]
+pub enum Memory {
+    /// SAMPLES只做参数检查：每个key的大小是写入时累加的，不需要抽样估算
+    Usage(String, Option<usize>),
+    Stats,
+    Doctor,
+}
+
+/// OBJECT ENCODING key | FREQ key | IDLETIME key | REFCOUNT key
+#[derive(Debug, PartialEq)]
+pub enum Object {
+    Encoding(String),
+    Freq(String),
+    IdleTime(String),
+    RefCount(String),
+}
+
+/// Redis共享的小整数对象个数
+const SHARED_INTEGERS: i64 = 10000;
+
+impl CommandExecutor for Memory {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match self {
+            Memory::Usage(key, _) => {
+                //先检查过期，已经过期的key按不存在处理
+                if backend.key_type(&key).is_none() {
+                    return RespFrame::Null(RespNull);
+                }
+                match backend.key_meta(&key) {
+                    Some(meta) => RespInteger::from(meta.size as i64).into(),
+                    None => RespFrame::Null(RespNull),
+                }
+            }
+            Memory::Stats => stats_reply(backend),
+            Memory::Doctor => RespVerbatimString::text(doctor_text(backend)).into(),
+        }
+    }
+}
+
+impl CommandExecutor for Object {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let policy = backend.eviction.config().policy;
+        let lfu = matches!(
+            policy,
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu
+        );
+        let key = self.key();
+        //OBJECT不算作一次访问，不会更新LRU/LFU
+        let Some((value, _)) = backend.value(key) else {
+            return RespFrame::Null(RespNull);
+        };
+        let meta = backend.key_meta(key);
+        let now = now_ms();
+        match self {
+            Object::Encoding(_) => RespBulkString::from(value.encoding()).into(),
+            Object::Freq(_) if !lfu => SimpleError::from(
+                "ERR An LFU maxmemory policy is not selected, access frequency not tracked.",
+            )
+            .into(),
+            Object::Freq(_) => {
+                RespInteger::from(meta.map_or(0, |meta| meta.freq(now)) as i64).into()
+            }
+            Object::IdleTime(_) if lfu => {
+                SimpleError::from("ERR An LFU maxmemory policy is selected, idle time not tracked.")
+                    .into()
+            }
+            Object::IdleTime(_) => {
+                RespInteger::from(meta.map_or(0, |meta| meta.idle_ms(now) / 1000) as i64).into()
+            }
+            Object::RefCount(_) => {
+                //和Redis一样，小整数是共享对象，引用计数是INT_MAX
+                let shared = match &value {
+                    Value::String(frame) => {
+                        as_integer(frame).is_some_and(|i| (0..SHARED_INTEGERS).contains(&i))
+                    }
+                    _ => false,
+                };
+                let count = if shared { i32::MAX as i64 } else { 1 };
+                RespInteger::from(count).into()
+            }
+        }
+    }
+}
+
+fn stats_reply(backend: &Backend) -> RespFrame {
+    let stats = backend.memory_stats();
+    let mut map = RespMaps::default();
+    let mut insert = |name: &'static str, value: RespFrame| {
+        map.insert(RespBulkString::from(name).into(), value);
+    };
+    let integer = |n: usize| RespFrame::from(RespInteger::from(n as i64));
+    insert("peak.allocated", integer(stats.peak));
+    insert("total.allocated", integer(stats.total));
+    insert("overhead.total", integer(stats.overhead));
+    insert("keys.count", integer(stats.keys));
+    let per_key = stats.total.checked_div(stats.keys).unwrap_or(0);
+    insert("keys.bytes-per-key", integer(per_key));
+    insert("dataset.bytes", integer(stats.dataset));
+    let percentage = match stats.total {
+        0 => 0.0,
+        total => stats.dataset as f64 * 100.0 / total as f64,
+    };
+    insert("dataset.percentage", RespDoubles::new(percentage).into());
+    let peak_percentage = match stats.peak {
+        0 => 0.0,
+        peak => stats.total as f64 * 100.0 / peak as f64,
+    };
+    insert("peak.percentage", RespDoubles::new(peak_percentage).into());
+    map.into()
+}
+
+/// 检查几种常见的内存问题，给出建议
+fn doctor_text(backend: &Backend) -> String {
+    let stats = backend.memory_stats();
+    if stats.keys == 0 {
+        return "Hi Sam, this instance is empty or is using very little memory, my issues detector can't be used in these conditions.".into();
+    }
+    let config = backend.eviction.config();
+    let mut issues = Vec::new();
+    //峰值比现在高很多，说明曾经有过大量写入后又删除
+    if stats.peak > stats.total + stats.total / 2 {
+        issues.push(format!(
+            " * Peak memory: In the past this instance used more than 150% the memory that is currently using ({} bytes now, {} bytes at peak). This may be caused by a burst of writes followed by deletions.",
+            stats.total, stats.peak
+        ));
+    }
+    if config.maxmemory > 0 && stats.total as u64 * 10 > config.maxmemory * 9 {
+        let advice = match config.policy {
+            EvictionPolicy::NoEviction => {
+                "write commands will be rejected with OOM errors once the limit is reached, consider raising maxmemory or selecting an eviction policy"
+            }
+            _ => "keys are being evicted, consider raising maxmemory if this is unexpected",
+        };
+        issues.push(format!(
+            " * Maxmemory: used memory is above 90% of maxmemory ({} of {} bytes), {}.",
+            stats.total, config.maxmemory, advice
+        ));
+    }
+    if issues.is_empty() {
+        return "Hi Sam, I can't find any memory issue in your instance. I can only account for what occurs on this base.".into();
+    }
+    format!(
+        "Sam, I detected a few issues in this instance memory implementation:\n\n{}\n",
+        issues.join("\n\n")
+    )
+}
+
+impl Memory {
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            Memory::Usage(key, _) => Some(key),
+            _ => None,
+        }
+    }
+}
+
+impl Object {
+    pub fn key(&self) -> &str {
+        match self {
+            Object::Encoding(key)
+            | Object::Freq(key)
+            | Object::IdleTime(key)
+            | Object::RefCount(key) => key,
+        }
+    }
+}
+
+impl TryFrom<RespArray> for Memory {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let sub = string_arg(args.next().ok_or_else(|| {
+            CommandError::InvalidArgument("memory command should have a subcommand".into())
+        })?)?
+        .to_ascii_lowercase();
+        let syntax_error = || CommandError::InvalidArgument("syntax error".into());
+        let cmd = match sub.as_str() {
+            "usage" => {
+                let key = string_arg(args.next().ok_or_else(syntax_error)?)?;
+                let samples = match args.next().map(string_arg).transpose()? {
+                    Some(arg) if arg.eq_ignore_ascii_case("samples") => {
+                        let count = integer_arg(args.next().ok_or_else(syntax_error)?)?;
+                        Some(usize::try_from(count).map_err(|_| {
+                            CommandError::InvalidArgument("value is out of range".into())
+                        })?)
+                    }
+                    Some(_) => return Err(syntax_error()),
+                    None => None,
+                };
+                Memory::Usage(key, samples)
+            }
+            "stats" => Memory::Stats,
+            "doctor" => Memory::Doctor,
+            _ => {
+                return Err(CommandError::InvalidCommand(format!(
+                    "unknown subcommand '{sub}'. Try MEMORY HELP."
+                )))
+            }
+        };
+        if args.next().is_some() {
+            return Err(syntax_error());
+        }
+        Ok(cmd)
+    }
+}
+
+impl TryFrom<RespArray> for Object {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let name = command_name(&value)?;
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let (Some(sub), Some(key), None) = (args.next(), args.next(), args.next()) else {
+            return Err(CommandError::InvalidArgument(format!(
+                "{name} command should have exactly 2 argument(s)!"
+            )));
+        };
+        let sub = string_arg(sub)?.to_ascii_lowercase();
+        let key = string_arg(key)?;
+        match sub.as_str() {
+            "encoding" => Ok(Object::Encoding(key)),
+            "freq" => Ok(Object::Freq(key)),
+            "idletime" => Ok(Object::IdleTime(key)),
+            "refcount" => Ok(Object::RefCount(key)),
+            _ => Err(CommandError::InvalidCommand(format!(
+                "unknown subcommand '{sub}'. Try OBJECT HELP."
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aof::command_array,
+        eviction::{EvictionConfig, EvictionPolicy},
+    };
+
+    fn memory(args: &[&str], backend: &Backend) -> RespFrame {
+        Memory::try_from(command_array(args.iter().copied()))
+            .unwrap()
+            .execute(backend)
+    }
+
+    fn object(sub: &str, key: &str, backend: &Backend) -> RespFrame {
+        Object::try_from(command_array(["OBJECT", sub, key]))
+            .unwrap()
+            .execute(backend)
+    }
+
+    fn bulk(s: &'static str) -> RespFrame {
+        RespBulkString::from(s).into()
+    }
+
+    #[test]
+    fn test_memory_from_resp_array() {
+        let cmd = Memory::try_from(command_array(["MEMORY", "usage", "k", "SAMPLES", "0"]));
+        assert_eq!(cmd.unwrap(), Memory::Usage("k".into(), Some(0)));
+        assert!(Memory::try_from(command_array(["MEMORY", "usage", "k", "x"])).is_err());
+        assert!(Memory::try_from(command_array(["MEMORY", "stats", "x"])).is_err());
+        assert!(Memory::try_from(command_array(["MEMORY", "purge"])).is_err());
+        assert!(Object::try_from(command_array(["OBJECT", "encoding"])).is_err());
+        assert!(Object::try_from(command_array(["OBJECT", "type", "k"])).is_err());
+    }
+
+    #[test]
+    fn test_memory_usage_and_stats() {
+        let backend = Backend::new();
+        assert_eq!(
+            memory(&["MEMORY", "USAGE", "k"], &backend),
+            RespFrame::Null(RespNull)
+        );
+        backend.set("small".into(), bulk("v"));
+        backend.set("big".into(), RespBulkString::new(vec![b'x'; 1000]).into());
+        let usage = |key| match memory(&["MEMORY", "USAGE", key], &backend) {
+            RespFrame::Integer(n) => n.0,
+            frame => panic!("unexpected reply {frame:?}"),
+        };
+        assert!(usage("big") > usage("small") + 990);
+
+        let RespFrame::Maps(stats) = memory(&["MEMORY", "STATS"], &backend) else {
+            panic!("MEMORY STATS should reply a map");
+        };
+        assert_eq!(
+            stats.get(&bulk("keys.count")),
+            Some(&RespInteger::from(2).into())
+        );
+        assert_eq!(
+            stats.get(&bulk("total.allocated")),
+            Some(&RespInteger::from(backend.used_memory() as i64).into())
+        );
+
+        backend.del("big");
+        let RespFrame::VerbatimString(text) = memory(&["MEMORY", "DOCTOR"], &backend) else {
+            panic!("MEMORY DOCTOR should reply a verbatim string");
+        };
+        assert!(String::from_utf8_lossy(text.data()).contains("Peak memory"));
+    }
+
+    #[test]
+    fn test_object_encoding() {
+        let backend = Backend::new();
+        let encoding = |key| object("ENCODING", key, &backend);
+        backend.set("int".into(), bulk("12345"));
+        backend.set("str".into(), bulk("007"));
+        backend.set("raw".into(), RespBulkString::new(vec![b'x'; 45]).into());
+        backend.sadd("ints".into(), vec![bulk("1"), bulk("2")]);
+        backend.sadd("set".into(), vec![bulk("a")]);
+        backend.rpush("list".into(), vec![bulk("a")]);
+        backend.hset(
+            "hash".into(),
+            "f".into(),
+            RespBulkString::new(vec![b'x'; 65]).into(),
+        );
+        backend.zadd("zset".into(), vec![(bulk("m"), 1.0)]);
+        assert_eq!(encoding("int"), bulk("int"));
+        assert_eq!(encoding("str"), bulk("embstr"));
+        assert_eq!(encoding("raw"), bulk("raw"));
+        assert_eq!(encoding("ints"), bulk("intset"));
+        assert_eq!(encoding("set"), bulk("listpack"));
+        assert_eq!(encoding("list"), bulk("listpack"));
+        assert_eq!(encoding("hash"), bulk("hashtable"));
+        assert_eq!(encoding("zset"), bulk("listpack"));
+        assert_eq!(encoding("missing"), RespFrame::Null(RespNull));
+
+        let members = (0..200).map(|i| bulk_string(format!("m{i}"))).collect();
+        backend.sadd("set".into(), members);
+        assert_eq!(encoding("set"), bulk("hashtable"));
+    }
+
+    fn bulk_string(s: String) -> RespFrame {
+        RespBulkString::from(s).into()
+    }
+
+    #[test]
+    fn test_object_freq_idletime_refcount() {
+        let backend = Backend::new();
+        backend.set("k".into(), bulk("100"));
+        backend.set("s".into(), bulk("hello"));
+        assert_eq!(
+            object("REFCOUNT", "k", &backend),
+            RespInteger::from(i32::MAX as i64).into()
+        );
+        assert_eq!(
+            object("REFCOUNT", "s", &backend),
+            RespInteger::from(1).into()
+        );
+
+        backend.set_idle("s", 30);
+        assert_eq!(
+            object("IDLETIME", "s", &backend),
+            RespInteger::from(30).into()
+        );
+        assert!(matches!(
+            object("FREQ", "s", &backend),
+            RespFrame::SimpleError(_)
+        ));
+
+        backend.eviction.set_config(EvictionConfig {
+            policy: EvictionPolicy::AllKeysLfu,
+            ..Default::default()
+        });
+        backend.set_freq("s", 42);
+        assert_eq!(object("FREQ", "s", &backend), RespInteger::from(42).into());
+        assert!(matches!(
+            object("IDLETIME", "s", &backend),
+            RespFrame::SimpleError(_)
+        ));
+    }
+}
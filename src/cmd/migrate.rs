@@ -0,0 +1,331 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use bytes::BytesMut;
+
+use crate::{
+    aof::command_array, backend::now_ms, execute_command, network::Session, rdb, Backend, Blocked,
+    DecodeResp, Del, EncodeResp, RespArray, RespBulkString, RespError, RespFrame, SimpleError,
+    SimpleString,
+};
+
+use super::{extract_cmd_args, integer_arg, string_arg, CommandError, RESP_OK};
+
+/// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [AUTH password]
+/// [AUTH2 username password] [KEYS key [key ...]]
+#[derive(Debug, PartialEq)]
+pub struct Migrate {
+    pub host: String,
+    pub port: u16,
+    pub keys: Vec<String>,
+    pub db: i64,
+    /// 毫秒，0表示使用默认的1秒
+    pub timeout: u64,
+    /// 不删除本地的key
+    pub copy: bool,
+    /// 覆盖目标节点上已经存在的key
+    pub replace: bool,
+    /// (用户名, 密码)，AUTH只有密码
+    pub auth: Option<(Option<String>, String)>,
+}
+
+impl Migrate {
+    /// 和目标节点的通信放到阻塞线程里进行，由连接循环等待结果
+    pub fn execute(
+        self,
+        session: &mut Session,
+        _backend: &Backend,
+    ) -> Result<RespFrame, CommandError> {
+        session.blocked = Some(Blocked::migrate(self));
+        Ok(RESP_OK.clone())
+    }
+
+    pub fn keys(&self) -> Vec<&str> {
+        self.keys.iter().map(String::as_str).collect()
+    }
+
+    /// 在阻塞线程里执行。整个过程独占Backend，和Redis一样，迁移期间这些key不会被其他命令修改
+    pub(super) fn run(self, backend: &Backend) -> RespFrame {
+        let _guard = backend.exclusive_lock();
+        let now = now_ms();
+        let entries = self
+            .keys
+            .iter()
+            .filter_map(|key| {
+                backend
+                    .value(key)
+                    .map(|(value, expire)| (key, value, expire))
+            })
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            return SimpleString::from("NOKEY").into();
+        }
+
+        let mut commands = Vec::new();
+        match &self.auth {
+            Some((Some(username), password)) => {
+                commands.push(command_array(["AUTH", username, password]))
+            }
+            Some((None, password)) => commands.push(command_array(["AUTH", password])),
+            None => {}
+        }
+        if self.db != 0 {
+            commands.push(command_array(["SELECT", &self.db.to_string()]));
+        }
+        let setup = commands.len();
+        //cluster模式下目标节点的slot还在迁入中，需要RESTORE-ASKING
+        let restore = if backend.cluster.is_enabled() {
+            "RESTORE-ASKING"
+        } else {
+            "RESTORE"
+        };
+        for (key, value, expire) in &entries {
+            //已经过期的key在上面就被删掉了，剩余时间至少是1毫秒
+            let ttl = expire.map_or(0, |when| when.saturating_sub(now).max(1));
+            let mut array = command_array([restore, key, &ttl.to_string()]);
+            array
+                .0
+                .push(RespBulkString::new(rdb::dump_payload(value)).into());
+            if self.replace {
+                array.0.push(RespBulkString::from("REPLACE").into());
+            }
+            commands.push(array);
+        }
+
+        let replies = match self.send(commands) {
+            Ok(replies) => replies,
+            Err(e) => return SimpleError::from(e).into(),
+        };
+        if let Some(e) = replies[..setup].iter().find_map(reply_error) {
+            return target_error(&e);
+        }
+        let mut error = None;
+        let mut moved = Vec::new();
+        for ((key, _, _), reply) in entries.iter().zip(&replies[setup..]) {
+            match reply_error(reply) {
+                Some(e) => {
+                    error.get_or_insert(e);
+                }
+                None => moved.push(key.to_string()),
+            }
+        }
+        //目标节点上已经有了的key从本地删除，DEL同样会写进AOF和复制流
+        if !self.copy && !moved.is_empty() {
+            execute_command(Del::new(moved).into(), backend);
+        }
+        match error {
+            Some(e) => target_error(&e),
+            None => RESP_OK.clone(),
+        }
+    }
+
+    /// 一次性发送所有命令，再按顺序读回同样数量的回复
+    fn send(&self, commands: Vec<RespArray>) -> Result<Vec<RespFrame>, &'static str> {
+        const CONNECT_ERROR: &str = "IOERR error or timeout connecting to the client";
+        const WRITE_ERROR: &str = "IOERR error or timeout writing to target instance";
+        const READ_ERROR: &str = "IOERR error or timeout reading to target instance";
+
+        let timeout = Duration::from_millis(if self.timeout == 0 {
+            1000
+        } else {
+            self.timeout
+        });
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or(CONNECT_ERROR)?;
+        let mut stream = TcpStream::connect_timeout(&addr, timeout).map_err(|_| CONNECT_ERROR)?;
+        stream
+            .set_read_timeout(Some(timeout))
+            .and_then(|_| stream.set_write_timeout(Some(timeout)))
+            .map_err(|_| CONNECT_ERROR)?;
+
+        let n = commands.len();
+        let data = commands
+            .into_iter()
+            .flat_map(|cmd| cmd.encode())
+            .collect::<Vec<_>>();
+        stream.write_all(&data).map_err(|_| WRITE_ERROR)?;
+
+        let mut buf = BytesMut::new();
+        let mut chunk = [0; 4096];
+        let mut replies = Vec::with_capacity(n);
+        while replies.len() < n {
+            match RespFrame::decode(&mut buf) {
+                Ok(frame) => replies.push(frame),
+                Err(RespError::NotComplete) => match stream.read(&mut chunk) {
+                    Ok(0) | Err(_) => return Err(READ_ERROR),
+                    Ok(len) => buf.extend_from_slice(&chunk[..len]),
+                },
+                Err(_) => return Err(READ_ERROR),
+            }
+        }
+        Ok(replies)
+    }
+}
+
+fn reply_error(reply: &RespFrame) -> Option<String> {
+    match reply {
+        RespFrame::SimpleError(e) => Some(e.0.clone()),
+        RespFrame::BulkErrors(e) => Some(String::from_utf8_lossy(e.as_ref()).into_owned()),
+        _ => None,
+    }
+}
+
+fn target_error(e: &str) -> RespFrame {
+    SimpleError::from(format!("ERR Target instance replied with error: {e}")).into()
+}
+
+impl TryFrom<RespArray> for Migrate {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let (Some(host), Some(port), Some(key), Some(db), Some(timeout)) = (
+            args.next(),
+            args.next(),
+            args.next(),
+            args.next(),
+            args.next(),
+        ) else {
+            return Err(CommandError::InvalidArgument(
+                "migrate command should have at least 5 argument(s)!".into(),
+            ));
+        };
+        let out_of_range = || CommandError::InvalidArgument("value is out of range".into());
+        let key = string_arg(key)?;
+        let mut cmd = Migrate {
+            host: string_arg(host)?,
+            port: u16::try_from(integer_arg(port)?).map_err(|_| out_of_range())?,
+            keys: vec![],
+            db: integer_arg(db)?,
+            timeout: u64::try_from(integer_arg(timeout)?).map_err(|_| out_of_range())?,
+            copy: false,
+            replace: false,
+            auth: None,
+        };
+        let syntax_error = || CommandError::InvalidArgument("syntax error".into());
+        let mut with_keys = false;
+        while let Some(arg) = args.next() {
+            match string_arg(arg)?.to_ascii_lowercase().as_str() {
+                "copy" => cmd.copy = true,
+                "replace" => cmd.replace = true,
+                "auth" => {
+                    let password = string_arg(args.next().ok_or_else(syntax_error)?)?;
+                    cmd.auth = Some((None, password));
+                }
+                "auth2" => {
+                    let username = string_arg(args.next().ok_or_else(syntax_error)?)?;
+                    let password = string_arg(args.next().ok_or_else(syntax_error)?)?;
+                    cmd.auth = Some((Some(username), password));
+                }
+                "keys" => {
+                    if !key.is_empty() {
+                        return Err(CommandError::InvalidArgument(
+                            "When using MIGRATE KEYS option, the key argument must be set to the empty string".into(),
+                        ));
+                    }
+                    with_keys = true;
+                    cmd.keys = args.by_ref().map(string_arg).collect::<Result<_, _>>()?;
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        if !with_keys {
+            cmd.keys.push(key);
+        }
+        Ok(cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::start_server, RespInteger};
+
+    async fn migrate(backend: &Backend, args: &[&str]) -> RespFrame {
+        let cmd = Migrate::try_from(command_array(args.iter().copied())).unwrap();
+        let mut session = Session::new();
+        cmd.execute(&mut session, backend).unwrap();
+        session.blocked.take().unwrap().wait(backend).await
+    }
+
+    #[test]
+    fn test_migrate_from_resp_array() {
+        let cmd = Migrate::try_from(command_array([
+            "MIGRATE", "10.0.0.1", "6380", "", "0", "500", "COPY", "AUTH2", "u", "p", "KEYS", "a",
+            "b",
+        ]))
+        .unwrap();
+        assert_eq!(
+            cmd,
+            Migrate {
+                host: "10.0.0.1".into(),
+                port: 6380,
+                keys: vec!["a".into(), "b".into()],
+                db: 0,
+                timeout: 500,
+                copy: true,
+                replace: false,
+                auth: Some((Some("u".into()), "p".into())),
+            }
+        );
+        let cmd = Migrate::try_from(command_array(["MIGRATE", "h", "1", "k", "0", "0"])).unwrap();
+        assert_eq!(cmd.keys, vec!["k".to_string()]);
+        assert!(Migrate::try_from(command_array([
+            "MIGRATE", "h", "1", "k", "0", "0", "KEYS", "a"
+        ]))
+        .is_err());
+        assert!(
+            Migrate::try_from(command_array(["MIGRATE", "h", "1", "k", "0", "0", "AUTH"])).is_err()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_migrate() {
+        let (source, _) = start_server().await;
+        let (target, port) = start_server().await;
+        let port = port.to_string();
+        source.set("a".into(), RespBulkString::from("1").into());
+        source.rpush("l".into(), vec![RespInteger::from(1).into()]);
+        source.expire_at("l", now_ms() + 100_000);
+
+        let reply = migrate(&source, &["MIGRATE", "127.0.0.1", &port, "a", "0", "1000"]).await;
+        assert_eq!(reply, RESP_OK.clone());
+        assert!(!source.exists("a"));
+        assert_eq!(target.get("a"), Some(RespBulkString::from("1").into()));
+
+        //COPY保留本地的key，过期时间一起迁移
+        let args = [
+            "MIGRATE",
+            "127.0.0.1",
+            &port,
+            "",
+            "0",
+            "1000",
+            "COPY",
+            "KEYS",
+            "l",
+            "x",
+        ];
+        assert_eq!(migrate(&source, &args).await, RESP_OK.clone());
+        assert!(source.exists("l"));
+        assert!(target.pttl("l") > 90_000);
+
+        //目标节点已经有这个key
+        let reply = migrate(&source, &args).await;
+        assert!(matches!(reply, RespFrame::SimpleError(e) if e.contains("BUSYKEY")));
+        let args = ["MIGRATE", "127.0.0.1", &port, "l", "0", "1000", "REPLACE"];
+        assert_eq!(migrate(&source, &args).await, RESP_OK.clone());
+        assert!(!source.exists("l"));
+
+        let reply = migrate(&source, &["MIGRATE", "127.0.0.1", &port, "x", "0", "1000"]).await;
+        assert_eq!(reply, SimpleString::from("NOKEY").into());
+        source.set("a".into(), RespBulkString::from("1").into());
+        let reply = migrate(&source, &["MIGRATE", "127.0.0.1", "1", "a", "0", "100"]).await;
+        assert!(matches!(reply, RespFrame::SimpleError(e) if e.starts_with("IOERR")));
+        assert!(source.exists("a"));
+    }
+}
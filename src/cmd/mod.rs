@@ -1,41 +1,154 @@
+mod acl;
+mod auth;
+mod client;
+mod cluster;
+mod config;
+mod connection;
+mod dump;
+mod hello;
 mod hmap;
+mod info;
+mod keys;
+mod latency;
+mod list;
 mod map;
+mod memory;
+mod migrate;
+mod persistence;
+mod propagate;
+mod replication;
+mod set;
+mod table;
+mod transaction;
+mod wait;
+mod zset;
 
 use std::string::FromUtf8Error;
 
-use crate::{Backend, RespArray, RespError, RespFrame};
+use crate::{
+    backend::now_ms, cluster::Redirect, eviction::EvictionError, Backend, RespArray, RespError,
+    RespFrame, SimpleError,
+};
+use enum_dispatch::enum_dispatch;
 use lazy_static::lazy_static;
 use thiserror::Error;
 
+pub use acl::Acl;
+pub use auth::Auth;
+pub use client::{current_entry, Client};
+pub use cluster::{Cluster, SlotAction};
+pub use config::Config;
+pub use dump::{Dump, Restore};
+pub use hello::Hello;
+pub use info::Info;
+pub use keys::{integer_arg, string_arg};
+pub use latency::{Latency, Slowlog};
+pub use memory::{Memory, Object};
+pub use migrate::Migrate;
+pub use propagate::{execute_command, execute_transaction};
+pub use replication::{sync_request, Psync, ReplConf};
+pub use table::{command_spec, commands_in_category, CommandSpec, CATEGORIES, COMMAND_TABLE};
+pub use transaction::{Queued, Transaction};
+pub use wait::{Blocked, Wait, WaitAof};
+
+pub const SERVER_NAME: &str = "redis";
+pub const SERVER_VERSION: &str = "7.2.0";
+
 lazy_static! {
     static ref RESP_OK: RespFrame = RespFrame::SimpleString("OK".into());
 }
 
 #[derive(Error, Debug)]
 pub enum CommandError {
-    #[error("invalid command:{0}")]
+    #[error("ERR invalid command:{0}")]
     InvalidCommand(String),
 
-    #[error("invalid argument:{0}")]
+    #[error("ERR invalid argument:{0}")]
     InvalidArgument(String),
 
-    #[error("{0}")]
+    #[error("ERR {0}")]
     RespError(#[from] RespError),
 
-    #[error("{0}")]
+    #[error("ERR {0}")]
     FromUtf8Error(#[from] FromUtf8Error),
+
+    #[error("NOPROTO unsupported protocol version")]
+    NoProto,
+
+    #[error("WRONGPASS invalid username-password pair or user is disabled.")]
+    WrongPass,
+
+    #[error("NOAUTH Authentication required.")]
+    NoAuth,
+
+    #[error("NOPERM {0}")]
+    NoPerm(String),
+
+    #[error("EXECABORT Transaction discarded because of previous errors.")]
+    ExecAbort,
+
+    #[error("READONLY You can't write against a read only replica.")]
+    ReadOnly,
+
+    #[error("{0}")]
+    Redirect(#[from] Redirect),
+
+    #[error("{0}")]
+    OutOfMemory(#[from] EvictionError),
 }
 
+#[enum_dispatch]
 pub trait CommandExecutor {
     fn execute(self, backend: &Backend) -> RespFrame;
 }
 
+#[enum_dispatch(CommandExecutor)]
+#[derive(Debug)]
 pub enum Command {
+    Ping(Ping),
     Set(Set),
     Get(Get),
     HSet(HSet),
     HGet(HGet),
     HGetAll(HGetAll),
+    RPush(RPush),
+    SAdd(SAdd),
+    ZAdd(ZAdd),
+    Info(Info),
+    Del(Del),
+    Expire(Expire),
+    PExpire(PExpire),
+    PExpireAt(PExpireAt),
+    Ttl(Ttl),
+    Pttl(Pttl),
+    FlushDb(FlushDb),
+    Type(Type),
+    Save(Save),
+    BgSave(BgSave),
+    LastSave(LastSave),
+    BgRewriteAof(BgRewriteAof),
+    ReplicaOf(ReplicaOf),
+    Cluster(Cluster),
+    Dump(Dump),
+    Restore(Restore),
+    Memory(Memory),
+    Object(Object),
+    Config(Config),
+    Slowlog(Slowlog),
+    Latency(Latency),
+    Unwatch(Unwatch),
+    Unrecognized(Unrecognized),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Ping {
+    pub message: Option<RespFrame>,
+}
+
+impl Ping {
+    pub fn new(message: Option<RespFrame>) -> Self {
+        Self { message }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -101,11 +214,294 @@ impl HGetAll {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct RPush {
+    pub key: String,
+    pub values: Vec<RespFrame>,
+}
+
+impl RPush {
+    pub fn new(key: String, values: Vec<RespFrame>) -> Self {
+        Self { key, values }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SAdd {
+    pub key: String,
+    pub members: Vec<RespFrame>,
+}
+
+impl SAdd {
+    pub fn new(key: String, members: Vec<RespFrame>) -> Self {
+        Self { key, members }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ZAdd {
+    pub key: String,
+    /// (member, score)
+    pub members: Vec<(RespFrame, f64)>,
+}
+
+impl ZAdd {
+    pub fn new(key: String, members: Vec<(RespFrame, f64)>) -> Self {
+        Self { key, members }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Del {
+    pub keys: Vec<String>,
+}
+
+impl Del {
+    pub fn new(keys: Vec<String>) -> Self {
+        Self { keys }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Expire {
+    pub key: String,
+    pub seconds: i64,
+}
+
+impl Expire {
+    pub fn new(key: String, seconds: i64) -> Self {
+        Self { key, seconds }
+    }
+
+    /// 换算成绝对时间，溢出时返回None
+    pub fn deadline(&self) -> Option<i64> {
+        self.seconds
+            .checked_mul(1000)
+            .and_then(|millis| millis.checked_add(now_ms() as i64))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PExpire {
+    pub key: String,
+    pub millis: i64,
+}
+
+impl PExpire {
+    pub fn new(key: String, millis: i64) -> Self {
+        Self { key, millis }
+    }
+
+    /// 换算成绝对时间，溢出时返回None
+    pub fn deadline(&self) -> Option<i64> {
+        self.millis.checked_add(now_ms() as i64)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PExpireAt {
+    pub key: String,
+    pub timestamp_ms: i64,
+}
+
+impl PExpireAt {
+    pub fn new(key: String, timestamp_ms: i64) -> Self {
+        Self { key, timestamp_ms }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Ttl {
+    pub key: String,
+}
+
+impl Ttl {
+    pub fn new(key: String) -> Self {
+        Self { key }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Pttl {
+    pub key: String,
+}
+
+impl Pttl {
+    pub fn new(key: String) -> Self {
+        Self { key }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FlushDb;
+
+#[derive(Debug, PartialEq)]
+pub struct Type {
+    pub key: String,
+}
+
+impl Type {
+    pub fn new(key: String) -> Self {
+        Self { key }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Save;
+
+#[derive(Debug, PartialEq)]
+pub struct BgSave;
+
+#[derive(Debug, PartialEq)]
+pub struct LastSave;
+
+#[derive(Debug, PartialEq)]
+pub struct BgRewriteAof;
+
+/// master为None表示REPLICAOF NO ONE
+#[derive(Debug, PartialEq)]
+pub struct ReplicaOf {
+    pub master: Option<(String, u16)>,
+}
+
+impl ReplicaOf {
+    pub fn new(master: Option<(String, u16)>) -> Self {
+        Self { master }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Unwatch;
+
+#[derive(Debug)]
+pub struct Unrecognized {
+    pub name: String,
+}
+
+impl CommandExecutor for Unrecognized {
+    fn execute(self, _backend: &Backend) -> RespFrame {
+        SimpleError::from(format!("ERR unknown command '{}'", self.name)).into()
+    }
+}
+
+impl Command {
+    /// 需要拿到Backend某一时刻一致快照的命令，执行时要独占Backend。
+    /// CONFIG SET appendonly yes开启AOF时会重写一次，和BGREWRITEAOF一样
+    pub fn needs_exclusive_lock(&self) -> bool {
+        match self {
+            Command::Save(_) | Command::BgSave(_) | Command::BgRewriteAof(_) => true,
+            Command::Config(Config::Set(params)) => params
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case("appendonly")),
+            _ => false,
+        }
+    }
+
+    /// 命令访问的key，cluster模式下用它们判断请求应该由哪个节点处理
+    pub fn keys(&self) -> Vec<&str> {
+        match self {
+            Command::Set(cmd) => vec![&cmd.key],
+            Command::Get(cmd) => vec![&cmd.key],
+            Command::HSet(cmd) => vec![&cmd.table_name],
+            Command::HGet(cmd) => vec![&cmd.table_name],
+            Command::HGetAll(cmd) => vec![&cmd.table_name],
+            Command::RPush(cmd) => vec![&cmd.key],
+            Command::SAdd(cmd) => vec![&cmd.key],
+            Command::ZAdd(cmd) => vec![&cmd.key],
+            Command::Del(cmd) => cmd.keys.iter().map(String::as_str).collect(),
+            Command::Expire(cmd) => vec![&cmd.key],
+            Command::PExpire(cmd) => vec![&cmd.key],
+            Command::PExpireAt(cmd) => vec![&cmd.key],
+            Command::Ttl(cmd) => vec![&cmd.key],
+            Command::Pttl(cmd) => vec![&cmd.key],
+            Command::Type(cmd) => vec![&cmd.key],
+            Command::Dump(cmd) => vec![&cmd.key],
+            Command::Restore(cmd) => vec![&cmd.key],
+            Command::Memory(cmd) => cmd.key().into_iter().collect(),
+            Command::Object(cmd) => vec![cmd.key()],
+            _ => vec![],
+        }
+    }
+}
+
 impl TryFrom<RespArray> for Command {
     type Error = CommandError;
 
-    fn try_from(_: RespArray) -> Result<Self, Self::Error> {
-        todo!()
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let name = command_name(&value)?;
+        match name.as_str() {
+            "ping" => Ok(Ping::try_from(value)?.into()),
+            "get" => Ok(Get::try_from(value)?.into()),
+            "set" => Ok(Set::try_from(value)?.into()),
+            "hget" => Ok(HGet::try_from(value)?.into()),
+            "hset" => Ok(HSet::try_from(value)?.into()),
+            "hgetall" => Ok(HGetAll::try_from(value)?.into()),
+            "rpush" => Ok(RPush::try_from(value)?.into()),
+            "sadd" => Ok(SAdd::try_from(value)?.into()),
+            "zadd" => Ok(ZAdd::try_from(value)?.into()),
+            "info" => Ok(Info::try_from(value)?.into()),
+            "del" => Ok(Del::try_from(value)?.into()),
+            "expire" => Ok(Expire::try_from(value)?.into()),
+            "pexpire" => Ok(PExpire::try_from(value)?.into()),
+            "pexpireat" => Ok(PExpireAt::try_from(value)?.into()),
+            "ttl" => Ok(Ttl::try_from(value)?.into()),
+            "pttl" => Ok(Pttl::try_from(value)?.into()),
+            "flushdb" => Ok(FlushDb::try_from(value)?.into()),
+            "type" => Ok(Type::try_from(value)?.into()),
+            "save" => Ok(Save::try_from(value)?.into()),
+            "bgsave" => Ok(BgSave::try_from(value)?.into()),
+            "lastsave" => Ok(LastSave::try_from(value)?.into()),
+            "bgrewriteaof" => Ok(BgRewriteAof::try_from(value)?.into()),
+            "replicaof" | "slaveof" => Ok(ReplicaOf::try_from(value)?.into()),
+            "cluster" => Ok(Cluster::try_from(value)?.into()),
+            "dump" => Ok(Dump::try_from(value)?.into()),
+            "restore" | "restore-asking" => Ok(Restore::try_from(value)?.into()),
+            "memory" => Ok(Memory::try_from(value)?.into()),
+            "object" => Ok(Object::try_from(value)?.into()),
+            "config" => Ok(Config::try_from(value)?.into()),
+            "slowlog" => Ok(Slowlog::try_from(value)?.into()),
+            "latency" => Ok(Latency::try_from(value)?.into()),
+            "unwatch" => Ok(Unwatch::try_from(value)?.into()),
+            _ => Ok(Unrecognized { name }.into()),
+        }
+    }
+}
+
+/// 取出命令名并转为小写，命令必须是以BulkString开头的数组
+pub fn command_name(value: &RespArray) -> Result<String, CommandError> {
+    match value.first() {
+        Some(RespFrame::BulkString(cmd)) => {
+            Ok(String::from_utf8_lossy(cmd.as_ref()).to_ascii_lowercase())
+        }
+        _ => Err(CommandError::InvalidCommand(
+            "cmd expect to be BulkString type!".into(),
+        )),
+    }
+}
+
+/// CLIENT LIST里的cmd和ACL检查用的命令名，容器命令带上子命令，比如client|list
+pub fn command_label(array: &RespArray) -> String {
+    let name = command_name(array).unwrap_or_default();
+    let sub = match array.get(1) {
+        Some(RespFrame::BulkString(sub)) => String::from_utf8_lossy(sub.as_ref()).to_lowercase(),
+        _ => return name,
+    };
+    match name.as_str() {
+        "client" | "config" | "cluster" | "memory" | "object" | "slowlog" | "latency" | "acl" => {
+            format!("{name}|{sub}")
+        }
+        _ => name,
+    }
+}
+
+/// key已经存在并且不是期望的类型时返回WRONGTYPE错误
+fn check_type(backend: &Backend, key: &str, expected: &str) -> Option<RespFrame> {
+    match backend.key_type(key) {
+        Some(actual) if actual != expected => Some(
+            SimpleError::from("WRONGTYPE Operation against a key holding the wrong kind of value")
+                .into(),
+        ),
+        _ => None,
     }
 }
 
@@ -0,0 +1,164 @@
+use crate::{aof, rdb, Backend, RespArray, RespFrame, RespInteger, SimpleError, SimpleString};
+
+use super::{
+    validate_command, BgRewriteAof, BgSave, CommandError, CommandExecutor, LastSave, Save, RESP_OK,
+};
+
+/// SAVE会阻塞所有客户端直到写盘完成，network层执行它时持有写锁
+impl CommandExecutor for Save {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match rdb::save(backend) {
+            Ok(()) => RESP_OK.clone(),
+            Err(e) => SimpleError::from(format!("ERR {e}")).into(),
+        }
+    }
+}
+
+impl CommandExecutor for BgSave {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match rdb::bgsave(backend) {
+            Ok(()) => SimpleString::from("Background saving started").into(),
+            Err(e) => SimpleError::from(format!("ERR {e}")).into(),
+        }
+    }
+}
+
+/// 和BGSAVE一样，持锁期间只登记快照并切换incr文件，拷贝数据和写base放在后台线程
+impl CommandExecutor for BgRewriteAof {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        match aof::bgrewriteaof(backend) {
+            Ok(()) => SimpleString::from("Background append only file rewriting started").into(),
+            Err(e) => SimpleError::from(format!("ERR {e}")).into(),
+        }
+    }
+}
+
+impl CommandExecutor for LastSave {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        RespInteger::from(backend.rdb.last_save() as i64).into()
+    }
+}
+
+impl TryFrom<RespArray> for Save {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "save", 0)?;
+        Ok(Save)
+    }
+}
+
+impl TryFrom<RespArray> for BgSave {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "bgsave", 0)?;
+        Ok(BgSave)
+    }
+}
+
+impl TryFrom<RespArray> for LastSave {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "lastsave", 0)?;
+        Ok(LastSave)
+    }
+}
+
+impl TryFrom<RespArray> for BgRewriteAof {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "bgrewriteaof", 0)?;
+        Ok(BgRewriteAof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::{rdb::RdbConfig, RespBulkString};
+
+    fn backend_in_tmp(name: &str) -> Backend {
+        let backend = Backend::new();
+        let dir = std::env::temp_dir().join(format!("simple-redis-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        backend.rdb.set_config(RdbConfig {
+            dir,
+            ..Default::default()
+        });
+        backend
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let backend = backend_in_tmp("save");
+        backend.set("a".into(), RespBulkString::from("1").into());
+        backend.hset("h".into(), "f".into(), RespBulkString::from("v").into());
+        backend.set("ttl".into(), RespBulkString::from("x").into());
+        backend.expire_at("ttl", crate::backend::now_ms() + 100_000);
+        assert!(rdb::changes_since_save(&backend) > 0);
+
+        assert_eq!(Save.execute(&backend), RESP_OK.clone());
+        assert_eq!(rdb::changes_since_save(&backend), 0);
+
+        let loaded = Backend::new();
+        loaded.rdb.set_config(backend.rdb.config());
+        assert_eq!(rdb::load(&loaded).unwrap().loaded, 3);
+        assert_eq!(loaded.get("a"), Some(RespBulkString::from("1").into()));
+        assert_eq!(
+            loaded.hget("h", "f"),
+            Some(RespBulkString::from("v").into())
+        );
+        assert!(loaded.pttl("ttl") > 90_000);
+        assert_eq!(rdb::changes_since_save(&loaded), 0);
+        std::fs::remove_dir_all(backend.rdb.config().dir).unwrap();
+    }
+
+    #[test]
+    fn test_bgsave() {
+        let backend = backend_in_tmp("bgsave");
+        backend.set("a".into(), RespBulkString::from("1").into());
+
+        let reply = BgSave.execute(&backend);
+        assert_eq!(
+            reply,
+            SimpleString::from("Background saving started").into()
+        );
+        //BGSAVE开始之后的写入不会出现在文件里
+        backend.set("a".into(), RespBulkString::from("2").into());
+        backend.set("b".into(), RespBulkString::from("2").into());
+
+        let start = Instant::now();
+        while backend.rdb.bgsave_in_progress() && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(backend.rdb.last_bgsave_ok());
+        assert_eq!(rdb::changes_since_save(&backend), 2);
+
+        let loaded = Backend::new();
+        loaded.rdb.set_config(backend.rdb.config());
+        assert_eq!(rdb::load(&loaded).unwrap().loaded, 1);
+        assert_eq!(loaded.get("a"), Some(RespBulkString::from("1").into()));
+        std::fs::remove_dir_all(backend.rdb.config().dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_rules() {
+        let backend = Backend::new();
+        backend.rdb.set_config(RdbConfig {
+            save_rules: vec![rdb::SaveRule {
+                seconds: 0,
+                changes: 2,
+            }],
+            ..Default::default()
+        });
+        backend.set("a".into(), RespBulkString::from("1").into());
+        assert!(!rdb::should_save(&backend));
+        backend.set("b".into(), RespBulkString::from("1").into());
+        assert!(rdb::should_save(&backend));
+    }
+}
@@ -0,0 +1,246 @@
+use crate::{aof::command_array, backend::now_ms, Backend, RespArray, RespBulkString, RespFrame};
+
+use super::{Command, CommandExecutor, PExpireAt, Queued};
+
+impl Command {
+    /// 相对过期时间在执行时换算成绝对时间，这样写进AOF的PEXPIREAT和RESTORE ... ABSTTL重放结果是确定的
+    pub fn normalize(self) -> Self {
+        match self {
+            //溢出的过期时间不换算，执行时回复错误，也就不会传播
+            Command::Expire(cmd) => match cmd.deadline() {
+                Some(when) => PExpireAt::new(cmd.key, when).into(),
+                None => cmd.into(),
+            },
+            Command::PExpire(cmd) => match cmd.deadline() {
+                Some(when) => PExpireAt::new(cmd.key, when).into(),
+                None => cmd.into(),
+            },
+            Command::Restore(mut cmd) if cmd.ttl > 0 && !cmd.absttl => {
+                cmd.ttl = cmd.ttl.saturating_add(now_ms());
+                cmd.absttl = true;
+                cmd.into()
+            }
+            cmd => cmd,
+        }
+    }
+
+    /// 会修改数据的命令，只读的replica拒绝客户端发来的这些命令
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Command::Set(_)
+                | Command::HSet(_)
+                | Command::RPush(_)
+                | Command::SAdd(_)
+                | Command::ZAdd(_)
+                | Command::Del(_)
+                | Command::Expire(_)
+                | Command::PExpire(_)
+                | Command::PExpireAt(_)
+                | Command::FlushDb(_)
+                | Command::Restore(_)
+        )
+    }
+
+    /// 可能占用更多内存的命令，超过maxmemory又淘汰不了key时拒绝执行
+    pub fn uses_memory(&self) -> bool {
+        matches!(
+            self,
+            Command::Set(_)
+                | Command::HSet(_)
+                | Command::RPush(_)
+                | Command::SAdd(_)
+                | Command::ZAdd(_)
+                | Command::Restore(_)
+        )
+    }
+
+    /// 写命令需要追加到AOF的RESP数组，只读命令返回None
+    pub fn to_write_array(&self) -> Option<RespArray> {
+        let array = match self {
+            Command::Set(cmd) => {
+                let mut array = command_array(["SET", &cmd.key]);
+                array.0.push(cmd.value.clone());
+                array
+            }
+            Command::HSet(cmd) => {
+                let mut array = command_array(["HSET", &cmd.table_name, &cmd.key]);
+                array.0.push(cmd.value.clone());
+                array
+            }
+            Command::RPush(cmd) => {
+                let mut array = command_array(["RPUSH", &cmd.key]);
+                array.0.extend(cmd.values.iter().cloned());
+                array
+            }
+            Command::SAdd(cmd) => {
+                let mut array = command_array(["SADD", &cmd.key]);
+                array.0.extend(cmd.members.iter().cloned());
+                array
+            }
+            Command::ZAdd(cmd) => {
+                let mut array = command_array(["ZADD", &cmd.key]);
+                for (member, score) in &cmd.members {
+                    array
+                        .0
+                        .push(RespBulkString::new(score.to_string().into_bytes()).into());
+                    array.0.push(member.clone());
+                }
+                array
+            }
+            Command::Del(cmd) => {
+                command_array(std::iter::once("DEL").chain(cmd.keys.iter().map(String::as_str)))
+            }
+            Command::Expire(cmd) => command_array(["EXPIRE", &cmd.key, &cmd.seconds.to_string()]),
+            Command::PExpire(cmd) => command_array(["PEXPIRE", &cmd.key, &cmd.millis.to_string()]),
+            Command::PExpireAt(cmd) => {
+                command_array(["PEXPIREAT", &cmd.key, &cmd.timestamp_ms.to_string()])
+            }
+            Command::FlushDb(_) => command_array(["FLUSHDB"]),
+            Command::Restore(cmd) => {
+                let mut array = command_array(["RESTORE", &cmd.key, &cmd.ttl.to_string()]);
+                array
+                    .0
+                    .push(RespBulkString::new(cmd.payload.clone()).into());
+                if cmd.replace {
+                    array.0.push(RespBulkString::from("REPLACE").into());
+                }
+                if cmd.absttl {
+                    array.0.push(RespBulkString::from("ABSTTL").into());
+                }
+                if let Some(idletime) = cmd.idletime {
+                    array
+                        .0
+                        .extend(command_array(["IDLETIME", &idletime.to_string()]).0);
+                }
+                if let Some(freq) = cmd.freq {
+                    array.0.extend(command_array(["FREQ", &freq.to_string()]).0);
+                }
+                array
+            }
+            _ => return None,
+        };
+        Some(array)
+    }
+}
+
+/// 执行一条普通命令，执行成功的写命令会被追加到AOF并发给所有replica。
+/// 写命令在执行和追加期间持有Backend的写命令锁，保证AOF和复制流里的顺序和内存里的执行顺序一致
+pub fn execute_command(cmd: Command, backend: &Backend) -> RespFrame {
+    let cmd = cmd.normalize();
+    if !is_propagating(backend) {
+        return cmd.execute(backend);
+    }
+    let Some(array) = cmd.to_write_array() else {
+        return cmd.execute(backend);
+    };
+
+    let _guard = backend.write_lock();
+    let reply = cmd.execute(backend);
+    if !is_error(&reply) {
+        propagate(backend, &[array]);
+    }
+    reply
+}
+
+/// 在已经独占Backend的情况下执行事务里的命令，写命令用MULTI/EXEC包起来整体追加
+pub fn execute_transaction(
+    cmds: Vec<Queued>,
+    backend: &Backend,
+    mut run_session: impl FnMut(RespArray) -> RespFrame,
+) -> Vec<RespFrame> {
+    let propagating = is_propagating(backend);
+    let mut writes = Vec::new();
+    let replies = cmds
+        .into_iter()
+        .map(|cmd| {
+            let cmd = match cmd {
+                Queued::Command(cmd) => cmd.normalize(),
+                Queued::Session(array) => return run_session(array),
+            };
+            let array = if propagating {
+                cmd.to_write_array()
+            } else {
+                None
+            };
+            let reply = cmd.execute(backend);
+            if let Some(array) = array.filter(|_| !is_error(&reply)) {
+                writes.push(array);
+            }
+            reply
+        })
+        .collect();
+
+    if !writes.is_empty() {
+        writes.insert(0, command_array(["MULTI"]));
+        writes.push(command_array(["EXEC"]));
+        propagate(backend, &writes);
+    }
+    replies
+}
+
+fn is_propagating(backend: &Backend) -> bool {
+    backend.aof.is_enabled() || backend.repl.is_feeding()
+}
+
+fn propagate(backend: &Backend, commands: &[RespArray]) {
+    let repl = &backend.repl;
+    //WAITAOF按复制流的offset判断是否已经落盘，所以开启了AOF的master没有replica也要维护复制流
+    if backend.aof.is_enabled() && repl.is_master() {
+        repl.ensure_backlog();
+    }
+    if repl.is_feeding() {
+        repl.feed_commands(commands);
+    }
+    if backend.aof.is_enabled() {
+        backend.aof.append(commands, repl.offset());
+    }
+}
+
+fn is_error(reply: &RespFrame) -> bool {
+    matches!(reply, RespFrame::SimpleError(_) | RespFrame::BulkErrors(_))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Expire, PExpire, Set, SimpleError};
+
+    #[test]
+    fn test_normalize_expire() {
+        let cmd = Command::from(Expire::new("k".into(), 10)).normalize();
+        let Command::PExpireAt(cmd) = cmd else {
+            panic!("EXPIRE should become PEXPIREAT");
+        };
+        let expected = now_ms() as i64 + 10_000;
+        assert!((expected - cmd.timestamp_ms).abs() < 1000);
+    }
+
+    #[test]
+    fn test_expire_overflow() {
+        let backend = Backend::new();
+        backend.set("k".into(), RespBulkString::from("v").into());
+        let cmd = Command::from(Expire::new("k".into(), i64::MAX)).normalize();
+        assert!(matches!(cmd, Command::Expire(_)));
+        assert_eq!(
+            cmd.execute(&backend),
+            SimpleError::from("ERR invalid expire time in 'expire' command").into()
+        );
+        let cmd = Command::from(PExpire::new("k".into(), i64::MAX)).normalize();
+        assert_eq!(
+            cmd.execute(&backend),
+            SimpleError::from("ERR invalid expire time in 'pexpire' command").into()
+        );
+        //key没有被删除
+        assert!(backend.get("k").is_some());
+        assert_eq!(backend.pttl("k"), -1);
+    }
+
+    #[test]
+    fn test_to_write_array() {
+        let cmd = Command::from(Set::new("k".into(), RespBulkString::from("v").into()));
+        assert_eq!(cmd.to_write_array(), Some(command_array(["SET", "k", "v"])));
+        let cmd = Command::from(crate::Get::new("k".into()));
+        assert_eq!(cmd.to_write_array(), None);
+    }
+}
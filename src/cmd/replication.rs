@@ -0,0 +1,198 @@
+use crate::{
+    network::Session, replication, Backend, RespArray, RespFrame, SimpleError, SimpleString,
+};
+
+use super::{
+    command_name, extract_cmd_args, integer_arg, string_arg, CommandError, CommandExecutor,
+    ReplicaOf, RESP_OK,
+};
+
+impl CommandExecutor for ReplicaOf {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        let Some((host, port)) = self.master else {
+            replication::promote(backend);
+            return RESP_OK.clone();
+        };
+        match replication::replicate(backend, host, port) {
+            Ok(true) => RESP_OK.clone(),
+            Ok(false) => SimpleString::from("OK Already connected to specified master").into(),
+            Err(e) => SimpleError::from(format!("ERR {e}")).into(),
+        }
+    }
+}
+
+///*3\r\n$9\r\nreplicaof\r\n$9\r\n127.0.0.1\r\n$4\r\n6379\r\n
+impl TryFrom<RespArray> for ReplicaOf {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let name = command_name(&value)?;
+        if value.len() != 3 {
+            return Err(CommandError::InvalidArgument(format!(
+                "{name} command should have exactly 2 argument(s)!"
+            )));
+        }
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let (host, port) = match (args.next(), args.next()) {
+            (Some(host), Some(port)) => (string_arg(host)?, string_arg(port)?),
+            _ => unreachable!("argument count checked above"),
+        };
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            return Ok(ReplicaOf::new(None));
+        }
+        let port = port
+            .parse::<u16>()
+            .map_err(|_| CommandError::InvalidArgument("Invalid master port".into()))?;
+        Ok(ReplicaOf::new(Some((host, port))))
+    }
+}
+
+/// REPLCONF option value [option value ...]，replica握手时用来告诉master自己的信息
+#[derive(Debug, PartialEq)]
+pub struct ReplConf {
+    pub options: Vec<(String, String)>,
+}
+
+impl ReplConf {
+    /// REPLCONF修改的是连接状态，由network层直接调用
+    pub fn execute(self, session: &mut Session) -> Result<RespFrame, CommandError> {
+        for (option, value) in self.options {
+            match option.as_str() {
+                "listening-port" => {
+                    let port = value.parse::<u16>().map_err(|_| {
+                        CommandError::InvalidArgument("value is not a valid port".into())
+                    })?;
+                    session.replica_listening_port = Some(port);
+                }
+                //ACK和GETACK只在复制连接上才有意义
+                "capa" | "ip-address" | "ack" | "fack" | "getack" => {}
+                _ => {
+                    return Err(CommandError::InvalidArgument(format!(
+                        "Unrecognized REPLCONF option: {option}"
+                    )))
+                }
+            }
+        }
+        Ok(RESP_OK.clone())
+    }
+
+    /// REPLCONF ACK offset里replica报告的offset
+    pub fn ack(&self) -> Option<u64> {
+        self.options
+            .iter()
+            .find(|(option, _)| option == "ack")
+            .and_then(|(_, value)| value.parse().ok())
+    }
+
+    /// REPLCONF ACK offset FACK aofoffset里已经fsync到AOF的offset
+    pub fn fack(&self) -> Option<u64> {
+        self.options
+            .iter()
+            .find(|(option, _)| option == "fack")
+            .and_then(|(_, value)| value.parse().ok())
+    }
+}
+
+impl TryFrom<RespArray> for ReplConf {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        if value.len() < 3 || value.len().is_multiple_of(2) {
+            return Err(CommandError::InvalidArgument(
+                "replconf command should have option/value pairs as arguments!".into(),
+            ));
+        }
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let mut options = Vec::new();
+        while let (Some(option), Some(value)) = (args.next(), args.next()) {
+            options.push((string_arg(option)?.to_ascii_lowercase(), string_arg(value)?));
+        }
+        Ok(ReplConf { options })
+    }
+}
+
+/// PSYNC replid offset，或者老版本replica使用的SYNC
+#[derive(Debug, PartialEq)]
+pub enum Psync {
+    Psync { replid: String, offset: i64 },
+    Sync,
+}
+
+impl TryFrom<RespArray> for Psync {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        let name = command_name(&value)?;
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        match (name.as_str(), args.next(), args.next(), args.next()) {
+            ("sync", None, None, None) => Ok(Psync::Sync),
+            ("psync", Some(replid), Some(offset), None) => Ok(Psync::Psync {
+                replid: string_arg(replid)?,
+                offset: integer_arg(offset)?,
+            }),
+            _ => Err(CommandError::InvalidArgument(format!(
+                "wrong number of arguments for '{name}' command"
+            ))),
+        }
+    }
+}
+
+/// 请求是PSYNC或SYNC时返回解析结果，network层靠它把连接交给复制模块
+pub fn sync_request(frame: &RespFrame) -> Option<Result<Psync, CommandError>> {
+    let RespFrame::Arrays(array) = frame else {
+        return None;
+    };
+    match command_name(array).ok()?.as_str() {
+        "psync" | "sync" => Some(Psync::try_from(array.clone())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aof::command_array;
+
+    #[test]
+    fn test_replicaof_from_resp_array() {
+        let cmd = ReplicaOf::try_from(command_array(["REPLICAOF", "127.0.0.1", "6380"])).unwrap();
+        assert_eq!(cmd.master, Some(("127.0.0.1".into(), 6380)));
+        let cmd = ReplicaOf::try_from(command_array(["slaveof", "NO", "one"])).unwrap();
+        assert_eq!(cmd.master, None);
+        assert!(ReplicaOf::try_from(command_array(["replicaof", "host", "port"])).is_err());
+        assert!(ReplicaOf::try_from(command_array(["replicaof", "host"])).is_err());
+    }
+
+    #[test]
+    fn test_replconf_and_psync() {
+        let mut session = Session::new();
+        let cmd = ReplConf::try_from(command_array([
+            "REPLCONF",
+            "listening-port",
+            "6380",
+            "capa",
+            "psync2",
+        ]))
+        .unwrap();
+        assert_eq!(cmd.execute(&mut session).unwrap(), RESP_OK.clone());
+        assert_eq!(session.replica_listening_port, Some(6380));
+
+        let cmd = ReplConf::try_from(command_array(["REPLCONF", "ACK", "100"])).unwrap();
+        assert_eq!(cmd.ack(), Some(100));
+        assert_eq!(cmd.fack(), None);
+        let cmd =
+            ReplConf::try_from(command_array(["REPLCONF", "ACK", "100", "FACK", "90"])).unwrap();
+        assert_eq!(cmd.fack(), Some(90));
+
+        let frame = command_array(["PSYNC", "?", "-1"]).into();
+        let psync = sync_request(&frame).unwrap().unwrap();
+        assert_eq!(
+            psync,
+            Psync::Psync {
+                replid: "?".into(),
+                offset: -1
+            }
+        );
+        assert!(sync_request(&command_array(["get", "a"]).into()).is_none());
+    }
+}
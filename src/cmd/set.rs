@@ -0,0 +1,44 @@
+use crate::{Backend, RespArray, RespFrame, RespInteger};
+
+use super::{check_type, extract_cmd_args, string_arg, CommandError, CommandExecutor, SAdd};
+
+impl CommandExecutor for SAdd {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if let Some(err) = check_type(backend, &self.key, "set") {
+            return err;
+        }
+        let added = backend.sadd(self.key, self.members);
+        RespInteger::from(added as i64).into()
+    }
+}
+
+impl TryFrom<RespArray> for SAdd {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        if value.len() < 3 {
+            return Err(CommandError::InvalidArgument(
+                "sadd command should have at least 2 argument(s)!".into(),
+            ));
+        }
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let key = args.next().map(string_arg).transpose()?.unwrap_or_default();
+        Ok(SAdd::new(key, args.collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aof::command_array;
+
+    #[test]
+    fn test_sadd_execute() {
+        let backend = Backend::new();
+        let cmd = SAdd::try_from(command_array(["sadd", "s", "a", "b", "a"])).unwrap();
+        assert_eq!(cmd.execute(&backend), RespInteger::from(2).into());
+        let cmd = SAdd::try_from(command_array(["sadd", "s", "b", "c"])).unwrap();
+        assert_eq!(cmd.execute(&backend), RespInteger::from(1).into());
+        assert_eq!(backend.sets.get("s").unwrap().len(), 3);
+    }
+}
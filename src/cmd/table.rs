@@ -0,0 +1,141 @@
+/*
+命令表：
+    每个命令的名字和所属的ACL分类，和Redis的命令表一致。
+    ACL的+@category规则、ACL CAT和ACL DRYRUN都从这里取命令的分类，
+    不在表里的命令按未知命令处理，不做ACL检查
+*/
+
+/// ACL分类，和Redis一样
+pub const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "set",
+    "sortedset",
+    "list",
+    "hash",
+    "string",
+    "bitmap",
+    "hyperloglog",
+    "geo",
+    "stream",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "blocking",
+    "dangerous",
+    "connection",
+    "transaction",
+    "scripting",
+];
+
+/// 命令表里的一项
+#[derive(Debug, PartialEq)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub categories: &'static [&'static str],
+}
+
+impl CommandSpec {
+    pub fn in_category(&self, category: &str) -> bool {
+        category == "all" || self.categories.contains(&category)
+    }
+}
+
+const fn spec(name: &'static str, categories: &'static [&'static str]) -> CommandSpec {
+    CommandSpec { name, categories }
+}
+
+pub static COMMAND_TABLE: &[CommandSpec] = &[
+    spec("ping", &["fast", "connection"]),
+    spec("get", &["read", "string", "fast"]),
+    spec("set", &["write", "string", "slow"]),
+    spec("hget", &["read", "hash", "fast"]),
+    spec("hset", &["write", "hash", "fast"]),
+    spec("hgetall", &["read", "hash", "slow"]),
+    spec("rpush", &["write", "list", "fast"]),
+    spec("sadd", &["write", "set", "fast"]),
+    spec("zadd", &["write", "sortedset", "fast"]),
+    spec("info", &["slow", "dangerous"]),
+    spec("del", &["keyspace", "write", "slow"]),
+    spec("expire", &["keyspace", "write", "fast"]),
+    spec("pexpire", &["keyspace", "write", "fast"]),
+    spec("pexpireat", &["keyspace", "write", "fast"]),
+    spec("ttl", &["keyspace", "read", "fast"]),
+    spec("pttl", &["keyspace", "read", "fast"]),
+    spec("flushdb", &["keyspace", "write", "slow", "dangerous"]),
+    spec("type", &["keyspace", "read", "fast"]),
+    spec("save", &["admin", "slow", "dangerous"]),
+    spec("bgsave", &["admin", "slow", "dangerous"]),
+    spec("lastsave", &["admin", "fast", "dangerous"]),
+    spec("bgrewriteaof", &["admin", "slow", "dangerous"]),
+    spec("replicaof", &["admin", "slow", "dangerous"]),
+    spec("slaveof", &["admin", "slow", "dangerous"]),
+    spec("cluster", &["slow"]),
+    spec("dump", &["keyspace", "read", "slow"]),
+    spec("restore", &["keyspace", "write", "slow", "dangerous"]),
+    spec(
+        "restore-asking",
+        &["keyspace", "write", "slow", "dangerous"],
+    ),
+    spec("memory", &["slow"]),
+    spec("object", &["keyspace", "read", "slow"]),
+    spec("config", &["admin", "slow", "dangerous"]),
+    spec("slowlog", &["admin", "slow", "dangerous"]),
+    spec("latency", &["admin", "slow", "dangerous"]),
+    spec("multi", &["fast", "transaction"]),
+    spec("exec", &["slow", "transaction"]),
+    spec("discard", &["fast", "transaction"]),
+    spec("watch", &["fast", "transaction"]),
+    spec("unwatch", &["fast", "transaction"]),
+    spec("hello", &["fast", "connection"]),
+    spec("auth", &["fast", "connection"]),
+    spec("client", &["slow", "connection"]),
+    spec("acl", &["slow"]),
+    spec("replconf", &["admin", "slow", "dangerous"]),
+    spec("psync", &["admin", "slow", "dangerous"]),
+    spec("sync", &["admin", "slow", "dangerous"]),
+    spec("wait", &["slow", "connection"]),
+    spec("waitaof", &["slow", "connection"]),
+    spec("monitor", &["admin", "slow", "dangerous"]),
+    spec("asking", &["fast", "connection"]),
+    spec("migrate", &["keyspace", "write", "slow", "dangerous"]),
+];
+
+/// 按小写的命令名查找
+pub fn command_spec(name: &str) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE.iter().find(|spec| spec.name == name)
+}
+
+/// 属于某个分类的所有命令
+pub fn commands_in_category(category: &str) -> Vec<&'static str> {
+    COMMAND_TABLE
+        .iter()
+        .filter(|spec| spec.in_category(category))
+        .map(|spec| spec.name)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_table() {
+        let get = command_spec("get").unwrap();
+        assert!(get.in_category("read"));
+        assert!(get.in_category("all"));
+        assert!(!get.in_category("write"));
+        assert!(command_spec("GET").is_none());
+        assert!(command_spec("nosuchcommand").is_none());
+
+        //命令表里的分类都是已知的分类，命令名不重复
+        for (i, spec) in COMMAND_TABLE.iter().enumerate() {
+            assert!(spec.categories.iter().all(|c| CATEGORIES.contains(c)));
+            assert!(COMMAND_TABLE[i + 1..].iter().all(|s| s.name != spec.name));
+        }
+        let hash = commands_in_category("hash");
+        assert_eq!(hash, vec!["hget", "hset", "hgetall"]);
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{Backend, RespArray, RespFrame, RespNullArray, SimpleString};
+
+use super::{
+    execute_transaction, extract_cmd_args, string_arg, validate_command, Command, CommandError,
+    CommandExecutor, Unrecognized, Unwatch, RESP_OK,
+};
+
+/// 事务外的UNWATCH由network层直接清空session里watch的key；
+/// 事务里的UNWATCH只是排队，EXEC本身就会清空watch，所以执行时什么都不用做
+impl CommandExecutor for Unwatch {
+    fn execute(self, _backend: &Backend) -> RespFrame {
+        RESP_OK.clone()
+    }
+}
+
+impl TryFrom<RespArray> for Unwatch {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        validate_command(&value, "unwatch", 0)?;
+        Ok(Unwatch)
+    }
+}
+
+/// 排队的命令，HELLO、CLIENT这类需要访问连接状态的命令原样保存，EXEC时交给network层执行
+#[derive(Debug)]
+pub enum Queued {
+    Command(Command),
+    Session(RespArray),
+}
+
+impl Queued {
+    fn keys(&self) -> Vec<&str> {
+        match self {
+            Queued::Command(cmd) => cmd.keys(),
+            Queued::Session(_) => Vec::new(),
+        }
+    }
+}
+
+/// 每个连接的事务状态：MULTI之后排队的命令，以及WATCH的key和当时的版本号
+#[derive(Debug, Default)]
+pub struct Transaction {
+    queued: Option<Vec<Queued>>,
+    /// 排队时出现过错误，EXEC会直接返回EXECABORT
+    dirty: bool,
+    watched: Vec<(String, u64)>,
+}
+
+impl Transaction {
+    pub fn in_multi(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    /// 排队的命令个数，不在事务里时为None
+    pub fn queued_len(&self) -> Option<usize> {
+        self.queued.as_ref().map(Vec::len)
+    }
+
+    /// 所有排队的命令访问的key
+    pub fn keys(&self) -> Vec<&str> {
+        self.queued
+            .iter()
+            .flatten()
+            .flat_map(Queued::keys)
+            .collect()
+    }
+
+    pub fn multi(&mut self) -> Result<RespFrame, CommandError> {
+        if self.in_multi() {
+            return Err(CommandError::InvalidCommand(
+                "MULTI calls can not be nested".into(),
+            ));
+        }
+        self.queued = Some(Vec::new());
+        Ok(RESP_OK.clone())
+    }
+
+    /// 解析并排队一条命令，解析失败会让整个事务在EXEC时被放弃。
+    /// check做和不在事务里时一样的检查，比如只读replica拒绝写命令、cluster模式下的重定向。
+    /// 和Redis一样拒绝带no-multi标记的命令，MULTI和WATCH由调用者处理。
+    /// MIGRATE要和目标节点通信，不能在持有写锁的EXEC里执行，它删除key的DEL也没法和事务的写入一起传播
+    pub fn queue(
+        &mut self,
+        name: &str,
+        value: RespArray,
+        check: impl Fn(&Command) -> Result<(), CommandError>,
+    ) -> Result<RespFrame, CommandError> {
+        let ret = match name {
+            "psync" | "sync" | "migrate" => Err(CommandError::InvalidCommand(
+                "Command not allowed inside a transaction".into(),
+            )),
+            "hello" | "client" | "replconf" | "wait" | "waitaof" | "asking" | "auth" | "acl"
+            | "monitor" => Ok(Queued::Session(value)),
+            _ => Command::try_from(value).and_then(|cmd| match cmd {
+                Command::Unrecognized(Unrecognized { name }) => Err(CommandError::InvalidCommand(
+                    format!("unknown command '{name}'"),
+                )),
+                cmd => check(&cmd).map(|_| Queued::Command(cmd)),
+            }),
+        };
+
+        match (ret, self.queued.as_mut()) {
+            (Ok(cmd), Some(queued)) => {
+                queued.push(cmd);
+                Ok(SimpleString::from("QUEUED").into())
+            }
+            (Err(e), _) => {
+                self.dirty = true;
+                Err(e)
+            }
+            (Ok(_), None) => Err(CommandError::InvalidCommand(
+                "command queued without MULTI".into(),
+            )),
+        }
+    }
+
+    /// 在MULTI里被拒绝的命令也会让整个事务在EXEC时被放弃
+    pub fn flag_error(&mut self) {
+        if self.in_multi() {
+            self.dirty = true;
+        }
+    }
+
+    pub fn discard(&mut self) -> Result<RespFrame, CommandError> {
+        if self.queued.take().is_none() {
+            return Err(CommandError::InvalidCommand("DISCARD without MULTI".into()));
+        }
+        self.dirty = false;
+        self.unwatch();
+        Ok(RESP_OK.clone())
+    }
+
+    /// 持有backend的写锁执行所有排队的命令，期间其他连接的命令都会被挡住。
+    /// 排队的连接命令交给run_session执行
+    pub fn exec(
+        &mut self,
+        backend: &Backend,
+        run_session: impl FnMut(RespArray) -> RespFrame,
+    ) -> Result<RespFrame, CommandError> {
+        let Some(queued) = self.queued.take() else {
+            return Err(CommandError::InvalidCommand("EXEC without MULTI".into()));
+        };
+        let dirty = std::mem::take(&mut self.dirty);
+        let watched = std::mem::take(&mut self.watched);
+        if dirty {
+            return Err(CommandError::ExecAbort);
+        }
+
+        let _guard = backend.exclusive_lock();
+        for (key, version) in watched {
+            //WATCH之后过期的key要先删掉，删除会改变版本号
+            backend.expire_if_needed(&key);
+            if backend.version(&key) != version {
+                return Ok(RespNullArray.into());
+            }
+        }
+
+        let replies = execute_transaction(queued, backend, run_session);
+        Ok(RespArray::new(replies).into())
+    }
+
+    pub fn watch(
+        &mut self,
+        value: RespArray,
+        backend: &Backend,
+    ) -> Result<RespFrame, CommandError> {
+        if self.in_multi() {
+            return Err(CommandError::InvalidCommand(
+                "WATCH inside MULTI is not allowed".into(),
+            ));
+        }
+        if value.len() < 2 {
+            return Err(CommandError::InvalidArgument(
+                "watch command should have at least 1 argument(s)!".into(),
+            ));
+        }
+
+        for key in extract_cmd_args(value, 1)? {
+            let key = string_arg(key)?;
+            if self.watched.iter().any(|(k, _)| *k == key) {
+                continue;
+            }
+            backend.expire_if_needed(&key);
+            let version = backend.version(&key);
+            self.watched.push((key, version));
+        }
+        Ok(RESP_OK.clone())
+    }
+
+    pub fn unwatch(&mut self) -> RespFrame {
+        self.watched.clear();
+        RESP_OK.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RespBulkString;
+
+    fn cmd(args: &[&'static str]) -> RespArray {
+        RespArray::new(
+            args.iter()
+                .map(|arg| RespBulkString::from(*arg).into())
+                .collect(),
+        )
+    }
+
+    fn queue(tx: &mut Transaction, args: &[&'static str]) -> Result<RespFrame, CommandError> {
+        tx.queue(args[0], cmd(args), |_| Ok(()))
+    }
+
+    /// 排队的连接命令原样回显，方便检查执行顺序
+    fn exec(tx: &mut Transaction, backend: &Backend) -> Result<RespFrame, CommandError> {
+        tx.exec(backend, RespFrame::from)
+    }
+
+    #[test]
+    fn test_multi_exec() -> Result<(), CommandError> {
+        let backend = Backend::new();
+        let mut tx = Transaction::default();
+
+        assert!(exec(&mut tx, &backend).is_err());
+        tx.multi()?;
+        assert!(tx.multi().is_err());
+        assert_eq!(
+            queue(&mut tx, &["set", "a", "1"])?,
+            SimpleString::from("QUEUED").into()
+        );
+        queue(&mut tx, &["get", "a"])?;
+        assert_eq!(backend.get("a"), None);
+
+        let ret = exec(&mut tx, &backend)?;
+        assert_eq!(
+            ret,
+            RespArray::new(vec![RESP_OK.clone(), RespBulkString::from("1").into()]).into()
+        );
+        assert!(!tx.in_multi());
+        Ok(())
+    }
+
+    #[test]
+    fn test_exec_abort_and_discard() -> Result<(), CommandError> {
+        let backend = Backend::new();
+        let mut tx = Transaction::default();
+
+        tx.multi()?;
+        queue(&mut tx, &["set", "a", "1"])?;
+        assert!(queue(&mut tx, &["foo"]).is_err());
+        assert!(queue(&mut tx, &["get"]).is_err());
+        assert!(matches!(
+            exec(&mut tx, &backend),
+            Err(CommandError::ExecAbort)
+        ));
+        assert_eq!(backend.get("a"), None);
+
+        tx.multi()?;
+        queue(&mut tx, &["set", "a", "1"])?;
+        tx.discard()?;
+        assert!(tx.discard().is_err());
+        assert_eq!(backend.get("a"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch() -> Result<(), CommandError> {
+        let backend = Backend::new();
+        let mut tx = Transaction::default();
+
+        tx.watch(cmd(&["watch", "a"]), &backend)?;
+        backend.set("a".into(), RespBulkString::from("other").into());
+        tx.multi()?;
+        queue(&mut tx, &["set", "a", "1"])?;
+        assert_eq!(exec(&mut tx, &backend)?, RespNullArray.into());
+        assert_eq!(backend.get("a"), Some(RespBulkString::from("other").into()));
+
+        //EXEC之后会自动UNWATCH
+        backend.set("a".into(), RespBulkString::from("again").into());
+        tx.multi()?;
+        queue(&mut tx, &["set", "a", "1"])?;
+        assert!(matches!(exec(&mut tx, &backend)?, RespFrame::Arrays(_)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch_detects_expire_and_flushdb() -> Result<(), CommandError> {
+        let backend = Backend::new();
+        let mut tx = Transaction::default();
+
+        backend.set("a".into(), RespBulkString::from("1").into());
+        backend.expire_at("a", crate::backend::now_ms() + 10_000);
+        tx.watch(cmd(&["watch", "a"]), &backend)?;
+        backend
+            .expires
+            .insert("a".into(), crate::backend::now_ms() - 1);
+        tx.multi()?;
+        assert_eq!(exec(&mut tx, &backend)?, RespNullArray.into());
+
+        backend.set("b".into(), RespBulkString::from("1").into());
+        tx.watch(cmd(&["watch", "b"]), &backend)?;
+        backend.flushdb();
+        tx.multi()?;
+        assert_eq!(exec(&mut tx, &backend)?, RespNullArray.into());
+
+        tx.watch(cmd(&["watch", "c"]), &backend)?;
+        tx.unwatch();
+        backend.set("c".into(), RespBulkString::from("1").into());
+        tx.multi()?;
+        assert_eq!(exec(&mut tx, &backend)?, RespArray::new(vec![]).into());
+        Ok(())
+    }
+
+    #[test]
+    fn test_queue_session_commands() -> Result<(), CommandError> {
+        let backend = Backend::new();
+        let mut tx = Transaction::default();
+
+        tx.multi()?;
+        queue(&mut tx, &["client", "setname", "app"])?;
+        queue(&mut tx, &["set", "a", "1"])?;
+        assert_eq!(
+            exec(&mut tx, &backend)?,
+            RespArray::new(vec![
+                cmd(&["client", "setname", "app"]).into(),
+                RESP_OK.clone()
+            ])
+            .into()
+        );
+
+        tx.multi()?;
+        assert!(queue(&mut tx, &["psync", "?", "-1"]).is_err());
+        assert!(matches!(
+            exec(&mut tx, &backend),
+            Err(CommandError::ExecAbort)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_watch_inside_multi() -> Result<(), CommandError> {
+        let backend = Backend::new();
+        let mut tx = Transaction::default();
+        tx.multi()?;
+        assert!(tx.watch(cmd(&["watch", "a"]), &backend).is_err());
+        Ok(())
+    }
+}
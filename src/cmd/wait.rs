@@ -0,0 +1,339 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{
+    network::Session, replication, Backend, RespArray, RespFrame, RespInteger, SimpleError,
+};
+
+use super::{extract_cmd_args, integer_arg, CommandError, Migrate};
+
+/// WAIT numreplicas timeout
+#[derive(Debug, PartialEq)]
+pub struct Wait {
+    pub numreplicas: usize,
+    /// 毫秒，0表示一直等下去
+    pub timeout: u64,
+}
+
+/// WAITAOF numlocal numreplicas timeout
+#[derive(Debug, PartialEq)]
+pub struct WaitAof {
+    pub numlocal: usize,
+    pub numreplicas: usize,
+    pub timeout: u64,
+}
+
+/// 阻塞中的连接在等待的条件，offset是这个连接最后一次写命令之后复制流的offset
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Condition {
+    Replicas {
+        offset: u64,
+        numreplicas: usize,
+    },
+    Aof {
+        offset: u64,
+        numlocal: usize,
+        numreplicas: usize,
+    },
+}
+
+/// 不能立即回复的命令，由连接循环异步等待，等待期间这个连接不处理新的请求
+#[derive(Debug)]
+pub struct Blocked(Pending);
+
+#[derive(Debug)]
+enum Pending {
+    /// WAIT/WAITAOF的条件还不满足
+    Acks {
+        condition: Condition,
+        deadline: Option<Instant>,
+    },
+    /// MIGRATE需要和目标节点通信
+    Migrate(Migrate),
+}
+
+impl Wait {
+    /// 条件已经满足时直接回复，否则在session上登记阻塞，返回的是超时时的回复
+    pub fn execute(
+        self,
+        session: &mut Session,
+        backend: &Backend,
+    ) -> Result<RespFrame, CommandError> {
+        if !backend.repl.is_master() {
+            return Ok(SimpleError::from(
+                "ERR WAIT cannot be used with replica instances. Please also note that since Redis 4.0 if a replica is configured to be writable (which is not the default) writes to replicas are just local and are not propagated.",
+            )
+            .into());
+        }
+        let condition = Condition::Replicas {
+            offset: session.woff,
+            numreplicas: self.numreplicas,
+        };
+        Ok(block(session, backend, condition, self.timeout))
+    }
+}
+
+impl WaitAof {
+    pub fn execute(
+        self,
+        session: &mut Session,
+        backend: &Backend,
+    ) -> Result<RespFrame, CommandError> {
+        if !backend.repl.is_master() {
+            return Ok(SimpleError::from(
+                "ERR WAITAOF cannot be used with replica instances. Please also note that writes to replicas are just local and are not propagated.",
+            )
+            .into());
+        }
+        if self.numlocal > 0 && !backend.aof.is_enabled() {
+            return Ok(SimpleError::from(
+                "ERR WAITAOF cannot be used when numlocal is set but appendonly is disabled.",
+            )
+            .into());
+        }
+        let condition = Condition::Aof {
+            offset: session.woff,
+            numlocal: self.numlocal,
+            numreplicas: self.numreplicas,
+        };
+        Ok(block(session, backend, condition, self.timeout))
+    }
+}
+
+fn block(
+    session: &mut Session,
+    backend: &Backend,
+    condition: Condition,
+    timeout: u64,
+) -> RespFrame {
+    if !condition.satisfied(backend) {
+        session.blocked = Some(Blocked(Pending::Acks {
+            condition,
+            deadline: (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout)),
+        }));
+    }
+    condition.reply(backend)
+}
+
+impl Condition {
+    /// (已经fsync到本地AOF的数量, 满足条件的replica数量)
+    fn acked(&self, backend: &Backend) -> (usize, usize) {
+        match *self {
+            Condition::Replicas { offset, .. } => (0, backend.repl.acked_replicas(offset).0),
+            Condition::Aof { offset, .. } => {
+                let local = backend.aof.is_enabled() && backend.aof.fsynced_reploff() >= offset;
+                (local as usize, backend.repl.acked_replicas(offset).1)
+            }
+        }
+    }
+
+    fn satisfied(&self, backend: &Backend) -> bool {
+        let (local, replicas) = self.acked(backend);
+        match *self {
+            Condition::Replicas { numreplicas, .. } => replicas >= numreplicas,
+            Condition::Aof {
+                numlocal,
+                numreplicas,
+                ..
+            } => local >= numlocal && replicas >= numreplicas,
+        }
+    }
+
+    fn reply(&self, backend: &Backend) -> RespFrame {
+        let (local, replicas) = self.acked(backend);
+        match self {
+            Condition::Replicas { .. } => RespInteger::from(replicas as i64).into(),
+            Condition::Aof { .. } => RespArray::new(vec![
+                RespInteger::from(local as i64).into(),
+                RespInteger::from(replicas as i64).into(),
+            ])
+            .into(),
+        }
+    }
+}
+
+impl Blocked {
+    pub(crate) fn migrate(cmd: Migrate) -> Self {
+        Self(Pending::Migrate(cmd))
+    }
+
+    /// 等到命令可以回复，返回最终的回复
+    pub async fn wait(self, backend: &Backend) -> RespFrame {
+        match self.0 {
+            Pending::Acks {
+                condition,
+                deadline,
+            } => wait_acks(condition, deadline, backend).await,
+            Pending::Migrate(cmd) => {
+                let inner = backend.clone();
+                tokio::task::spawn_blocking(move || cmd.run(&inner))
+                    .await
+                    .unwrap_or_else(|e| SimpleError::from(format!("ERR {e}")).into())
+            }
+        }
+    }
+}
+
+/// 等到条件满足或者超时，返回那时的回复。
+/// 开始等待时让replica立即ACK，everysec模式下本地AOF立即fsync，不用等下一次定时任务
+async fn wait_acks(
+    condition: Condition,
+    deadline: Option<Instant>,
+    backend: &Backend,
+) -> RespFrame {
+    replication::request_acks(backend);
+    if matches!(condition, Condition::Aof { numlocal, .. } if numlocal > 0) {
+        let inner = backend.clone();
+        let _ = tokio::task::spawn_blocking(move || inner.aof.fsync_if_pending()).await;
+    }
+
+    let timeout = async {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(timeout);
+    loop {
+        //先登记唤醒再检查条件，检查之后才到来的ACK不会被错过
+        let acked = backend.repl.ack_notified();
+        let fsynced = backend.aof.fsync_notified();
+        tokio::pin!(acked, fsynced);
+        acked.as_mut().enable();
+        fsynced.as_mut().enable();
+        if condition.satisfied(backend) {
+            break;
+        }
+        tokio::select! {
+            _ = acked => {}
+            _ = fsynced => {}
+            _ = &mut timeout => break,
+        }
+    }
+    condition.reply(backend)
+}
+
+impl TryFrom<RespArray> for Wait {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        match <[RespFrame; 2]>::try_from(extract_cmd_args(value, 1)?) {
+            Ok([numreplicas, timeout]) => Ok(Wait {
+                numreplicas: count_arg(numreplicas)?,
+                timeout: timeout_arg(timeout)?,
+            }),
+            Err(_) => Err(CommandError::InvalidArgument(
+                "wait command should have exactly 2 argument(s)!".into(),
+            )),
+        }
+    }
+}
+
+impl TryFrom<RespArray> for WaitAof {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        match <[RespFrame; 3]>::try_from(extract_cmd_args(value, 1)?) {
+            Ok([numlocal, numreplicas, timeout]) => Ok(WaitAof {
+                numlocal: count_arg(numlocal)?,
+                numreplicas: count_arg(numreplicas)?,
+                timeout: timeout_arg(timeout)?,
+            }),
+            Err(_) => Err(CommandError::InvalidArgument(
+                "waitaof command should have exactly 3 argument(s)!".into(),
+            )),
+        }
+    }
+}
+
+fn count_arg(frame: RespFrame) -> Result<usize, CommandError> {
+    usize::try_from(integer_arg(frame)?)
+        .map_err(|_| CommandError::InvalidArgument("value is out of range".into()))
+}
+
+fn timeout_arg(frame: RespFrame) -> Result<u64, CommandError> {
+    u64::try_from(integer_arg(frame)?)
+        .map_err(|_| CommandError::InvalidArgument("timeout is negative".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aof::{command_array, AofConfig},
+        network::request_handler,
+    };
+
+    #[test]
+    fn test_wait_from_resp_array() {
+        let cmd = Wait::try_from(command_array(["WAIT", "1", "100"])).unwrap();
+        assert_eq!(
+            cmd,
+            Wait {
+                numreplicas: 1,
+                timeout: 100
+            }
+        );
+        assert!(Wait::try_from(command_array(["WAIT", "1", "-1"])).is_err());
+        assert!(WaitAof::try_from(command_array(["WAITAOF", "1", "0"])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wait_without_replicas() {
+        let backend = Backend::new();
+        let mut session = Session::new();
+
+        //不需要任何replica时立即返回
+        let cmd = Wait::try_from(command_array(["WAIT", "0", "0"])).unwrap();
+        let reply = cmd.execute(&mut session, &backend).unwrap();
+        assert_eq!(reply, RespInteger::from(0).into());
+        assert!(session.blocked.is_none());
+
+        //没有replica时一直等到超时
+        let cmd = Wait::try_from(command_array(["WAIT", "1", "50"])).unwrap();
+        cmd.execute(&mut session, &backend).unwrap();
+        let blocked = session.blocked.take().unwrap();
+        let start = Instant::now();
+        assert_eq!(blocked.wait(&backend).await, RespInteger::from(0).into());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let cmd = WaitAof::try_from(command_array(["WAITAOF", "1", "0", "0"])).unwrap();
+        let reply = cmd.execute(&mut session, &backend).unwrap();
+        assert!(matches!(reply, RespFrame::SimpleError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_waitaof_local_fsync() {
+        let backend = Backend::new();
+        let dir = std::env::temp_dir().join(format!("simple-redis-waitaof-{}", std::process::id()));
+        backend.aof.set_config(AofConfig {
+            enabled: true,
+            dir: dir.clone(),
+            ..Default::default()
+        });
+        backend.aof.open().unwrap();
+        let mut session = Session::new();
+
+        request_handler(
+            command_array(["SET", "a", "1"]).into(),
+            &mut session,
+            &backend,
+        );
+        assert!(session.woff > 0);
+        //everysec模式下还没有fsync，WAITAOF会立即触发一次fsync
+        let cmd = WaitAof::try_from(command_array(["WAITAOF", "1", "0", "0"])).unwrap();
+        cmd.execute(&mut session, &backend).unwrap();
+        let blocked = session.blocked.take().unwrap();
+        let expected: RespFrame = RespArray::new(vec![
+            RespInteger::from(1).into(),
+            RespInteger::from(0).into(),
+        ])
+        .into();
+        assert_eq!(blocked.wait(&backend).await, expected);
+        assert_eq!(backend.aof.fsynced_reploff(), session.woff);
+
+        backend.aof.close();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{Backend, RespArray, RespFrame, RespInteger};
+
+use super::{check_type, extract_cmd_args, string_arg, CommandError, CommandExecutor, ZAdd};
+
+impl CommandExecutor for ZAdd {
+    fn execute(self, backend: &Backend) -> RespFrame {
+        if let Some(err) = check_type(backend, &self.key, "zset") {
+            return err;
+        }
+        let added = backend.zadd(self.key, self.members);
+        RespInteger::from(added as i64).into()
+    }
+}
+
+///*4\r\n$4\r\nzadd\r\n$4\r\nzset\r\n$3\r\n1.5\r\n$1\r\na\r\n
+impl TryFrom<RespArray> for ZAdd {
+    type Error = CommandError;
+
+    fn try_from(value: RespArray) -> Result<Self, Self::Error> {
+        if value.len() < 4 || !value.len().is_multiple_of(2) {
+            return Err(CommandError::InvalidArgument(
+                "zadd command should have a key and score/member pairs as arguments!".into(),
+            ));
+        }
+        let mut args = extract_cmd_args(value, 1)?.into_iter();
+        let key = args.next().map(string_arg).transpose()?.unwrap_or_default();
+        let mut members = Vec::new();
+        while let (Some(score), Some(member)) = (args.next(), args.next()) {
+            let score = string_arg(score)?
+                .parse::<f64>()
+                .ok()
+                .filter(|score| !score.is_nan())
+                .ok_or_else(|| {
+                    CommandError::InvalidArgument("value is not a valid float".into())
+                })?;
+            members.push((member, score));
+        }
+        Ok(ZAdd::new(key, members))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aof::command_array, RespBulkString};
+
+    #[test]
+    fn test_zadd_execute() {
+        let backend = Backend::new();
+        let cmd = ZAdd::try_from(command_array(["zadd", "z", "1", "a", "-inf", "b"])).unwrap();
+        assert_eq!(cmd.execute(&backend), RespInteger::from(2).into());
+        let cmd = ZAdd::try_from(command_array(["zadd", "z", "2.5", "a"])).unwrap();
+        assert_eq!(cmd.execute(&backend), RespInteger::from(0).into());
+
+        let zset = backend.zsets.get("z").unwrap();
+        assert_eq!(
+            zset.get(&RespFrame::from(RespBulkString::from("a"))),
+            Some(&2.5)
+        );
+        assert_eq!(
+            zset.get(&RespFrame::from(RespBulkString::from("b"))),
+            Some(&f64::NEG_INFINITY)
+        );
+        assert!(ZAdd::try_from(command_array(["zadd", "z", "nan", "a"])).is_err());
+        assert!(ZAdd::try_from(command_array(["zadd", "z", "1"])).is_err());
+    }
+}
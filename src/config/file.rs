@@ -0,0 +1,449 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::Backend;
+
+use super::{apply, find, ConfigEntry, ConfigError, ENTRIES};
+
+/// CONFIG REWRITE在追加的配置项之前写入这一行
+const REWRITE_SIGNATURE: &str = "# Generated by CONFIG REWRITE";
+/// 可以带多个参数的指令，其余的指令只能有一个参数
+const MULTI_ARG_DIRECTIVES: [&str; 4] = ["bind", "save", "replicaof", "slaveof"];
+/// include嵌套的最大深度，防止文件互相include
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// 和Redis的sdssplitargs一样按空白切分一行，支持"..."(可以用\n、\xHH这样的转义)和'...'(只能转义\')。
+/// \xHH得到的是原始字节，拼起来不是合法UTF-8的参数会被拒绝
+pub fn split_args(line: &str) -> Result<Vec<String>, ConfigError> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(args);
+        }
+        let mut arg = Vec::new();
+        //引号之后必须是空白或者行尾
+        let mut closed = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if !closed => {
+                    read_double_quoted(&mut chars, &mut arg)?;
+                    closed = true;
+                }
+                '\'' if !closed => {
+                    read_single_quoted(&mut chars, &mut arg)?;
+                    closed = true;
+                }
+                c if c.is_whitespace() => break,
+                _ if closed => return Err(ConfigError::UnbalancedQuotes),
+                c => push_char(&mut arg, c),
+            }
+        }
+        args.push(String::from_utf8(arg).map_err(|_| ConfigError::InvalidUtf8)?);
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+fn push_char(arg: &mut Vec<u8>, c: char) {
+    arg.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+fn read_double_quoted(chars: &mut Chars<'_>, arg: &mut Vec<u8>) -> Result<(), ConfigError> {
+    loop {
+        match chars.next().ok_or(ConfigError::UnbalancedQuotes)? {
+            '"' => return Ok(()),
+            '\\' => match chars.next().ok_or(ConfigError::UnbalancedQuotes)? {
+                'n' => arg.push(b'\n'),
+                'r' => arg.push(b'\r'),
+                't' => arg.push(b'\t'),
+                'b' => arg.push(b'\x08'),
+                'a' => arg.push(b'\x07'),
+                //后面不是两位十六进制数时和Redis一样当作普通的x
+                'x' => match hex_byte(chars) {
+                    Some(byte) => arg.push(byte),
+                    None => arg.push(b'x'),
+                },
+                c => push_char(arg, c),
+            },
+            c => push_char(arg, c),
+        }
+    }
+}
+
+/// 读取\x之后的两位十六进制数，不合法时不消耗任何字符
+fn hex_byte(chars: &mut Chars<'_>) -> Option<u8> {
+    let mut ahead = chars.clone();
+    let (h, l) = (ahead.next()?, ahead.next()?);
+    let byte = ((h.to_digit(16)? << 4) | l.to_digit(16)?) as u8;
+    *chars = ahead;
+    Some(byte)
+}
+
+fn read_single_quoted(chars: &mut Chars<'_>, arg: &mut Vec<u8>) -> Result<(), ConfigError> {
+    loop {
+        match chars.next().ok_or(ConfigError::UnbalancedQuotes)? {
+            '\'' => return Ok(()),
+            '\\' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                arg.push(b'\'');
+            }
+            c => push_char(arg, c),
+        }
+    }
+}
+
+/// 解析命令行：第一个不以--开头的参数是配置文件，之后每个--name开始一条指令，
+/// 比如`redis.conf --port 6380 --replicaof 127.0.0.1 6379`
+pub fn parse_args(
+    args: impl IntoIterator<Item = String>,
+) -> Result<(Option<PathBuf>, Vec<Vec<String>>), ConfigError> {
+    let mut args = args.into_iter().peekable();
+    let path = args
+        .next_if(|arg| !arg.starts_with("--"))
+        .map(PathBuf::from);
+    let mut directives: Vec<Vec<String>> = Vec::new();
+    for arg in args {
+        match (arg.strip_prefix("--"), directives.last_mut()) {
+            (Some(name), _) if !name.is_empty() => directives.push(vec![name.to_string()]),
+            (_, Some(directive)) => directive.push(arg),
+            (_, None) => return Err(ConfigError::BadDirective),
+        }
+    }
+    Ok((path, directives))
+}
+
+/// 加载配置文件(如果有)，再应用命令行参数。命令行参数覆盖配置文件里的值
+pub fn load(
+    backend: &Backend,
+    path: Option<&Path>,
+    overrides: &[Vec<String>],
+) -> Result<(), ConfigError> {
+    let mut loader = Loader {
+        backend,
+        saves: None,
+    };
+    if let Some(path) = path {
+        loader.load_file(path, 0)?;
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        backend.config.set_file(&path);
+    }
+    for (i, args) in overrides.iter().enumerate() {
+        loader
+            .directive(args, 0)
+            .map_err(|e| file_error("command line", i + 1, e))?;
+    }
+    loader.finish()
+}
+
+struct Loader<'a> {
+    backend: &'a Backend,
+    /// 第一条save指令清空默认的规则，之后的save指令追加规则，全部读完再一起生效
+    saves: Option<Vec<String>>,
+}
+
+impl Loader<'_> {
+    fn load_file(&mut self, path: &Path, depth: usize) -> Result<(), ConfigError> {
+        let display = path.display().to_string();
+        let content = fs::read_to_string(path).map_err(|e| file_error(&display, 0, e.into()))?;
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            split_args(line)
+                .and_then(|args| self.directive(&args, depth))
+                .map_err(|e| file_error(&display, i + 1, e))?;
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, args: &[String], depth: usize) -> Result<(), ConfigError> {
+        let Some((name, values)) = args.split_first() else {
+            return Ok(());
+        };
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "include" => {
+                let [path] = values else {
+                    return Err(ConfigError::BadDirective);
+                };
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(ConfigError::File {
+                        path: path.clone(),
+                        line: 0,
+                        reason: "too many nested includes".into(),
+                    });
+                }
+                self.load_file(Path::new(path), depth + 1)
+            }
+            "save" => {
+                let saves = self.saves.get_or_insert_with(Vec::new);
+                match values {
+                    //save ""表示关闭RDB快照
+                    [value] if value.is_empty() => saves.clear(),
+                    [seconds, changes] => saves.extend([seconds.clone(), changes.clone()]),
+                    _ => return Err(ConfigError::BadDirective),
+                }
+                Ok(())
+            }
+            name if MULTI_ARG_DIRECTIVES.contains(&name) => {
+                apply(self.backend, name, &values.join(" "))
+            }
+            name => match values {
+                [value] => apply(self.backend, name, value),
+                _ => Err(ConfigError::BadDirective),
+            },
+        }
+    }
+
+    fn finish(self) -> Result<(), ConfigError> {
+        if let Some(saves) = self.saves {
+            apply(self.backend, "save", &saves.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+fn file_error(path: &str, line: usize, e: ConfigError) -> ConfigError {
+    match e {
+        //include的文件里的错误已经带了位置
+        e @ ConfigError::File { .. } => e,
+        e => ConfigError::File {
+            path: path.into(),
+            line,
+            reason: e.to_string(),
+        },
+    }
+}
+
+/// CONFIG REWRITE：原文件里的注释、空行和不认识的指令原样保留，已有的配置项就地改成当前的值，
+/// 重复出现的配置项只保留第一处，文件里没有并且不等于默认值的配置项追加到文件末尾
+pub fn rewrite(backend: &Backend) -> Result<(), ConfigError> {
+    let path = backend.config.file().ok_or(ConfigError::NoConfigFile)?;
+    //文件可能在启动之后被删掉了，这时候相当于从空文件开始
+    let content = fs::read_to_string(&path).unwrap_or_default();
+    let mut lines = Vec::new();
+    let mut written = HashSet::new();
+    //之前重写过的文件已经有签名行了，追加的配置项直接放在文件末尾
+    let mut signed = false;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        signed |= trimmed == REWRITE_SIGNATURE;
+        let entry = match split_args(trimmed) {
+            Ok(args) if !trimmed.starts_with('#') => args.first().and_then(|name| find(name)),
+            _ => None,
+        };
+        match entry {
+            Some(entry) => {
+                if written.insert(entry.name) {
+                    lines.extend(render(entry, &(entry.get)(backend)));
+                }
+            }
+            None => lines.push(line.to_string()),
+        }
+    }
+
+    for entry in ENTRIES.iter().filter(|e| !written.contains(e.name)) {
+        let value = (entry.get)(backend);
+        if value == entry.default {
+            continue;
+        }
+        if !signed {
+            lines.push(REWRITE_SIGNATURE.into());
+            signed = true;
+        }
+        lines.extend(render(entry, &value));
+    }
+
+    //先写临时文件再rename，写到一半崩溃也不会破坏原来的配置文件
+    let tmp = path.with_extension("rewrite.tmp");
+    let mut content = lines.join("\n");
+    content.push('\n');
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+/// 一个配置项写进配置文件的行
+fn render(entry: &ConfigEntry, value: &str) -> Vec<String> {
+    match entry.name {
+        "save" if value.is_empty() => vec!["save \"\"".into()],
+        "save" => {
+            let numbers = value.split_whitespace().collect::<Vec<_>>();
+            numbers
+                .chunks(2)
+                .map(|pair| format!("save {}", pair.join(" ")))
+                .collect()
+        }
+        //master不需要写replicaof
+        "replicaof" if value.is_empty() => vec![],
+        "bind" | "replicaof" => vec![format!("{} {}", entry.name, value)],
+        name => vec![format!("{} {}", name, quote(value))],
+    }
+}
+
+/// 空字符串和包含空白、引号、控制字符的值需要加引号
+fn quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| !c.is_whitespace() && !c.is_control() && c != '"' && c != '\'');
+    if plain {
+        return value.into();
+    }
+    let mut ret = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c if c.is_control() => ret.push_str(&format!("\\x{:02x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eviction::EvictionPolicy;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "simple-redis-config-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_split_args() {
+        let args = split_args(r#"  set "hello world" 'it\'s' "a\x41\n" plain  "#).unwrap();
+        assert_eq!(args, vec!["set", "hello world", "it's", "aA\n", "plain"]);
+        assert_eq!(split_args(r#"save """#).unwrap(), vec!["save", ""]);
+        assert!(split_args(r#"dir "unterminated"#).is_err());
+        assert!(split_args(r#"dir "a"b"#).is_err());
+        assert!(split_args("").unwrap().is_empty());
+        //\xHH是原始字节，不是Unicode码点
+        assert_eq!(split_args(r#""\xc3\xa9""#).unwrap(), vec!["\u{e9}"]);
+        assert!(matches!(
+            split_args(r#""\xff""#),
+            Err(ConfigError::InvalidUtf8)
+        ));
+        //不完整的\x是普通的x，不会吃掉后面的引号
+        assert_eq!(split_args(r#""a\x" "\xzz""#).unwrap(), vec!["ax", "xzz"]);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = [
+            "redis.conf",
+            "--port",
+            "6380",
+            "--replicaof",
+            "h",
+            "1",
+            "--save",
+            "",
+        ];
+        let (path, directives) = parse_args(args.map(String::from)).unwrap();
+        assert_eq!(path, Some(PathBuf::from("redis.conf")));
+        assert_eq!(
+            directives,
+            vec![
+                vec!["port".to_string(), "6380".into()],
+                vec!["replicaof".to_string(), "h".into(), "1".into()],
+                vec!["save".to_string(), "".into()],
+            ]
+        );
+        let (path, _) = parse_args(["--port".to_string(), "1".into()]).unwrap();
+        assert!(path.is_none());
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = temp_dir("load");
+        let included = dir.join("included.conf");
+        fs::write(&included, "maxmemory-policy allkeys-lfu\n").unwrap();
+        let path = dir.join("redis.conf");
+        fs::write(
+            &path,
+            format!(
+                "# comment\nport 7000\nsave 900 1\nsave 60 100\nmaxmemory 1mb\ninclude {}\nbind 127.0.0.1 ::1\n",
+                included.display()
+            ),
+        )
+        .unwrap();
+
+        let backend = Backend::new();
+        let overrides = vec![vec!["port".to_string(), "7001".into()]];
+        load(&backend, Some(&path), &overrides).unwrap();
+        assert_eq!(backend.repl.config().port, 7001);
+        assert_eq!(backend.eviction.config().maxmemory, 1024 * 1024);
+        assert_eq!(backend.eviction.config().policy, EvictionPolicy::AllKeysLfu);
+        assert_eq!(backend.rdb.config().save_rules.len(), 2);
+        assert_eq!(backend.config.server().bind, vec!["127.0.0.1", "::1"]);
+
+        fs::write(&path, "port 7000\nmaxmemory lots\n").unwrap();
+        let err = load(&Backend::new(), Some(&path), &[]).unwrap_err();
+        assert!(matches!(err, ConfigError::File { line: 2, .. }), "{err}");
+        fs::write(&path, "port 1 2\n").unwrap();
+        assert!(load(&Backend::new(), Some(&path), &[]).is_err());
+        fs::write(&path, "nosuchoption yes\n").unwrap();
+        assert!(load(&Backend::new(), Some(&path), &[]).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let dir = temp_dir("rewrite");
+        let path = dir.join("redis.conf");
+        fs::write(
+            &path,
+            "# my config\nport 7000\n\n# memory\nmaxmemory 1mb\nmaxmemory 2mb\nsave 900 1\n",
+        )
+        .unwrap();
+        let backend = Backend::new();
+        assert!(matches!(rewrite(&backend), Err(ConfigError::NoConfigFile)));
+        load(&backend, Some(&path), &[]).unwrap();
+
+        let param = |name: &str, value: &str| (name.to_string(), value.to_string());
+        super::super::set(
+            &backend,
+            &[
+                param("maxmemory", "100"),
+                param("maxmemory-policy", "volatile-ttl"),
+                param("save", ""),
+            ],
+        )
+        .unwrap();
+        rewrite(&backend).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            content,
+            "# my config\nport 7000\n\n# memory\nmaxmemory 100\nsave \"\"\n# Generated by CONFIG REWRITE\nmaxmemory-policy volatile-ttl\n"
+        );
+
+        //重写之后的文件能被重新加载，再次重写结果不变
+        let reloaded = Backend::new();
+        load(&reloaded, Some(&path), &[]).unwrap();
+        assert_eq!(
+            reloaded.eviction.config().policy,
+            EvictionPolicy::VolatileTtl
+        );
+        assert!(reloaded.rdb.config().save_rules.is_empty());
+        rewrite(&reloaded).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
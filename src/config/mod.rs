@@ -0,0 +1,1170 @@
+/*
+配置子系统：
+    每个配置项是表里的一项，包括名字、默认值、能否在运行时修改，以及怎么从各个子系统的config里读出和写入，
+    配置文件、命令行参数和CONFIG SET都通过同一张表生效，值本身仍然保存在各个子系统里
+    配置文件的解析、include和CONFIG REWRITE在file.rs里
+*/
+mod file;
+
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use thiserror::Error;
+
+use crate::{
+    aof::AppendFsync, eviction::EvictionPolicy, glob::glob_match, rdb::SaveRule, replication::Role,
+    Backend, RespLimits,
+};
+
+pub use file::{load, parse_args, rewrite, split_args};
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Unknown option or number of arguments for CONFIG SET - '{0}'")]
+    UnknownOption(String),
+    #[error("CONFIG SET failed (possibly related to argument '{name}') - {reason}")]
+    SetFailed { name: String, reason: String },
+    #[error("Duplicate parameter - {0}")]
+    Duplicate(String),
+    #[error("Unbalanced quotes in configuration line")]
+    UnbalancedQuotes,
+    #[error("Invalid UTF-8 in configuration line")]
+    InvalidUtf8,
+    #[error("Bad directive or wrong number of arguments")]
+    BadDirective,
+    #[error("The server is running without a config file")]
+    NoConfigFile,
+    #[error("Reading configuration file '{path}' at line {line}: {reason}")]
+    File {
+        path: String,
+        line: usize,
+        reason: String,
+    },
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// 不属于任何子系统的配置
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// 监听的地址，*表示所有IPv4地址
+    pub bind: Vec<String>,
+    /// 配置文件里的replicaof，启动之后再连接master
+    pub replicaof: Option<(String, u16)>,
+    /// 除了TCP之外还监听的Unix socket
+    pub unixsocket: Option<PathBuf>,
+    /// socket文件的权限，0表示不修改
+    pub unixsocketperm: u32,
+    /// proto-max-bulk-len、client-query-buffer-limit等协议层的限制
+    pub proto_limits: RespLimits,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: vec!["*".into()],
+            replicaof: None,
+            unixsocket: None,
+            unixsocketperm: 0,
+            proto_limits: RespLimits::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// bind里的地址转换成可以监听的地址
+    pub fn bind_addrs(&self) -> Vec<&str> {
+        self.bind
+            .iter()
+            .map(|addr| match addr.as_str() {
+                "*" => "0.0.0.0",
+                "::*" => "::",
+                addr => addr,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ConfigState {
+    server: RwLock<ServerConfig>,
+    /// 启动时加载的配置文件，CONFIG REWRITE写回这里
+    file: RwLock<Option<PathBuf>>,
+}
+
+impl ConfigState {
+    pub fn server(&self) -> ServerConfig {
+        self.server
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_server(&self, config: ServerConfig) {
+        *self.server.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    /// 每个请求都要用到，不复制整个ServerConfig
+    pub fn proto_limits(&self) -> RespLimits {
+        self.server
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .proto_limits
+    }
+
+    fn set_proto_limits(&self, limits: RespLimits) {
+        self.server
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .proto_limits = limits;
+    }
+
+    pub fn file(&self) -> Option<PathBuf> {
+        self.file.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn set_file(&self, path: &Path) {
+        *self.file.write().unwrap_or_else(|e| e.into_inner()) = Some(path.to_path_buf());
+    }
+}
+
+type Getter = fn(&Backend) -> String;
+type Setter = fn(&Backend, &str) -> Result<(), String>;
+
+/// 一个配置项
+struct ConfigEntry {
+    name: &'static str,
+    alias: Option<&'static str>,
+    /// 能否用CONFIG SET修改，不能修改的只能在配置文件或命令行里设置
+    mutable: bool,
+    /// CONFIG REWRITE不会把等于默认值的配置项追加到文件里
+    default: &'static str,
+    get: Getter,
+    set: Setter,
+}
+
+impl ConfigEntry {
+    fn matches(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self
+                .alias
+                .is_some_and(|alias| alias.eq_ignore_ascii_case(name))
+    }
+}
+
+static ENTRIES: &[ConfigEntry] = &[
+    ConfigEntry {
+        name: "bind",
+        alias: None,
+        mutable: false,
+        default: "*",
+        get: |b| b.config.server().bind.join(" "),
+        set: |b, v| {
+            let bind = v.split_whitespace().map(String::from).collect::<Vec<_>>();
+            if bind.is_empty() {
+                return Err("Too many bind addresses specified.".into());
+            }
+            b.config.set_server(ServerConfig {
+                bind,
+                ..b.config.server()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "unixsocket",
+        alias: None,
+        mutable: false,
+        default: "",
+        get: |b| display_path(b.config.server().unixsocket),
+        set: |b, v| {
+            b.config.set_server(ServerConfig {
+                unixsocket: (!v.is_empty()).then(|| PathBuf::from(v)),
+                ..b.config.server()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "unixsocketperm",
+        alias: None,
+        mutable: false,
+        default: "0",
+        get: |b| format!("{:o}", b.config.server().unixsocketperm),
+        set: |b, v| {
+            //和chmod一样是八进制
+            let unixsocketperm = u32::from_str_radix(v, 8)
+                .ok()
+                .filter(|perm| *perm <= 0o777)
+                .ok_or("argument must be an octal number between 0 and 777")?;
+            b.config.set_server(ServerConfig {
+                unixsocketperm,
+                ..b.config.server()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "port",
+        alias: None,
+        mutable: false,
+        default: "6379",
+        get: |b| b.repl.config().port.to_string(),
+        set: |b, v| {
+            let port = parse_number(v)?;
+            b.repl.set_config(crate::replication::ReplConfig {
+                port,
+                ..b.repl.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "maxclients",
+        alias: None,
+        mutable: true,
+        default: "10000",
+        get: |b| b.stats.max_clients().to_string(),
+        set: |b, v| {
+            let max = parse_number::<usize>(v)?;
+            if max == 0 {
+                return Err("argument must be between 1 and 4294967295 inclusive".into());
+            }
+            b.stats.set_max_clients(max);
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "proto-max-bulk-len",
+        alias: None,
+        mutable: true,
+        default: "536870912",
+        get: |b| b.config.proto_limits().max_bulk_len.to_string(),
+        set: |b, v| {
+            let max_bulk_len = parse_memory_limit(v)?;
+            b.config.set_proto_limits(RespLimits {
+                max_bulk_len,
+                ..b.config.proto_limits()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "proto-max-multibulk-len",
+        alias: None,
+        mutable: true,
+        default: "1048576",
+        get: |b| b.config.proto_limits().max_multibulk_len.to_string(),
+        set: |b, v| {
+            let max_multibulk_len = parse_number::<usize>(v)?;
+            b.config.set_proto_limits(RespLimits {
+                max_multibulk_len,
+                ..b.config.proto_limits()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "proto-max-nesting",
+        alias: None,
+        mutable: true,
+        default: "128",
+        get: |b| b.config.proto_limits().max_depth.to_string(),
+        set: |b, v| {
+            let max_depth = parse_number::<usize>(v)?;
+            if max_depth == 0 {
+                return Err("argument must be greater than 0".into());
+            }
+            b.config.set_proto_limits(RespLimits {
+                max_depth,
+                ..b.config.proto_limits()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "client-query-buffer-limit",
+        alias: None,
+        mutable: true,
+        default: "1073741824",
+        get: |b| b.config.proto_limits().max_query_buffer.to_string(),
+        set: |b, v| {
+            let max_query_buffer = parse_memory_limit(v)?;
+            b.config.set_proto_limits(RespLimits {
+                max_query_buffer,
+                ..b.config.proto_limits()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "dir",
+        alias: None,
+        mutable: true,
+        default: ".",
+        get: |b| b.rdb.config().dir.display().to_string(),
+        set: |b, v| {
+            let dir = PathBuf::from(v);
+            if !dir.is_dir() {
+                return Err("No such file or directory".into());
+            }
+            b.rdb.set_config(crate::rdb::RdbConfig {
+                dir: dir.clone(),
+                ..b.rdb.config()
+            });
+            b.aof.set_config(crate::aof::AofConfig {
+                dir,
+                ..b.aof.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "dbfilename",
+        alias: None,
+        mutable: true,
+        default: "dump.rdb",
+        get: |b| b.rdb.config().dbfilename,
+        set: |b, v| {
+            if v.contains('/') {
+                return Err("dbfilename can't be a path, just a filename".into());
+            }
+            b.rdb.set_config(crate::rdb::RdbConfig {
+                dbfilename: v.into(),
+                ..b.rdb.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "save",
+        alias: None,
+        mutable: true,
+        default: "3600 1 300 100 60 10000",
+        get: |b| {
+            let rules = b.rdb.config().save_rules;
+            rules
+                .iter()
+                .map(|rule| format!("{} {}", rule.seconds, rule.changes))
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+        set: |b, v| {
+            let save_rules = parse_save_rules(v)?;
+            b.rdb.set_config(crate::rdb::RdbConfig {
+                save_rules,
+                ..b.rdb.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "appendonly",
+        alias: None,
+        mutable: true,
+        default: "no",
+        get: |b| yes_no(b.aof.config().enabled),
+        set: |b, v| {
+            let enabled = parse_bool(v)?;
+            b.aof.set_config(crate::aof::AofConfig {
+                enabled,
+                ..b.aof.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "appendfilename",
+        alias: None,
+        mutable: false,
+        default: "appendonly.aof",
+        get: |b| b.aof.config().filename,
+        set: |b, v| {
+            if v.contains('/') {
+                return Err("appendfilename can't be a path, just a filename".into());
+            }
+            b.aof.set_config(crate::aof::AofConfig {
+                filename: v.into(),
+                ..b.aof.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "appenddirname",
+        alias: None,
+        mutable: false,
+        default: "appendonlydir",
+        get: |b| b.aof.config().dirname,
+        set: |b, v| {
+            if v.contains('/') {
+                return Err("appenddirname can't be a path, just a dirname".into());
+            }
+            b.aof.set_config(crate::aof::AofConfig {
+                dirname: v.into(),
+                ..b.aof.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "appendfsync",
+        alias: None,
+        mutable: true,
+        default: "everysec",
+        get: |b| {
+            match b.aof.config().fsync {
+                AppendFsync::Always => "always",
+                AppendFsync::EverySec => "everysec",
+                AppendFsync::No => "no",
+            }
+            .into()
+        },
+        set: |b, v| {
+            let fsync = v
+                .parse::<AppendFsync>()
+                .map_err(|_| "argument(s) must be one of the following: always, everysec, no")?;
+            b.aof.set_config(crate::aof::AofConfig {
+                fsync,
+                ..b.aof.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "aof-load-truncated",
+        alias: None,
+        mutable: true,
+        default: "yes",
+        get: |b| yes_no(b.aof.config().load_truncated),
+        set: |b, v| {
+            let load_truncated = parse_bool(v)?;
+            b.aof.set_config(crate::aof::AofConfig {
+                load_truncated,
+                ..b.aof.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "aof-use-rdb-preamble",
+        alias: None,
+        mutable: true,
+        default: "yes",
+        get: |b| yes_no(b.aof.config().use_rdb_preamble),
+        set: |b, v| {
+            let use_rdb_preamble = parse_bool(v)?;
+            b.aof.set_config(crate::aof::AofConfig {
+                use_rdb_preamble,
+                ..b.aof.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "auto-aof-rewrite-percentage",
+        alias: None,
+        mutable: true,
+        default: "100",
+        get: |b| b.aof.config().auto_rewrite_percentage.to_string(),
+        set: |b, v| {
+            let auto_rewrite_percentage = parse_number(v)?;
+            b.aof.set_config(crate::aof::AofConfig {
+                auto_rewrite_percentage,
+                ..b.aof.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "auto-aof-rewrite-min-size",
+        alias: None,
+        mutable: true,
+        default: "67108864",
+        get: |b| b.aof.config().auto_rewrite_min_size.to_string(),
+        set: |b, v| {
+            let auto_rewrite_min_size = parse_memory(v)?;
+            b.aof.set_config(crate::aof::AofConfig {
+                auto_rewrite_min_size,
+                ..b.aof.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "replicaof",
+        alias: Some("slaveof"),
+        mutable: false,
+        default: "",
+        get: |b| match b.repl.role() {
+            Role::Master => String::new(),
+            Role::Replica { host, port } => format!("{host} {port}"),
+        },
+        set: |b, v| {
+            let replicaof = match v.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => None,
+                [host, port] => Some((host.to_string(), parse_number(port)?)),
+                _ => return Err("wrong number of arguments".into()),
+            };
+            b.config.set_server(ServerConfig {
+                replicaof,
+                ..b.config.server()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "masteruser",
+        alias: None,
+        mutable: true,
+        default: "",
+        get: |b| b.repl.config().masteruser,
+        set: |b, v| {
+            b.repl.set_config(crate::replication::ReplConfig {
+                masteruser: v.to_string(),
+                ..b.repl.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "masterauth",
+        alias: None,
+        mutable: true,
+        default: "",
+        get: |b| b.repl.config().masterauth,
+        set: |b, v| {
+            b.repl.set_config(crate::replication::ReplConfig {
+                masterauth: v.to_string(),
+                ..b.repl.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "replica-read-only",
+        alias: Some("slave-read-only"),
+        mutable: true,
+        default: "yes",
+        get: |b| yes_no(b.repl.config().read_only),
+        set: |b, v| {
+            let read_only = parse_bool(v)?;
+            b.repl.set_config(crate::replication::ReplConfig {
+                read_only,
+                ..b.repl.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "repl-backlog-size",
+        alias: None,
+        mutable: true,
+        default: "1048576",
+        get: |b| b.repl.config().backlog_size.to_string(),
+        set: |b, v| {
+            let backlog_size = parse_memory(v)?;
+            if backlog_size == 0 {
+                return Err("argument must be a memory value greater than 0".into());
+            }
+            b.repl.set_config(crate::replication::ReplConfig {
+                backlog_size: backlog_size as usize,
+                ..b.repl.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "repl-ping-replica-period",
+        alias: Some("repl-ping-slave-period"),
+        mutable: true,
+        default: "10",
+        get: |b| b.repl.config().ping_period.to_string(),
+        set: |b, v| {
+            let ping_period = parse_positive(v)?;
+            b.repl.set_config(crate::replication::ReplConfig {
+                ping_period,
+                ..b.repl.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "repl-timeout",
+        alias: None,
+        mutable: true,
+        default: "60",
+        get: |b| b.repl.config().timeout.to_string(),
+        set: |b, v| {
+            let timeout = parse_positive(v)?;
+            b.repl.set_config(crate::replication::ReplConfig {
+                timeout,
+                ..b.repl.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "cluster-enabled",
+        alias: None,
+        mutable: false,
+        default: "no",
+        get: |b| yes_no(b.cluster.config().enabled),
+        set: |b, v| {
+            let enabled = parse_bool(v)?;
+            b.cluster.set_config(crate::cluster::ClusterConfig {
+                enabled,
+                ..b.cluster.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "cluster-port",
+        alias: None,
+        mutable: false,
+        default: "0",
+        get: |b| b.cluster.config().port.to_string(),
+        set: |b, v| {
+            let port = parse_number(v)?;
+            b.cluster.set_config(crate::cluster::ClusterConfig {
+                port,
+                ..b.cluster.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "cluster-node-timeout",
+        alias: None,
+        mutable: true,
+        default: "15000",
+        get: |b| b.cluster.config().node_timeout.to_string(),
+        set: |b, v| {
+            let node_timeout = parse_positive(v)?;
+            b.cluster.set_config(crate::cluster::ClusterConfig {
+                node_timeout,
+                ..b.cluster.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "cluster-announce-ip",
+        alias: None,
+        mutable: true,
+        default: "",
+        get: |b| b.cluster.config().announce_ip.unwrap_or_default(),
+        set: |b, v| {
+            let announce_ip = (!v.is_empty()).then(|| v.to_string());
+            b.cluster.set_config(crate::cluster::ClusterConfig {
+                announce_ip,
+                ..b.cluster.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "maxmemory",
+        alias: None,
+        mutable: true,
+        default: "0",
+        get: |b| b.eviction.config().maxmemory.to_string(),
+        set: |b, v| {
+            let maxmemory = parse_memory(v)?;
+            b.eviction.set_config(crate::eviction::EvictionConfig {
+                maxmemory,
+                ..b.eviction.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "maxmemory-policy",
+        alias: None,
+        mutable: true,
+        default: "noeviction",
+        get: |b| b.eviction.config().policy.as_str().into(),
+        set: |b, v| {
+            let policy = v.parse::<EvictionPolicy>().map_err(|_| {
+                "argument(s) must be one of the following: volatile-lru, volatile-lfu, volatile-random, volatile-ttl, allkeys-lru, allkeys-lfu, allkeys-random, noeviction"
+            })?;
+            b.eviction.set_config(crate::eviction::EvictionConfig {
+                policy,
+                ..b.eviction.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "maxmemory-samples",
+        alias: None,
+        mutable: true,
+        default: "5",
+        get: |b| b.eviction.config().samples.to_string(),
+        set: |b, v| {
+            let samples = parse_number::<usize>(v)?;
+            if !(1..=64).contains(&samples) {
+                return Err("argument must be between 1 and 64 inclusive".into());
+            }
+            b.eviction.set_config(crate::eviction::EvictionConfig {
+                samples,
+                ..b.eviction.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "slowlog-log-slower-than",
+        alias: None,
+        mutable: true,
+        default: "10000",
+        get: |b| b.slowlog.config().slower_than.to_string(),
+        set: |b, v| {
+            b.slowlog.set_config(crate::slowlog::SlowlogConfig {
+                slower_than: parse_number(v)?,
+                ..b.slowlog.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "slowlog-max-len",
+        alias: None,
+        mutable: true,
+        default: "128",
+        get: |b| b.slowlog.config().max_len.to_string(),
+        set: |b, v| {
+            b.slowlog.set_config(crate::slowlog::SlowlogConfig {
+                max_len: parse_number(v)?,
+                ..b.slowlog.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "latency-monitor-threshold",
+        alias: None,
+        mutable: true,
+        default: "0",
+        get: |b| b.latency.config().threshold.to_string(),
+        set: |b, v| {
+            b.latency.set_config(crate::latency::LatencyConfig {
+                threshold: parse_number(v)?,
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "tls-port",
+        alias: None,
+        mutable: false,
+        default: "0",
+        get: |b| b.tls.config().port.to_string(),
+        set: |b, v| {
+            b.tls.set_config(crate::tls::TlsConfig {
+                port: parse_number(v)?,
+                ..b.tls.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "tls-cert-file",
+        alias: None,
+        mutable: true,
+        default: "",
+        get: |b| display_path(b.tls.config().cert_file),
+        set: |b, v| {
+            b.tls.set_config(crate::tls::TlsConfig {
+                cert_file: (!v.is_empty()).then(|| PathBuf::from(v)),
+                ..b.tls.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "tls-key-file",
+        alias: None,
+        mutable: true,
+        default: "",
+        get: |b| display_path(b.tls.config().key_file),
+        set: |b, v| {
+            b.tls.set_config(crate::tls::TlsConfig {
+                key_file: (!v.is_empty()).then(|| PathBuf::from(v)),
+                ..b.tls.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "tls-ca-cert-file",
+        alias: None,
+        mutable: true,
+        default: "",
+        get: |b| display_path(b.tls.config().ca_cert_file),
+        set: |b, v| {
+            b.tls.set_config(crate::tls::TlsConfig {
+                ca_cert_file: (!v.is_empty()).then(|| PathBuf::from(v)),
+                ..b.tls.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "tls-auth-clients",
+        alias: None,
+        mutable: true,
+        default: "yes",
+        get: |b| b.tls.config().auth_clients.as_str().into(),
+        set: |b, v| {
+            b.tls.set_config(crate::tls::TlsConfig {
+                auth_clients: v.parse()?,
+                ..b.tls.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "tls-auth-clients-user",
+        alias: None,
+        mutable: true,
+        default: "off",
+        get: |b| match b.tls.config().auth_clients_user {
+            true => "CN".into(),
+            false => "off".into(),
+        },
+        set: |b, v| {
+            let auth_clients_user = match v.to_ascii_lowercase().as_str() {
+                "cn" => true,
+                "off" => false,
+                _ => return Err("argument must be 'CN' or 'off'".into()),
+            };
+            b.tls.set_config(crate::tls::TlsConfig {
+                auth_clients_user,
+                ..b.tls.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "requirepass",
+        alias: None,
+        mutable: true,
+        default: "",
+        get: |b| b.acl.config().requirepass,
+        set: |b, v| {
+            b.acl.set_requirepass(v);
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "aclfile",
+        alias: None,
+        mutable: false,
+        default: "",
+        get: |b| display_path(b.acl.config().file),
+        set: |b, v| {
+            b.acl.set_config(crate::acl::AclConfig {
+                file: (!v.is_empty()).then(|| PathBuf::from(v)),
+                ..b.acl.config()
+            });
+            Ok(())
+        },
+    },
+    ConfigEntry {
+        name: "acllog-max-len",
+        alias: None,
+        mutable: true,
+        default: "128",
+        get: |b| b.acl.config().log_max_len.to_string(),
+        set: |b, v| {
+            b.acl.set_config(crate::acl::AclConfig {
+                log_max_len: parse_number(v)?,
+                ..b.acl.config()
+            });
+            Ok(())
+        },
+    },
+];
+
+fn find(name: &str) -> Option<&'static ConfigEntry> {
+    ENTRIES.iter().find(|entry| entry.matches(name))
+}
+
+/// CONFIG GET：匹配任意一个pattern的配置项，按配置表的顺序返回(名字, 值)
+pub fn get(backend: &Backend, patterns: &[String]) -> Vec<(&'static str, String)> {
+    ENTRIES
+        .iter()
+        .filter(|entry| {
+            patterns.iter().any(|pattern| {
+                let matches = |name: &str| glob_match(pattern.as_bytes(), name.as_bytes(), true);
+                matches(entry.name) || entry.alias.is_some_and(matches)
+            })
+        })
+        .map(|entry| {
+            let value = (entry.get)(backend);
+            //master的密码不通过CONFIG GET返回，CONFIG REWRITE仍然写入明文
+            match entry.name {
+                "masterauth" if !value.is_empty() => (entry.name, "(redacted)".to_string()),
+                _ => (entry.name, value),
+            }
+        })
+        .collect()
+}
+
+/// CONFIG SET：所有参数都检查通过才生效，中途失败时已经修改的配置项恢复原来的值
+pub fn set(backend: &Backend, params: &[(String, String)]) -> Result<(), ConfigError> {
+    let mut entries = Vec::with_capacity(params.len());
+    for (name, value) in params {
+        let entry = find(name).ok_or_else(|| ConfigError::UnknownOption(name.clone()))?;
+        if entries
+            .iter()
+            .any(|(e, _): &(&ConfigEntry, _)| e.name == entry.name)
+        {
+            return Err(ConfigError::Duplicate(name.clone()));
+        }
+        if !entry.mutable {
+            return Err(ConfigError::SetFailed {
+                name: name.clone(),
+                reason: "can't set immutable config".into(),
+            });
+        }
+        entries.push((entry, value.as_str()));
+    }
+
+    let mut applied: Vec<(&ConfigEntry, String)> = Vec::new();
+    for (entry, value) in entries {
+        let old = (entry.get)(backend);
+        if let Err(reason) = (entry.set)(backend, value) {
+            rollback(backend, applied);
+            return Err(ConfigError::SetFailed {
+                name: entry.name.into(),
+                reason,
+            });
+        }
+        applied.push((entry, old));
+    }
+
+    //证书和私钥经常一起换，所有tls-*都修改之后才重新加载，加载失败时全部恢复
+    let tls = applied
+        .iter()
+        .find(|(entry, _)| entry.name.starts_with("tls-"));
+    if let Some((entry, _)) = tls {
+        if backend.tls.is_enabled() {
+            if let Err(e) = backend.tls.reload() {
+                let name = entry.name.into();
+                rollback(backend, applied);
+                return Err(ConfigError::SetFailed {
+                    name,
+                    reason: e.to_string(),
+                });
+            }
+        }
+    }
+
+    //appendonly的setter只修改配置，生效之后再打开或者关闭文件，打开失败时同样全部恢复
+    if applied.iter().any(|(entry, _)| entry.name == "appendonly") {
+        let enabled = backend.aof.config().enabled;
+        if enabled && !backend.aof.is_enabled() {
+            if let Err(e) = crate::aof::start(backend) {
+                rollback(backend, applied);
+                return Err(ConfigError::SetFailed {
+                    name: "appendonly".into(),
+                    reason: e.to_string(),
+                });
+            }
+        } else if !enabled && backend.aof.is_enabled() {
+            backend.aof.close();
+        }
+    }
+    Ok(())
+}
+
+/// 按相反的顺序恢复已经修改的配置项
+fn rollback(backend: &Backend, applied: Vec<(&ConfigEntry, String)>) {
+    for (entry, old) in applied.into_iter().rev() {
+        let _ = (entry.set)(backend, &old);
+    }
+}
+
+/// 配置文件和命令行里的一条指令，不可修改的配置项也可以设置
+fn apply(backend: &Backend, name: &str, value: &str) -> Result<(), ConfigError> {
+    let entry = find(name).ok_or(ConfigError::BadDirective)?;
+    (entry.set)(backend, value).map_err(|reason| ConfigError::SetFailed {
+        name: entry.name.into(),
+        reason,
+    })
+}
+
+/// CONFIG RESETSTAT
+pub fn reset_stats(backend: &Backend) {
+    backend.stats.reset();
+    backend.eviction.reset_stats();
+}
+
+/// 没有设置的文件路径显示为空字符串
+fn display_path(path: Option<PathBuf>) -> String {
+    path.map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.into()
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'".into()),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| "argument couldn't be parsed into an integer".into())
+}
+
+fn parse_positive(value: &str) -> Result<u64, String> {
+    match parse_number::<u64>(value)? {
+        0 => Err("argument must be greater than 0".into()),
+        n => Ok(n),
+    }
+}
+
+/// 和Redis的memtoll一样：k/m/g是1000的幂，kb/mb/gb是1024的幂，不区分大小写
+pub fn parse_memory(value: &str) -> Result<u64, String> {
+    let lower = value.to_ascii_lowercase();
+    let units: [(&str, u64); 6] = [
+        ("kb", 1024),
+        ("mb", 1024 * 1024),
+        ("gb", 1024 * 1024 * 1024),
+        ("k", 1000),
+        ("m", 1000 * 1000),
+        ("g", 1000 * 1000 * 1000),
+    ];
+    let (digits, unit) = units
+        .iter()
+        .find_map(|(suffix, unit)| lower.strip_suffix(suffix).map(|digits| (digits, *unit)))
+        .unwrap_or((lower.as_str(), 1));
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .ok_or_else(|| "argument must be a memory value".into())
+}
+
+/// proto-max-bulk-len和client-query-buffer-limit：和Redis一样不能小于1mb
+fn parse_memory_limit(value: &str) -> Result<usize, String> {
+    parse_memory(value)?
+        .try_into()
+        .ok()
+        .filter(|limit| *limit >= 1024 * 1024)
+        .ok_or_else(|| "argument must be a memory value of at least 1mb".into())
+}
+
+fn parse_save_rules(value: &str) -> Result<Vec<SaveRule>, String> {
+    let numbers = value
+        .split_whitespace()
+        .map(|n| n.parse::<u64>().map_err(|_| "Invalid save parameters"))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !numbers.len().is_multiple_of(2) {
+        return Err("Invalid save parameters".into());
+    }
+    Ok(numbers
+        .chunks(2)
+        .map(|pair| SaveRule {
+            seconds: pair[0],
+            changes: pair[1],
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_match_backend() {
+        let backend = Backend::new();
+        for entry in ENTRIES {
+            assert_eq!((entry.get)(&backend), entry.default, "{}", entry.name);
+        }
+    }
+
+    #[test]
+    fn test_config_get() {
+        let backend = Backend::new();
+        let values = get(&backend, &["maxmemory*".into(), "PORT".into()]);
+        assert_eq!(
+            values,
+            vec![
+                ("port", "6379".to_string()),
+                ("maxmemory", "0".to_string()),
+                ("maxmemory-policy", "noeviction".to_string()),
+                ("maxmemory-samples", "5".to_string()),
+            ]
+        );
+        //别名也能匹配，返回的是正式的名字
+        let values = get(&backend, &["slave-read-only".into()]);
+        assert_eq!(values, vec![("replica-read-only", "yes".to_string())]);
+        assert!(get(&backend, &["nothing".into()]).is_empty());
+    }
+
+    #[test]
+    fn test_config_set() {
+        let backend = Backend::new();
+        let param = |name: &str, value: &str| (name.to_string(), value.to_string());
+        set(
+            &backend,
+            &[
+                param("maxmemory", "10mb"),
+                param("maxmemory-policy", "allkeys-lru"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(backend.eviction.config().maxmemory, 10 * 1024 * 1024);
+        assert_eq!(backend.eviction.config().policy, EvictionPolicy::AllKeysLru);
+
+        //失败时前面已经修改的配置项被恢复
+        let err = set(
+            &backend,
+            &[param("maxmemory", "1g"), param("appendfsync", "sometimes")],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("'appendfsync'"));
+        assert_eq!(backend.eviction.config().maxmemory, 10 * 1024 * 1024);
+
+        assert!(matches!(
+            set(&backend, &[param("port", "6380")]),
+            Err(ConfigError::SetFailed { .. })
+        ));
+        assert!(matches!(
+            set(&backend, &[param("nothing", "1")]),
+            Err(ConfigError::UnknownOption(_))
+        ));
+        assert!(matches!(
+            set(&backend, &[param("save", ""), param("SAVE", "1 1")]),
+            Err(ConfigError::Duplicate(_))
+        ));
+        set(&backend, &[param("save", "")]).unwrap();
+        assert!(backend.rdb.config().save_rules.is_empty());
+        assert!(set(&backend, &[param("save", "1 2 3")]).is_err());
+        set(&backend, &[param("proto-max-bulk-len", "2mb")]).unwrap();
+        assert_eq!(backend.config.proto_limits().max_bulk_len, 2 * 1024 * 1024);
+        assert!(set(&backend, &[param("client-query-buffer-limit", "1000")]).is_err());
+        assert!(set(&backend, &[param("proto-max-nesting", "0")]).is_err());
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(parse_memory("100"), Ok(100));
+        assert_eq!(parse_memory("1k"), Ok(1000));
+        assert_eq!(parse_memory("1KB"), Ok(1024));
+        assert_eq!(parse_memory("2gb"), Ok(2 * 1024 * 1024 * 1024));
+        assert!(parse_memory("1tb").is_err());
+        assert!(parse_memory("-1").is_err());
+    }
+}
@@ -0,0 +1,350 @@
+/*
+maxmemory和淘汰策略：
+    used_memory是Backend里所有key估算的字节数，超过maxmemory之后每条命令执行前先淘汰key，
+    直到回到限制以内；淘汰不了(noeviction或者没有可淘汰的key)时拒绝会占用更多内存的命令
+和Redis一样用采样近似：每次随机取maxmemory-samples个key放进一个按分数排序的候选池，
+淘汰池里分数最高的key，LRU的分数是空闲时间，LFU是255减去访问计数，TTL是越早过期分数越高
+*/
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+
+use rand::seq::SliceRandom;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{backend::now_ms, execute_command, Backend, Del};
+
+/// 候选池的大小，和Redis的EVPOOL_SIZE一致
+const EVICTION_POOL_SIZE: usize = 16;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum EvictionError {
+    #[error("OOM command not allowed when used memory > 'maxmemory'.")]
+    OutOfMemory,
+    #[error("Invalid maxmemory-policy: {0}")]
+    InvalidPolicy(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    #[default]
+    NoEviction,
+    AllKeysLru,
+    VolatileLru,
+    AllKeysLfu,
+    VolatileLfu,
+    AllKeysRandom,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EvictionPolicy::NoEviction => "noeviction",
+            EvictionPolicy::AllKeysLru => "allkeys-lru",
+            EvictionPolicy::VolatileLru => "volatile-lru",
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu",
+            EvictionPolicy::VolatileLfu => "volatile-lfu",
+            EvictionPolicy::AllKeysRandom => "allkeys-random",
+            EvictionPolicy::VolatileRandom => "volatile-random",
+            EvictionPolicy::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    /// 只淘汰设置了过期时间的key
+    fn volatile(&self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::VolatileLru
+                | EvictionPolicy::VolatileLfu
+                | EvictionPolicy::VolatileRandom
+                | EvictionPolicy::VolatileTtl
+        )
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = EvictionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let policy = match s.to_ascii_lowercase().as_str() {
+            "noeviction" => EvictionPolicy::NoEviction,
+            "allkeys-lru" => EvictionPolicy::AllKeysLru,
+            "volatile-lru" => EvictionPolicy::VolatileLru,
+            "allkeys-lfu" => EvictionPolicy::AllKeysLfu,
+            "volatile-lfu" => EvictionPolicy::VolatileLfu,
+            "allkeys-random" => EvictionPolicy::AllKeysRandom,
+            "volatile-random" => EvictionPolicy::VolatileRandom,
+            "volatile-ttl" => EvictionPolicy::VolatileTtl,
+            _ => return Err(EvictionError::InvalidPolicy(s.into())),
+        };
+        Ok(policy)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EvictionConfig {
+    /// 字节数，0表示不限制
+    pub maxmemory: u64,
+    pub policy: EvictionPolicy,
+    /// 每次采样的key数量
+    pub samples: usize,
+}
+
+impl Default for EvictionConfig {
+    fn default() -> Self {
+        Self {
+            maxmemory: 0,
+            policy: EvictionPolicy::default(),
+            samples: 5,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct EvictionState {
+    config: RwLock<EvictionConfig>,
+    evicted_keys: AtomicU64,
+    /// (分数, key)，按分数从小到大排列
+    pool: Mutex<Vec<(u64, String)>>,
+}
+
+impl EvictionState {
+    pub fn config(&self) -> EvictionConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_config(&self, config: EvictionConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+        self.pool.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// 启动以来淘汰的key数量
+    pub fn evicted_keys(&self) -> u64 {
+        self.evicted_keys.load(Ordering::SeqCst)
+    }
+
+    /// CONFIG RESETSTAT
+    pub fn reset_stats(&self) {
+        self.evicted_keys.store(0, Ordering::SeqCst);
+    }
+}
+
+/// 超过maxmemory时淘汰key直到回到限制以内，调用方需要持有Backend的共享锁。
+/// 淘汰用DEL实现，所以同样会写进AOF和复制流
+pub fn perform_evictions(backend: &Backend) -> Result<(), EvictionError> {
+    let config = backend.eviction.config();
+    if config.maxmemory == 0 || backend.used_memory() as u64 <= config.maxmemory {
+        return Ok(());
+    }
+    let start = Instant::now();
+    let ret = evict_until_fit(backend, &config);
+    backend.latency.record("eviction-cycle", start.elapsed());
+    ret
+}
+
+fn evict_until_fit(backend: &Backend, config: &EvictionConfig) -> Result<(), EvictionError> {
+    while backend.used_memory() as u64 > config.maxmemory {
+        if config.policy == EvictionPolicy::NoEviction {
+            return Err(EvictionError::OutOfMemory);
+        }
+        let Some(key) = pick_key(backend, config) else {
+            return Err(EvictionError::OutOfMemory);
+        };
+        debug!("Evicting key {} by {}", key, config.policy.as_str());
+        execute_command(Del::new(vec![key]).into(), backend);
+        backend.eviction.evicted_keys.fetch_add(1, Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+/// 选出下一个要淘汰的key，没有可以淘汰的key时返回None
+fn pick_key(backend: &Backend, config: &EvictionConfig) -> Option<String> {
+    let policy = config.policy;
+    let volatile = policy.volatile();
+    if matches!(
+        policy,
+        EvictionPolicy::AllKeysRandom | EvictionPolicy::VolatileRandom
+    ) {
+        let keys = backend.sample_keys(1, volatile);
+        return keys
+            .choose(&mut rand::thread_rng())
+            .map(|(key, _)| key.clone());
+    }
+
+    let now = now_ms();
+    let mut pool = backend
+        .eviction
+        .pool
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    for (key, meta) in backend.sample_keys(config.samples, volatile) {
+        let score = match policy {
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => {
+                (u8::MAX - meta.freq(now)) as u64
+            }
+            EvictionPolicy::VolatileTtl => match backend.expires.get(&key) {
+                Some(when) => u64::MAX - *when,
+                None => continue,
+            },
+            _ => meta.idle_ms(now),
+        };
+        insert_candidate(&mut pool, score, key);
+    }
+    //分数最高的候选可能已经被删除了
+    while let Some((_, key)) = pool.pop() {
+        let alive = match volatile {
+            true => backend.expires.contains_key(&key),
+            false => backend.key_meta(&key).is_some(),
+        };
+        if alive {
+            return Some(key);
+        }
+    }
+    None
+}
+
+/// 池满时只有分数比最小的那个更高才能进来
+fn insert_candidate(pool: &mut Vec<(u64, String)>, score: u64, key: String) {
+    if let Some(pos) = pool.iter().position(|(_, k)| *k == key) {
+        pool.remove(pos);
+    }
+    let pos = pool.partition_point(|(s, _)| *s < score);
+    if pool.len() >= EVICTION_POOL_SIZE {
+        if pos == 0 {
+            return;
+        }
+        pool.remove(0);
+        pool.insert(pos - 1, (score, key));
+    } else {
+        pool.insert(pos, (score, key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aof::command_array, network::request_handler, network::Session, RespBulkString, RespFrame,
+    };
+
+    fn limited(policy: EvictionPolicy, maxmemory: u64) -> Backend {
+        let backend = Backend::new();
+        backend.eviction.set_config(EvictionConfig {
+            maxmemory,
+            policy,
+            samples: 10,
+        });
+        backend
+    }
+
+    fn set(backend: &Backend, key: &str) {
+        backend.set(key.into(), RespBulkString::from("value").into());
+    }
+
+    #[test]
+    fn test_policy_from_str() {
+        assert_eq!(
+            "ALLKEYS-LRU".parse::<EvictionPolicy>(),
+            Ok(EvictionPolicy::AllKeysLru)
+        );
+        assert_eq!(EvictionPolicy::VolatileTtl.as_str(), "volatile-ttl");
+        assert!("lru".parse::<EvictionPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_insert_candidate() {
+        let mut pool = Vec::new();
+        for score in 0..20u64 {
+            insert_candidate(&mut pool, score, score.to_string());
+        }
+        assert_eq!(pool.len(), EVICTION_POOL_SIZE);
+        assert_eq!(pool.first().unwrap().0, 4);
+        assert_eq!(pool.last().unwrap().0, 19);
+        insert_candidate(&mut pool, 1, "low".into());
+        assert_eq!(pool.first().unwrap().0, 4);
+    }
+
+    #[test]
+    fn test_evict_lru_and_lfu() {
+        let backend = Backend::new();
+        for key in ["a", "b", "c"] {
+            set(&backend, key);
+        }
+        let limit = backend.used_memory() as u64;
+        backend.set_idle("b", 100);
+        backend.eviction.set_config(EvictionConfig {
+            maxmemory: limit,
+            policy: EvictionPolicy::AllKeysLru,
+            samples: 10,
+        });
+        set(&backend, "d");
+        perform_evictions(&backend).unwrap();
+        assert!(!backend.exists("b"));
+        assert_eq!(backend.eviction.evicted_keys(), 1);
+
+        backend.eviction.set_config(EvictionConfig {
+            maxmemory: limit,
+            policy: EvictionPolicy::AllKeysLfu,
+            samples: 10,
+        });
+        for key in ["a", "c", "d"] {
+            backend.set_freq(key, 100);
+        }
+        backend.set_freq("c", 0);
+        set(&backend, "e");
+        backend.set_freq("e", 100);
+        perform_evictions(&backend).unwrap();
+        assert!(!backend.exists("c"));
+    }
+
+    #[test]
+    fn test_evict_volatile() {
+        let backend = limited(EvictionPolicy::VolatileTtl, 1);
+        set(&backend, "a");
+        set(&backend, "b");
+        set(&backend, "c");
+        backend.expire_at("b", now_ms() + 10_000);
+        backend.expire_at("c", now_ms() + 5_000);
+        //只能淘汰设置了过期时间的key，先淘汰更早过期的
+        assert_eq!(perform_evictions(&backend), Err(EvictionError::OutOfMemory));
+        assert!(backend.exists("a"));
+        assert!(!backend.exists("b") && !backend.exists("c"));
+        assert_eq!(backend.eviction.evicted_keys(), 2);
+
+        let backend = limited(EvictionPolicy::AllKeysRandom, 1);
+        set(&backend, "a");
+        set(&backend, "b");
+        perform_evictions(&backend).unwrap();
+        assert_eq!(backend.dbsize(), 0);
+    }
+
+    #[test]
+    fn test_noeviction_rejects_writes() {
+        let backend = limited(EvictionPolicy::NoEviction, 1);
+        let mut session = Session::new();
+        set(&backend, "a");
+        let reply = request_handler(
+            command_array(["SET", "b", "1"]).into(),
+            &mut session,
+            &backend,
+        );
+        assert_eq!(
+            reply,
+            crate::SimpleError::from("OOM command not allowed when used memory > 'maxmemory'.")
+                .into()
+        );
+        //读命令和DEL不受影响
+        let reply = request_handler(command_array(["GET", "a"]).into(), &mut session, &backend);
+        assert_eq!(reply, RespBulkString::from("value").into());
+        let reply = request_handler(command_array(["DEL", "a"]).into(), &mut session, &backend);
+        assert_eq!(reply, RespFrame::Integer(1.into()));
+        assert_eq!(backend.used_memory(), 0);
+    }
+}
@@ -0,0 +1,131 @@
+/// Redis的glob风格匹配(stringmatchlen)：*匹配任意长度，?匹配一个字符，
+/// [abc]、[^abc]、[a-z]匹配字符集合，\转义下一个字符。nocase时忽略ASCII大小写
+pub fn glob_match(pattern: &[u8], text: &[u8], nocase: bool) -> bool {
+    let eq = |a: u8, b: u8| match nocase {
+        true => a.eq_ignore_ascii_case(&b),
+        false => a == b,
+    };
+    let (mut p, mut t) = (0, 0);
+    //最近一个*的位置和它当时对应的text位置，失配时回到这里让*多吃一个字符
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    //连续的*等价于一个
+                    while p < pattern.len() && pattern[p] == b'*' {
+                        p += 1;
+                    }
+                    if p == pattern.len() {
+                        return true;
+                    }
+                    star = Some((p, t));
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                b'[' => {
+                    let (matched, next) = match_class(pattern, p, text[t], nocase);
+                    if matched {
+                        p = next;
+                        t += 1;
+                        continue;
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if eq(pattern[p + 1], text[t]) {
+                        p += 2;
+                        t += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if eq(c, text[t]) {
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        match star {
+            Some((star_p, star_t)) => {
+                p = star_p;
+                t = star_t + 1;
+                star = Some((star_p, t));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// 匹配从start开始的[...]，返回(是否匹配, ]之后的位置)。没有闭合的]时和Redis一样把末尾当作结束
+fn match_class(pattern: &[u8], start: usize, c: u8, nocase: bool) -> (bool, usize) {
+    let fold = |b: u8| match nocase {
+        true => b.to_ascii_lowercase(),
+        false => b,
+    };
+    let c = fold(c);
+    let mut p = start + 1;
+    let negate = pattern.get(p) == Some(&b'^');
+    if negate {
+        p += 1;
+    }
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            matched |= fold(pattern[p + 1]) == c;
+            p += 2;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            let (mut lo, mut hi) = (fold(pattern[p]), fold(pattern[p + 2]));
+            if lo > hi {
+                std::mem::swap(&mut lo, &mut hi);
+            }
+            matched |= (lo..=hi).contains(&c);
+            p += 3;
+        } else {
+            matched |= fold(pattern[p]) == c;
+            p += 1;
+        }
+    }
+    //跳过]
+    let next = (p + 1).min(pattern.len());
+    (matched != negate, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        glob_match(pattern.as_bytes(), text.as_bytes(), false)
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("max*", "maxmemory-policy"));
+        assert!(matches("*memory*", "maxmemory-policy"));
+        assert!(!matches("max*", "appendonly"));
+        assert!(matches("h?llo", "hello"));
+        assert!(!matches("h?llo", "hllo"));
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+        assert!(matches("h[a-b]llo", "hbllo"));
+        assert!(matches("h[z-a]llo", "hbllo"));
+        assert!(matches("a\\*b", "a*b"));
+        assert!(!matches("a\\*b", "axb"));
+        assert!(matches("a*b*c", "aXXbYYbZc"));
+        assert!(!matches("a*b*c", "aXXbYYbZ"));
+        assert!(!matches("abc", "ab"));
+        assert!(glob_match(b"MAX*", b"maxmemory", true));
+        assert!(glob_match(b"[A-C]", b"b", true));
+    }
+}
@@ -0,0 +1,246 @@
+/*
+LATENCY监控：
+    耗时不小于latency-monitor-threshold毫秒的事件按事件名记录，每个事件保留最近160个采样，
+    同一秒内的多次采样合并成一个，取最大值。阈值为0时关闭
+目前记录的事件：
+    command         普通命令的执行时间
+    eviction-cycle  超过maxmemory之后一次淘汰key的时间
+每个命令的耗时直方图在ServerStats里，LATENCY HISTOGRAM直接用它
+*/
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use crate::{backend::now_ms, Backend};
+
+/// 每个事件保留的采样个数
+pub const LATENCY_TS_LEN: usize = 160;
+
+#[derive(Debug, Clone, Default)]
+pub struct LatencyConfig {
+    /// 毫秒，0表示关闭
+    pub threshold: u64,
+}
+
+/// 一个采样点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySample {
+    /// unix秒
+    pub time: u64,
+    /// 毫秒
+    pub latency: u64,
+}
+
+#[derive(Debug, Default)]
+struct LatencyEvent {
+    samples: VecDeque<LatencySample>,
+    /// 所有采样里最大的，包括已经被挤出去的
+    max: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct LatencyState {
+    config: RwLock<LatencyConfig>,
+    events: Mutex<BTreeMap<String, LatencyEvent>>,
+}
+
+impl LatencyState {
+    pub fn config(&self) -> LatencyConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_config(&self, config: LatencyConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    fn events(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, LatencyEvent>> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 耗时超过阈值时给事件加一个采样
+    pub fn record(&self, event: &str, elapsed: Duration) {
+        let threshold = self.config().threshold;
+        let latency = elapsed.as_millis() as u64;
+        if threshold == 0 || latency < threshold {
+            return;
+        }
+        let time = now_ms() / 1000;
+        let mut events = self.events();
+        let event = events.entry(event.to_string()).or_default();
+        event.max = event.max.max(latency);
+        match event.samples.back_mut() {
+            Some(last) if last.time == time => last.latency = last.latency.max(latency),
+            _ => {
+                event.samples.push_back(LatencySample { time, latency });
+                if event.samples.len() > LATENCY_TS_LEN {
+                    event.samples.pop_front();
+                }
+            }
+        }
+    }
+
+    /// LATENCY LATEST：(事件名, 最近一次的时间, 最近一次的耗时, 最大耗时)
+    pub fn latest(&self) -> Vec<(String, LatencySample, u64)> {
+        self.events()
+            .iter()
+            .filter_map(|(name, event)| {
+                let last = event.samples.back()?;
+                Some((name.clone(), *last, event.max))
+            })
+            .collect()
+    }
+
+    /// LATENCY HISTORY：一个事件的所有采样，从旧到新
+    pub fn history(&self, event: &str) -> Vec<LatencySample> {
+        self.events()
+            .get(event)
+            .map(|event| event.samples.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// LATENCY RESET：清空指定的事件，不指定时清空所有，返回清空的事件个数
+    pub fn reset(&self, events: &[String]) -> usize {
+        let mut all = self.events();
+        if events.is_empty() {
+            let count = all.len();
+            all.clear();
+            return count;
+        }
+        events
+            .iter()
+            .filter(|event| all.remove(event.as_str()).is_some())
+            .count()
+    }
+}
+
+/// LATENCY DOCTOR：根据记录的事件给出一份可读的分析报告
+pub fn doctor(backend: &Backend) -> String {
+    let threshold = backend.latency.config().threshold;
+    if threshold == 0 {
+        return "I'm sorry, Dave, I can't do that. Latency monitoring is disabled in this Redis \
+                instance. You may use \"CONFIG SET latency-monitor-threshold <milliseconds>.\" \
+                in order to enable it.\n"
+            .into();
+    }
+    let events = backend.latency.events();
+    if events.is_empty() {
+        return "Dave, no latency spike was observed during the lifetime of this Redis instance, \
+                not in the slightest bit. I honestly think you ought to sleep a bit more, Dave!\n"
+            .into();
+    }
+
+    let mut report = String::from(
+        "Dave, I have observed latency spikes in this Redis instance. \
+         You don't mind talking about it, do you Dave?\n\n",
+    );
+    for (i, (name, event)) in events.iter().enumerate() {
+        let samples = &event.samples;
+        let count = samples.len() as u64;
+        let avg = samples.iter().map(|s| s.latency).sum::<u64>() / count.max(1);
+        let mad = samples.iter().map(|s| s.latency.abs_diff(avg)).sum::<u64>() / count.max(1);
+        //平均多久出现一次
+        let period = match (samples.front(), samples.back()) {
+            (Some(first), Some(last)) if count > 1 => {
+                (last.time - first.time) as f64 / (count - 1) as f64
+            }
+            _ => 0.0,
+        };
+        let _ = writeln!(
+            report,
+            "{}. {}: {} latency spikes (average {}ms, mean deviation {}ms, period {:.2} sec). \
+             Worst all time event {}ms.",
+            i + 1,
+            name,
+            count,
+            avg,
+            mad,
+            period,
+            event.max
+        );
+    }
+
+    report.push_str("\nI have a few advices for you:\n\n");
+    let slowlog = backend.slowlog.config();
+    if events.contains_key("command") {
+        if slowlog.slower_than < 0 || slowlog.slower_than as u64 > threshold * 1000 {
+            let _ = writeln!(
+                report,
+                "- Your current Slow Log configuration only logs events that are slower than \
+                 your configured latency monitor threshold. Please use \
+                 'CONFIG SET slowlog-log-slower-than {}'.",
+                threshold * 1000
+            );
+        }
+        report.push_str(
+            "- Check your Slow Log to understand what are the commands you are running which \
+             are too slow to execute. Please check https://redis.io/commands/slowlog for more \
+             information.\n",
+        );
+        report.push_str(
+            "- Deleting, expiring or evicting (because of maxmemory policy) large objects is a \
+             blocking operation. If you have very large objects that are often deleted, expired, \
+             or evicted, try to fragment those objects into multiple smaller objects.\n",
+        );
+    }
+    if events.contains_key("eviction-cycle") {
+        report.push_str(
+            "- Evicting keys takes too long: consider raising 'maxmemory' or lowering \
+             'maxmemory-samples' so that every eviction cycle does less work.\n",
+        );
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_record() {
+        let latency = LatencyState::default();
+        latency.record("command", Duration::from_millis(500));
+        assert!(latency.latest().is_empty());
+
+        latency.set_config(LatencyConfig { threshold: 100 });
+        latency.record("command", Duration::from_millis(99));
+        assert!(latency.latest().is_empty());
+        //同一秒内的采样合并成一个，取最大值
+        latency.record("command", Duration::from_millis(150));
+        latency.record("command", Duration::from_millis(300));
+        latency.record("command", Duration::from_millis(200));
+        latency.record("eviction-cycle", Duration::from_millis(120));
+
+        let history = latency.history("command");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].latency, 300);
+        let latest = latency.latest();
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].0, "command");
+        assert_eq!((latest[0].1.latency, latest[0].2), (300, 300));
+        assert!(latency.history("nothing").is_empty());
+
+        assert_eq!(
+            latency.reset(&["eviction-cycle".into(), "nothing".into()]),
+            1
+        );
+        assert_eq!(latency.reset(&[]), 1);
+        assert!(latency.latest().is_empty());
+    }
+
+    #[test]
+    fn test_latency_doctor() {
+        let backend = Backend::new();
+        assert!(doctor(&backend).contains("Latency monitoring is disabled"));
+        backend.latency.set_config(LatencyConfig { threshold: 5 });
+        assert!(doctor(&backend).contains("no latency spike was observed"));
+        backend.latency.record("command", Duration::from_millis(20));
+        let report = doctor(&backend);
+        assert!(report.contains("1. command: 1 latency spikes (average 20ms"));
+        assert!(report.contains("CONFIG SET slowlog-log-slower-than 5000"));
+        assert!(report.contains("Check your Slow Log"));
+    }
+}
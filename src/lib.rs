@@ -1,6 +1,24 @@
+pub mod acl;
+pub mod aof;
 mod backend;
+pub mod clients;
+pub mod cluster;
 pub mod cmd;
+pub mod config;
+pub mod eviction;
+pub mod glob;
+pub mod latency;
+pub mod monitor;
+pub mod network;
+pub mod rdb;
+pub mod replication;
 pub mod resp;
+pub mod slowlog;
+pub mod stats;
+#[cfg(test)]
+mod test_util;
+pub mod tls;
+pub mod tracking;
 
 pub use backend::*;
 pub use cmd::*;
@@ -1,8 +1,12 @@
+use anyhow::Result;
+use futures::FutureExt;
+use simple_redis::{aof, cluster, config, network, rdb, replication, Backend};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
 use tracing_subscriber::FmtSubscriber;
 
-mod resp;
-
-fn main() {
+#[tokio::main]
+async fn main() -> Result<()> {
     // 创建一个日志订阅者
     let subscriber = FmtSubscriber::builder()
         .with_max_level(tracing::Level::TRACE)
@@ -10,6 +14,98 @@ fn main() {
 
     // 全局设置订阅者
     tracing::subscriber::set_global_default(subscriber).expect("设置全局默认订阅者失败");
-    println!("Hello, world!");
-    println!("where is the automatically hint?");
+
+    //redis-server [/path/to/redis.conf] [--option value ...]
+    let backend = Backend::new();
+    let (path, overrides) = config::parse_args(std::env::args().skip(1))?;
+    config::load(&backend, path.as_deref(), &overrides)?;
+    //配置了aclfile时用文件里的用户，文件有错误时不启动
+    if backend.acl.config().file.is_some() {
+        backend.acl.load()?;
+    }
+
+    //port为0时不监听TCP，只用Unix socket或者TLS
+    let port = backend.repl.config().port;
+    let mut listeners = Vec::new();
+    if port != 0 {
+        for addr in backend.config.server().bind_addrs() {
+            let listener = TcpListener::bind((addr, port)).await?;
+            info!("Simple-Redis-Server is listening on {}:{}", addr, port);
+            listeners.push(listener);
+        }
+    }
+    let server_config = backend.config.server();
+    let unix_listener = match &server_config.unixsocket {
+        Some(path) => {
+            let listener = network::bind_unix(path, server_config.unixsocketperm)?;
+            info!(
+                "Simple-Redis-Server is listening on unix socket {}",
+                path.display()
+            );
+            Some(listener)
+        }
+        None => None,
+    };
+
+    //和Redis一样，开启AOF时只从AOF恢复数据，RDB文件会被忽略
+    if backend.aof.config().enabled {
+        match aof::load(&backend) {
+            Ok(commands) => info!("DB loaded from append only file: {} commands", commands),
+            Err(e) => return Err(e.into()),
+        }
+        backend.aof.open()?;
+    } else {
+        match rdb::load(&backend) {
+            Ok(report) => {
+                info!("DB loaded from disk: {} keys", report.loaded);
+                if let Some(e) = report.unsupported() {
+                    warn!("{}", e);
+                }
+            }
+            Err(e) => warn!("failed to load RDB file: {}", e),
+        }
+    }
+    //AOF可以在运行中通过CONFIG SET开启，后台任务总是启动
+    tokio::spawn(aof::cron(backend.clone()));
+    tokio::spawn(rdb::save_scheduler(backend.clone()));
+
+    if let Some((host, master_port)) = backend.config.server().replicaof {
+        if let Err(e) = replication::replicate(&backend, host, master_port) {
+            warn!("failed to start replication: {}", e);
+        }
+    }
+    tokio::spawn(replication::cron(backend.clone()));
+    let cluster_config = backend.cluster.config();
+    if cluster_config.enabled {
+        let bus_port = cluster_config.bus_port(port);
+        let bus = TcpListener::bind(("0.0.0.0", bus_port)).await?;
+        info!("Cluster bus is listening on port {}", bus_port);
+        cluster::start(&backend, port, bus)?;
+    }
+
+    let mut servers = listeners
+        .into_iter()
+        .map(|listener| network::serve(listener, backend.clone()).boxed())
+        .collect::<Vec<_>>();
+    if let Some(listener) = unix_listener {
+        servers.push(network::serve_unix(listener, backend.clone()).boxed());
+    }
+    //tls-port不为0时证书必须能加载，否则不启动
+    let tls_port = backend.tls.config().port;
+    if tls_port != 0 {
+        backend.tls.reload()?;
+        for addr in backend.config.server().bind_addrs() {
+            let listener = TcpListener::bind((addr, tls_port)).await?;
+            info!(
+                "Simple-Redis-Server is listening on {}:{} (TLS)",
+                addr, tls_port
+            );
+            servers.push(network::serve_tls(listener, backend.clone()).boxed());
+        }
+    }
+    if servers.is_empty() {
+        anyhow::bail!("Configured to not listen anywhere, exiting.");
+    }
+    futures::future::try_join_all(servers).await?;
+    Ok(())
 }
@@ -0,0 +1,278 @@
+/*
+MONITOR：
+    执行MONITOR的连接登记一个channel，之后任何连接执行的每条命令都格式化成一行发给所有登记的连接：
+        +1339518083.107412 [0 127.0.0.1:60866] "keys" "*"
+    参数和Redis的sdscatrepr一样加引号并转义，AUTH、HELLO AUTH、ACL SETUSER这类带密码的参数替换成(redacted)
+    被拒绝(参数错误、OOM、READONLY等)没有执行的命令不会发送，MULTI里的命令在排队时发送
+    连接断开时由连接循环注销
+*/
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::{network::ConnAddr, RespArray, RespFrame, SimpleString};
+
+const REDACTED: &[u8] = b"(redacted)";
+/// CONFIG SET的值是密码的配置项
+const SENSITIVE_CONFIGS: &[&str] = &["requirepass", "masterauth"];
+
+#[derive(Debug, Default)]
+pub struct MonitorState {
+    /// (连接id, 发送给这个连接的channel)
+    monitors: Mutex<Vec<(u64, UnboundedSender<RespFrame>)>>,
+    /// 没有MONITOR连接时连接循环不需要保留命令的参数
+    count: AtomicUsize,
+}
+
+impl MonitorState {
+    /// 登记一个MONITOR连接，重复登记时沿用原来的channel，返回None
+    pub fn add(&self, id: u64) -> Option<UnboundedReceiver<RespFrame>> {
+        let mut monitors = self.monitors.lock().unwrap_or_else(|e| e.into_inner());
+        if monitors.iter().any(|(monitor, _)| *monitor == id) {
+            return None;
+        }
+        let (sender, receiver) = mpsc::unbounded_channel();
+        monitors.push((id, sender));
+        self.count.store(monitors.len(), Ordering::SeqCst);
+        Some(receiver)
+    }
+
+    pub fn remove(&self, id: u64) {
+        let mut monitors = self.monitors.lock().unwrap_or_else(|e| e.into_inner());
+        monitors.retain(|(monitor, _)| *monitor != id);
+        self.count.store(monitors.len(), Ordering::SeqCst);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.count.load(Ordering::SeqCst) > 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 把一条已经执行的命令发给所有MONITOR连接
+    pub fn feed(&self, addr: Option<ConnAddr>, array: &RespArray) {
+        if !self.is_active() {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or_default();
+        let line = format_line(now, addr, array);
+        let mut monitors = self.monitors.lock().unwrap_or_else(|e| e.into_inner());
+        //接收端已经关闭的连接顺便注销
+        monitors.retain(|(_, sender)| sender.send(SimpleString::from(line.clone()).into()).is_ok());
+        self.count.store(monitors.len(), Ordering::SeqCst);
+    }
+}
+
+/// 格式化成`1339518083.107412 [0 127.0.0.1:60866] "keys" "*"`，时间是unix微秒，
+/// Unix socket的连接显示成`[0 unix:/tmp/redis.sock]`
+pub fn format_line(time_us: u64, addr: Option<ConnAddr>, array: &RespArray) -> String {
+    let addr = match addr {
+        Some(ConnAddr::Unix(path)) => format!("unix:{}", path.display()),
+        Some(addr) => addr.to_string(),
+        None => "unknown".to_string(),
+    };
+    format!(
+        "{}.{:06} [0 {}] {}",
+        time_us / 1_000_000,
+        time_us % 1_000_000,
+        addr,
+        redacted_args(array)
+    )
+}
+
+/// 用空格分隔的参数，带密码的参数替换成(redacted)，也用于日志
+pub fn redacted_args(array: &RespArray) -> String {
+    redact(array)
+        .into_iter()
+        .map(repr)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 取出参数，带密码的参数替换成(redacted)
+pub fn redact(array: &RespArray) -> Vec<&[u8]> {
+    let mut args = array
+        .iter()
+        .map(|arg| match arg {
+            RespFrame::BulkString(s) => s.as_ref(),
+            RespFrame::SimpleString(s) => s.as_bytes(),
+            _ => &[],
+        })
+        .collect::<Vec<_>>();
+    let Some(name) = args.first().map(|name| name.to_ascii_lowercase()) else {
+        return args;
+    };
+    match name.as_slice() {
+        //AUTH [username] password
+        b"auth" => args[1..].fill(REDACTED),
+        //HELLO [protover [AUTH username password] [SETNAME clientname]]
+        b"hello" => {
+            if let Some(i) = args
+                .iter()
+                .position(|arg| arg.eq_ignore_ascii_case(b"auth"))
+            {
+                let end = (i + 3).min(args.len());
+                args[i + 1..end].fill(REDACTED);
+            }
+        }
+        //MIGRATE host port key db timeout [COPY] [REPLACE] [AUTH password | AUTH2 username password]
+        b"migrate" => {
+            let option =
+                |name: &[u8]| (6..args.len()).find(|i| args[*i].eq_ignore_ascii_case(name));
+            let secret = match (option(b"auth"), option(b"auth2")) {
+                (Some(i), _) => Some(i + 1..i + 2),
+                (None, Some(i)) => Some(i + 1..i + 3),
+                _ => None,
+            };
+            if let Some(range) = secret {
+                let end = range.end.min(args.len());
+                args[range.start.min(end)..end].fill(REDACTED);
+            }
+        }
+        //ACL SETUSER username rule ...，>password和<password是明文密码
+        b"acl"
+            if args
+                .get(1)
+                .is_some_and(|sub| sub.eq_ignore_ascii_case(b"setuser")) =>
+        {
+            for arg in args.iter_mut().skip(3) {
+                if arg.starts_with(b">") || arg.starts_with(b"<") {
+                    *arg = REDACTED;
+                }
+            }
+        }
+        //CONFIG SET requirepass|masterauth password
+        b"config"
+            if args
+                .get(1)
+                .is_some_and(|sub| sub.eq_ignore_ascii_case(b"set")) =>
+        {
+            for i in (2..args.len().saturating_sub(1)).step_by(2) {
+                if SENSITIVE_CONFIGS
+                    .iter()
+                    .any(|name| args[i].eq_ignore_ascii_case(name.as_bytes()))
+                {
+                    args[i + 1] = REDACTED;
+                }
+            }
+        }
+        _ => {}
+    }
+    args
+}
+
+/// 和Redis的sdscatrepr一样：加双引号，转义引号、反斜杠和控制字符，其余不可打印的字节写成\xHH
+pub fn repr(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() + 2);
+    s.push('"');
+    for &b in bytes {
+        match b {
+            b'\\' => s.push_str("\\\\"),
+            b'"' => s.push_str("\\\""),
+            b'\n' => s.push_str("\\n"),
+            b'\r' => s.push_str("\\r"),
+            b'\t' => s.push_str("\\t"),
+            0x07 => s.push_str("\\a"),
+            0x08 => s.push_str("\\b"),
+            b if b.is_ascii_graphic() || b == b' ' => s.push(b as char),
+            b => s.push_str(&format!("\\x{b:02x}")),
+        }
+    }
+    s.push('"');
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aof::command_array;
+
+    #[test]
+    fn test_format_line() {
+        let addr = "127.0.0.1:60866".parse().ok();
+        let line = format_line(1339518083107412, addr, &command_array(["keys", "*"]));
+        assert_eq!(line, r#"1339518083.107412 [0 127.0.0.1:60866] "keys" "*""#);
+        let addr = Some(ConnAddr::Unix("/tmp/redis.sock".into()));
+        let line = format_line(0, addr, &command_array(["ping"]));
+        assert_eq!(line, r#"0.000000 [0 unix:/tmp/redis.sock] "ping""#);
+
+        let line = format_line(
+            1_000_005,
+            None,
+            &command_array([&b"set"[..], &b"k"[..], &b"a\"b\\c\r\n\x01\xff "[..]]),
+        );
+        assert_eq!(
+            line,
+            r#"1.000005 [0 unknown] "set" "k" "a\"b\\c\r\n\x01\xff ""#
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        let redacted = |args: &[&str]| {
+            let line = format_line(0, None, &command_array(args.iter().copied()));
+            line["0.000000 [0 unknown] ".len()..].to_string()
+        };
+        assert_eq!(
+            redacted(&["AUTH", "user", "secret"]),
+            r#""AUTH" "(redacted)" "(redacted)""#
+        );
+        assert_eq!(
+            redacted(&["hello", "3", "auth", "user", "secret", "setname", "app"]),
+            r#""hello" "3" "auth" "(redacted)" "(redacted)" "setname" "app""#
+        );
+        assert_eq!(
+            redacted(&["migrate", "h", "1", "", "0", "10", "auth", "pw", "keys", "a"]),
+            r#""migrate" "h" "1" "" "0" "10" "auth" "(redacted)" "keys" "a""#
+        );
+        assert_eq!(
+            redacted(&["migrate", "h", "1", "auth", "0", "10", "auth2", "u", "pw"]),
+            r#""migrate" "h" "1" "auth" "0" "10" "auth2" "(redacted)" "(redacted)""#
+        );
+        assert_eq!(
+            redacted(&["acl", "setuser", "u", "on", ">pw", "~*"]),
+            r#""acl" "setuser" "u" "on" "(redacted)" "~*""#
+        );
+        assert_eq!(
+            redacted(&["config", "set", "maxmemory", "1", "requirepass", "pw"]),
+            r#""config" "set" "maxmemory" "1" "requirepass" "(redacted)""#
+        );
+        assert_eq!(
+            redacted(&["config", "set", "MASTERAUTH", "pw", "masteruser", "u"]),
+            r#""config" "set" "MASTERAUTH" "(redacted)" "masteruser" "u""#
+        );
+        assert_eq!(redacted(&["get", "auth"]), r#""get" "auth""#);
+    }
+
+    #[tokio::test]
+    async fn test_monitor_feed() {
+        let state = MonitorState::default();
+        assert!(!state.is_active());
+        let mut receiver = state.add(1).unwrap();
+        assert!(state.add(1).is_none());
+        let closed = state.add(2).unwrap();
+        drop(closed);
+        assert_eq!(state.len(), 2);
+
+        state.feed(None, &command_array(["ping"]));
+        let RespFrame::SimpleString(line) = receiver.recv().await.unwrap() else {
+            panic!("monitor line should be a simple string");
+        };
+        assert!(line.ends_with(r#"[0 unknown] "ping""#));
+        //接收端关闭的连接在发送时被注销
+        assert_eq!(state.len(), 1);
+        state.remove(1);
+        assert!(state.is_empty());
+    }
+}
@@ -0,0 +1,1330 @@
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use bytes::BytesMut;
+use futures::SinkExt;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    Notify,
+};
+use tokio_stream::StreamExt;
+use tokio_util::codec::{Decoder, Encoder, Framed};
+use tracing::{info, trace, warn};
+
+use crate::{
+    acl::{Denied, DEFAULT_USER},
+    cluster::{ClusterError, Redirect},
+    cmd::{command_label, command_name},
+    eviction, execute_command, monitor,
+    replication::serve_replica,
+    stats::CallOutcome,
+    sync_request, tracking, without_touch, Acl, Auth, Backend, Blocked, Client, Command,
+    CommandError, DecodeResp, EncodeResp, FrameScanner, Hello, Migrate, ReplConf, RespArray,
+    RespError, RespFrame, RespLimits, RespProtocol, SimpleError, Transaction, Wait, WaitAof,
+};
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+const MAX_CLIENTS_ERROR: &[u8] = b"-ERR max number of clients reached\r\n";
+
+/// 解码之前先检查协议层的限制，默认的限制用于连接其它服务端的客户端
+#[derive(Debug, Default)]
+pub struct RespFrameCodec {
+    limits: RespLimits,
+    scanner: FrameScanner,
+}
+
+impl RespFrameCodec {
+    pub fn new(limits: RespLimits) -> Self {
+        Self {
+            limits,
+            scanner: FrameScanner::default(),
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: RespLimits) {
+        self.limits = limits;
+    }
+}
+
+/// 连接一端的地址：TCP是ip:port，Unix socket是socket文件的路径
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ConnAddr {
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            ConnAddr::Tcp(addr) => Some(addr.ip()),
+            ConnAddr::Unix(_) => None,
+        }
+    }
+}
+
+/// 和Redis一样，Unix socket显示成/path/to/sock:0
+impl fmt::Display for ConnAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnAddr::Tcp(addr) => write!(f, "{addr}"),
+            ConnAddr::Unix(path) => write!(f, "{}:0", path.display()),
+        }
+    }
+}
+
+impl From<SocketAddr> for ConnAddr {
+    fn from(addr: SocketAddr) -> Self {
+        ConnAddr::Tcp(addr)
+    }
+}
+
+impl FromStr for ConnAddr {
+    type Err = std::net::AddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(ConnAddr::Tcp)
+    }
+}
+
+/// 每个连接独有的状态，随连接创建，随连接销毁
+#[derive(Debug)]
+pub struct Session {
+    pub id: u64,
+    pub protocol: RespProtocol,
+    pub name: Option<String>,
+    pub addr: Option<ConnAddr>,
+    pub laddr: Option<ConnAddr>,
+    pub transaction: Transaction,
+    /// 这个连接是replica到master的复制连接或者重放AOF的内部连接，
+    /// 执行的命令不需要认证，不受ACL、只读、集群重定向和maxmemory的限制
+    pub is_master: bool,
+    /// replica通过REPLCONF listening-port告诉master的端口
+    pub replica_listening_port: Option<u16>,
+    /// 这个连接最后一次写命令之后复制流的offset，WAIT/WAITAOF等待的就是它
+    pub woff: u64,
+    /// WAIT/WAITAOF的条件还不满足时或者MIGRATE登记在这里，由连接循环等待
+    pub blocked: Option<Blocked>,
+    /// 发送过ASKING，只对紧接着的一条命令有效
+    pub asking: bool,
+    /// 执行过MONITOR，连接循环从这里取出其他连接执行的命令发给客户端
+    pub monitor: Option<UnboundedReceiver<RespFrame>>,
+    /// CLIENT NO-EVICT，目前只是一个标记
+    pub no_evict: bool,
+    /// CLIENT NO-TOUCH，这个连接的读命令不更新key的LRU/LFU
+    pub no_touch: bool,
+    /// CLIENT KILL通过它通知连接循环退出
+    pub kill: Arc<Notify>,
+    /// 其他连接通过push发送推送消息(比如CLIENT TRACKING的invalidate)，连接循环从pushes取出发给客户端
+    pub push: UnboundedSender<RespFrame>,
+    pub pushes: UnboundedReceiver<RespFrame>,
+    /// CLIENT CACHING YES|NO，只对紧接着的一条命令或者一个事务有效
+    pub caching: Option<bool>,
+    /// 以这个ACL用户的身份执行命令
+    pub user: String,
+    /// 通过了AUTH，或者连接建立时default用户不需要密码
+    pub authenticated: bool,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        let (push, pushes) = mpsc::unbounded_channel();
+        Self {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            protocol: RespProtocol::default(),
+            name: None,
+            addr: None,
+            laddr: None,
+            transaction: Transaction::default(),
+            is_master: false,
+            replica_listening_port: None,
+            woff: 0,
+            blocked: None,
+            asking: false,
+            monitor: None,
+            no_evict: false,
+            no_touch: false,
+            kill: Arc::new(Notify::new()),
+            push,
+            pushes,
+            caching: None,
+            user: DEFAULT_USER.into(),
+            authenticated: false,
+        }
+    }
+
+    /// master的命令流和AOF重放用的内部连接
+    pub fn internal() -> Self {
+        Self {
+            is_master: true,
+            authenticated: true,
+            ..Self::new()
+        }
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 接受连接，每个连接在单独的task里处理
+pub async fn serve(listener: TcpListener, backend: Backend) -> Result<()> {
+    accept(listener, backend, false).await
+}
+
+/// TLS端口：握手成功之后和普通连接一样处理
+pub async fn serve_tls(listener: TcpListener, backend: Backend) -> Result<()> {
+    accept(listener, backend, true).await
+}
+
+async fn accept(listener: TcpListener, backend: Backend, tls: bool) -> Result<()> {
+    loop {
+        let (stream, raddr) = listener.accept().await?;
+        info!("Accepted connection from: {}", raddr);
+        spawn_connection(
+            stream,
+            raddr.to_string(),
+            &backend,
+            move |stream, backend| async move {
+                match tls {
+                    true => tls_stream_handler(stream, backend).await,
+                    false => stream_handler(stream, backend).await,
+                }
+            },
+        );
+    }
+}
+
+/// Unix socket：和TCP连接共用连接处理流程，连接的地址显示成socket文件的路径
+pub async fn serve_unix(listener: UnixListener, backend: Backend) -> Result<()> {
+    let path = listener
+        .local_addr()?
+        .as_pathname()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    loop {
+        let (stream, _) = listener.accept().await?;
+        info!("Accepted connection from unix socket: {}", path.display());
+        let peer = path.display().to_string();
+        let path = path.clone();
+        spawn_connection(stream, peer, &backend, move |stream, backend| {
+            unix_stream_handler(stream, path, backend)
+        });
+    }
+}
+
+/// 删除残留的socket文件后监听，perm不为0时设置socket文件的权限，比如0o700
+pub fn bind_unix(path: &Path, perm: u32) -> Result<UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    if perm != 0 {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(perm))?;
+    }
+    Ok(listener)
+}
+
+/// 超过maxclients的连接回复一个错误后直接关闭，其余的在单独的task里处理
+fn spawn_connection<S, F, Fut>(mut stream: S, peer: String, backend: &Backend, handler: F)
+where
+    S: AsyncWrite + Unpin + Send + 'static,
+    F: FnOnce(S, Backend) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>> + Send,
+{
+    if !backend.stats.client_connected() {
+        warn!(
+            "Rejected connection from {}: max number of clients reached",
+            peer
+        );
+        tokio::spawn(async move {
+            let _ = stream.write_all(MAX_CLIENTS_ERROR).await;
+        });
+        return;
+    }
+    let backend = backend.clone();
+    tokio::spawn(async move {
+        match handler(stream, backend.clone()).await {
+            Ok(_) => info!("Connection from {} exited", peer),
+            Err(e) => warn!("handle error for {}: {:?}", peer, e),
+        }
+        backend.stats.client_disconnected();
+    });
+}
+
+pub async fn stream_handler(stream: TcpStream, backend: Backend) -> Result<()> {
+    let session = tcp_session(&stream, &backend);
+    handle_session(stream, session, &backend).await
+}
+
+/// 客户端证书的CN是一个启用的ACL用户时，连接直接以这个用户认证
+pub async fn tls_stream_handler(stream: TcpStream, backend: Backend) -> Result<()> {
+    let mut session = tcp_session(&stream, &backend);
+    let stream = backend.tls.accept(stream).await?;
+    if let Some(user) = backend.tls.client_user(stream.get_ref().1) {
+        if backend.acl.get_user(&user).is_some_and(|user| user.enabled) {
+            session.user = user;
+            session.authenticated = true;
+        }
+    }
+    handle_session(stream, session, &backend).await
+}
+
+/// Unix socket的客户端一般没有绑定路径，两端都用监听的socket文件表示
+pub async fn unix_stream_handler(
+    stream: UnixStream,
+    path: PathBuf,
+    backend: Backend,
+) -> Result<()> {
+    let addr = Some(ConnAddr::Unix(path));
+    let session = new_session(addr.clone(), addr, &backend);
+    handle_session(stream, session, &backend).await
+}
+
+fn tcp_session(stream: &TcpStream, backend: &Backend) -> Session {
+    let addr = stream.peer_addr().ok().map(ConnAddr::from);
+    let laddr = stream.local_addr().ok().map(ConnAddr::from);
+    new_session(addr, laddr, backend)
+}
+
+fn new_session(addr: Option<ConnAddr>, laddr: Option<ConnAddr>, backend: &Backend) -> Session {
+    let mut session = Session::new();
+    session.addr = addr;
+    session.laddr = laddr;
+    session.authenticated = backend.acl.no_auth_required();
+    session
+}
+
+async fn handle_session<S>(stream: S, session: Session, backend: &Backend) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let id = session.id;
+    backend.clients.register(&session);
+    let codec = RespFrameCodec::new(backend.config.proto_limits());
+    let ret = serve_session(Framed::new(stream, codec), session, backend).await;
+    //MONITOR的连接断开之后不再给它发送
+    backend.monitor.remove(id);
+    backend.clients.unregister(id);
+    backend.tracking.disable(id);
+    ret
+}
+
+async fn serve_session<S>(
+    mut framed: Framed<S, RespFrameCodec>,
+    mut session: Session,
+    backend: &Backend,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let kill = session.kill.clone();
+    loop {
+        //CONFIG SET修改的限制对已有的连接也生效
+        framed.codec_mut().set_limits(backend.config.proto_limits());
+        let monitor = session.monitor.as_mut();
+        let frame = tokio::select! {
+            frame = framed.next() => frame,
+            Some(line) = async { monitor?.recv().await } => {
+                framed.send(line).await?;
+                continue;
+            }
+            Some(push) = session.pushes.recv() => {
+                framed.send(push.with_protocol(session.protocol)).await?;
+                continue;
+            }
+            //被CLIENT KILL的连接直接关闭
+            _ = kill.notified() => return Ok(()),
+        };
+        match frame {
+            Some(Ok(frame)) => {
+                //不能直接打印frame，AUTH等命令的参数里有明文密码
+                if let RespFrame::Arrays(array) = &frame {
+                    trace!("received command: {}", monitor::redacted_args(array));
+                }
+                //PSYNC/SYNC之后这个连接变成复制连接，交给复制模块处理
+                //拿到RDB和复制流之前要和其它命令一样通过认证和ACL检查
+                let sync = sync_request(&frame);
+                if let (Some(_), RespFrame::Arrays(array)) = (&sync, &frame) {
+                    let name = command_name(array).unwrap_or_default();
+                    if let Err(e) = authorize(array, &name, &session, backend) {
+                        framed.send(SimpleError::from(e.to_string()).into()).await?;
+                        continue;
+                    }
+                }
+                match sync {
+                    Some(Ok(psync)) => {
+                        backend.clients.set_replica(session.id);
+                        return serve_replica(framed, session, backend.clone(), psync).await;
+                    }
+                    Some(Err(e)) => {
+                        framed.send(SimpleError::from(e.to_string()).into()).await?;
+                        continue;
+                    }
+                    None => {}
+                }
+                if let RespFrame::Arrays(array) = &frame {
+                    backend
+                        .clients
+                        .command_received(session.id, command_label(array));
+                    //CLIENT PAUSE不影响master的复制连接
+                    if backend.clients.paused().is_some() && !session.is_master {
+                        tokio::select! {
+                            _ = backend.clients.wait_unpaused(is_write_request(array)) => {}
+                            _ = kill.notified() => return Ok(()),
+                        }
+                    }
+                }
+                let mut response = request_handler(frame, &mut session, backend);
+                if let Some(blocked) = session.blocked.take() {
+                    backend.stats.set_blocked(true);
+                    response = blocked.wait(backend).await;
+                    backend.stats.set_blocked(false);
+                }
+                //按照处理完请求之后的协议回复，HELLO切换协议后的回复就已经是新协议了
+                framed
+                    .send(response.with_protocol(session.protocol))
+                    .await?;
+                let qbuf = framed.read_buffer();
+                backend.clients.sync(
+                    &session,
+                    qbuf.len(),
+                    qbuf.capacity() - qbuf.len(),
+                    framed.write_buffer().len(),
+                );
+            }
+            Some(Err(e)) => {
+                //和Redis一样，协议错误先回复错误再关闭连接，超过client-query-buffer-limit的直接关闭
+                match e.downcast_ref::<RespError>() {
+                    Some(RespError::QueryBufferLimit(_)) | None => {}
+                    Some(e) => {
+                        let reply = SimpleError::from(format!("ERR {e}"));
+                        let _ = framed.send(reply.into()).await;
+                    }
+                }
+                return Err(e);
+            }
+            None => return Ok(()),
+        }
+    }
+}
+
+/// WRITE模式的暂停只挡住写命令，EXEC和MIGRATE按写命令对待
+fn is_write_request(array: &RespArray) -> bool {
+    match command_name(array).as_deref() {
+        Ok("exec" | "migrate") => true,
+        _ => Command::try_from(array.clone()).is_ok_and(|cmd| cmd.is_write()),
+    }
+}
+
+pub fn request_handler(frame: RespFrame, session: &mut Session, backend: &Backend) -> RespFrame {
+    let start = Instant::now();
+    //只有存在MONITOR连接时才需要保留一份参数
+    let monitored = match &frame {
+        RespFrame::Arrays(array) if backend.monitor.is_active() => Some(array.clone()),
+        _ => None,
+    };
+    let (name, ret) = match frame {
+        RespFrame::Arrays(array) => (
+            command_name(&array).ok(),
+            dispatch_as(array, session, backend),
+        ),
+        _ => (
+            None,
+            Err(CommandError::InvalidCommand(
+                "command should be a RespArray".into(),
+            )),
+        ),
+    };
+
+    //没有执行就被拒绝的命令和执行后回复错误的命令分开统计
+    let (outcome, reply) = match ret {
+        Ok(reply) => match reply {
+            RespFrame::SimpleError(_) => (CallOutcome::Failed, reply),
+            reply => (CallOutcome::Ok, reply),
+        },
+        Err(e) => (
+            CallOutcome::Rejected,
+            SimpleError::from(e.to_string()).into(),
+        ),
+    };
+    let error = match &reply {
+        RespFrame::SimpleError(e) => Some(e.0.as_str()),
+        _ => None,
+    };
+    //未知命令不出现在commandstats里
+    let name = name.filter(|_| !error.is_some_and(|e| e.starts_with("ERR unknown command")));
+    backend
+        .stats
+        .record_call(name.as_deref(), start.elapsed(), outcome, error);
+    //被拒绝没有执行的命令和MONITOR自己不发给MONITOR连接
+    if let Some(array) = monitored {
+        if outcome != CallOutcome::Rejected && name.is_some_and(|name| name != "monitor") {
+            backend.monitor.feed(session.addr.clone(), &array);
+        }
+    }
+    reply
+}
+
+/// 以当前连接的身份执行命令：NO-TOUCH的连接不更新key的访问时间，
+/// 打开了TRACKING的连接读写的key由tracking记录和通知
+fn dispatch_as(
+    array: RespArray,
+    session: &mut Session,
+    backend: &Backend,
+) -> Result<RespFrame, CommandError> {
+    let caching_cmd = command_label(&array) == "client|caching";
+    let no_touch = session.no_touch;
+    let caller = backend.tracking.caller(session.id, session.caching);
+    let ret = tracking::with_caller(caller, || match no_touch {
+        true => without_touch(|| dispatch(array, session, backend)),
+        false => dispatch(array, session, backend),
+    });
+    //CLIENT CACHING只对紧接着的一条命令有效，MULTI里对整个事务有效
+    if !caching_cmd && !session.transaction.in_multi() {
+        session.caching = None;
+    }
+    ret
+}
+
+/// EXEC里执行排队的连接命令(HELLO、CLIENT等)，这时已经持有backend的写锁，
+/// 和Redis一样不能阻塞：WAIT/WAITAOF直接回复当前的数量
+fn exec_queued(array: RespArray, session: &mut Session, backend: &Backend) -> RespFrame {
+    let reply = dispatch(array, session, backend)
+        .unwrap_or_else(|e| SimpleError::from(e.to_string()).into());
+    session.blocked = None;
+    reply
+}
+
+fn dispatch(
+    array: RespArray,
+    session: &mut Session,
+    backend: &Backend,
+) -> Result<RespFrame, CommandError> {
+    let name = command_name(&array)?;
+    //master通过复制连接发来的命令不受只读限制，也不会被重定向
+    let is_master = session.is_master;
+    let read_only = !is_master && backend.repl.read_only();
+    let asking = std::mem::take(&mut session.asking);
+    let route = |keys: &[&str], asking: bool| match is_master {
+        true => Ok(()),
+        false => backend.cluster.route(backend, keys, asking),
+    };
+    //master通过复制连接发来的命令不需要认证，也不受ACL限制
+    if !is_master {
+        if let Err(e) = authorize(&array, &name, session, backend) {
+            session.transaction.flag_error();
+            return Err(e);
+        }
+    }
+    let user = &session.user;
+    let in_multi = session.transaction.in_multi();
+    let check_keys = |keys: &[&str], write: bool| match is_master {
+        true => Ok(()),
+        false => match backend.acl.check_keys(user, keys, write) {
+            Err(Denied::Key(key)) => {
+                log_denied(backend, session.id, user, in_multi, "key", key);
+                Err(CommandError::NoPerm(
+                    "No permissions to access a key".into(),
+                ))
+            }
+            _ => Ok(()),
+        },
+    };
+    let check = |cmd: &Command| {
+        if read_only && cmd.is_write() {
+            return Err(CommandError::ReadOnly);
+        }
+        check_keys(&cmd.keys(), cmd.is_write())?;
+        let asking = asking || matches!(cmd, Command::Restore(cmd) if cmd.asking);
+        route(&cmd.keys(), asking)?;
+        //replica的key由master的DEL淘汰，自己不淘汰
+        if !is_master && backend.repl.is_master() {
+            let _guard = backend.shared_lock();
+            if let Err(e) = eviction::perform_evictions(backend) {
+                if cmd.uses_memory() {
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(())
+    };
+    let tx = &mut session.transaction;
+    match name.as_str() {
+        "multi" => tx.multi(),
+        "exec" => {
+            //不在事务里时WATCH的key保持不变
+            if !tx.in_multi() {
+                return Err(CommandError::InvalidCommand("EXEC without MULTI".into()));
+            }
+            //事务里的所有key必须在同一个slot
+            if let Err(e) = route(&tx.keys(), asking) {
+                let _ = tx.discard();
+                return Err(e.into());
+            }
+            let args = backend.slowlog.capture(&array);
+            let start = Instant::now();
+            //EXEC之后事务状态会被清空，先拿出来，排队的连接命令执行时需要整个session
+            let mut tx = std::mem::take(tx);
+            let ret = tx.exec(backend, |array| exec_queued(array, session, backend));
+            record_latency(backend, session, args, start.elapsed());
+            session.woff = backend.repl.offset();
+            ret
+        }
+        "discard" => tx.discard(),
+        "watch" => {
+            let keys = array.iter().skip(1).filter_map(|arg| match arg {
+                RespFrame::BulkString(key) => std::str::from_utf8(key.as_ref()).ok(),
+                _ => None,
+            });
+            let keys = keys.collect::<Vec<_>>();
+            check_keys(&keys, false)?;
+            route(&keys, asking)?;
+            tx.watch(array, backend)
+        }
+        //MULTI之后除了上面几个命令，其余的都只排队不执行
+        _ if tx.in_multi() => tx.queue(&name, array, check),
+        "unwatch" => Ok(tx.unwatch()),
+        "hello" => Hello::try_from(array)?.execute(session, backend),
+        "auth" => Auth::try_from(array)?.execute(session, backend),
+        "acl" => Acl::try_from(array)?.execute(session, backend),
+        "client" => Client::try_from(array)?.execute(session, backend),
+        "replconf" => ReplConf::try_from(array)?.execute(session),
+        "wait" => Wait::try_from(array)?.execute(session, backend),
+        "waitaof" => WaitAof::try_from(array)?.execute(session, backend),
+        "monitor" => {
+            if let Some(receiver) = backend.monitor.add(session.id) {
+                session.monitor = Some(receiver);
+            }
+            Ok(RespFrame::SimpleString("OK".into()))
+        }
+        "asking" => {
+            if !backend.cluster.is_enabled() {
+                return Ok(SimpleError::from(format!("ERR {}", ClusterError::Disabled)).into());
+            }
+            session.asking = true;
+            Ok(RespFrame::SimpleString("OK".into()))
+        }
+        "migrate" => {
+            let cmd = Migrate::try_from(array)?;
+            if read_only && !cmd.copy {
+                return Err(CommandError::ReadOnly);
+            }
+            check_keys(&cmd.keys(), !cmd.copy)?;
+            //迁出中的slot里已经迁走的key不需要ASK，MIGRATE只处理本地还有的key
+            match route(&cmd.keys(), asking) {
+                Ok(()) | Err(Redirect::Ask(..) | Redirect::TryAgain) => {}
+                Err(e) => return Err(e.into()),
+            }
+            cmd.execute(session, backend)
+        }
+        //正常情况下PSYNC/SYNC在stream_handler里就被接管了
+        "psync" | "sync" => Err(CommandError::InvalidCommand(format!(
+            "'{name}' can only be used on a replication connection"
+        ))),
+        _ => {
+            let args = backend.slowlog.capture(&array);
+            let cmd = Command::try_from(array)?;
+            check(&cmd)?;
+            let is_write = cmd.is_write();
+            //拿到锁之后才开始计时，等锁的时间不算执行时间
+            let (reply, elapsed) = if cmd.needs_exclusive_lock() {
+                let _guard = backend.exclusive_lock();
+                timed(|| execute_command(cmd, backend))
+            } else {
+                let _guard = backend.shared_lock();
+                timed(|| execute_command(cmd, backend))
+            };
+            record_latency(backend, session, args, elapsed);
+            if is_write {
+                session.woff = backend.repl.offset();
+            }
+            Ok(reply)
+        }
+    }
+}
+
+/// 没有认证的连接只能执行AUTH和HELLO，认证之后检查用户能不能执行这个命令
+fn authorize(
+    array: &RespArray,
+    name: &str,
+    session: &Session,
+    backend: &Backend,
+) -> Result<(), CommandError> {
+    if matches!(name, "auth" | "hello") {
+        return Ok(());
+    }
+    if !session.authenticated && !backend.acl.no_auth_required() {
+        return Err(CommandError::NoAuth);
+    }
+    let label = command_label(array);
+    if backend.acl.check_command(&session.user, &label).is_err() {
+        let in_multi = session.transaction.in_multi();
+        log_denied(
+            backend,
+            session.id,
+            &session.user,
+            in_multi,
+            "command",
+            label.clone(),
+        );
+        return Err(CommandError::NoPerm(format!(
+            "User {} has no permissions to run the '{label}' command",
+            session.user
+        )));
+    }
+    Ok(())
+}
+
+/// 被ACL拒绝的命令记进ACL LOG
+fn log_denied(
+    backend: &Backend,
+    id: u64,
+    user: &str,
+    in_multi: bool,
+    reason: &'static str,
+    object: String,
+) {
+    let context = match in_multi {
+        true => "multi",
+        false => "toplevel",
+    };
+    let info = backend
+        .clients
+        .get(id)
+        .map(|entry| entry.info())
+        .unwrap_or_default();
+    backend
+        .acl
+        .log_denied(reason, context, object, user.to_string(), info);
+}
+
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let ret = f();
+    (ret, start.elapsed())
+}
+
+/// 执行时间超过阈值的命令记进SLOWLOG和LATENCY的command事件
+fn record_latency(
+    backend: &Backend,
+    session: &Session,
+    args: Option<Vec<Vec<u8>>>,
+    elapsed: Duration,
+) {
+    backend.latency.record("command", elapsed);
+    if let Some(args) = args {
+        backend
+            .slowlog
+            .record(args, elapsed, session.addr.clone(), session.name.as_deref());
+    }
+}
+
+impl Encoder<RespFrame> for RespFrameCodec {
+    type Error = anyhow::Error;
+
+    fn encode(&mut self, item: RespFrame, dst: &mut BytesMut) -> Result<()> {
+        let encoded = item.encode();
+        dst.extend_from_slice(&encoded);
+        Ok(())
+    }
+}
+
+impl Decoder for RespFrameCodec {
+    type Item = RespFrame;
+    type Error = anyhow::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<RespFrame>> {
+        //frame收全并且没有超过限制之后才真正解码
+        if self.scanner.scan(src, &self.limits)?.is_none() {
+            if src.len() > self.limits.max_query_buffer {
+                return Err(RespError::QueryBufferLimit(src.len()).into());
+            }
+            return Ok(None);
+        }
+        match RespFrame::decode(src) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(RespError::NotComplete) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RespArray, RespBulkString, RespInteger, RespNull, RespNullArray};
+
+    fn cmd(args: &[&'static str]) -> RespFrame {
+        RespArray::new(
+            args.iter()
+                .map(|arg| RespBulkString::from(*arg).into())
+                .collect(),
+        )
+        .into()
+    }
+
+    #[test]
+    fn test_hello_negotiates_protocol() {
+        let backend = Backend::new();
+        let mut session = Session::new();
+
+        let reply = request_handler(cmd(&["hgetall", "t"]), &mut session, &backend);
+        assert_eq!(reply.with_protocol(session.protocol).encode(), b"*0\r\n");
+
+        let reply = request_handler(cmd(&["HELLO", "3"]), &mut session, &backend);
+        assert_eq!(session.protocol, RespProtocol::Resp3);
+        assert!(matches!(reply, RespFrame::Maps(_)));
+
+        let reply = request_handler(cmd(&["hgetall", "t"]), &mut session, &backend);
+        assert_eq!(reply.with_protocol(session.protocol).encode(), b"%0\r\n");
+
+        let reply = request_handler(cmd(&["get", "missing"]), &mut session, &backend);
+        assert_eq!(reply.with_protocol(session.protocol).encode(), b"_\r\n");
+
+        request_handler(cmd(&["hello", "2"]), &mut session, &backend);
+        let reply = request_handler(cmd(&["get", "missing"]), &mut session, &backend);
+        assert_eq!(reply.with_protocol(session.protocol).encode(), b"$-1\r\n");
+    }
+
+    #[test]
+    fn test_transaction() {
+        let backend = Backend::new();
+        let mut session = Session::new();
+
+        request_handler(cmd(&["multi"]), &mut session, &backend);
+        let reply = request_handler(cmd(&["set", "a", "1"]), &mut session, &backend);
+        assert_eq!(reply.encode(), b"+QUEUED\r\n");
+        let reply = request_handler(cmd(&["unwatch"]), &mut session, &backend);
+        assert_eq!(reply.encode(), b"+QUEUED\r\n");
+        let reply = request_handler(cmd(&["exec"]), &mut session, &backend);
+        assert_eq!(reply.encode(), b"*2\r\n+OK\r\n+OK\r\n");
+
+        let mut other = Session::new();
+        request_handler(cmd(&["watch", "a"]), &mut session, &backend);
+        request_handler(cmd(&["set", "a", "2"]), &mut other, &backend);
+        request_handler(cmd(&["multi"]), &mut session, &backend);
+        request_handler(cmd(&["get", "a"]), &mut session, &backend);
+        let reply = request_handler(cmd(&["exec"]), &mut session, &backend);
+        assert_eq!(
+            reply.clone().with_protocol(RespProtocol::Resp2).encode(),
+            b"*-1\r\n"
+        );
+        assert_eq!(reply.with_protocol(RespProtocol::Resp3).encode(), b"_\r\n");
+
+        //没有MULTI的EXEC不会清掉WATCH的key
+        request_handler(cmd(&["watch", "a"]), &mut session, &backend);
+        let reply = request_handler(cmd(&["exec"]), &mut session, &backend);
+        assert_eq!(
+            reply.encode(),
+            b"-ERR invalid command:EXEC without MULTI\r\n"
+        );
+        request_handler(cmd(&["set", "a", "3"]), &mut other, &backend);
+        request_handler(cmd(&["multi"]), &mut session, &backend);
+        let reply = request_handler(cmd(&["exec"]), &mut session, &backend);
+        assert_eq!(reply, RespFrame::NullArray(RespNullArray));
+    }
+
+    #[test]
+    fn test_transaction_session_commands() {
+        let backend = Backend::new();
+        let mut session = Session::new();
+
+        //和Redis一样HELLO、CLIENT、WAIT等可以排队，在EXEC时按顺序执行
+        request_handler(cmd(&["multi"]), &mut session, &backend);
+        for args in [
+            &["client", "setname", "app"][..],
+            &["hello", "3"],
+            &["set", "a", "1"],
+            &["wait", "1", "0"],
+        ] {
+            let reply = request_handler(cmd(args), &mut session, &backend);
+            assert_eq!(reply.encode(), b"+QUEUED\r\n");
+        }
+        assert_eq!(session.name, None);
+        let RespFrame::Arrays(replies) = request_handler(cmd(&["exec"]), &mut session, &backend)
+        else {
+            panic!("EXEC should reply an array");
+        };
+        assert_eq!(replies[0], RespFrame::SimpleString("OK".into()));
+        assert!(matches!(replies[1], RespFrame::Maps(_)));
+        assert_eq!(replies[2], RespFrame::SimpleString("OK".into()));
+        //EXEC里不能阻塞，WAIT直接回复当前的数量
+        assert_eq!(replies[3], RespInteger::from(0).into());
+        assert!(session.blocked.is_none());
+        assert_eq!(session.name.as_deref(), Some("app"));
+        assert_eq!(session.protocol, RespProtocol::Resp3);
+
+        //带no-multi标记的命令被拒绝，整个事务被放弃
+        request_handler(cmd(&["multi"]), &mut session, &backend);
+        let reply = request_handler(cmd(&["psync", "?", "-1"]), &mut session, &backend);
+        assert_eq!(
+            reply.encode(),
+            b"-ERR invalid command:Command not allowed inside a transaction\r\n"
+        );
+        let reply = request_handler(cmd(&["watch", "a"]), &mut session, &backend);
+        assert_eq!(
+            reply.encode(),
+            b"-ERR invalid command:WATCH inside MULTI is not allowed\r\n"
+        );
+        //MIGRATE要和目标节点通信，不能在EXEC持有写锁时执行
+        let reply = request_handler(
+            cmd(&["migrate", "127.0.0.1", "1", "a", "0", "100"]),
+            &mut session,
+            &backend,
+        );
+        assert_eq!(
+            reply.encode(),
+            b"-ERR invalid command:Command not allowed inside a transaction\r\n"
+        );
+        let reply = request_handler(cmd(&["exec"]), &mut session, &backend);
+        assert!(matches!(reply, RespFrame::SimpleError(e) if e.starts_with("EXECABORT")));
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let backend = Backend::new();
+        let mut session = Session::new();
+
+        let reply = request_handler(cmd(&["foo"]), &mut session, &backend);
+        assert_eq!(reply, SimpleError::from("ERR unknown command 'foo'").into());
+    }
+
+    #[test]
+    fn test_command_stats() {
+        let backend = Backend::new();
+        let mut session = Session::new();
+
+        request_handler(cmd(&["set", "a", "1"]), &mut session, &backend);
+        request_handler(cmd(&["get", "a"]), &mut session, &backend);
+        request_handler(cmd(&["get", "b"]), &mut session, &backend);
+        request_handler(cmd(&["get"]), &mut session, &backend);
+        request_handler(cmd(&["rpush", "a", "v"]), &mut session, &backend);
+        request_handler(cmd(&["foo"]), &mut session, &backend);
+
+        let stats = &backend.stats;
+        assert_eq!(stats.total_commands(), 6);
+        assert_eq!((stats.keyspace_hits(), stats.keyspace_misses()), (1, 1));
+        let get = stats.command("get").unwrap();
+        assert_eq!((get.calls, get.rejected_calls, get.failed_calls), (2, 1, 0));
+        assert_eq!(stats.command("rpush").unwrap().failed_calls, 1);
+        assert!(stats.command("foo").is_none());
+        let errors = stats.errors();
+        assert_eq!(
+            errors,
+            vec![("ERR".to_string(), 2), ("WRONGTYPE".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_slowlog() {
+        let backend = Backend::new();
+        let mut session = Session::new();
+        session.addr = "10.0.0.1:5000".parse().ok();
+        session.name = Some("worker".into());
+        //阈值为0时记录所有执行了的命令，参数错误没有执行的命令不记录
+        request_handler(
+            cmd(&["config", "set", "slowlog-log-slower-than", "0"]),
+            &mut session,
+            &backend,
+        );
+        request_handler(cmd(&["set", "a", "1"]), &mut session, &backend);
+        request_handler(cmd(&["get"]), &mut session, &backend);
+        request_handler(cmd(&["multi"]), &mut session, &backend);
+        request_handler(cmd(&["get", "a"]), &mut session, &backend);
+        request_handler(cmd(&["exec"]), &mut session, &backend);
+
+        let entries = backend.slowlog.get(None);
+        let args = entries
+            .iter()
+            .map(|entry| String::from_utf8(entry.args.concat()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            args,
+            vec!["exec", "seta1", "configsetslowlog-log-slower-than0"]
+        );
+        assert_eq!(entries[0].addr, "10.0.0.1:5000");
+        assert_eq!(entries[0].name, "worker");
+    }
+
+    #[test]
+    fn test_acl() {
+        let backend = Backend::new();
+        let mut admin = Session::new();
+        let reply = request_handler(
+            cmd(&["config", "set", "requirepass", "secret"]),
+            &mut admin,
+            &backend,
+        );
+        assert_eq!(reply, RespFrame::SimpleString("OK".into()));
+
+        let reply = request_handler(cmd(&["get", "a"]), &mut admin, &backend);
+        assert_eq!(
+            reply,
+            SimpleError::from("NOAUTH Authentication required.").into()
+        );
+        let reply = request_handler(cmd(&["auth", "wrong"]), &mut admin, &backend);
+        assert!(matches!(reply, RespFrame::SimpleError(e) if e.starts_with("WRONGPASS")));
+        request_handler(cmd(&["auth", "secret"]), &mut admin, &backend);
+        let reply = request_handler(
+            cmd(&[
+                "acl", "setuser", "alice", "on", ">pw", "~cache:*", "+@read", "+multi", "+exec",
+            ]),
+            &mut admin,
+            &backend,
+        );
+        assert_eq!(reply, RespFrame::SimpleString("OK".into()));
+
+        let mut alice = Session::new();
+        request_handler(cmd(&["auth", "alice", "pw"]), &mut alice, &backend);
+        let reply = request_handler(cmd(&["get", "cache:1"]), &mut alice, &backend);
+        assert_eq!(reply, RespNull.into());
+        let reply = request_handler(cmd(&["get", "other"]), &mut alice, &backend);
+        assert_eq!(
+            reply,
+            SimpleError::from("NOPERM No permissions to access a key").into()
+        );
+        let reply = request_handler(cmd(&["set", "cache:1", "v"]), &mut alice, &backend);
+        assert_eq!(
+            reply,
+            SimpleError::from("NOPERM User alice has no permissions to run the 'set' command")
+                .into()
+        );
+        //事务里被拒绝的命令让整个事务被放弃
+        request_handler(cmd(&["multi"]), &mut alice, &backend);
+        request_handler(cmd(&["set", "cache:1", "v"]), &mut alice, &backend);
+        let reply = request_handler(cmd(&["exec"]), &mut alice, &backend);
+        assert!(matches!(reply, RespFrame::SimpleError(e) if e.starts_with("EXECABORT")));
+
+        let log = backend.acl.log.get(10);
+        assert_eq!(
+            log.iter()
+                .map(|entry| (
+                    entry.reason,
+                    entry.context,
+                    entry.object.as_str(),
+                    entry.count
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("command", "multi", "set", 1),
+                ("command", "toplevel", "set", 1),
+                ("key", "toplevel", "other", 1),
+                ("auth", "toplevel", "AUTH", 1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_clients() {
+        use tokio::io::AsyncReadExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let backend = Backend::new();
+        backend.stats.set_max_clients(1);
+        tokio::spawn(serve(listener, backend.clone()));
+
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        first.write_all(b"*1\r\n$4\r\nPING\r\n").await.unwrap();
+        let mut buf = [0; 64];
+        let n = first.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"+PONG\r\n");
+
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        let n = second.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], MAX_CLIENTS_ERROR);
+        assert_eq!(backend.stats.rejected_connections(), 1);
+        assert_eq!(backend.stats.connected_clients(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_monitor() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let backend = Backend::new();
+        tokio::spawn(serve(listener, backend.clone()));
+
+        let mut monitor = Framed::new(
+            TcpStream::connect(addr).await.unwrap(),
+            RespFrameCodec::default(),
+        );
+        monitor.send(cmd(&["MONITOR"])).await.unwrap();
+        let reply = monitor.next().await.unwrap().unwrap();
+        assert_eq!(reply, RespFrame::SimpleString("OK".into()));
+
+        let mut client = Framed::new(
+            TcpStream::connect(addr).await.unwrap(),
+            RespFrameCodec::default(),
+        );
+        let client_addr = client.get_ref().local_addr().unwrap();
+        client.send(cmd(&["set", "a", "1 2"])).await.unwrap();
+        client.next().await.unwrap().unwrap();
+        //参数错误没有执行的命令不发送
+        client.send(cmd(&["get"])).await.unwrap();
+        client.next().await.unwrap().unwrap();
+        client
+            .send(cmd(&["hello", "2", "auth", "default", "pw"]))
+            .await
+            .unwrap();
+        client.next().await.unwrap().unwrap();
+
+        let mut lines = Vec::new();
+        for _ in 0..2 {
+            let Some(Ok(RespFrame::SimpleString(line))) = monitor.next().await else {
+                panic!("monitor should receive a simple string");
+            };
+            let (_, rest) = line.split_once(' ').unwrap();
+            lines.push(rest.to_string());
+        }
+        assert_eq!(
+            lines,
+            vec![
+                format!(r#"[0 {client_addr}] "set" "a" "1 2""#),
+                format!(r#"[0 {client_addr}] "hello" "2" "auth" "(redacted)" "(redacted)""#),
+            ]
+        );
+
+        //MONITOR的连接断开之后被注销
+        drop(monitor);
+        for _ in 0..100 {
+            if backend.monitor.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(backend.monitor.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_client_kill() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let backend = Backend::new();
+        tokio::spawn(serve(listener, backend.clone()));
+
+        let mut victim = Framed::new(
+            TcpStream::connect(addr).await.unwrap(),
+            RespFrameCodec::default(),
+        );
+        let victim_addr = victim.get_ref().local_addr().unwrap();
+        victim
+            .send(cmd(&["client", "setname", "victim"]))
+            .await
+            .unwrap();
+        victim.next().await.unwrap().unwrap();
+
+        let mut admin = Framed::new(
+            TcpStream::connect(addr).await.unwrap(),
+            RespFrameCodec::default(),
+        );
+        admin.send(cmd(&["client", "list"])).await.unwrap();
+        let Some(Ok(RespFrame::BulkString(list))) = admin.next().await else {
+            panic!("client list should be a bulk string");
+        };
+        let list = String::from_utf8_lossy(list.as_ref()).to_string();
+        assert_eq!(list.lines().count(), 2);
+        assert!(list.contains(&format!("addr={victim_addr} ")));
+        assert!(list.contains("name=victim "));
+        assert!(list.contains("cmd=client|list "));
+
+        let victim_addr = victim_addr.to_string();
+        admin
+            .send(crate::aof::command_array(["client", "kill", "addr", &victim_addr]).into())
+            .await
+            .unwrap();
+        let reply = admin.next().await.unwrap().unwrap();
+        assert_eq!(reply, crate::RespInteger::from(1).into());
+        //被kill的连接被服务端关闭
+        assert!(victim.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_psync_requires_auth() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let backend = Backend::new();
+        backend.acl.set_requirepass("secret");
+        let rules = ["on", ">pw", "~*", "+@all", "-psync", "-sync"].map(String::from);
+        backend.acl.set_user("alice", &rules).unwrap();
+        tokio::spawn(serve(listener, backend.clone()));
+
+        let mut client = Framed::new(
+            TcpStream::connect(addr).await.unwrap(),
+            RespFrameCodec::default(),
+        );
+        let error = |reply: RespFrame| match reply {
+            RespFrame::SimpleError(e) => e.0,
+            reply => panic!("expected an error, got {reply:?}"),
+        };
+        for psync in [cmd(&["psync", "?", "-1"]), cmd(&["sync"])] {
+            client.send(psync).await.unwrap();
+            let reply = client.next().await.unwrap().unwrap();
+            assert!(error(reply).contains("NOAUTH"));
+        }
+
+        //没有psync权限的用户也拿不到RDB和复制流
+        client.send(cmd(&["auth", "alice", "pw"])).await.unwrap();
+        client.next().await.unwrap().unwrap();
+        client.send(cmd(&["psync", "?", "-1"])).await.unwrap();
+        let reply = client.next().await.unwrap().unwrap();
+        assert!(error(reply).contains("NOPERM"));
+        client.send(cmd(&["ping"])).await.unwrap();
+        let reply = client.next().await.unwrap().unwrap();
+        assert_eq!(reply, RespFrame::SimpleString("PONG".into()));
+        assert!(backend.repl.replicas().is_empty());
+        assert_eq!(backend.repl.sync_stats().0, 0);
+    }
+
+    #[tokio::test]
+    async fn test_protocol_limits() {
+        use tokio::io::AsyncReadExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let backend = Backend::new();
+        tokio::spawn(serve(listener, backend.clone()));
+        let reply = |error: &str| format!("-ERR Protocol error: {error}\r\n");
+
+        //超过限制的头部不等数据收全，回复协议错误后关闭连接
+        let inputs: [(&[u8], _); 3] = [
+            (b"*2147483647\r\n", reply("invalid multibulk length")),
+            (b"*1\r\n$536870913\r\n", reply("invalid bulk length")),
+            (&b"*1\r\n".repeat(129), reply("too many nested levels")),
+        ];
+        for (input, expected) in inputs {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(input).await.unwrap();
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(String::from_utf8_lossy(&buf), expected);
+        }
+
+        //限制可以在运行时修改，超过client-query-buffer-limit的连接直接关闭
+        let mut admin = Framed::new(
+            TcpStream::connect(addr).await.unwrap(),
+            RespFrameCodec::default(),
+        );
+        admin
+            .send(cmd(&["config", "set", "client-query-buffer-limit", "1mb"]))
+            .await
+            .unwrap();
+        admin.next().await.unwrap().unwrap();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"*2\r\n$3\r\nget\r\n$2000000\r\n")
+            .await
+            .unwrap();
+        let chunk = vec![b'x'; 64 * 1024];
+        for _ in 0..20 {
+            if stream.write_all(&chunk).await.is_err() {
+                break;
+            }
+        }
+        let mut buf = Vec::new();
+        let _ = stream.read_to_end(&mut buf).await;
+        assert!(buf.is_empty());
+
+        //正常的请求不受影响
+        admin.send(cmd(&["ping"])).await.unwrap();
+        let reply = admin.next().await.unwrap().unwrap();
+        assert_eq!(reply, RespFrame::SimpleString("PONG".into()));
+    }
+
+    #[tokio::test]
+    async fn test_unix_socket() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path =
+            std::env::temp_dir().join(format!("simple-redis-unix-{}.sock", std::process::id()));
+        let listener = bind_unix(&path, 0o700).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        let backend = Backend::new();
+        tokio::spawn(serve_unix(listener, backend.clone()));
+
+        let stream = UnixStream::connect(&path).await.unwrap();
+        let mut client = Framed::new(stream, RespFrameCodec::default());
+        client.send(cmd(&["set", "a", "1"])).await.unwrap();
+        client.next().await.unwrap().unwrap();
+        client.send(cmd(&["client", "info"])).await.unwrap();
+        let Some(Ok(RespFrame::BulkString(info))) = client.next().await else {
+            panic!("client info should be a bulk string");
+        };
+        let info = String::from_utf8_lossy(info.as_ref()).to_string();
+        let addr = format!("{}:0", path.display());
+        assert!(info.contains(&format!("addr={addr} laddr={addr} ")));
+        assert_eq!(backend.stats.connected_clients(), 1);
+
+        //残留的socket文件不影响再次监听
+        drop(client);
+        assert!(bind_unix(&path, 0).is_ok());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_client_tracking() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let backend = Backend::new();
+        tokio::spawn(serve(listener, backend.clone()));
+        let connect = || async {
+            Framed::new(
+                TcpStream::connect(addr).await.unwrap(),
+                RespFrameCodec::default(),
+            )
+        };
+
+        //RESP3的连接直接收到invalidate推送
+        let mut reader = connect().await;
+        for args in [
+            &["hello", "3"][..],
+            &["client", "tracking", "on"],
+            &["get", "k"],
+        ] {
+            reader.send(cmd(args)).await.unwrap();
+            reader.next().await.unwrap().unwrap();
+        }
+        let mut writer = connect().await;
+        writer.send(cmd(&["set", "k", "v"])).await.unwrap();
+        writer.next().await.unwrap().unwrap();
+        let push = reader.next().await.unwrap().unwrap();
+        assert_eq!(push, tracking::invalidate_message(3, "k"));
+
+        //RESP2的连接把通知REDIRECT到另一个连接
+        let mut target = connect().await;
+        target.send(cmd(&["client", "id"])).await.unwrap();
+        let Some(Ok(RespFrame::Integer(id))) = target.next().await else {
+            panic!("client id should be an integer");
+        };
+        let id = id.0.to_string();
+        let mut bcast = connect().await;
+        bcast
+            .send(
+                crate::aof::command_array([
+                    "client", "tracking", "on", "bcast", "prefix", "user:", "redirect", &id,
+                ])
+                .into(),
+            )
+            .await
+            .unwrap();
+        let reply = bcast.next().await.unwrap().unwrap();
+        assert_eq!(reply, RespFrame::SimpleString("OK".into()));
+        writer.send(cmd(&["set", "order:1", "v"])).await.unwrap();
+        writer.next().await.unwrap().unwrap();
+        writer.send(cmd(&["set", "user:1", "v"])).await.unwrap();
+        writer.next().await.unwrap().unwrap();
+        let message = target.next().await.unwrap().unwrap();
+        assert_eq!(message, tracking::invalidate_message(2, "user:1"));
+    }
+}
@@ -0,0 +1,721 @@
+use crate::{RespBulkString, RespFrame, Value};
+
+use super::{
+    intset::parse_intset, listpack::parse_listpack, lzf, ziplist::parse_ziplist,
+    ziplist::parse_zipmap, RdbContent, RdbError, CRC64, RDB_14BITLEN, RDB_32BITLEN, RDB_64BITLEN,
+    RDB_6BITLEN, RDB_ENCVAL, RDB_ENC_INT16, RDB_ENC_INT32, RDB_ENC_INT8, RDB_ENC_LZF, RDB_MAGIC,
+    RDB_MAX_LOAD_VERSION, RDB_OPCODE_AUX, RDB_OPCODE_EOF, RDB_OPCODE_EXPIRETIME,
+    RDB_OPCODE_EXPIRETIME_MS, RDB_OPCODE_FREQ, RDB_OPCODE_FUNCTION2, RDB_OPCODE_IDLE,
+    RDB_OPCODE_MODULE_AUX, RDB_OPCODE_RESIZEDB, RDB_OPCODE_SELECTDB, RDB_TYPE_HASH,
+    RDB_TYPE_HASH_LISTPACK, RDB_TYPE_HASH_ZIPLIST, RDB_TYPE_HASH_ZIPMAP, RDB_TYPE_LIST,
+    RDB_TYPE_LIST_QUICKLIST, RDB_TYPE_LIST_QUICKLIST_2, RDB_TYPE_LIST_ZIPLIST, RDB_TYPE_MODULE_2,
+    RDB_TYPE_MODULE_PRE_GA, RDB_TYPE_SET, RDB_TYPE_SET_INTSET, RDB_TYPE_SET_LISTPACK,
+    RDB_TYPE_STREAM_LISTPACKS, RDB_TYPE_STREAM_LISTPACKS_2, RDB_TYPE_STREAM_LISTPACKS_3,
+    RDB_TYPE_STRING, RDB_TYPE_ZSET, RDB_TYPE_ZSET_2, RDB_TYPE_ZSET_LISTPACK, RDB_TYPE_ZSET_ZIPLIST,
+};
+
+//quicklist 2里每个节点的容器类型
+const QUICKLIST_NODE_CONTAINER_PLAIN: u64 = 1;
+const QUICKLIST_NODE_CONTAINER_PACKED: u64 = 2;
+
+//模块序列化数据里每个字段前面的类型标记
+const RDB_MODULE_OPCODE_EOF: u64 = 0;
+const RDB_MODULE_OPCODE_SINT: u64 = 1;
+const RDB_MODULE_OPCODE_UINT: u64 = 2;
+const RDB_MODULE_OPCODE_FLOAT: u64 = 3;
+const RDB_MODULE_OPCODE_DOUBLE: u64 = 4;
+const RDB_MODULE_OPCODE_STRING: u64 = 5;
+
+/// 解析完整的RDB文件。Backend不支持的类型只要能确定长度就跳过并记录下来，不会让整个解析失败
+pub fn parse_rdb(data: &[u8]) -> Result<RdbContent, RdbError> {
+    let mut reader = RdbReader::new(data);
+    if reader.read_bytes(RDB_MAGIC.len())? != RDB_MAGIC {
+        return Err(RdbError::InvalidFormat("wrong signature".into()));
+    }
+    let version = std::str::from_utf8(reader.read_bytes(4)?)
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .ok_or_else(|| RdbError::InvalidFormat("bad version".into()))?;
+    if !(1..=RDB_MAX_LOAD_VERSION).contains(&version) {
+        return Err(RdbError::UnsupportedVersion(version));
+    }
+
+    let mut content = RdbContent::default();
+    let mut expire_at = None;
+    loop {
+        let opcode = reader.read_u8()?;
+        match opcode {
+            RDB_OPCODE_EXPIRETIME_MS => expire_at = Some(reader.read_u64_le()?),
+            RDB_OPCODE_EXPIRETIME => expire_at = Some(reader.read_u32_le()? as u64 * 1000),
+            RDB_OPCODE_SELECTDB => {
+                //只有一个库，所有db都加载到同一个Backend里
+                reader.read_length()?;
+            }
+            RDB_OPCODE_RESIZEDB => {
+                reader.read_length()?;
+                reader.read_length()?;
+            }
+            RDB_OPCODE_AUX => {
+                reader.read_string()?;
+                reader.read_string()?;
+            }
+            RDB_OPCODE_IDLE => {
+                reader.read_length()?;
+            }
+            RDB_OPCODE_FREQ => {
+                reader.read_u8()?;
+            }
+            RDB_OPCODE_MODULE_AUX => {
+                let module_id = reader.read_length()?;
+                //when_opcode + when
+                reader.read_length()?;
+                reader.read_length()?;
+                reader.skip_module_value()?;
+                content
+                    .skipped
+                    .push((format!("module aux data {module_id:#x}"), "module"));
+            }
+            RDB_OPCODE_FUNCTION2 => {
+                let code = reader.read_string()?;
+                let name = String::from_utf8_lossy(&code)
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                content.skipped.push((name, "function"));
+            }
+            RDB_OPCODE_EOF => {
+                reader.verify_checksum(version)?;
+                return Ok(content);
+            }
+            object_type => {
+                let key = String::from_utf8_lossy(&reader.read_string()?).into_owned();
+                match reader.skip_unsupported(object_type)? {
+                    Some(type_name) => {
+                        expire_at = None;
+                        content.skipped.push((key, type_name));
+                    }
+                    None => {
+                        let value = reader.read_object(object_type)?;
+                        content.entries.push((key, value, expire_at.take()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 解析DUMP的序列化结果：校验结尾的RDB版本和CRC64，然后读出唯一的一个对象
+pub fn parse_payload(data: &[u8]) -> Result<Value, RdbError> {
+    let Some(body_len) = data.len().checked_sub(10) else {
+        return Err(RdbError::InvalidFormat("payload too short".into()));
+    };
+    let (body, footer) = data.split_at(body_len);
+    let version = u16::from_le_bytes([footer[0], footer[1]]) as u32;
+    if version > RDB_MAX_LOAD_VERSION {
+        return Err(RdbError::UnsupportedVersion(version));
+    }
+    let actual = CRC64.checksum(&data[..body_len + 2]);
+    let expected = u64::from_le_bytes(footer[2..].try_into().expect("8 bytes"));
+    if expected != actual {
+        return Err(RdbError::Checksum { expected, actual });
+    }
+
+    let mut reader = RdbReader::new(body);
+    let object_type = reader.read_u8()?;
+    if let Some(type_name) = reader.skip_unsupported(object_type)? {
+        return Err(RdbError::UnsupportedTypes(type_name.into()));
+    }
+    let value = reader.read_object(object_type)?;
+    if reader.pos != body.len() {
+        return Err(RdbError::InvalidFormat(
+            "trailing bytes after the object".into(),
+        ));
+    }
+    Ok(value)
+}
+
+struct RdbReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> RdbReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn read_bytes(&mut self, n: usize) -> Result<&'a [u8], RdbError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| RdbError::InvalidFormat("unexpected end of file".into()))?;
+        let ret = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(ret)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], RdbError> {
+        let mut ret = [0; N];
+        ret.copy_from_slice(self.read_bytes(N)?);
+        Ok(ret)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, RdbError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32_le(&mut self) -> Result<u32, RdbError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u64_le(&mut self) -> Result<u64, RdbError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    /// 返回 (长度, 是否是特殊编码)，特殊编码时长度字段是编码类型
+    fn read_length_with_encoding(&mut self) -> Result<(u64, bool), RdbError> {
+        let first = self.read_u8()?;
+        match first >> 6 {
+            RDB_ENCVAL => Ok(((first & 0x3f) as u64, true)),
+            RDB_6BITLEN => Ok(((first & 0x3f) as u64, false)),
+            RDB_14BITLEN => {
+                let next = self.read_u8()?;
+                Ok(((((first & 0x3f) as u64) << 8) | next as u64, false))
+            }
+            _ => match first {
+                RDB_32BITLEN => Ok((u32::from_be_bytes(self.read_array()?) as u64, false)),
+                RDB_64BITLEN => Ok((u64::from_be_bytes(self.read_array()?), false)),
+                _ => Err(RdbError::InvalidFormat(format!(
+                    "unknown length encoding {first:#x}"
+                ))),
+            },
+        }
+    }
+
+    fn read_length(&mut self) -> Result<u64, RdbError> {
+        match self.read_length_with_encoding()? {
+            (len, false) => Ok(len),
+            (_, true) => Err(RdbError::InvalidFormat(
+                "unexpected encoded value in place of length".into(),
+            )),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<Vec<u8>, RdbError> {
+        let (len, encoded) = self.read_length_with_encoding()?;
+        if !encoded {
+            return Ok(self.read_bytes(len as usize)?.to_vec());
+        }
+        let n = match len as u8 {
+            RDB_ENC_INT8 => self.read_u8()? as i8 as i64,
+            RDB_ENC_INT16 => i16::from_le_bytes(self.read_array()?) as i64,
+            RDB_ENC_INT32 => i32::from_le_bytes(self.read_array()?) as i64,
+            RDB_ENC_LZF => {
+                let compressed_len = self.read_length()? as usize;
+                let len = self.read_length()? as usize;
+                let compressed = self.read_bytes(compressed_len)?;
+                return lzf::decompress(compressed, len);
+            }
+            enc => {
+                return Err(RdbError::InvalidFormat(format!(
+                    "unknown string encoding {enc}"
+                )))
+            }
+        };
+        Ok(n.to_string().into_bytes())
+    }
+
+    /// ZSET(type 3)里的score：1字节长度 + ASCII，253/254/255分别表示nan/inf/-inf
+    fn read_double_string(&mut self) -> Result<f64, RdbError> {
+        match self.read_u8()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => parse_score(self.read_bytes(len as usize)?),
+        }
+    }
+
+    fn read_strings(&mut self) -> Result<Vec<Vec<u8>>, RdbError> {
+        let len = self.read_length()?;
+        (0..len).map(|_| self.read_string()).collect()
+    }
+
+    fn read_object(&mut self, object_type: u8) -> Result<Value, RdbError> {
+        let value = match object_type {
+            RDB_TYPE_STRING => Value::String(bulk(self.read_string()?)),
+            RDB_TYPE_LIST => Value::List(self.read_strings()?.into_iter().map(bulk).collect()),
+            RDB_TYPE_SET => Value::Set(self.read_strings()?.into_iter().map(bulk).collect()),
+            RDB_TYPE_ZSET | RDB_TYPE_ZSET_2 => {
+                let len = self.read_length()?;
+                let mut zset = Vec::new();
+                for _ in 0..len {
+                    let member = bulk(self.read_string()?);
+                    let score = if object_type == RDB_TYPE_ZSET_2 {
+                        f64::from_le_bytes(self.read_array()?)
+                    } else {
+                        self.read_double_string()?
+                    };
+                    zset.push((member, score));
+                }
+                Value::ZSet(zset)
+            }
+            RDB_TYPE_HASH => {
+                let len = self.read_length()?;
+                let mut fields = Vec::new();
+                for _ in 0..len {
+                    let field = String::from_utf8_lossy(&self.read_string()?).into_owned();
+                    fields.push((field, bulk(self.read_string()?)));
+                }
+                Value::Hash(fields)
+            }
+            RDB_TYPE_HASH_ZIPMAP => hash_from(parse_zipmap(&self.read_string()?)?)?,
+            RDB_TYPE_HASH_ZIPLIST => hash_from(parse_ziplist(&self.read_string()?)?)?,
+            RDB_TYPE_HASH_LISTPACK => hash_from(parse_listpack(&self.read_string()?)?)?,
+            RDB_TYPE_LIST_ZIPLIST => list_from(parse_ziplist(&self.read_string()?)?),
+            RDB_TYPE_LIST_QUICKLIST => {
+                let mut elements = Vec::new();
+                for _ in 0..self.read_length()? {
+                    elements.extend(parse_ziplist(&self.read_string()?)?);
+                }
+                list_from(elements)
+            }
+            RDB_TYPE_LIST_QUICKLIST_2 => {
+                let mut elements = Vec::new();
+                for _ in 0..self.read_length()? {
+                    let container = self.read_length()?;
+                    let node = self.read_string()?;
+                    match container {
+                        QUICKLIST_NODE_CONTAINER_PLAIN => elements.push(node),
+                        QUICKLIST_NODE_CONTAINER_PACKED => elements.extend(parse_listpack(&node)?),
+                        _ => {
+                            return Err(RdbError::InvalidFormat(format!(
+                                "unknown quicklist container {container}"
+                            )))
+                        }
+                    }
+                }
+                list_from(elements)
+            }
+            RDB_TYPE_SET_INTSET => set_from(parse_intset(&self.read_string()?)?),
+            RDB_TYPE_SET_LISTPACK => set_from(parse_listpack(&self.read_string()?)?),
+            RDB_TYPE_ZSET_ZIPLIST => zset_from(parse_ziplist(&self.read_string()?)?)?,
+            RDB_TYPE_ZSET_LISTPACK => zset_from(parse_listpack(&self.read_string()?)?)?,
+            RDB_TYPE_MODULE_PRE_GA => {
+                return Err(RdbError::InvalidFormat(
+                    "pre-GA module values (type 6) can not be skipped".into(),
+                ))
+            }
+            _ => {
+                return Err(RdbError::InvalidFormat(format!(
+                    "unknown object type {object_type}"
+                )))
+            }
+        };
+        Ok(value)
+    }
+
+    /// Backend没有对应类型但能确定长度的对象直接跳过，返回类型名；其他类型返回None
+    fn skip_unsupported(&mut self, object_type: u8) -> Result<Option<&'static str>, RdbError> {
+        match object_type {
+            RDB_TYPE_MODULE_2 => {
+                self.read_length()?;
+                self.skip_module_value()?;
+                Ok(Some("module"))
+            }
+            RDB_TYPE_STREAM_LISTPACKS
+            | RDB_TYPE_STREAM_LISTPACKS_2
+            | RDB_TYPE_STREAM_LISTPACKS_3 => {
+                self.skip_stream(object_type)?;
+                Ok(Some("stream"))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// 模块通过RedisModule_Save*写入的数据每个字段都带类型标记，以EOF标记结束
+    fn skip_module_value(&mut self) -> Result<(), RdbError> {
+        loop {
+            match self.read_length()? {
+                RDB_MODULE_OPCODE_EOF => return Ok(()),
+                RDB_MODULE_OPCODE_SINT | RDB_MODULE_OPCODE_UINT => {
+                    self.read_length()?;
+                }
+                RDB_MODULE_OPCODE_FLOAT => {
+                    self.read_bytes(4)?;
+                }
+                RDB_MODULE_OPCODE_DOUBLE => {
+                    self.read_bytes(8)?;
+                }
+                RDB_MODULE_OPCODE_STRING => {
+                    self.read_string()?;
+                }
+                opcode => {
+                    return Err(RdbError::InvalidFormat(format!(
+                        "unknown module opcode {opcode}"
+                    )))
+                }
+            }
+        }
+    }
+
+    fn skip_stream(&mut self, object_type: u8) -> Result<(), RdbError> {
+        //listpack节点：16字节的起始ID + listpack
+        for _ in 0..self.read_length()? {
+            self.read_string()?;
+            self.read_string()?;
+        }
+        //length, last_id
+        self.skip_lengths(3)?;
+        if object_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+            //first_id, max_deleted_entry_id, entries_added
+            self.skip_lengths(5)?;
+        }
+
+        for _ in 0..self.read_length()? {
+            //消费组名字和last_id
+            self.read_string()?;
+            self.skip_lengths(2)?;
+            if object_type >= RDB_TYPE_STREAM_LISTPACKS_2 {
+                //entries_read
+                self.skip_lengths(1)?;
+            }
+            //消费组的PEL：ID + 投递时间 + 投递次数
+            for _ in 0..self.read_length()? {
+                self.read_bytes(16 + 8)?;
+                self.read_length()?;
+            }
+            //消费者：名字 + seen_time [+ active_time] + 只有ID的PEL
+            for _ in 0..self.read_length()? {
+                self.read_string()?;
+                self.read_bytes(8)?;
+                if object_type >= RDB_TYPE_STREAM_LISTPACKS_3 {
+                    self.read_bytes(8)?;
+                }
+                let pending = self.read_length()? as usize;
+                self.read_bytes(pending.saturating_mul(16))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_lengths(&mut self, n: usize) -> Result<(), RdbError> {
+        for _ in 0..n {
+            self.read_length()?;
+        }
+        Ok(())
+    }
+
+    /// 版本5开始EOF之后带8字节CRC64，值为0表示写入时关闭了校验
+    fn verify_checksum(&mut self, version: u32) -> Result<(), RdbError> {
+        if version < 5 {
+            return Ok(());
+        }
+        let actual = CRC64.checksum(&self.data[..self.pos]);
+        let expected = self.read_u64_le()?;
+        if expected != 0 && expected != actual {
+            return Err(RdbError::Checksum { expected, actual });
+        }
+        Ok(())
+    }
+}
+
+fn bulk(s: Vec<u8>) -> RespFrame {
+    RespBulkString::new(s).into()
+}
+
+fn parse_score(s: &[u8]) -> Result<f64, RdbError> {
+    std::str::from_utf8(s)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| RdbError::InvalidFormat("invalid zset score".into()))
+}
+
+/// hash和zset的紧凑编码里元素是 field value field value ... 交替排列的
+fn pairs(elements: &[Vec<u8>]) -> Result<std::slice::ChunksExact<'_, Vec<u8>>, RdbError> {
+    if !elements.len().is_multiple_of(2) {
+        return Err(RdbError::InvalidFormat(
+            "odd number of elements in encoded hash or zset".into(),
+        ));
+    }
+    Ok(elements.chunks_exact(2))
+}
+
+fn hash_from(elements: Vec<Vec<u8>>) -> Result<Value, RdbError> {
+    let fields = pairs(&elements)?
+        .map(|pair| {
+            let field = String::from_utf8_lossy(&pair[0]).into_owned();
+            (field, bulk(pair[1].clone()))
+        })
+        .collect();
+    Ok(Value::Hash(fields))
+}
+
+fn list_from(elements: Vec<Vec<u8>>) -> Value {
+    Value::List(elements.into_iter().map(bulk).collect())
+}
+
+fn set_from(elements: Vec<Vec<u8>>) -> Value {
+    Value::Set(elements.into_iter().map(bulk).collect())
+}
+
+fn zset_from(elements: Vec<Vec<u8>>) -> Result<Value, RdbError> {
+    let zset = pairs(&elements)?
+        .map(|pair| Ok((bulk(pair[0].clone()), parse_score(&pair[1])?)))
+        .collect::<Result<_, RdbError>>()?;
+    Ok(Value::ZSet(zset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdb::{dump_payload, dump_rdb};
+    use anyhow::Result;
+
+    fn frame(s: &str) -> RespFrame {
+        RespBulkString::from(s.to_string()).into()
+    }
+
+    /// 按Redis的格式拼一个只有db0的RDB文件
+    fn rdb_with(objects: &[u8]) -> Vec<u8> {
+        let mut data = b"REDIS0011\xfe\x00".to_vec();
+        data.extend_from_slice(objects);
+        data.push(RDB_OPCODE_EOF);
+        let checksum = CRC64.checksum(&data);
+        data.extend_from_slice(&checksum.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_rdb_round_trip() -> Result<()> {
+        let entries = vec![
+            ("a".to_string(), Value::String(frame("hello")), None),
+            (
+                "n".to_string(),
+                Value::String(frame("-12345")),
+                Some(1 << 42),
+            ),
+            (
+                "h".to_string(),
+                Value::Hash(vec![("f1".into(), frame("v1")), ("f2".into(), frame("2"))]),
+                None,
+            ),
+            (
+                "big".to_string(),
+                Value::String(frame(&"x".repeat(20000))),
+                None,
+            ),
+            (
+                "l".to_string(),
+                Value::List(vec![frame("x"), frame("1"), frame("x")]),
+                None,
+            ),
+            (
+                "s".to_string(),
+                Value::Set(vec![frame("a"), frame("b")]),
+                None,
+            ),
+            (
+                "z".to_string(),
+                Value::ZSet(vec![(frame("m"), 1.5), (frame("n"), f64::NEG_INFINITY)]),
+                None,
+            ),
+        ];
+        let data = dump_rdb(&entries);
+        let content = parse_rdb(&data)?;
+        assert_eq!(content.entries, entries);
+        assert!(content.skipped.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rdb_rejects_corruption() {
+        let mut data = dump_rdb(&[("a".to_string(), Value::String(frame("hello")), None)]);
+        let pos = data.len() - 12;
+        data[pos] ^= 0xff;
+        assert!(matches!(parse_rdb(&data), Err(RdbError::Checksum { .. })));
+
+        let data = dump_rdb(&[]);
+        assert!(parse_rdb(&data[..data.len() - 3]).is_err());
+        assert!(matches!(
+            parse_rdb(b"REDIS0099\xff"),
+            Err(RdbError::UnsupportedVersion(99))
+        ));
+    }
+
+    #[test]
+    fn test_parse_encoded_strings() -> Result<()> {
+        //foo=bar，整数编码的n=100，LZF压缩的z=aaaaaaaaaa
+        let data =
+            rdb_with(b"\x00\x03foo\x03bar\x00\x01n\xc0\x64\x00\x01z\xc3\x05\x0a\x00a\xe0\x00\x00");
+        let entries = parse_rdb(&data)?.entries;
+        assert_eq!(
+            entries,
+            vec![
+                ("foo".to_string(), Value::String(frame("bar")), None),
+                ("n".to_string(), Value::String(frame("100")), None),
+                ("z".to_string(), Value::String(frame("aaaaaaaaaa")), None),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_compact_encodings() -> Result<()> {
+        let mut objects = Vec::new();
+
+        //listpack编码的hash：f=v
+        objects.extend_from_slice(b"\x10\x01h");
+        let listpack = b"\x00\x00\x00\x00\x02\x00\x81f\x02\x81v\x02\xff";
+        objects.push(listpack.len() as u8);
+        objects.extend_from_slice(listpack);
+
+        //intset编码的set：1 2
+        objects.extend_from_slice(b"\x0b\x01s");
+        let intset = b"\x02\x00\x00\x00\x02\x00\x00\x00\x01\x00\x02\x00";
+        objects.push(intset.len() as u8);
+        objects.extend_from_slice(intset);
+
+        //listpack编码的zset：m score=1.5
+        objects.extend_from_slice(b"\x11\x01z");
+        let listpack = b"\x00\x00\x00\x00\x02\x00\x81m\x02\x831.5\x04\xff";
+        objects.push(listpack.len() as u8);
+        objects.extend_from_slice(listpack);
+
+        //quicklist 2：一个PLAIN节点 + 一个PACKED节点
+        objects.extend_from_slice(b"\x12\x01l\x02\x01\x03big\x02");
+        let listpack = b"\x00\x00\x00\x00\x02\x00\x07\x01\x81x\x02\xff";
+        objects.push(listpack.len() as u8);
+        objects.extend_from_slice(listpack);
+
+        //旧版quicklist：节点是ziplist
+        objects.extend_from_slice(b"\x0e\x02ol\x01");
+        let ziplist = b"\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01a\x03\xf2\xff";
+        objects.push(ziplist.len() as u8);
+        objects.extend_from_slice(ziplist);
+
+        let entries = parse_rdb(&rdb_with(&objects))?.entries;
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "h".to_string(),
+                    Value::Hash(vec![("f".into(), frame("v"))]),
+                    None
+                ),
+                (
+                    "s".to_string(),
+                    Value::Set(vec![frame("1"), frame("2")]),
+                    None
+                ),
+                ("z".to_string(), Value::ZSet(vec![(frame("m"), 1.5)]), None),
+                (
+                    "l".to_string(),
+                    Value::List(vec![frame("big"), frame("7"), frame("x")]),
+                    None
+                ),
+                (
+                    "ol".to_string(),
+                    Value::List(vec![frame("a"), frame("1")]),
+                    None
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_unsupported_types() -> Result<()> {
+        let mut objects = Vec::new();
+        //带过期时间的模块对象：module id + UINT 1 + STRING "x" + EOF
+        objects.push(RDB_OPCODE_EXPIRETIME_MS);
+        objects.extend_from_slice(&1u64.to_le_bytes());
+        objects.extend_from_slice(b"\x07\x01m\x05\x02\x01\x05\x01x\x00");
+        //没有消费组的空stream(v1)
+        objects.extend_from_slice(b"\x0f\x02st\x00\x00\x00\x00\x00");
+        objects.extend_from_slice(b"\x00\x01a\x01b");
+
+        let content = parse_rdb(&rdb_with(&objects))?;
+        assert_eq!(
+            content.entries,
+            vec![("a".to_string(), Value::String(frame("b")), None)]
+        );
+        assert_eq!(
+            content.skipped,
+            vec![("m".to_string(), "module"), ("st".to_string(), "stream")]
+        );
+
+        let objects = b"\x06\x01m\x00";
+        assert!(parse_rdb(&rdb_with(objects)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload() -> Result<()> {
+        let value = Value::ZSet(vec![(frame("a"), 1.5), (frame("b"), -2.0)]);
+        let payload = dump_payload(&value);
+        assert_eq!(parse_payload(&payload)?, value);
+
+        //和Redis 7.2的DUMP结果一致：类型 + 值 + 版本11
+        let payload = dump_payload(&Value::String(frame("bar")));
+        assert_eq!(&payload[..7], b"\x00\x03bar\x0b\x00");
+
+        let mut corrupted = payload.clone();
+        corrupted[2] = b'c';
+        assert!(matches!(
+            parse_payload(&corrupted),
+            Err(RdbError::Checksum { .. })
+        ));
+
+        //更新的RDB版本写出的payload不能加载
+        let mut newer = payload[..5].to_vec();
+        newer.extend_from_slice(&13u16.to_le_bytes());
+        let checksum = CRC64.checksum(&newer);
+        newer.extend_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(
+            parse_payload(&newer),
+            Err(RdbError::UnsupportedVersion(13))
+        ));
+        assert!(parse_payload(b"\x00").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload_oversized_lzf() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let payload = |lzf: &[u8]| {
+            let mut payload = b"\x00\xc3".to_vec();
+            payload.extend_from_slice(lzf);
+            payload.extend_from_slice(b"\x0b\x00");
+            let checksum = CRC64.checksum(&payload);
+            payload.extend_from_slice(&checksum.to_le_bytes());
+            payload
+        };
+        //压缩前的长度声明成1TB，不能按它分配内存
+        let mut lzf = vec![0x02, RDB_64BITLEN];
+        lzf.extend_from_slice(&(1u64 << 40).to_be_bytes());
+        lzf.extend_from_slice(b"\x00a");
+        assert!(matches!(
+            parse_payload(&payload(&lzf)),
+            Err(RdbError::InvalidFormat(_))
+        ));
+
+        //随机修改LZF的头部和数据，只能返回错误或者正常解码，不能panic
+        let valid = b"\x05\x0a\x00a\xe0\x00\x00";
+        assert_eq!(
+            parse_payload(&payload(valid)).unwrap(),
+            Value::String(frame("aaaaaaaaaa"))
+        );
+        let mut rng = StdRng::seed_from_u64(31);
+        for _ in 0..5000 {
+            let mut lzf = valid.to_vec();
+            for _ in 0..rng.gen_range(1..4) {
+                let i = rng.gen_range(0..lzf.len());
+                lzf[i] = rng.gen();
+            }
+            let _ = parse_payload(&payload(&lzf));
+        }
+    }
+}
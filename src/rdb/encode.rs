@@ -0,0 +1,211 @@
+use crate::{backend::now_ms, EncodeResp, RespFrame, Value, SERVER_VERSION};
+
+use super::{
+    CRC64, RDB_14BITLEN, RDB_32BITLEN, RDB_64BITLEN, RDB_ENCVAL, RDB_ENC_INT16, RDB_ENC_INT32,
+    RDB_ENC_INT8, RDB_MAGIC, RDB_OPCODE_AUX, RDB_OPCODE_EOF, RDB_OPCODE_EXPIRETIME_MS,
+    RDB_OPCODE_RESIZEDB, RDB_OPCODE_SELECTDB, RDB_TYPE_HASH, RDB_TYPE_LIST, RDB_TYPE_SET,
+    RDB_TYPE_STRING, RDB_TYPE_ZSET_2, RDB_VERSION,
+};
+
+/// 把Backend::snapshot()的结果序列化成完整的RDB文件内容
+pub fn dump_rdb(entries: &[(String, Value, Option<u64>)]) -> Vec<u8> {
+    dump(entries, false)
+}
+
+/// AOF重写出来的RDB格式base文件，和普通RDB的区别只有aof-base这个aux字段
+pub fn dump_aof_preamble(entries: &[(String, Value, Option<u64>)]) -> Vec<u8> {
+    dump(entries, true)
+}
+
+fn dump(entries: &[(String, Value, Option<u64>)], aof_base: bool) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(RDB_MAGIC);
+    buf.extend_from_slice(format!("{RDB_VERSION:04}").as_bytes());
+
+    write_aux(&mut buf, "redis-ver", SERVER_VERSION);
+    write_aux(&mut buf, "redis-bits", &usize::BITS.to_string());
+    write_aux(&mut buf, "ctime", &(now_ms() / 1000).to_string());
+    write_aux(&mut buf, "aof-base", if aof_base { "1" } else { "0" });
+
+    if !entries.is_empty() {
+        buf.push(RDB_OPCODE_SELECTDB);
+        write_length(&mut buf, 0);
+        buf.push(RDB_OPCODE_RESIZEDB);
+        write_length(&mut buf, entries.len() as u64);
+        let expires = entries.iter().filter(|(_, _, e)| e.is_some()).count();
+        write_length(&mut buf, expires as u64);
+    }
+
+    for (key, value, expire_at) in entries {
+        if let Some(when) = expire_at {
+            buf.push(RDB_OPCODE_EXPIRETIME_MS);
+            buf.extend_from_slice(&when.to_le_bytes());
+        }
+        write_object(&mut buf, key, value);
+    }
+
+    buf.push(RDB_OPCODE_EOF);
+    let checksum = CRC64.checksum(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+    buf
+}
+
+/// DUMP的序列化格式：类型 + value + 2字节小端RDB版本 + 8字节小端CRC64，不包含key
+pub fn dump_payload(value: &Value) -> Vec<u8> {
+    let mut buf = vec![object_type(value)];
+    write_value(&mut buf, value);
+    buf.extend_from_slice(&(RDB_VERSION as u16).to_le_bytes());
+    let checksum = CRC64.checksum(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+    buf
+}
+
+/// 类型 + key + value
+pub(crate) fn write_object(buf: &mut Vec<u8>, key: &str, value: &Value) {
+    buf.push(object_type(value));
+    write_string(buf, key.as_bytes());
+    write_value(buf, value);
+}
+
+//list和set用最朴素的编码写出，Redis加载时会自己转换成listpack/quicklist
+fn object_type(value: &Value) -> u8 {
+    match value {
+        Value::String(_) => RDB_TYPE_STRING,
+        Value::Hash(_) => RDB_TYPE_HASH,
+        Value::List(_) => RDB_TYPE_LIST,
+        Value::Set(_) => RDB_TYPE_SET,
+        Value::ZSet(_) => RDB_TYPE_ZSET_2,
+    }
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::String(frame) => write_string(buf, &frame_bytes(frame)),
+        Value::Hash(fields) => {
+            write_length(buf, fields.len() as u64);
+            for (field, frame) in fields {
+                write_string(buf, field.as_bytes());
+                write_string(buf, &frame_bytes(frame));
+            }
+        }
+        Value::List(elements) | Value::Set(elements) => {
+            write_length(buf, elements.len() as u64);
+            for frame in elements {
+                write_string(buf, &frame_bytes(frame));
+            }
+        }
+        Value::ZSet(members) => {
+            write_length(buf, members.len() as u64);
+            for (member, score) in members {
+                write_string(buf, &frame_bytes(member));
+                buf.extend_from_slice(&score.to_le_bytes());
+            }
+        }
+    }
+}
+
+fn write_aux(buf: &mut Vec<u8>, key: &str, value: &str) {
+    buf.push(RDB_OPCODE_AUX);
+    write_string(buf, key.as_bytes());
+    write_string(buf, value.as_bytes());
+}
+
+pub(crate) fn write_length(buf: &mut Vec<u8>, len: u64) {
+    if len < 1 << 6 {
+        buf.push(len as u8);
+    } else if len < 1 << 14 {
+        buf.push((RDB_14BITLEN << 6) | (len >> 8) as u8);
+        buf.push(len as u8);
+    } else if len <= u32::MAX as u64 {
+        buf.push(RDB_32BITLEN);
+        buf.extend_from_slice(&(len as u32).to_be_bytes());
+    } else {
+        buf.push(RDB_64BITLEN);
+        buf.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+/// 能无损表示为32位整数的字符串按整数编码，和Redis的rdbTryIntegerEncoding一致
+pub(crate) fn write_string(buf: &mut Vec<u8>, s: &[u8]) {
+    if let Some(n) = integer_encodable(s) {
+        let enc = RDB_ENCVAL << 6;
+        if let Ok(n) = i8::try_from(n) {
+            buf.push(enc | RDB_ENC_INT8);
+            buf.extend_from_slice(&n.to_le_bytes());
+        } else if let Ok(n) = i16::try_from(n) {
+            buf.push(enc | RDB_ENC_INT16);
+            buf.extend_from_slice(&n.to_le_bytes());
+        } else {
+            buf.push(enc | RDB_ENC_INT32);
+            buf.extend_from_slice(&n.to_le_bytes());
+        }
+        return;
+    }
+    write_length(buf, s.len() as u64);
+    buf.extend_from_slice(s);
+}
+
+fn integer_encodable(s: &[u8]) -> Option<i32> {
+    if s.is_empty() || s.len() > 11 {
+        return None;
+    }
+    let n = std::str::from_utf8(s).ok()?.parse::<i32>().ok()?;
+    //"+1"、"01"这类转回字符串后会变样的不能按整数存
+    (n.to_string().as_bytes() == s).then_some(n)
+}
+
+/// RDB里的字符串就是字节序列，客户端写入的值都是BulkString，其他类型按RESP编码原样保存
+fn frame_bytes(frame: &RespFrame) -> Vec<u8> {
+    match frame {
+        RespFrame::BulkString(s) => s.0.clone(),
+        RespFrame::SimpleString(s) => s.0.as_bytes().to_vec(),
+        RespFrame::Integer(n) => n.0.to_string().into_bytes(),
+        frame => frame.clone().encode(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length_encoding() {
+        let encoded = |len| {
+            let mut buf = Vec::new();
+            write_length(&mut buf, len);
+            buf
+        };
+        assert_eq!(encoded(10), vec![0x0a]);
+        assert_eq!(encoded(700), vec![0x42, 0xbc]);
+        assert_eq!(encoded(17000), vec![0x80, 0x00, 0x00, 0x42, 0x68]);
+        assert_eq!(encoded(1 << 33)[0], 0x81);
+    }
+
+    #[test]
+    fn test_string_encoding() {
+        let encoded = |s: &[u8]| {
+            let mut buf = Vec::new();
+            write_string(&mut buf, s);
+            buf
+        };
+        assert_eq!(encoded(b"hello"), b"\x05hello");
+        assert_eq!(encoded(b"-1"), vec![0xc0, 0xff]);
+        assert_eq!(encoded(b"1000"), vec![0xc1, 0xe8, 0x03]);
+        assert_eq!(encoded(b"100000"), vec![0xc2, 0xa0, 0x86, 0x01, 0x00]);
+        assert_eq!(encoded(b"01"), b"\x0201");
+    }
+
+    #[test]
+    fn test_empty_rdb_checksum() {
+        let data = dump_rdb(&[]);
+        assert!(data.starts_with(b"REDIS0011"));
+        let (body, checksum) = data.split_at(data.len() - 8);
+        assert_eq!(body.last(), Some(&RDB_OPCODE_EOF));
+        assert_eq!(
+            u64::from_le_bytes(checksum.try_into().unwrap()),
+            CRC64.checksum(body)
+        );
+        //Redis的CRC64(Jones)标准测试向量
+        assert_eq!(CRC64.checksum(b"123456789"), 0xe9c6d914c4b8d9ca);
+    }
+}
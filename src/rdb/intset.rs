@@ -0,0 +1,39 @@
+//intset是只包含整数的set的编码：encoding(u32, 2/4/8) + length(u32) + 小端整数数组
+use super::{ziplist::Cursor, RdbError};
+
+pub(super) fn parse_intset(data: &[u8]) -> Result<Vec<Vec<u8>>, RdbError> {
+    let mut cursor = Cursor::new(data);
+    let encoding = u32::from_le_bytes(cursor.array()?);
+    let len = u32::from_le_bytes(cursor.array()?);
+
+    let mut elements = Vec::new();
+    for _ in 0..len {
+        let n = match encoding {
+            2 => i16::from_le_bytes(cursor.array()?) as i64,
+            4 => i32::from_le_bytes(cursor.array()?) as i64,
+            8 => i64::from_le_bytes(cursor.array()?),
+            _ => {
+                return Err(RdbError::InvalidFormat(format!(
+                    "unknown intset encoding {encoding}"
+                )))
+            }
+        };
+        elements.push(n.to_string().into_bytes());
+    }
+    Ok(elements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_intset() {
+        let data = [2, 0, 0, 0, 3, 0, 0, 0, 0xff, 0xff, 0x01, 0x00, 0xe8, 0x03];
+        assert_eq!(
+            parse_intset(&data).unwrap(),
+            vec![b"-1".to_vec(), b"1".to_vec(), b"1000".to_vec()]
+        );
+        assert!(parse_intset(&data[..12]).is_err());
+    }
+}
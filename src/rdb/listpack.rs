@@ -0,0 +1,110 @@
+/*
+listpack是Redis 7开始hash、list、set、zset的紧凑编码：
+    total_bytes(u32) num_elements(u16) entry... 0xFF
+    entry = encoding + data + backlen(1到5个字节，反向遍历用)
+*/
+use super::{ziplist::Cursor, RdbError};
+
+const LP_EOF: u8 = 0xff;
+
+/// 按顺序取出listpack里的所有元素，整数元素转为十进制字符串
+pub(super) fn parse_listpack(data: &[u8]) -> Result<Vec<Vec<u8>>, RdbError> {
+    let mut cursor = Cursor::new(data);
+    cursor.take(6)?;
+
+    let mut elements = Vec::new();
+    loop {
+        let encoding = cursor.u8()?;
+        if encoding == LP_EOF {
+            return Ok(elements);
+        }
+
+        let (element, entry_len) = match encoding {
+            //0xxxxxxx：7位无符号整数
+            0x00..=0x7f => (integer(encoding as i64), 1),
+            //10xxxxxx：6位长度的字符串
+            0x80..=0xbf => {
+                let len = (encoding & 0x3f) as usize;
+                (cursor.take(len)?.to_vec(), 1 + len)
+            }
+            //110xxxxx yyyyyyyy：13位有符号整数
+            0xc0..=0xdf => {
+                let n = (((encoding & 0x1f) as i64) << 8) | cursor.u8()? as i64;
+                let n = if n >= 1 << 12 { n - (1 << 13) } else { n };
+                (integer(n), 2)
+            }
+            //1110xxxx yyyyyyyy：12位长度的字符串
+            0xe0..=0xef => {
+                let len = (((encoding & 0x0f) as usize) << 8) | cursor.u8()? as usize;
+                (cursor.take(len)?.to_vec(), 2 + len)
+            }
+            0xf0 => {
+                let len = u32::from_le_bytes(cursor.array()?) as usize;
+                (cursor.take(len)?.to_vec(), 5 + len)
+            }
+            0xf1 => (integer(i16::from_le_bytes(cursor.array()?) as i64), 3),
+            0xf2 => {
+                let [a, b, c] = cursor.array()?;
+                (integer((i32::from_le_bytes([0, a, b, c]) >> 8) as i64), 4)
+            }
+            0xf3 => (integer(i32::from_le_bytes(cursor.array()?) as i64), 5),
+            0xf4 => (integer(i64::from_le_bytes(cursor.array()?)), 9),
+            _ => {
+                return Err(RdbError::InvalidFormat(format!(
+                    "unknown listpack encoding {encoding:#x}"
+                )))
+            }
+        };
+        cursor.take(backlen_size(entry_len))?;
+        elements.push(element);
+    }
+}
+
+fn integer(n: i64) -> Vec<u8> {
+    n.to_string().into_bytes()
+}
+
+/// backlen每个字节存7位，和lpEncodeBacklen的分段保持一致
+fn backlen_size(entry_len: usize) -> usize {
+    match entry_len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listpack() {
+        let mut data = vec![0; 6];
+        data.extend_from_slice(&[0x83, b'a', b'b', b'c', 0x04]);
+        data.extend_from_slice(&[0x05, 0x01]);
+        data.extend_from_slice(&[0xdf, 0xff, 0x02]);
+        data.extend_from_slice(&[0xf1, 0xe8, 0x03, 0x03]);
+        data.extend_from_slice(&[0xf2, 0xff, 0xff, 0xff, 0x04]);
+        let long = vec![b'x'; 100];
+        data.extend_from_slice(&[0xe0, 100]);
+        data.extend_from_slice(&long);
+        data.push(102);
+        data.push(LP_EOF);
+
+        let elements = parse_listpack(&data).unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                b"abc".to_vec(),
+                b"5".to_vec(),
+                b"-1".to_vec(),
+                b"1000".to_vec(),
+                b"-1".to_vec(),
+                long
+            ]
+        );
+        assert!(parse_listpack(&data[..data.len() - 1]).is_err());
+    }
+}
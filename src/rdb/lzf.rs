@@ -0,0 +1,76 @@
+use super::RdbError;
+use crate::DEFAULT_MAX_BULK_LEN;
+
+/// LZF解压，对应Redis的lzf_d.c，len是压缩前的长度
+pub(super) fn decompress(input: &[u8], len: usize) -> Result<Vec<u8>, RdbError> {
+    let corrupted = || RdbError::InvalidFormat("corrupted LZF compressed string".into());
+    //len来自不可信的数据，不能直接按它分配内存，超过bulk长度限制的直接拒绝
+    if len > DEFAULT_MAX_BULK_LEN {
+        return Err(RdbError::InvalidFormat(format!(
+            "LZF uncompressed length {len} exceeds the bulk length limit"
+        )));
+    }
+    let mut output = Vec::with_capacity(len.min(input.len().saturating_mul(4)));
+    let mut ip = 0;
+
+    while ip < input.len() {
+        let ctrl = input[ip] as usize;
+        ip += 1;
+
+        if ctrl < 1 << 5 {
+            //字面量：后面ctrl+1个字节原样拷贝
+            let run = input.get(ip..ip + ctrl + 1).ok_or_else(corrupted)?;
+            output.extend_from_slice(run);
+            ip += ctrl + 1;
+        } else {
+            //回溯引用：从已经输出的内容里拷贝len+2个字节，源和目标可以重叠
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(ip).ok_or_else(corrupted)? as usize;
+                ip += 1;
+            }
+            let low = *input.get(ip).ok_or_else(corrupted)? as usize;
+            ip += 1;
+            let distance = ((ctrl & 0x1f) << 8) + low + 1;
+            let start = output.len().checked_sub(distance).ok_or_else(corrupted)?;
+            for i in 0..run + 2 {
+                output.push(output[start + i]);
+            }
+        }
+
+        if output.len() > len {
+            return Err(corrupted());
+        }
+    }
+
+    if output.len() != len {
+        return Err(corrupted());
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lzf_decompress() {
+        assert_eq!(
+            decompress(&[0x00, b'a', 0xe0, 0x00, 0x00], 10).unwrap(),
+            b"aaaaaaaaaa"
+        );
+        assert_eq!(
+            decompress(&[0x02, b'a', b'b', b'c', 0x80, 0x02], 9).unwrap(),
+            b"abcabcabc"
+        );
+        assert!(decompress(&[0x02, b'a', b'b', b'c', 0x80, 0x02], 8).is_err());
+        assert!(decompress(&[0x80, 0x05], 2).is_err());
+
+        //头部声明的长度很大时不会按它分配内存
+        assert!(decompress(&[0x00, b'a'], 1 << 40).is_err());
+        assert!(decompress(&[0x00, b'a'], DEFAULT_MAX_BULK_LEN).is_err());
+        //输出比声明的短或者长都是错误
+        assert!(decompress(&[0x00, b'a', 0xe0, 0x00, 0x00], 11).is_err());
+        assert!(decompress(&[0x00, b'a', 0xe0, 0x00, 0x00], 9).is_err());
+    }
+}
@@ -0,0 +1,393 @@
+/*
+RDB快照持久化，文件格式和Redis保持一致，redis-check-rdb可以直接校验：
+    "REDIS" + 4位十进制版本号
+    AUX字段 (0xFA key value)
+    SELECTDB (0xFE dbnum) + RESIZEDB (0xFB db_size expires_size)
+    [EXPIRETIME_MS (0xFC 8字节小端毫秒)] + 类型 + key + value
+    EOF (0xFF) + 8字节小端CRC64
+*/
+mod decode;
+mod encode;
+mod intset;
+mod listpack;
+mod lzf;
+mod ziplist;
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+
+use crc::{Crc, CRC_64_REDIS};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{backend::now_ms, Backend, Value};
+
+pub use decode::{parse_payload, parse_rdb};
+pub use encode::{dump_aof_preamble, dump_payload, dump_rdb};
+
+/// Redis 7.x写出的RDB版本
+pub const RDB_VERSION: u32 = 11;
+/// Redis 7.4开始写12，只多了带field过期时间的hash类型，其余格式不变
+const RDB_MAX_LOAD_VERSION: u32 = 12;
+
+pub const RDB_MAGIC: &[u8] = b"REDIS";
+
+const RDB_OPCODE_FUNCTION2: u8 = 245;
+const RDB_OPCODE_MODULE_AUX: u8 = 247;
+const RDB_OPCODE_IDLE: u8 = 248;
+const RDB_OPCODE_FREQ: u8 = 249;
+const RDB_OPCODE_AUX: u8 = 250;
+const RDB_OPCODE_RESIZEDB: u8 = 251;
+const RDB_OPCODE_EXPIRETIME_MS: u8 = 252;
+const RDB_OPCODE_EXPIRETIME: u8 = 253;
+const RDB_OPCODE_SELECTDB: u8 = 254;
+const RDB_OPCODE_EOF: u8 = 255;
+
+const RDB_TYPE_STRING: u8 = 0;
+const RDB_TYPE_LIST: u8 = 1;
+const RDB_TYPE_SET: u8 = 2;
+const RDB_TYPE_ZSET: u8 = 3;
+const RDB_TYPE_HASH: u8 = 4;
+const RDB_TYPE_ZSET_2: u8 = 5;
+const RDB_TYPE_MODULE_PRE_GA: u8 = 6;
+const RDB_TYPE_MODULE_2: u8 = 7;
+const RDB_TYPE_HASH_ZIPMAP: u8 = 9;
+const RDB_TYPE_LIST_ZIPLIST: u8 = 10;
+const RDB_TYPE_SET_INTSET: u8 = 11;
+const RDB_TYPE_ZSET_ZIPLIST: u8 = 12;
+const RDB_TYPE_HASH_ZIPLIST: u8 = 13;
+const RDB_TYPE_LIST_QUICKLIST: u8 = 14;
+const RDB_TYPE_STREAM_LISTPACKS: u8 = 15;
+const RDB_TYPE_HASH_LISTPACK: u8 = 16;
+const RDB_TYPE_ZSET_LISTPACK: u8 = 17;
+const RDB_TYPE_LIST_QUICKLIST_2: u8 = 18;
+const RDB_TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const RDB_TYPE_SET_LISTPACK: u8 = 20;
+const RDB_TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+//长度编码的最高两位
+const RDB_6BITLEN: u8 = 0;
+const RDB_14BITLEN: u8 = 1;
+const RDB_32BITLEN: u8 = 0x80;
+const RDB_64BITLEN: u8 = 0x81;
+const RDB_ENCVAL: u8 = 3;
+
+//RDB_ENCVAL之后低6位表示字符串的特殊编码
+const RDB_ENC_INT8: u8 = 0;
+const RDB_ENC_INT16: u8 = 1;
+const RDB_ENC_INT32: u8 = 2;
+const RDB_ENC_LZF: u8 = 3;
+
+const CRC64: Crc<u64> = Crc::<u64>::new(&CRC_64_REDIS);
+
+#[derive(Error, Debug)]
+pub enum RdbError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+
+    #[error("bad rdb format: {0}")]
+    InvalidFormat(String),
+
+    #[error("unsupported rdb version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("wrong rdb checksum, expected {expected:#018x} got {actual:#018x}")]
+    Checksum { expected: u64, actual: u64 },
+
+    #[error("Background save already in progress")]
+    BgSaveInProgress,
+
+    #[error("skipped keys of unsupported types: {0}")]
+    UnsupportedTypes(String),
+}
+
+/// parse_rdb的结果，skipped里是因为类型不支持而跳过的 (key, 类型名)
+#[derive(Debug, Default, PartialEq)]
+pub struct RdbContent {
+    pub entries: Vec<(String, Value, Option<u64>)>,
+    pub skipped: Vec<(String, &'static str)>,
+}
+
+/// 加载RDB的结果，不支持的类型只会跳过对应的key，不会让整个加载失败
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub loaded: usize,
+    pub skipped: Vec<(String, &'static str)>,
+}
+
+impl LoadReport {
+    /// 有key被跳过时，返回按类型汇总的错误，例如 "stream: 2 key(s), module: 1 key(s)"
+    pub fn unsupported(&self) -> Option<RdbError> {
+        if self.skipped.is_empty() {
+            return None;
+        }
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        for (_, type_name) in &self.skipped {
+            match counts.iter_mut().find(|(name, _)| name == type_name) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((type_name, 1)),
+            }
+        }
+        let summary = counts
+            .iter()
+            .map(|(name, count)| format!("{name}: {count} key(s)"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Some(RdbError::UnsupportedTypes(summary))
+    }
+}
+
+/// save <seconds> <changes>：距离上次保存超过seconds秒并且至少有changes次修改就触发BGSAVE
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SaveRule {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RdbConfig {
+    pub dir: PathBuf,
+    pub dbfilename: String,
+    pub save_rules: Vec<SaveRule>,
+}
+
+impl Default for RdbConfig {
+    fn default() -> Self {
+        //和Redis默认的save规则一致
+        let save_rules = [(3600, 1), (300, 100), (60, 10000)]
+            .into_iter()
+            .map(|(seconds, changes)| SaveRule { seconds, changes })
+            .collect();
+        Self {
+            dir: PathBuf::from("."),
+            dbfilename: "dump.rdb".into(),
+            save_rules,
+        }
+    }
+}
+
+impl RdbConfig {
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(&self.dbfilename)
+    }
+}
+
+/// 保存相关的运行时状态，挂在Backend上供SAVE/BGSAVE/LASTSAVE/INFO使用
+#[derive(Debug)]
+pub struct RdbState {
+    config: RwLock<RdbConfig>,
+    /// 上次成功保存的时间(unix秒)
+    last_save: AtomicU64,
+    /// 上次保存时Backend::changes()的值
+    saved_changes: AtomicU64,
+    bgsave_in_progress: AtomicBool,
+    last_bgsave_ok: AtomicBool,
+}
+
+impl Default for RdbState {
+    fn default() -> Self {
+        Self {
+            config: RwLock::default(),
+            last_save: AtomicU64::new(now_ms() / 1000),
+            saved_changes: AtomicU64::new(0),
+            bgsave_in_progress: AtomicBool::new(false),
+            last_bgsave_ok: AtomicBool::new(true),
+        }
+    }
+}
+
+impl RdbState {
+    pub fn config(&self) -> RdbConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_config(&self, config: RdbConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    pub fn last_save(&self) -> u64 {
+        self.last_save.load(Ordering::SeqCst)
+    }
+
+    pub fn bgsave_in_progress(&self) -> bool {
+        self.bgsave_in_progress.load(Ordering::SeqCst)
+    }
+
+    pub fn last_bgsave_ok(&self) -> bool {
+        self.last_bgsave_ok.load(Ordering::SeqCst)
+    }
+
+    fn mark_saved(&self, changes: u64) {
+        self.saved_changes.store(changes, Ordering::SeqCst);
+        self.last_save.store(now_ms() / 1000, Ordering::SeqCst);
+    }
+}
+
+/// 上次保存之后的修改次数
+pub fn changes_since_save(backend: &Backend) -> u64 {
+    backend
+        .changes()
+        .saturating_sub(backend.rdb.saved_changes.load(Ordering::SeqCst))
+}
+
+/// SAVE：在当前线程生成快照并写盘，调用方需要持有Backend的写锁
+pub fn save(backend: &Backend) -> Result<(), RdbError> {
+    if backend.rdb.bgsave_in_progress() {
+        return Err(RdbError::BgSaveInProgress);
+    }
+    let changes = backend.changes();
+    let data = dump_rdb(&backend.snapshot());
+    write_file(&backend.rdb.config().path(), &data)?;
+    backend.rdb.mark_saved(changes);
+    Ok(())
+}
+
+/// BGSAVE：持锁期间只登记一个快照，拷贝数据、序列化和写盘都放到后台线程，不阻塞其他客户端
+pub fn bgsave(backend: &Backend) -> Result<(), RdbError> {
+    if backend
+        .rdb
+        .bgsave_in_progress
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return Err(RdbError::BgSaveInProgress);
+    }
+
+    let changes = backend.changes();
+    let snapshot = backend.begin_snapshot();
+    let backend = backend.clone();
+    thread::spawn(move || {
+        let path = backend.rdb.config().path();
+        match write_file(&path, &dump_rdb(&snapshot.collect())) {
+            Ok(()) => {
+                info!(
+                    "Background saving to {} terminated with success",
+                    path.display()
+                );
+                backend.rdb.mark_saved(changes);
+                backend.rdb.last_bgsave_ok.store(true, Ordering::SeqCst);
+            }
+            Err(e) => {
+                warn!("Background saving to {} failed: {}", path.display(), e);
+                backend.rdb.last_bgsave_ok.store(false, Ordering::SeqCst);
+            }
+        }
+        backend
+            .rdb
+            .bgsave_in_progress
+            .store(false, Ordering::SeqCst);
+    });
+    Ok(())
+}
+
+/// 启动时从配置的路径加载RDB，文件不存在时什么都不做
+pub fn load(backend: &Backend) -> Result<LoadReport, RdbError> {
+    let path = backend.rdb.config().path();
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(LoadReport::default()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let report = load_data(backend, &data)?;
+    backend.rdb.mark_saved(backend.changes());
+    Ok(report)
+}
+
+/// 把一份完整的RDB内容恢复到Backend，AOF的RDB格式base文件也走这里
+pub fn load_data(backend: &Backend, data: &[u8]) -> Result<LoadReport, RdbError> {
+    let content = parse_rdb(data)?;
+    let now = now_ms();
+    let mut report = LoadReport {
+        loaded: 0,
+        skipped: content.skipped,
+    };
+    for (key, value, expire_at) in content.entries {
+        //已经过期的key不再加载
+        if expire_at.is_some_and(|when| when <= now) {
+            continue;
+        }
+        backend.restore(key, value, expire_at);
+        report.loaded += 1;
+    }
+    Ok(report)
+}
+
+/// 有任何一条save规则满足时返回true
+pub fn should_save(backend: &Backend) -> bool {
+    if backend.rdb.bgsave_in_progress() {
+        return false;
+    }
+    let changes = changes_since_save(backend);
+    let elapsed = (now_ms() / 1000).saturating_sub(backend.rdb.last_save());
+    backend
+        .rdb
+        .config()
+        .save_rules
+        .iter()
+        .any(|rule| changes >= rule.changes && elapsed >= rule.seconds)
+}
+
+/// 每秒检查一次save规则，相当于Redis serverCron里的那部分逻辑
+pub async fn save_scheduler(backend: Backend) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        if !should_save(&backend) {
+            continue;
+        }
+        info!(
+            "{} changes since last save, saving...",
+            changes_since_save(&backend)
+        );
+        //等锁会阻塞线程，不能放在async task里
+        let inner = backend.clone();
+        let started = tokio::task::spawn_blocking(move || {
+            let _guard = inner.exclusive_lock();
+            bgsave(&inner)
+        })
+        .await;
+        if let Ok(Err(e)) = started {
+            warn!("Background saving failed to start: {}", e);
+        }
+    }
+}
+
+/// 先写临时文件再rename，保证磁盘上的RDB文件总是完整的
+fn write_file(path: &Path, data: &[u8]) -> Result<(), RdbError> {
+    let tmp = path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_report_lists_unsupported_types() {
+        let mut report = LoadReport::default();
+        assert!(report.unsupported().is_none());
+
+        report.skipped = vec![
+            ("a".into(), "stream"),
+            ("b".into(), "module"),
+            ("c".into(), "stream"),
+        ];
+        assert_eq!(
+            report.unsupported().unwrap().to_string(),
+            "skipped keys of unsupported types: stream: 2 key(s), module: 1 key(s)"
+        );
+    }
+}
@@ -0,0 +1,165 @@
+/*
+ziplist是Redis 7之前hash、list、zset的紧凑编码：
+    zlbytes(u32) zltail(u32) zllen(u16) entry... 0xFF
+    entry = prevlen(1字节，或0xFE + 4字节) + encoding + data
+zipmap是更早的hash编码：zmlen(1字节) + (len key len free value)... 0xFF
+*/
+use super::RdbError;
+
+const ZIP_END: u8 = 0xff;
+const ZIP_BIG_PREVLEN: u8 = 0xfe;
+
+/// 按顺序取出ziplist里的所有元素，整数元素转为十进制字符串
+pub(super) fn parse_ziplist(data: &[u8]) -> Result<Vec<Vec<u8>>, RdbError> {
+    let mut cursor = Cursor::new(data);
+    cursor.take(10)?;
+
+    let mut elements = Vec::new();
+    loop {
+        let first = cursor.u8()?;
+        if first == ZIP_END {
+            return Ok(elements);
+        }
+        if first == ZIP_BIG_PREVLEN {
+            cursor.take(4)?;
+        }
+
+        let encoding = cursor.u8()?;
+        let element = match encoding >> 6 {
+            0 => cursor.take((encoding & 0x3f) as usize)?.to_vec(),
+            1 => {
+                let len = (((encoding & 0x3f) as usize) << 8) | cursor.u8()? as usize;
+                cursor.take(len)?.to_vec()
+            }
+            2 => {
+                let len = u32::from_be_bytes(cursor.array()?) as usize;
+                cursor.take(len)?.to_vec()
+            }
+            _ => {
+                let n = match encoding {
+                    0xc0 => i16::from_le_bytes(cursor.array()?) as i64,
+                    0xd0 => i32::from_le_bytes(cursor.array()?) as i64,
+                    0xe0 => i64::from_le_bytes(cursor.array()?),
+                    0xf0 => {
+                        let [a, b, c] = cursor.array()?;
+                        i32::from_le_bytes([0, a, b, c]) as i64 >> 8
+                    }
+                    0xfe => cursor.u8()? as i8 as i64,
+                    //1111xxxx：xxxx-1就是0到12之间的整数
+                    0xf1..=0xfd => (encoding & 0x0f) as i64 - 1,
+                    _ => {
+                        return Err(RdbError::InvalidFormat(format!(
+                            "unknown ziplist encoding {encoding:#x}"
+                        )))
+                    }
+                };
+                n.to_string().into_bytes()
+            }
+        };
+        elements.push(element);
+    }
+}
+
+/// zipmap展开成 key value key value ... 的顺序
+pub(super) fn parse_zipmap(data: &[u8]) -> Result<Vec<Vec<u8>>, RdbError> {
+    let mut cursor = Cursor::new(data);
+    cursor.take(1)?;
+
+    let mut elements = Vec::new();
+    loop {
+        let Some(len) = zipmap_len(&mut cursor)? else {
+            return Ok(elements);
+        };
+        elements.push(cursor.take(len)?.to_vec());
+
+        let len = zipmap_len(&mut cursor)?
+            .ok_or_else(|| RdbError::InvalidFormat("zipmap key without value".into()))?;
+        let free = cursor.u8()? as usize;
+        elements.push(cursor.take(len)?.to_vec());
+        cursor.take(free)?;
+    }
+}
+
+fn zipmap_len(cursor: &mut Cursor) -> Result<Option<usize>, RdbError> {
+    match cursor.u8()? {
+        ZIP_END => Ok(None),
+        ZIP_BIG_PREVLEN => Ok(Some(u32::from_le_bytes(cursor.array()?) as usize)),
+        len => Ok(Some(len as usize)),
+    }
+}
+
+/// 紧凑编码共用的只读游标，越界时返回错误而不是panic
+pub(super) struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    pub(super) fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub(super) fn take(&mut self, n: usize) -> Result<&'a [u8], RdbError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| RdbError::InvalidFormat("unexpected end of encoded value".into()))?;
+        let ret = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(ret)
+    }
+
+    pub(super) fn u8(&mut self) -> Result<u8, RdbError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(super) fn array<const N: usize>(&mut self) -> Result<[u8; N], RdbError> {
+        let mut ret = [0; N];
+        ret.copy_from_slice(self.take(N)?);
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ziplist() {
+        let mut data = vec![0; 10];
+        data.extend_from_slice(&[0x00, 0x03, b'a', b'b', b'c']);
+        data.extend_from_slice(&[0x05, 0xf3]);
+        data.extend_from_slice(&[0x02, 0xc0, 0xe8, 0x03]);
+        data.extend_from_slice(&[0x04, 0xf0, 0xff, 0xff, 0xff]);
+        data.extend_from_slice(&[0x05, 0xfe, 0x9c]);
+        data.push(ZIP_END);
+
+        let elements = parse_ziplist(&data).unwrap();
+        assert_eq!(
+            elements,
+            vec![
+                b"abc".to_vec(),
+                b"2".to_vec(),
+                b"1000".to_vec(),
+                b"-1".to_vec(),
+                b"-100".to_vec()
+            ]
+        );
+        assert!(parse_ziplist(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_parse_zipmap() {
+        let data = b"\x02\x03foo\x03\x01bar\x00\x01k\x01\x00v\xff";
+        assert_eq!(
+            parse_zipmap(data).unwrap(),
+            vec![
+                b"foo".to_vec(),
+                b"bar".to_vec(),
+                b"k".to_vec(),
+                b"v".to_vec()
+            ]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::VecDeque;
+
+/// 复制流最近的一段数据，replica断线重连后从这里补发缺失的部分。
+/// offset从1开始编号，end_offset是最后一个字节的offset，和Redis的master_repl_offset一致
+#[derive(Debug)]
+pub(super) struct Backlog {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    end_offset: u64,
+}
+
+impl Backlog {
+    pub fn new(capacity: usize, end_offset: u64) -> Self {
+        Self {
+            buf: VecDeque::new(),
+            capacity,
+            end_offset,
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buf.extend(data);
+        self.end_offset += data.len() as u64;
+        let excess = self.buf.len().saturating_sub(self.capacity);
+        self.buf.drain(..excess);
+    }
+
+    /// 修改容量，变小时丢掉最旧的数据
+    pub fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let excess = self.buf.len().saturating_sub(capacity);
+        self.buf.drain(..excess);
+    }
+
+    pub fn end_offset(&self) -> u64 {
+        self.end_offset
+    }
+
+    /// backlog里第一个字节的offset
+    pub fn first_offset(&self) -> u64 {
+        self.end_offset + 1 - self.buf.len() as u64
+    }
+
+    pub fn histlen(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// 从offset(包含)开始到末尾的数据，offset已经不在backlog里时返回None
+    pub fn since(&self, offset: u64) -> Option<Vec<u8>> {
+        if offset < self.first_offset() || offset > self.end_offset + 1 {
+            return None;
+        }
+        let skip = (offset - self.first_offset()) as usize;
+        Some(self.buf.range(skip..).copied().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backlog_wraps() {
+        let mut backlog = Backlog::new(8, 100);
+        assert_eq!(backlog.first_offset(), 101);
+        assert_eq!(backlog.since(101), Some(vec![]));
+
+        backlog.push(b"abcde");
+        assert_eq!(backlog.end_offset(), 105);
+        assert_eq!(backlog.since(103), Some(b"cde".to_vec()));
+
+        backlog.push(b"fghij");
+        assert_eq!(backlog.histlen(), 8);
+        assert_eq!(backlog.first_offset(), 103);
+        assert_eq!(backlog.since(102), None);
+        assert_eq!(backlog.since(103), Some(b"cdefghij".to_vec()));
+        assert_eq!(backlog.since(111), Some(vec![]));
+        assert_eq!(backlog.since(112), None);
+    }
+}
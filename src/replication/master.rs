@@ -0,0 +1,200 @@
+use anyhow::Result;
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::StreamExt;
+use tokio_util::codec::{Framed, FramedRead};
+use tracing::info;
+
+use crate::{
+    backend::now_ms,
+    network::{RespFrameCodec, Session},
+    rdb::dump_rdb,
+    Backend, Psync, ReplConf, RespFrame, Snapshot,
+};
+
+use super::{ReplicaLink, ReplicationState};
+
+use std::sync::atomic::Ordering;
+
+/// 回复PSYNC之后先发送的内容
+enum SyncStart {
+    Full {
+        /// SYNC没有+FULLRESYNC这一行，直接发送RDB
+        header: Option<String>,
+        snapshot: Snapshot,
+    },
+    Partial {
+        header: String,
+        backlog: Vec<u8>,
+    },
+}
+
+/// 收到PSYNC/SYNC之后这个连接就只用来向replica发送复制流，以及接收replica的REPLCONF ACK
+pub async fn serve_replica<S>(
+    framed: Framed<S, RespFrameCodec>,
+    session: Session,
+    backend: Backend,
+    psync: Psync,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let id = session.id;
+    //持有独占锁时没有命令在执行，快照和注册replica之后的复制流正好衔接。
+    //等锁会阻塞线程，放到阻塞线程里；持锁期间只登记快照，拷贝数据在生成RDB时进行
+    let inner = backend.clone();
+    let (session, start) = tokio::task::spawn_blocking(move || {
+        let _guard = inner.exclusive_lock();
+        let start = start_sync(&inner, &session, psync, sender);
+        (session, start)
+    })
+    .await?;
+
+    let parts = framed.into_parts();
+    let (read_half, mut write_half) = tokio::io::split(parts.io);
+    let mut reader = FramedRead::new(read_half, RespFrameCodec::default());
+    reader.read_buffer_mut().extend_from_slice(&parts.read_buf);
+
+    let ret: Result<()> = async {
+        match start {
+            SyncStart::Full { header, snapshot } => {
+                if let Some(header) = header {
+                    write_half.write_all(header.as_bytes()).await?;
+                }
+                let rdb =
+                    tokio::task::spawn_blocking(move || dump_rdb(&snapshot.collect())).await?;
+                write_half
+                    .write_all(format!("${}\r\n", rdb.len()).as_bytes())
+                    .await?;
+                write_half.write_all(&rdb).await?;
+                backend.repl.set_online(id);
+                info!("Synchronization with replica {:?} succeeded", session.addr);
+            }
+            SyncStart::Partial { header, backlog } => {
+                write_half.write_all(header.as_bytes()).await?;
+                write_half.write_all(&backlog).await?;
+                info!(
+                    "Partial resynchronization request from {:?} accepted, sending {} bytes of backlog",
+                    session.addr,
+                    backlog.len()
+                );
+            }
+        }
+
+        //全量同步期间的写命令都缓存在channel里，RDB发完之后按顺序发出去
+        loop {
+            tokio::select! {
+                data = receiver.recv() => match data {
+                    Some(data) => write_half.write_all(&data).await?,
+                    //master主动断开了这个replica
+                    None => return Ok(()),
+                },
+                frame = reader.next() => match frame {
+                    Some(Ok(RespFrame::Arrays(array))) => {
+                        if let Ok(conf) = ReplConf::try_from(array) {
+                            if let Some(offset) = conf.ack() {
+                                backend.repl.ack(id, offset, conf.fack());
+                            }
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+    .await;
+
+    backend.repl.remove_replica(id);
+    info!("Connection with replica {:?} lost", session.addr);
+    ret
+}
+
+/// 调用方持有Backend的独占锁。replid和offset都对得上并且数据还在backlog里时部分同步，否则全量同步
+fn start_sync(
+    backend: &Backend,
+    session: &Session,
+    psync: Psync,
+    sender: UnboundedSender<Bytes>,
+) -> SyncStart {
+    let repl = &backend.repl;
+    repl.ensure_backlog();
+
+    let mut header = None;
+    if let Psync::Psync { replid, offset } = &psync {
+        if let Some(backlog) = repl.partial_data(replid, *offset) {
+            repl.sync_partial_ok.fetch_add(1, Ordering::SeqCst);
+            repl.add_replica(session, sender, true);
+            return SyncStart::Partial {
+                header: format!("+CONTINUE {}\r\n", repl.replid()),
+                backlog,
+            };
+        }
+        if replid != "?" {
+            repl.sync_partial_err.fetch_add(1, Ordering::SeqCst);
+        }
+        header = Some(format!(
+            "+FULLRESYNC {} {}\r\n",
+            repl.replid(),
+            repl.offset()
+        ));
+    }
+
+    repl.sync_full.fetch_add(1, Ordering::SeqCst);
+    repl.add_replica(session, sender, false);
+    SyncStart::Full {
+        header,
+        snapshot: backend.begin_snapshot(),
+    }
+}
+
+impl ReplicationState {
+    /// replica请求的offset开始到现在的复制流，无法部分同步时返回None
+    fn partial_data(&self, replid: &str, offset: i64) -> Option<Vec<u8>> {
+        let offset = u64::try_from(offset).ok()?;
+        {
+            let ids = self.ids_guard();
+            let same_history = replid == ids.replid
+                || (replid == ids.replid2 && offset as i64 <= ids.second_replid_offset);
+            if !same_history {
+                return None;
+            }
+        }
+        self.backlog_guard().as_ref()?.since(offset)
+    }
+
+    fn add_replica(&self, session: &Session, sender: UnboundedSender<Bytes>, online: bool) {
+        self.replicas_guard().push(ReplicaLink {
+            id: session.id,
+            addr: session.addr.clone(),
+            port: session.replica_listening_port.unwrap_or_default(),
+            sender,
+            online,
+            ack_offset: 0,
+            fack_offset: 0,
+            last_ack: now_ms(),
+        });
+    }
+
+    fn set_online(&self, id: u64) {
+        if let Some(replica) = self.replicas_guard().iter_mut().find(|r| r.id == id) {
+            replica.online = true;
+        }
+    }
+
+    fn ack(&self, id: u64, offset: u64, fack: Option<u64>) {
+        if let Some(replica) = self.replicas_guard().iter_mut().find(|r| r.id == id) {
+            replica.ack_offset = offset;
+            replica.fack_offset = fack.unwrap_or_default();
+            replica.last_ack = now_ms();
+        }
+        self.acked.notify_waiters();
+    }
+
+    fn remove_replica(&self, id: u64) {
+        self.replicas_guard().retain(|r| r.id != id);
+    }
+}
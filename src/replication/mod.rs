@@ -0,0 +1,529 @@
+/*
+主从复制：
+    replica连上master后依次发送PING、REPLCONF listening-port、REPLCONF capa psync2、PSYNC replid offset
+    master回复+FULLRESYNC replid offset并发送RDB快照，或者回复+CONTINUE replid并从backlog补发缺失的数据
+    之后master把每条写命令按和AOF相同的RESP编码发给所有replica，replica按顺序执行
+offset是复制流的字节数，replica每秒用REPLCONF ACK offset告诉master自己处理到了哪里
+*/
+mod backlog;
+mod master;
+mod replica;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, RwLock};
+use std::time::Duration;
+
+use bytes::Bytes;
+use rand::Rng;
+use thiserror::Error;
+use tokio::sync::futures::Notified;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+use tracing::info;
+
+use crate::{
+    aof::command_array, backend::now_ms, network::ConnAddr, Backend, EncodeResp, RespArray,
+};
+
+use backlog::Backlog;
+
+pub use master::serve_replica;
+
+#[derive(Error, Debug)]
+pub enum ReplicationError {
+    #[error("REPLICAOF can only be used while the server is running")]
+    NoRuntime,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplConfig {
+    /// 本实例监听的端口，作为replica握手时通过REPLCONF listening-port告诉master
+    pub port: u16,
+    /// replica是否拒绝客户端的写命令
+    pub read_only: bool,
+    pub backlog_size: usize,
+    /// master每隔多少秒向replica发送一次PING
+    pub ping_period: u64,
+    /// replica超过这么多秒没有收到master的任何数据就断开重连
+    pub timeout: u64,
+    /// 握手时用AUTH [masteruser] masterauth认证，空字符串表示master不需要认证
+    pub masteruser: String,
+    pub masterauth: String,
+}
+
+impl Default for ReplConfig {
+    fn default() -> Self {
+        Self {
+            port: 6379,
+            read_only: true,
+            backlog_size: 1024 * 1024,
+            ping_period: 10,
+            timeout: 60,
+            masteruser: String::new(),
+            masterauth: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Role {
+    #[default]
+    Master,
+    Replica {
+        host: String,
+        port: u16,
+    },
+}
+
+/// replid是当前复制历史的标识，replid2是切换master之前的历史，
+/// 在second_replid_offset之前的offset用replid2也能部分同步
+#[derive(Debug)]
+struct ReplIds {
+    replid: String,
+    replid2: String,
+    second_replid_offset: i64,
+}
+
+impl Default for ReplIds {
+    fn default() -> Self {
+        Self {
+            replid: random_replid(),
+            replid2: "0".repeat(40),
+            second_replid_offset: -1,
+        }
+    }
+}
+
+impl ReplIds {
+    /// 开始一段新的复制历史，旧的replid在offset之前依然有效
+    fn shift(&mut self, new_replid: String, offset: u64) {
+        self.replid2 = std::mem::replace(&mut self.replid, new_replid);
+        self.second_replid_offset = offset as i64 + 1;
+    }
+}
+
+/// master这边看到的一个replica连接
+#[derive(Debug)]
+struct ReplicaLink {
+    id: u64,
+    addr: Option<ConnAddr>,
+    port: u16,
+    sender: UnboundedSender<Bytes>,
+    /// 全量同步的RDB发送完之前是false
+    online: bool,
+    /// replica通过REPLCONF ACK报告的offset
+    ack_offset: u64,
+    /// replica通过REPLCONF ACK offset FACK aofoffset报告的已经写进AOF并fsync的offset
+    fack_offset: u64,
+    last_ack: u64,
+}
+
+/// INFO replication里一个replica的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaStatus {
+    pub ip: String,
+    pub port: u16,
+    pub state: &'static str,
+    pub offset: u64,
+    /// 距离上次收到ACK的秒数
+    pub lag: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ReplicationState {
+    config: RwLock<ReplConfig>,
+    role: RwLock<Role>,
+    ids: RwLock<ReplIds>,
+    /// master上是写入复制流的总字节数，replica上是已经处理的字节数
+    offset: AtomicU64,
+    /// 第一个replica连上来时创建，之后一直保留
+    backlog: Mutex<Option<Backlog>>,
+    replicas: Mutex<Vec<ReplicaLink>>,
+    /// 作为replica时连接master的后台任务
+    link: Mutex<Option<AbortHandle>>,
+    link_up: AtomicBool,
+    sync_in_progress: AtomicBool,
+    /// 最后一次收到master数据的时间(unix毫秒)
+    last_io: AtomicU64,
+    last_ping: AtomicU64,
+    sync_full: AtomicU64,
+    sync_partial_ok: AtomicU64,
+    sync_partial_err: AtomicU64,
+    /// 收到replica的ACK时唤醒WAIT/WAITAOF
+    acked: Notify,
+}
+
+impl ReplicationState {
+    pub fn config(&self) -> ReplConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_config(&self, config: ReplConfig) {
+        if let Some(backlog) = self.backlog_guard().as_mut() {
+            backlog.resize(config.backlog_size);
+        }
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    pub fn role(&self) -> Role {
+        self.role.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn is_master(&self) -> bool {
+        self.role() == Role::Master
+    }
+
+    /// 只读的replica拒绝客户端的写命令，master发来的命令不受影响
+    pub fn read_only(&self) -> bool {
+        !self.is_master() && self.config().read_only
+    }
+
+    pub fn replid(&self) -> String {
+        self.ids_guard().replid.clone()
+    }
+
+    pub fn replid2(&self) -> String {
+        self.ids_guard().replid2.clone()
+    }
+
+    pub fn second_replid_offset(&self) -> i64 {
+        self.ids_guard().second_replid_offset
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset.load(Ordering::SeqCst)
+    }
+
+    pub fn link_up(&self) -> bool {
+        self.link_up.load(Ordering::SeqCst)
+    }
+
+    pub fn sync_in_progress(&self) -> bool {
+        self.sync_in_progress.load(Ordering::SeqCst)
+    }
+
+    /// 距离上次收到master数据的秒数，从来没收到过时返回None
+    pub fn last_io_seconds_ago(&self) -> Option<u64> {
+        match self.last_io.load(Ordering::SeqCst) {
+            0 => None,
+            last => Some(now_ms().saturating_sub(last) / 1000),
+        }
+    }
+
+    /// (sync_full, sync_partial_ok, sync_partial_err)
+    pub fn sync_stats(&self) -> (u64, u64, u64) {
+        (
+            self.sync_full.load(Ordering::SeqCst),
+            self.sync_partial_ok.load(Ordering::SeqCst),
+            self.sync_partial_err.load(Ordering::SeqCst),
+        )
+    }
+
+    /// backlog第一个字节的offset和backlog里的字节数，还没有backlog时返回None
+    pub fn backlog_info(&self) -> Option<(u64, usize)> {
+        self.backlog_guard()
+            .as_ref()
+            .map(|backlog| (backlog.first_offset(), backlog.histlen()))
+    }
+
+    pub fn replicas(&self) -> Vec<ReplicaStatus> {
+        let now = now_ms();
+        self.replicas_guard()
+            .iter()
+            .map(|replica| ReplicaStatus {
+                ip: replica
+                    .addr
+                    .as_ref()
+                    .and_then(ConnAddr::ip)
+                    .map(|ip| ip.to_string())
+                    .unwrap_or_default(),
+                port: replica.port,
+                state: if replica.online {
+                    "online"
+                } else {
+                    "wait_bgsave"
+                },
+                offset: replica.ack_offset,
+                lag: now.saturating_sub(replica.last_ack) / 1000,
+            })
+            .collect()
+    }
+
+    /// ACK的offset不小于offset的replica数量，以及FACK的offset不小于offset的replica数量
+    pub fn acked_replicas(&self, offset: u64) -> (usize, usize) {
+        let replicas = self.replicas_guard();
+        let online = replicas.iter().filter(|r| r.online);
+        let acked = online.clone().filter(|r| r.ack_offset >= offset).count();
+        let fsynced = online.filter(|r| r.fack_offset >= offset).count();
+        (acked, fsynced)
+    }
+
+    /// 收到replica的ACK时被唤醒
+    pub fn ack_notified(&self) -> Notified<'_> {
+        self.acked.notified()
+    }
+
+    /// master上写命令需要进入复制流
+    pub fn is_feeding(&self) -> bool {
+        self.is_master() && self.backlog_guard().is_some()
+    }
+
+    /// 把写命令追加到复制流，调用方需要保证调用顺序和执行顺序一致
+    pub fn feed_commands(&self, commands: &[RespArray]) {
+        let mut data = Vec::new();
+        for cmd in commands {
+            data.extend(cmd.clone().encode());
+        }
+        self.feed(&data);
+    }
+
+    /// 数据进入backlog并发给所有replica，replica上用它把master的复制流原样转发给下一级
+    fn feed(&self, data: &[u8]) {
+        let mut backlog = self.backlog_guard();
+        let Some(backlog) = backlog.as_mut() else {
+            return;
+        };
+        backlog.push(data);
+        self.offset.store(backlog.end_offset(), Ordering::SeqCst);
+
+        let data = Bytes::copy_from_slice(data);
+        //发送失败说明连接已经断开，对应的replica会在连接结束时被移除
+        for replica in self.replicas_guard().iter() {
+            let _ = replica.sender.send(data.clone());
+        }
+    }
+
+    /// 创建backlog，之后写命令会进入复制流，offset开始增长
+    pub fn ensure_backlog(&self) {
+        let mut backlog = self.backlog_guard();
+        if backlog.is_none() {
+            *backlog = Some(Backlog::new(self.config().backlog_size, self.offset()));
+        }
+    }
+
+    /// 断开所有replica，它们会重新连接并重新同步
+    fn disconnect_replicas(&self) {
+        self.replicas_guard().clear();
+    }
+
+    fn stop_link(&self) {
+        if let Some(handle) = self.link.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            handle.abort();
+        }
+        self.link_up.store(false, Ordering::SeqCst);
+        self.sync_in_progress.store(false, Ordering::SeqCst);
+    }
+
+    fn ids_guard(&self) -> std::sync::RwLockReadGuard<'_, ReplIds> {
+        self.ids.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn ids_mut(&self) -> std::sync::RwLockWriteGuard<'_, ReplIds> {
+        self.ids.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn backlog_guard(&self) -> MutexGuard<'_, Option<Backlog>> {
+        self.backlog.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn replicas_guard(&self) -> MutexGuard<'_, Vec<ReplicaLink>> {
+        self.replicas.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// REPLICAOF host port：断开当前的master连接和所有replica，在后台连接新的master。
+/// 已经是这个master的replica时返回false
+pub fn replicate(backend: &Backend, host: String, port: u16) -> Result<bool, ReplicationError> {
+    let runtime = tokio::runtime::Handle::try_current().map_err(|_| ReplicationError::NoRuntime)?;
+    let repl = &backend.repl;
+    let role = Role::Replica {
+        host: host.clone(),
+        port,
+    };
+    if repl.role() == role {
+        return Ok(false);
+    }
+
+    repl.stop_link();
+    repl.disconnect_replicas();
+    *repl.role.write().unwrap_or_else(|e| e.into_inner()) = role;
+    info!("Connecting to MASTER {}:{}", host, port);
+    let task = runtime.spawn(replica::run(backend.clone(), host, port));
+    *repl.link.lock().unwrap_or_else(|e| e.into_inner()) = Some(task.abort_handle());
+    Ok(true)
+}
+
+/// REPLICAOF NO ONE：变回master，换一个新的replid，之前的复制历史通过replid2继续有效
+pub fn promote(backend: &Backend) {
+    let repl = &backend.repl;
+    if repl.is_master() {
+        return;
+    }
+    repl.stop_link();
+    *repl.role.write().unwrap_or_else(|e| e.into_inner()) = Role::Master;
+    repl.ids_mut().shift(random_replid(), repl.offset());
+    info!("MASTER MODE enabled");
+}
+
+/// master每隔repl-ping-replica-period秒往复制流里写一个PING，replica靠它判断连接是否还活着
+pub async fn cron(backend: Backend) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        let repl = &backend.repl;
+        if !repl.is_master() || repl.replicas_guard().is_empty() {
+            continue;
+        }
+        let now = now_ms();
+        let period = repl.config().ping_period * 1000;
+        if now.saturating_sub(repl.last_ping.load(Ordering::SeqCst)) >= period {
+            repl.last_ping.store(now, Ordering::SeqCst);
+            feed_locked(&backend, command_array(["PING"]));
+        }
+    }
+}
+
+/// WAIT/WAITAOF开始阻塞时调用，让所有replica立即回复REPLCONF ACK
+pub fn request_acks(backend: &Backend) {
+    let repl = &backend.repl;
+    if repl.is_feeding() && !repl.replicas_guard().is_empty() {
+        feed_locked(backend, command_array(["REPLCONF", "GETACK", "*"]));
+    }
+}
+
+/// 和写命令一样持锁，不会插进全量同步的快照和offset之间
+fn feed_locked(backend: &Backend, cmd: RespArray) {
+    let _guard = backend.shared_lock();
+    let _write = backend.write_lock();
+    backend.repl.feed_commands(&[cmd]);
+}
+
+fn random_replid() -> String {
+    let bytes = rand::thread_rng().gen::<[u8; 20]>();
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{call, calls, start_server, wait_for},
+        RespBulkString, RespFrame, RespInteger,
+    };
+
+    fn info(reply: RespFrame) -> String {
+        match reply {
+            RespFrame::BulkString(s) => String::from_utf8_lossy(s.as_ref()).into_owned(),
+            frame => panic!("unexpected INFO reply: {frame:?}"),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_replication() {
+        let (master, master_port) = start_server().await;
+        let (replica, replica_port) = start_server().await;
+        call(master_port, &["SET", "a", "1"]).await;
+        call(master_port, &["RPUSH", "l", "x", "y"]).await;
+
+        let port = master_port.to_string();
+        let reply = call(replica_port, &["REPLICAOF", "127.0.0.1", &port]).await;
+        assert_eq!(reply, RespFrame::SimpleString("OK".into()));
+        wait_for(|| replica.repl.link_up() && replica.get("a").is_some()).await;
+        assert_eq!(replica.dbsize(), 2);
+
+        //新的写命令通过复制流到达replica
+        call(master_port, &["SET", "b", "2"]).await;
+        wait_for(|| replica.get("b") == Some(RespBulkString::from("2").into())).await;
+        wait_for(|| master.repl.replicas().first().map(|r| r.offset) == Some(master.repl.offset()))
+            .await;
+
+        let reply = call(replica_port, &["SET", "c", "3"]).await;
+        assert!(matches!(reply, RespFrame::SimpleError(e) if e.contains("READONLY")));
+
+        //WAIT等到replica确认收到了这个连接的写命令；replica没有开启AOF，WAITAOF只能等到超时
+        let replies = calls(
+            master_port,
+            &[
+                &["SET", "e", "5"],
+                &["WAIT", "1", "5000"],
+                &["WAITAOF", "0", "1", "100"],
+            ],
+        )
+        .await;
+        assert_eq!(replies[1], RespInteger::from(1).into());
+        assert_eq!(
+            replies[2],
+            RespArray::new(vec![
+                RespInteger::from(0).into(),
+                RespInteger::from(0).into()
+            ])
+            .into()
+        );
+        assert!(replica.get("e").is_some());
+        let text = info(call(replica_port, &["INFO", "replication"]).await);
+        assert!(text.contains("role:slave"));
+        assert!(text.contains("master_link_status:up"));
+        assert!(text.contains(&format!("master_replid:{}", master.repl.replid())));
+        let text = info(call(master_port, &["INFO", "replication"]).await);
+        assert!(text.contains("connected_slaves:1"));
+        assert!(text.contains(&format!(
+            "slave0:ip=127.0.0.1,port={replica_port},state=online"
+        )));
+
+        //断开之后replica带着offset重连，缺失的数据从backlog补发
+        master.repl.disconnect_replicas();
+        call(master_port, &["SET", "d", "4"]).await;
+        wait_for(|| replica.get("d").is_some()).await;
+        assert_eq!(master.repl.sync_stats(), (1, 1, 0));
+        assert_eq!(replica.repl.offset(), master.repl.offset());
+
+        let reply = call(replica_port, &["REPLICAOF", "NO", "ONE"]).await;
+        assert_eq!(reply, RespFrame::SimpleString("OK".into()));
+        assert!(replica.repl.is_master());
+        let reply = call(replica_port, &["SET", "c", "3"]).await;
+        assert_eq!(reply, RespFrame::SimpleString("OK".into()));
+        assert_eq!(replica.repl.replid2(), master.repl.replid());
+    }
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_replication_with_masterauth() {
+        let (master, master_port) = start_server().await;
+        let (replica, replica_port) = start_server().await;
+        master.acl.set_requirepass("secret");
+        let rules = ["on", ">replpw", "+psync", "+replconf", "+ping"].map(String::from);
+        master.acl.set_user("repl", &rules).unwrap();
+        let set = &["SET", "a", "1"][..];
+        calls(master_port, &[&["AUTH", "secret"], set]).await;
+
+        //没有配置masterauth时握手失败
+        let port = master_port.to_string();
+        call(replica_port, &["REPLICAOF", "127.0.0.1", &port]).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!replica.repl.link_up());
+        assert_eq!(master.repl.sync_stats().0, 0);
+
+        let replies = calls(
+            replica_port,
+            &[
+                &[
+                    "CONFIG",
+                    "SET",
+                    "masteruser",
+                    "repl",
+                    "masterauth",
+                    "replpw",
+                ],
+                &["CONFIG", "GET", "masterauth"],
+            ],
+        )
+        .await;
+        assert_eq!(replies[0], RespFrame::SimpleString("OK".into()));
+        assert!(!format!("{:?}", replies[1]).contains("replpw"));
+        wait_for(|| replica.repl.link_up() && replica.get("a").is_some()).await;
+        calls(master_port, &[&["AUTH", "secret"], &["SET", "b", "2"]]).await;
+        wait_for(|| replica.get("b").is_some()).await;
+    }
+}
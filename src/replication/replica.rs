@@ -0,0 +1,257 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+use crate::{
+    aof::{self, command_array},
+    backend::now_ms,
+    cmd::command_name,
+    network::{request_handler, Session},
+    rdb, Backend, DecodeResp, EncodeResp, RespArray, RespError, RespFrame,
+};
+
+use super::{backlog::Backlog, ReplicationState};
+
+/// REPLICAOF之后在后台运行，连接断开后每秒重连一次，直到REPLICAOF NO ONE或者换了master
+pub(super) async fn run(backend: Backend, host: String, port: u16) {
+    loop {
+        if let Err(e) = sync_with_master(&backend, &host, port).await {
+            warn!("Connection with master {}:{} lost: {}", host, port, e);
+        }
+        backend.repl.link_up.store(false, Ordering::SeqCst);
+        backend.repl.sync_in_progress.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+async fn sync_with_master(backend: &Backend, host: &str, port: u16) -> Result<()> {
+    let repl = &backend.repl;
+    let config = repl.config();
+    let timeout = Duration::from_secs(config.timeout);
+    let stream = tokio::time::timeout(timeout, TcpStream::connect((host, port))).await??;
+    let mut link = MasterLink {
+        stream,
+        buf: BytesMut::new(),
+    };
+
+    //master设置了密码时PING和REPLCONF都需要先认证
+    match (config.masteruser.as_str(), config.masterauth.as_str()) {
+        (_, "") => {}
+        ("", pass) => {
+            link.command(&["AUTH", pass]).await?;
+        }
+        (user, pass) => {
+            link.command(&["AUTH", user, pass]).await?;
+        }
+    }
+    link.command(&["PING"]).await?;
+    link.command(&["REPLCONF", "listening-port", &config.port.to_string()])
+        .await?;
+    link.command(&["REPLCONF", "capa", "psync2"]).await?;
+
+    let (replid, offset) = repl.psync_args();
+    repl.sync_in_progress.store(true, Ordering::SeqCst);
+    let reply = link
+        .command(&["PSYNC", &replid, &offset.to_string()])
+        .await?;
+    let reply = reply.split_whitespace().collect::<Vec<_>>();
+    match reply.as_slice() {
+        ["FULLRESYNC", replid, offset] => {
+            let offset = offset.parse::<u64>()?;
+            info!("Full resync from master: {}:{}", replid, offset);
+            let data = link.read_rdb().await?;
+            let (backend, replid) = (backend.clone(), replid.to_string());
+            tokio::task::spawn_blocking(move || load_snapshot(&backend, &data, replid, offset))
+                .await??;
+        }
+        ["CONTINUE", rest @ ..] => {
+            info!("Successful partial resynchronization with master");
+            repl.continue_with(rest.first().copied());
+        }
+        _ => bail!("unexpected reply to PSYNC: {}", reply.join(" ")),
+    }
+    repl.sync_in_progress.store(false, Ordering::SeqCst);
+    repl.link_up.store(true, Ordering::SeqCst);
+    repl.last_io.store(now_ms(), Ordering::SeqCst);
+    info!("MASTER <-> REPLICA sync succeeded");
+
+    let mut session = Session::internal();
+    let mut ack = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            frame = link.read_frame() => {
+                let frame = frame?;
+                repl.last_io.store(now_ms(), Ordering::SeqCst);
+                if let Some(reply) = apply(backend, &mut session, frame) {
+                    link.send(reply).await?;
+                }
+            }
+            _ = ack.tick() => {
+                if repl.last_io_seconds_ago().is_some_and(|secs| secs > config.timeout) {
+                    bail!("MASTER timeout: no data nor PING received");
+                }
+                link.send(ack_command(backend)).await?;
+            }
+        }
+    }
+}
+
+/// 执行master发来的一条命令并把它原样放进自己的backlog，offset随之增加。
+/// REPLCONF GETACK需要立即回复当前的offset
+fn apply(backend: &Backend, session: &mut Session, frame: RespFrame) -> Option<RespArray> {
+    let raw = frame.clone().encode();
+    if let RespFrame::Arrays(array) = &frame {
+        if is_getack(array) {
+            let reply = ack_command(backend);
+            backend.repl.feed(&raw);
+            return Some(reply);
+        }
+    }
+    //先计入offset再执行，写进AOF的命令记录的是包含它自己的offset
+    backend.repl.feed(&raw);
+    request_handler(frame, session, backend);
+    None
+}
+
+fn is_getack(array: &RespArray) -> bool {
+    command_name(array).is_ok_and(|name| name == "replconf")
+        && matches!(array.get(1), Some(RespFrame::BulkString(arg)) if arg.as_ref().eq_ignore_ascii_case(b"getack"))
+}
+
+/// REPLCONF ACK offset FACK aofoffset，没有开启AOF时FACK是0，不会被WAITAOF计入
+fn ack_command(backend: &Backend) -> RespArray {
+    let fack = if backend.aof.is_enabled() {
+        backend.aof.fsynced_reploff()
+    } else {
+        0
+    };
+    command_array([
+        "REPLCONF",
+        "ACK",
+        &backend.repl.offset().to_string(),
+        "FACK",
+        &fack.to_string(),
+    ])
+}
+
+/// 用master的RDB替换掉本地的所有数据，开启了AOF时顺便重写AOF
+fn load_snapshot(backend: &Backend, data: &[u8], replid: String, offset: u64) -> Result<()> {
+    let _guard = backend.exclusive_lock();
+    backend.flushdb();
+    let report = rdb::load_data(backend, data)?;
+    info!("MASTER <-> REPLICA sync: loaded {} keys", report.loaded);
+    backend.repl.reset(replid, offset);
+    if backend.aof.is_enabled() {
+        if let Err(e) = aof::bgrewriteaof(backend) {
+            warn!(
+                "Background AOF rewrite after full sync failed to start: {}",
+                e
+            );
+        }
+    }
+    Ok(())
+}
+
+impl ReplicationState {
+    /// 有backlog时可以尝试从上次的位置部分同步，否则只能全量同步
+    fn psync_args(&self) -> (String, i64) {
+        if self.backlog_guard().is_some() {
+            (self.replid(), self.offset() as i64 + 1)
+        } else {
+            ("?".into(), -1)
+        }
+    }
+
+    /// 全量同步完成：复制历史和offset都换成master的，下级replica也需要重新全量同步
+    fn reset(&self, replid: String, offset: u64) {
+        {
+            let mut ids = self.ids_mut();
+            ids.replid = replid;
+            ids.replid2 = "0".repeat(40);
+            ids.second_replid_offset = -1;
+        }
+        self.offset.store(offset, Ordering::SeqCst);
+        *self.backlog_guard() = Some(Backlog::new(self.config().backlog_size, offset));
+        self.disconnect_replicas();
+    }
+
+    /// 部分同步成功，master换了replid时之前的历史通过replid2继续有效
+    fn continue_with(&self, replid: Option<&str>) {
+        self.ensure_backlog();
+        if let Some(replid) = replid {
+            let mut ids = self.ids_mut();
+            if ids.replid != replid {
+                ids.shift(replid.to_string(), self.offset());
+            }
+        }
+    }
+}
+
+/// 到master的连接。全量同步的RDB不是合法的RESP，所以这里自己管理读缓冲
+struct MasterLink {
+    stream: TcpStream,
+    buf: BytesMut,
+}
+
+impl MasterLink {
+    async fn send(&mut self, cmd: RespArray) -> Result<()> {
+        self.stream.write_all(&cmd.encode()).await?;
+        Ok(())
+    }
+
+    /// 握手阶段的命令，master回复错误时返回Err
+    async fn command(&mut self, args: &[&str]) -> Result<String> {
+        self.send(command_array(args.iter().copied())).await?;
+        match self.read_frame().await? {
+            RespFrame::SimpleString(s) => Ok(s.0),
+            RespFrame::SimpleError(e) => Err(anyhow!("{} replied: {}", args.join(" "), e.0)),
+            frame => Err(anyhow!("{} replied: {:?}", args.join(" "), frame)),
+        }
+    }
+
+    /// 读缓冲里的数据不够一个完整的frame时才会去读socket，所以可以安全地放在select!里
+    async fn read_frame(&mut self) -> Result<RespFrame> {
+        loop {
+            match RespFrame::decode(&mut self.buf) {
+                Ok(frame) => return Ok(frame),
+                Err(RespError::NotComplete) => self.fill().await?,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// 全量同步的RDB：$<len>\r\n之后跟着len个字节，结尾没有\r\n
+    async fn read_rdb(&mut self) -> Result<Vec<u8>> {
+        let len = loop {
+            //master在生成RDB期间会发送单独的\n保持连接
+            while self.buf.first() == Some(&b'\n') {
+                self.buf.advance(1);
+            }
+            if let Some(end) = self.buf.windows(2).position(|w| w == b"\r\n") {
+                let line = self.buf.split_to(end + 2);
+                break std::str::from_utf8(&line[..end])
+                    .ok()
+                    .and_then(|line| line.strip_prefix('$'))
+                    .and_then(|len| len.parse::<usize>().ok())
+                    .ok_or_else(|| anyhow!("bad protocol from master reading the RDB payload"))?;
+            }
+            self.fill().await?;
+        };
+        while self.buf.len() < len {
+            self.fill().await?;
+        }
+        Ok(self.buf.split_to(len).to_vec())
+    }
+
+    async fn fill(&mut self) -> Result<()> {
+        if self.stream.read_buf(&mut self.buf).await? == 0 {
+            bail!("connection closed by master");
+        }
+        Ok(())
+    }
+}
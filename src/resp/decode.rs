@@ -1,7 +1,6 @@
 use bytes::{Buf, BytesMut};
 use std::iter::Peekable;
 use std::slice::Iter;
-use tracing::instrument;
 
 use super::*;
 
@@ -28,6 +27,10 @@ Doubles: ,[<+|->]<integral>[.<fractional>][<E|e>[sign]<exponent>]\r\n
 Bulk errors: !<length>\r\n<error>\r\n
 Maps: %<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n>
 Sets: ~<number-of-elements>\r\n<element-1>...<element-n>
+Big numbers: ([+|-]<number>\r\n
+Verbatim strings: =<length>\r\n<encoding>:<data>\r\n
+Attributes: |<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>
+Pushes: ><number-of-elements>\r\n<element-1>...<element-n>
 */
 
 impl DecodeResp for RespFrame {
@@ -90,6 +93,22 @@ impl DecodeResp for RespFrame {
                 let frame = RespSets::decode(buf)?;
                 Ok(frame.into())
             }
+            Some(&&LEFT_PARENTHESIS) => {
+                let frame = RespBigNumber::decode(buf)?;
+                Ok(frame.into())
+            }
+            Some(&&EQUAL_SIGN) => {
+                let frame = RespVerbatimString::decode(buf)?;
+                Ok(frame.into())
+            }
+            Some(&&VERTICAL_BAR) => {
+                let frame = RespAttributes::decode(buf)?;
+                Ok(frame.into())
+            }
+            Some(&&GREATER_THAN_SIGN) => {
+                let frame = RespPush::decode(buf)?;
+                Ok(frame.into())
+            }
             None => Err(RespError::NotComplete),
             _ => Err(RespError::InvalidFrameType(format!("{peek_first:?}"))),
         }
@@ -98,8 +117,6 @@ impl DecodeResp for RespFrame {
     fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
         let mut peekable = buf.iter().peekable();
         let peek_first = peekable.peek();
-        let mut bytes = BytesMut::new();
-        bytes.extend_from_slice(buf);
 
         match peek_first {
             Some(&&POSITIVE_SIGN) => Ok(SimpleString::expect_length(buf)?),
@@ -110,15 +127,14 @@ impl DecodeResp for RespFrame {
                 Err(RespError::NotComplete) => Err(RespError::NotComplete),
                 Err(_) => Ok(RespNullBulkString::expect_length(buf)?),
             },
-            Some(&&ASTERISK) => match RespArray::decode(&mut bytes) {
-                Ok(_) => Ok(RespArray::expect_length(buf)?),
+            //只看头部推算长度，不能先解码一遍，否则嵌套的数组每一层都会重复解码
+            Some(&&ASTERISK) => match RespArray::expect_length(buf) {
+                Ok(len) => Ok(len),
                 Err(RespError::NotComplete) => Err(RespError::NotComplete),
-                Err(_) => match RespNullArray::decode(&mut bytes) {
-                    Ok(_) => Ok(RespNullArray::expect_length(buf)?),
-                    _ => Err(RespError::InvalidFrameType(
-                        "neither RespArray nor RespNullArray".into(),
-                    )),
-                },
+                Err(_) if buf.starts_with(b"*-1\r\n") => Ok(RespNullArray::expect_length(buf)?),
+                Err(_) => Err(RespError::InvalidFrameType(
+                    "neither RespArray nor RespNullArray".into(),
+                )),
             },
             Some(&&UNDERLINE) => Ok(RespNull::expect_length(buf)?),
             Some(&&POND_SIGN) => Ok(RespBooleans::expect_length(buf)?),
@@ -126,6 +142,10 @@ impl DecodeResp for RespFrame {
             Some(&&EXCLAMATION_MARK) => Ok(RespBulkErrors::expect_length(buf)?),
             Some(&&PERCENT_SIGN) => Ok(RespMaps::expect_length(buf)?),
             Some(&&TILDE_SIGN) => Ok(RespSets::expect_length(buf)?),
+            Some(&&LEFT_PARENTHESIS) => Ok(RespBigNumber::expect_length(buf)?),
+            Some(&&EQUAL_SIGN) => Ok(RespVerbatimString::expect_length(buf)?),
+            Some(&&VERTICAL_BAR) => Ok(RespAttributes::expect_length(buf)?),
+            Some(&&GREATER_THAN_SIGN) => Ok(RespPush::expect_length(buf)?),
             None => Err(RespError::NotComplete),
             _ => Err(RespError::InvalidFrameType(format!("{peek_first:?}"))),
         }
@@ -136,7 +156,6 @@ impl DecodeResp for RespFrame {
 impl DecodeResp for SimpleString {
     const PREFIX: u8 = POSITIVE_SIGN;
 
-    #[instrument]
     fn decode(buf: &mut BytesMut) -> Result<Self, RespError> {
         let end = extract_simple_frame_data(buf, Self::PREFIX, 3)?;
         let data = buf.split_to(end + CRLF.len());
@@ -226,9 +245,7 @@ impl DecodeResp for RespArray {
 
     fn decode(buf: &mut BytesMut) -> Result<Self, RespError> {
         let (end, element_count) = parse_length(buf, Self::PREFIX)?;
-        println!("end:{end}, element_count:{element_count}");
         let total_len = calc_total_length(buf, end, element_count, Self::PREFIX)?;
-        println!("total_len:{total_len}");
         if buf.len() < total_len {
             return Err(RespError::NotComplete);
         }
@@ -249,6 +266,32 @@ impl DecodeResp for RespArray {
     }
 }
 
+///Pushes: ><number-of-elements>\r\n<element-1>...<element-n>
+impl DecodeResp for RespPush {
+    const PREFIX: u8 = GREATER_THAN_SIGN;
+
+    fn decode(buf: &mut BytesMut) -> Result<Self, RespError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        let total_len = Self::expect_length(buf)?;
+        if buf.len() < total_len {
+            return Err(RespError::NotComplete);
+        }
+
+        buf.advance(end + CRLF.len());
+        let mut vec = Vec::with_capacity(len);
+        for _ in 0..len {
+            vec.push(RespFrame::decode(buf)?);
+        }
+
+        Ok(RespPush::new(vec))
+    }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        calc_total_length(buf, end, len, Self::PREFIX)
+    }
+}
+
 ///Null arrays: *-1\r\n
 impl DecodeResp for RespNullArray {
     const PREFIX: u8 = ASTERISK;
@@ -341,11 +384,11 @@ impl DecodeResp for RespMaps {
         }
 
         buf.advance(end + CRLF.len());
-        let mut map = BTreeMap::new();
+        let mut map = IndexMap::with_capacity(len);
         for _ in 0..len {
-            let key = SimpleString::decode(buf)?;
+            let key = RespFrame::decode(buf)?;
             let value = RespFrame::decode(buf)?;
-            map.insert(key.0, value);
+            map.insert(key, value);
         }
 
         Ok(RespMaps::from(map))
@@ -370,13 +413,13 @@ impl DecodeResp for RespSets {
         }
 
         buf.advance(end + CRLF.len());
-        let mut vec = Vec::with_capacity(len);
+        let mut set = IndexSet::with_capacity(len);
         for _ in 0..len {
             let frame = RespFrame::decode(buf)?;
-            vec.push(frame);
+            set.insert(frame);
         }
 
-        Ok(RespSets::from(vec))
+        Ok(RespSets(set))
     }
 
     fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
@@ -386,6 +429,85 @@ impl DecodeResp for RespSets {
     }
 }
 
+///Big numbers: ([+|-]<number>\r\n
+///              (3492890328409238509324850943850943825024385\r\n
+impl DecodeResp for RespBigNumber {
+    const PREFIX: u8 = LEFT_PARENTHESIS;
+
+    fn decode(buf: &mut BytesMut) -> Result<Self, RespError> {
+        let end = extract_simple_frame_data(buf, Self::PREFIX, 3)?;
+        let data = buf.split_to(end + CRLF.len());
+        let msg = String::from_utf8_lossy(&data[LEN_ONE..end]);
+
+        RespBigNumber::new(msg)
+    }
+}
+
+///Verbatim strings: =<length>\r\n<encoding>:<data>\r\n
+///              =15\r\ntxt:Some string\r\n
+impl DecodeResp for RespVerbatimString {
+    const PREFIX: u8 = EQUAL_SIGN;
+
+    fn decode(buf: &mut BytesMut) -> Result<Self, RespError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        let remained = &buf[end + CRLF.len()..];
+        if remained.len() < len + CRLF.len() {
+            return Err(RespError::NotComplete);
+        }
+        if len < VERBATIM_ENCODING_LEN + 1 || remained[VERBATIM_ENCODING_LEN] != COLON {
+            return Err(RespError::InvalidFrameType(format!(
+                "verbatim string should start with <encoding>:, got {:?}",
+                String::from_utf8_lossy(&remained[..len])
+            )));
+        }
+
+        buf.advance(end + CRLF.len());
+
+        let data = buf.split_to(len + CRLF.len());
+        let encoding = String::from_utf8_lossy(&data[..VERBATIM_ENCODING_LEN]);
+        let msg = &data[VERBATIM_ENCODING_LEN + 1..len];
+
+        RespVerbatimString::new(encoding, msg)
+    }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        Ok(end + CRLF.len() + len + CRLF.len())
+    }
+}
+
+///Attributes: |<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>
+///              |1\r\n+ttl\r\n:3600\r\n$5\r\nhello\r\n
+impl DecodeResp for RespAttributes {
+    const PREFIX: u8 = VERTICAL_BAR;
+
+    fn decode(buf: &mut BytesMut) -> Result<Self, RespError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        let total_len = Self::expect_length(buf)?;
+        if buf.len() < total_len {
+            return Err(RespError::NotComplete);
+        }
+
+        buf.advance(end + CRLF.len());
+        let mut map = IndexMap::with_capacity(len);
+        for _ in 0..len {
+            let key = RespFrame::decode(buf)?;
+            let value = RespFrame::decode(buf)?;
+            map.insert(key, value);
+        }
+        let data = RespFrame::decode(buf)?;
+
+        Ok(RespAttributes::new(RespMaps::from(map), data))
+    }
+
+    fn expect_length(buf: &[u8]) -> Result<usize, RespError> {
+        let (end, len) = parse_length(buf, Self::PREFIX)?;
+        let attributes_len = calc_total_length(buf, end, len, Self::PREFIX)?;
+        let data_len = RespFrame::expect_length(&buf[attributes_len..])?;
+        Ok(attributes_len + data_len)
+    }
+}
+
 fn extract_fixed_data(
     buf: &mut BytesMut,
     expect: &str,
@@ -457,6 +579,7 @@ Arrays: *<number-of-elements>\r\n<element-1>...<element-n>
                 *3\r\n:1\r\n:2\r\n:3\r\n
 Sets: ~<number-of-elements>\r\n<element-1>...<element-n>
 Maps: %<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n>
+Attributes: |<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n>
 */
 fn calc_total_length(
     buf: &[u8],
@@ -464,26 +587,28 @@ fn calc_total_length(
     element_count: usize,
     prefix: u8,
 ) -> Result<usize, RespError> {
+    //元素长度是按头部推算出来的，数据可能还没收全，切片前要先检查边界
+    let rest = |total_len: usize| buf.get(total_len..).ok_or(RespError::NotComplete);
     let mut total_len = end + CRLF.len();
-    let mut data = &buf[total_len..];
+    let mut data = rest(total_len)?;
     match prefix {
-        ASTERISK | TILDE_SIGN => {
+        ASTERISK | TILDE_SIGN | GREATER_THAN_SIGN => {
             for _ in 0..element_count {
                 let len = RespFrame::expect_length(data)?;
                 total_len += len;
-                data = &buf[total_len..];
+                data = rest(total_len)?;
             }
             Ok(total_len)
         }
-        PERCENT_SIGN => {
+        PERCENT_SIGN | VERTICAL_BAR => {
             for _ in 0..element_count {
-                let key_len = SimpleString::expect_length(data)?;
+                let key_len = RespFrame::expect_length(data)?;
                 total_len += key_len;
-                data = &buf[total_len..];
+                data = rest(total_len)?;
 
                 let value_len = RespFrame::expect_length(data)?;
                 total_len += value_len;
-                data = &buf[total_len..];
+                data = rest(total_len)?;
             }
             Ok(total_len)
         }
@@ -505,16 +630,15 @@ fn calc_total_length(
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
-
     use anyhow::Result;
     use bytes::{BufMut, BytesMut};
 
     use crate::resp::{DecodeResp, RespArray, RespBulkString, RespError, RespFrame};
 
     use super::{
-        RespBooleans, RespBulkErrors, RespDoubles, RespInteger, RespMaps, RespNull, RespNullArray,
-        RespNullBulkString, RespSets, SimpleError, SimpleString,
+        EncodeResp, RespAttributes, RespBigNumber, RespBooleans, RespBulkErrors, RespDoubles,
+        RespInteger, RespMaps, RespNull, RespNullArray, RespNullBulkString, RespPush, RespSets,
+        RespVerbatimString, SimpleError, SimpleString,
     };
 
     ///Simple strings: +OK\r\n
@@ -617,6 +741,11 @@ mod tests {
         let ra = RespArray::from(vec![frame1, frame2]);
         assert_eq!(resp_array, ra);
 
+        //中间元素的数据还没收全，不能越界
+        bytesmut.extend_from_slice(b"*3\r\n$3\r\nset\r\n$1\r\nc");
+        let decoded = RespFrame::decode(&mut bytesmut);
+        assert_eq!(decoded.unwrap_err(), RespError::NotComplete);
+
         Ok(())
     }
 
@@ -710,15 +839,40 @@ mod tests {
         let value2: RespInteger = 2.into();
         let value2: RespFrame = value2.into();
 
-        let mut map = RespMaps::new(BTreeMap::new());
-        map.insert(key1.into(), value1);
-        map.insert(key2.into(), value2);
+        let mut map = RespMaps::default();
+        map.insert(SimpleString::from(key1).into(), value1);
+        map.insert(SimpleString::from(key2).into(), value2);
 
         assert_eq!(decoded, map);
 
         Ok(())
     }
 
+    #[test]
+    fn test_decode_resp_map_with_any_key() -> Result<()> {
+        let data = b"%3\r\n$6\r\nserver\r\n$5\r\nredis\r\n:+1\r\n#t\r\n*1\r\n,1.5\r\n_\r\n";
+        let mut bytes_mut = BytesMut::from(&data[..]);
+        let decoded = RespMaps::decode(&mut bytes_mut)?;
+
+        let keys = decoded.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                RespBulkString::from("server").into(),
+                RespInteger::from(1).into(),
+                RespArray::new(vec![RespDoubles::new(1.5).into()]).into(),
+            ]
+        );
+        assert_eq!(
+            decoded.get(&RespFrame::from(RespInteger::from(1))),
+            Some(&RespBooleans::new(true).into())
+        );
+        //保持插入顺序，编码结果和输入一致
+        assert_eq!(decoded.encode(), data);
+
+        Ok(())
+    }
+
     /// Sets: ~<number-of-elements>\r\n<element-1>...<element-n>
     #[test]
     fn test_decode_resp_set() -> Result<()> {
@@ -733,6 +887,120 @@ mod tests {
             ])
         );
 
+        let mut bytes_mut = BytesMut::from(&b"~3\r\n:+1\r\n:+2\r\n:+1\r\n"[..]);
+        let decoded = RespSets::decode(&mut bytes_mut)?;
+        assert_eq!(decoded.len(), 2);
+        assert!(decoded.contains(&RespFrame::from(RespInteger::from(2))));
+
+        Ok(())
+    }
+
+    ///Pushes: ><number-of-elements>\r\n<element-1>...<element-n>
+    #[test]
+    fn test_decode_resp_push() -> Result<()> {
+        let data = b">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nkey\r\n";
+        let mut bytes_mut = BytesMut::from(&data[..]);
+        let decoded = RespFrame::decode(&mut bytes_mut)?;
+        assert_eq!(
+            decoded,
+            RespPush::new(vec![
+                RespBulkString::from("invalidate").into(),
+                RespArray::new(vec![RespBulkString::from("key").into()]).into(),
+            ])
+            .into()
+        );
+        assert!(bytes_mut.is_empty());
+
+        let mut bytes_mut = BytesMut::from(&data[..data.len() - 3]);
+        assert_eq!(
+            RespPush::decode(&mut bytes_mut),
+            Err(RespError::NotComplete)
+        );
+
+        Ok(())
+    }
+
+    ///Big numbers: ([+|-]<number>\r\n
+    #[test]
+    fn test_decode_big_number() -> Result<()> {
+        let mut bytes_mut =
+            BytesMut::from(&b"(3492890328409238509324850943850943825024385\r\n"[..]);
+        let decoded = RespBigNumber::decode(&mut bytes_mut)?;
+        assert_eq!(
+            decoded,
+            RespBigNumber::new("3492890328409238509324850943850943825024385")?
+        );
+        assert_eq!(
+            decoded.encode(),
+            b"(3492890328409238509324850943850943825024385\r\n"
+        );
+
+        bytes_mut.extend_from_slice(b"(-12\r\n");
+        let decoded = RespFrame::decode(&mut bytes_mut)?;
+        assert_eq!(decoded, RespBigNumber::new("-12")?.into());
+
+        bytes_mut.extend_from_slice(b"(1.5\r\n");
+        assert!(RespBigNumber::decode(&mut bytes_mut).is_err());
+
+        Ok(())
+    }
+
+    ///Verbatim strings: =<length>\r\n<encoding>:<data>\r\n
+    #[test]
+    fn test_decode_verbatim_string() -> Result<()> {
+        let mut bytes_mut = BytesMut::from(&b"=15\r\ntxt:Some string\r\n"[..]);
+        let decoded = RespVerbatimString::decode(&mut bytes_mut)?;
+        assert_eq!(decoded, RespVerbatimString::text("Some string"));
+        assert_eq!(decoded.encode(), b"=15\r\ntxt:Some string\r\n");
+
+        bytes_mut.extend_from_slice(b"=11\r\nmkd:# ti");
+        let decoded = RespFrame::decode(&mut bytes_mut);
+        assert_eq!(decoded.unwrap_err(), RespError::NotComplete);
+
+        bytes_mut.extend_from_slice(b"tle\r\n");
+        let decoded = RespFrame::decode(&mut bytes_mut)?;
+        assert_eq!(decoded, RespVerbatimString::markdown("# title").into());
+
+        let mut bytes_mut = BytesMut::from(&b"=5\r\ntxt-a\r\n"[..]);
+        assert!(RespVerbatimString::decode(&mut bytes_mut).is_err());
+
+        Ok(())
+    }
+
+    ///Attributes: |<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>
+    #[test]
+    fn test_decode_attributes() -> Result<()> {
+        let data = b"|1\r\n+key-popularity\r\n%2\r\n+a\r\n,0.1923\r\n+b\r\n,0.0012\r\n*2\r\n:+2039123\r\n:+9543892\r\n";
+        let mut bytes_mut = BytesMut::from(&data[..]);
+        assert_eq!(RespFrame::expect_length(&bytes_mut)?, data.len());
+        let decoded = RespAttributes::decode(&mut bytes_mut)?;
+
+        let mut popularity = RespMaps::default();
+        popularity.insert(
+            SimpleString::from("a").into(),
+            RespDoubles::new(0.1923).into(),
+        );
+        popularity.insert(
+            SimpleString::from("b").into(),
+            RespDoubles::new(0.0012).into(),
+        );
+        let mut attributes = RespMaps::default();
+        attributes.insert(
+            SimpleString::from("key-popularity").into(),
+            popularity.into(),
+        );
+        let reply = RespArray::new(vec![
+            RespInteger::from(2039123).into(),
+            RespInteger::from(9543892).into(),
+        ]);
+
+        assert_eq!(decoded, RespAttributes::new(attributes, reply));
+        assert_eq!(decoded.encode(), data);
+
+        bytes_mut.extend_from_slice(&data[..data.len() - 4]);
+        let decoded = RespFrame::decode(&mut bytes_mut);
+        assert_eq!(decoded.unwrap_err(), RespError::NotComplete);
+
         Ok(())
     }
 
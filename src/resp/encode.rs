@@ -23,6 +23,10 @@ Doubles: ,[<+|->]<integral>[.<fractional>][<E|e>[sign]<exponent>]\r\n
 Bulk errors: !<length>\r\n<error>\r\n
 Maps: %<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n>
 Sets: ~<number-of-elements>\r\n<element-1>...<element-n>
+Big numbers: ([+|-]<number>\r\n
+Verbatim strings: =<length>\r\n<encoding>:<data>\r\n
+Attributes: |<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>
+Pushes: ><number-of-elements>\r\n<element-1>...<element-n>
 */
 
 ///+OK\r\n
@@ -39,10 +43,9 @@ impl EncodeResp for SimpleString {
 impl EncodeResp for SimpleError {
     fn encode(self) -> Vec<u8> {
         let msg_len = self.len();
-        let mut ret = Vec::with_capacity(msg_len + 9);
+        //错误码(ERR、MOVED、NOAUTH等)由构造错误的地方写在消息开头，客户端按第一个单词识别
+        let mut ret = Vec::with_capacity(msg_len + 3);
         ret.push(NEGATIVE_SIGN);
-        ret.extend_from_slice(ERROR);
-        ret.push(WHITE_SPACE);
         ret.extend_from_slice(self.as_bytes());
         ret.extend_from_slice(CRLF);
 
@@ -123,6 +126,22 @@ impl EncodeResp for RespArray {
     }
 }
 
+///Pushes: ><number-of-elements>\r\n<element-1>...<element-n>
+///         >2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nkey\r\n
+impl EncodeResp for RespPush {
+    fn encode(self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(MAX_BUF_SIZE);
+        ret.push(GREATER_THAN_SIGN);
+        ret.extend_from_slice(self.len().to_string().as_bytes());
+        ret.extend_from_slice(CRLF);
+        for x in self.0 {
+            ret.extend_from_slice(x.encode().as_slice());
+        }
+
+        ret
+    }
+}
+
 ///Null arrays: *-1\r\n
 impl EncodeResp for RespNullArray {
     fn encode(self) -> Vec<u8> {
@@ -217,8 +236,7 @@ impl EncodeResp for RespMaps {
         ret.extend_from_slice(msg_len.to_string().as_bytes());
         ret.extend_from_slice(CRLF);
         for (key, value) in self.0 {
-            ret.extend_from_slice(SimpleString::from(key).encode().as_slice());
-            ret.push(COLON);
+            ret.extend_from_slice(key.encode().as_slice());
             ret.extend_from_slice(value.encode().as_slice());
         }
 
@@ -232,6 +250,7 @@ impl EncodeResp for RespSets {
         let mut ret = Vec::with_capacity(MAX_BUF_SIZE);
         ret.push(TILDE_SIGN);
         ret.extend_from_slice(msg_len.to_string().as_bytes());
+        ret.extend_from_slice(CRLF);
         for e in self.0 {
             ret.extend_from_slice(e.encode().as_slice());
         }
@@ -240,6 +259,57 @@ impl EncodeResp for RespSets {
     }
 }
 
+///Big numbers: ([+|-]<number>\r\n
+///             (3492890328409238509324850943850943825024385\r\n
+impl EncodeResp for RespBigNumber {
+    fn encode(self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(1 + self.len() + 2);
+        ret.push(LEFT_PARENTHESIS);
+        ret.extend_from_slice(self.as_bytes());
+        ret.extend_from_slice(CRLF);
+
+        ret
+    }
+}
+
+///Verbatim strings: =<length>\r\n<encoding>:<data>\r\n
+///             =15\r\ntxt:Some string\r\n
+impl EncodeResp for RespVerbatimString {
+    fn encode(self) -> Vec<u8> {
+        let msg_len = self.encoding.len() + 1 + self.data.len();
+        let mut ret = Vec::with_capacity(1 + 8 + 2 + msg_len + 2);
+        ret.push(EQUAL_SIGN);
+        ret.extend_from_slice(msg_len.to_string().as_bytes());
+        ret.extend_from_slice(CRLF);
+        ret.extend_from_slice(self.encoding.as_bytes());
+        ret.push(COLON);
+        ret.extend_from_slice(&self.data);
+        ret.extend_from_slice(CRLF);
+
+        ret
+    }
+}
+
+///Attributes: |<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>
+///             |1\r\n+ttl\r\n:+3600\r\n$5\r\nhello\r\n
+impl EncodeResp for RespAttributes {
+    fn encode(self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(MAX_BUF_SIZE);
+        let msg_len = self.attributes.len();
+
+        ret.push(VERTICAL_BAR);
+        ret.extend_from_slice(msg_len.to_string().as_bytes());
+        ret.extend_from_slice(CRLF);
+        for (key, value) in self.attributes.0 {
+            ret.extend_from_slice(key.encode().as_slice());
+            ret.extend_from_slice(value.encode().as_slice());
+        }
+        ret.extend_from_slice(self.data.encode().as_slice());
+
+        ret
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -251,12 +321,12 @@ mod tests {
         assert_eq!(frame.encode(), b"+OK\r\n");
     }
 
-    ///-Error message\r\n
+    ///-ERR message\r\n
     #[test]
     fn encode_simple_error_should_work() {
-        let se: SimpleError = "Error".into();
+        let se: SimpleError = "ERR unknown command 'asdf'".into();
         let frame: RespFrame = se.into();
-        assert_eq!(frame.encode(), b"-Error Error\r\n");
+        assert_eq!(frame.encode(), b"-ERR unknown command 'asdf'\r\n");
     }
 
     ///Integers: :[<+|->]<value>\r\n
@@ -408,19 +478,20 @@ mod tests {
     ///         :2\r\n
     #[test]
     fn encode_resp_maps_should_work() {
-        let key1: String = "hello".into();
+        let key1: SimpleString = "hello".into();
+        let key1: RespFrame = key1.into();
         let value1: SimpleString = "world".into();
         let value1: RespFrame = value1.into();
         let mut resp_map = RespMaps::default();
         resp_map.insert(key1, value1);
         let frame: RespFrame = resp_map.into();
 
-        assert_eq!(frame.encode(), b"%1\r\n+hello\r\n:+world\r\n");
+        assert_eq!(frame.encode(), b"%1\r\n+hello\r\n+world\r\n");
 
-        let key2 = "A".to_string();
+        let key2: RespFrame = SimpleString::from("B").into();
         let value2: RespDoubles = 1.23.into();
         let value2: RespFrame = value2.into();
-        let key3 = "B".to_string();
+        let key3: RespFrame = SimpleString::from("A").into();
         let value3: RespDoubles = (-1.23).into();
         let value3: RespFrame = value3.into();
         let mut resp_map = RespMaps::default();
@@ -430,7 +501,17 @@ mod tests {
 
         assert_eq!(
             String::from_utf8_lossy(&frame.encode()),
-            "%2\r\n+A\r\n:,1.23\r\n+B\r\n:,-1.23\r\n"
+            "%2\r\n+B\r\n,1.23\r\n+A\r\n,-1.23\r\n"
+        );
+
+        let mut resp_map = RespMaps::default();
+        resp_map.insert(RespInteger::from(1).into(), RespBooleans::new(true).into());
+        resp_map.insert(RespBulkString::from("key").into(), RespNull.into());
+        let frame: RespFrame = resp_map.into();
+
+        assert_eq!(
+            String::from_utf8_lossy(&frame.encode()),
+            "%2\r\n:+1\r\n#t\r\n$3\r\nkey\r\n_\r\n"
         );
     }
 
@@ -441,24 +522,86 @@ mod tests {
         let element1: RespFrame = element1.into();
         let element2: RespInteger = 42.into();
         let element2: RespFrame = element2.into();
-        let resp_set = RespSets(vec![element1, element2]);
+        let resp_set = RespSets::new(vec![element1, element2]);
         let frame: RespFrame = resp_set.into();
 
         assert_eq!(
             String::from_utf8_lossy(&frame.encode()),
-            "~2+element1\r\n:+42\r\n"
+            "~2\r\n+element1\r\n:+42\r\n"
         );
 
         let element3: RespDoubles = 3.33.into();
         let element3: RespFrame = element3.into();
         let element4: SimpleError = "error".into();
         let element4: RespFrame = element4.into();
-        let resp_set = RespSets(vec![element3, element4]);
+        let resp_set = RespSets::new(vec![element3.clone(), element4, element3]);
         let frame: RespFrame = resp_set.into();
 
         assert_eq!(
             String::from_utf8_lossy(&frame.encode()),
-            "~2,3.33\r\n-Error error\r\n"
+            "~2\r\n,3.33\r\n-error\r\n"
+        );
+    }
+
+    ///Pushes: ><number-of-elements>\r\n<element-1>...<element-n>
+    #[test]
+    fn encode_resp_push_should_work() {
+        let keys = RespArray::new(vec![RespBulkString::from("key").into()]);
+        let frame: RespFrame =
+            RespPush::new(vec![RespBulkString::from("invalidate").into(), keys.into()]).into();
+
+        assert_eq!(
+            String::from_utf8_lossy(&frame.clone().encode()),
+            ">2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nkey\r\n"
+        );
+        //RESP2没有推送类型，降级为数组
+        assert_eq!(
+            String::from_utf8_lossy(&frame.downgrade().encode()),
+            "*2\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nkey\r\n"
+        );
+    }
+
+    ///Big numbers: ([+|-]<number>\r\n
+    #[test]
+    fn encode_resp_big_number_should_work() -> Result<(), RespError> {
+        let frame: RespFrame =
+            RespBigNumber::new("3492890328409238509324850943850943825024385")?.into();
+        assert_eq!(
+            frame.encode(),
+            b"(3492890328409238509324850943850943825024385\r\n"
+        );
+
+        assert!(RespBigNumber::new("12a").is_err());
+        Ok(())
+    }
+
+    ///Verbatim strings: =<length>\r\n<encoding>:<data>\r\n
+    #[test]
+    fn encode_resp_verbatim_string_should_work() -> Result<(), RespError> {
+        let frame: RespFrame = RespVerbatimString::text("Some string").into();
+        assert_eq!(frame.encode(), b"=15\r\ntxt:Some string\r\n");
+
+        let frame: RespFrame = RespVerbatimString::new("mkd", "# title")?.into();
+        assert_eq!(frame.encode(), b"=11\r\nmkd:# title\r\n");
+
+        assert!(RespVerbatimString::new("text", "abc").is_err());
+        Ok(())
+    }
+
+    ///Attributes: |<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>
+    #[test]
+    fn encode_resp_attributes_should_work() {
+        let mut attributes = RespMaps::default();
+        attributes.insert(
+            SimpleString::from("ttl").into(),
+            RespInteger::from(3600).into(),
+        );
+        let frame: RespFrame =
+            RespAttributes::new(attributes, RespBulkString::from("hello")).into();
+
+        assert_eq!(
+            String::from_utf8_lossy(&frame.encode()),
+            "|1\r\n+ttl\r\n:+3600\r\n$5\r\nhello\r\n"
         );
     }
 }
@@ -0,0 +1,326 @@
+/*
+协议层的限制：
+    解码之前先用FrameScanner扫一遍frame的头部，确认整个frame已经收全，同时检查：
+        bulk string的长度不超过proto-max-bulk-len
+        数组、集合、map的元素个数不超过proto-max-multibulk-len
+        嵌套的层数不超过proto-max-nesting
+    扫描是增量的，数据没收全时记住已经扫过的完整元素，下次从这里继续，
+    所以`*2147483647\r\n`这样的头部不会让服务端预先分配内存，每次收到数据也不用从头扫描
+    扫描用显式的栈而不是递归，恶意的深层嵌套不会耗尽调用栈
+*/
+use super::*;
+
+/// bulk和aggregate的头部最长64KB，和Redis的PROTO_INLINE_MAX_SIZE一样
+pub const MAX_HEADER_LEN: usize = 64 * 1024;
+
+/// proto-max-bulk-len的默认值，没有连接配置的地方(比如RDB解码)也用它限制字符串长度
+pub const DEFAULT_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RespLimits {
+    /// 单个bulk string的最大长度
+    pub max_bulk_len: usize,
+    /// 数组、集合、push的最大元素个数，map按entry个数计算
+    pub max_multibulk_len: usize,
+    /// 最多嵌套几层，顶层的数组是第1层
+    pub max_depth: usize,
+    /// 一个没收全的请求最多缓存多少字节
+    pub max_query_buffer: usize,
+}
+
+impl Default for RespLimits {
+    fn default() -> Self {
+        Self {
+            max_bulk_len: DEFAULT_MAX_BULK_LEN,
+            max_multibulk_len: 1024 * 1024,
+            max_depth: 128,
+            max_query_buffer: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// 增量扫描buf开头的一个frame，frame解码之后要重新开始
+#[derive(Debug, Default)]
+pub struct FrameScanner {
+    /// 已经扫描过的完整元素的结尾
+    offset: usize,
+    /// 每一层还没扫描的元素个数
+    pending: Vec<usize>,
+}
+
+impl FrameScanner {
+    /// frame收全时返回它的长度，没收全时返回None，超过限制或者格式错误时返回协议错误
+    pub fn scan(&mut self, buf: &[u8], limits: &RespLimits) -> Result<Option<usize>, RespError> {
+        loop {
+            let data = buf.get(self.offset..).unwrap_or_default();
+            let Some((len, children)) = scan_element(data, limits)? else {
+                return Ok(None);
+            };
+            self.offset += len;
+            if let Some(children) = children {
+                if self.pending.len() >= limits.max_depth {
+                    return Err(RespError::TooDeep);
+                }
+                if children > 0 {
+                    self.pending.push(children);
+                    continue;
+                }
+            }
+            //一个元素完整之后，已经满了的上一层也跟着完整
+            loop {
+                match self.pending.last_mut() {
+                    None => {
+                        let len = self.offset;
+                        self.reset();
+                        return Ok(Some(len));
+                    }
+                    Some(1) => {
+                        self.pending.pop();
+                    }
+                    Some(left) => {
+                        *left -= 1;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.offset = 0;
+        self.pending.clear();
+    }
+}
+
+/// 返回元素头部(aggregate)或者整个元素(其它类型)的长度，aggregate还返回子元素的个数
+fn scan_element(
+    data: &[u8],
+    limits: &RespLimits,
+) -> Result<Option<(usize, Option<usize>)>, RespError> {
+    let Some(&prefix) = data.first() else {
+        return Ok(None);
+    };
+    match prefix {
+        POSITIVE_SIGN | NEGATIVE_SIGN | COLON | UNDERLINE | POND_SIGN | COMMA
+        | LEFT_PARENTHESIS => Ok(find_crlf(data).map(|end| (end + CRLF.len(), None))),
+        DOLLAR | EXCLAMATION_MARK | EQUAL_SIGN => {
+            let Some((end, len)) = parse_header(data)? else {
+                return Ok(None);
+            };
+            let header = end + CRLF.len();
+            let len = match len {
+                Some(-1) if prefix == DOLLAR => return Ok(Some((header, None))),
+                Some(len) => usize::try_from(len).ok(),
+                None => None,
+            }
+            .filter(|len| *len <= limits.max_bulk_len)
+            .ok_or(RespError::InvalidBulkLength)?;
+            let total = header + len + CRLF.len();
+            Ok((data.len() >= total).then_some((total, None)))
+        }
+        ASTERISK | TILDE_SIGN | GREATER_THAN_SIGN | PERCENT_SIGN | VERTICAL_BAR => {
+            let Some((end, count)) = parse_header(data)? else {
+                return Ok(None);
+            };
+            let header = end + CRLF.len();
+            let count = match count {
+                Some(-1) if prefix == ASTERISK => return Ok(Some((header, None))),
+                Some(count) => usize::try_from(count).ok(),
+                None => None,
+            }
+            .filter(|count| *count <= limits.max_multibulk_len)
+            .ok_or(RespError::InvalidMultibulkLength)?;
+            let children = match prefix {
+                PERCENT_SIGN => count * 2,
+                //属性后面还跟着一个真正的回复
+                VERTICAL_BAR => count * 2 + 1,
+                _ => count,
+            };
+            Ok(Some((header, Some(children))))
+        }
+        _ => Err(RespError::InvalidFrameType(format!(
+            "unknown frame type {:?}",
+            char::from(prefix)
+        ))),
+    }
+}
+
+/// 长度不是整数时返回Some((end, None))，头部还没收全时返回None
+fn parse_header(data: &[u8]) -> Result<Option<(usize, Option<i64>)>, RespError> {
+    let Some(end) = find_crlf(data) else {
+        if data.len() > MAX_HEADER_LEN {
+            return Err(RespError::HeaderTooLong);
+        }
+        return Ok(None);
+    };
+    let len = std::str::from_utf8(&data[LEN_ONE..end])
+        .ok()
+        .and_then(|len| len.parse().ok());
+    Ok(Some((end, len)))
+}
+
+fn find_crlf(data: &[u8]) -> Option<usize> {
+    data.windows(CRLF.len()).position(|window| window == CRLF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn scan(input: &[u8], limits: &RespLimits) -> Result<Option<usize>, RespError> {
+        FrameScanner::default().scan(input, limits)
+    }
+
+    #[test]
+    fn test_scan_complete_frames() {
+        let limits = RespLimits::default();
+        let frames: &[&[u8]] = &[
+            b"+OK\r\n",
+            b":-12\r\n",
+            b"$5\r\nhello\r\n",
+            b"$-1\r\n",
+            b"*-1\r\n",
+            b"*0\r\n",
+            b"*2\r\n$3\r\nset\r\n*1\r\n:1\r\n",
+            b"%1\r\n+a\r\n~2\r\n#t\r\n,1.5\r\n",
+            b"|1\r\n+ttl\r\n:3600\r\n$5\r\nhello\r\n",
+            b">2\r\n+message\r\n=7\r\ntxt:abc\r\n",
+        ];
+        for frame in frames {
+            let mut input = frame.to_vec();
+            input.extend_from_slice(b"+next\r\n");
+            assert_eq!(scan(&input, &limits), Ok(Some(frame.len())), "{frame:?}");
+            //任何一个前缀都是没收全
+            for end in 0..frame.len() {
+                assert_eq!(scan(&frame[..end], &limits), Ok(None), "{frame:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan_incremental() {
+        let limits = RespLimits::default();
+        let frame = b"*3\r\n$3\r\nset\r\n$1\r\na\r\n*2\r\n:1\r\n:2\r\n";
+        let mut scanner = FrameScanner::default();
+        for end in 0..frame.len() {
+            assert_eq!(scanner.scan(&frame[..end], &limits), Ok(None));
+        }
+        assert_eq!(scanner.scan(frame, &limits), Ok(Some(frame.len())));
+        //完整之后重新开始
+        assert_eq!(scanner.scan(b"+OK\r\n", &limits), Ok(Some(5)));
+    }
+
+    #[test]
+    fn test_scan_limits() {
+        let limits = RespLimits {
+            max_bulk_len: 10,
+            max_multibulk_len: 3,
+            max_depth: 2,
+            ..RespLimits::default()
+        };
+        assert_eq!(scan(b"$10\r\n", &limits), Ok(None));
+        assert_eq!(scan(b"$11\r\n", &limits), Err(RespError::InvalidBulkLength));
+        assert_eq!(scan(b"$-2\r\n", &limits), Err(RespError::InvalidBulkLength));
+        assert_eq!(scan(b"$x\r\n", &limits), Err(RespError::InvalidBulkLength));
+        assert_eq!(scan(b"!-1\r\n", &limits), Err(RespError::InvalidBulkLength));
+        assert_eq!(
+            scan(b"$99999999999999999999999\r\n", &limits),
+            Err(RespError::InvalidBulkLength)
+        );
+        assert_eq!(scan(b"*3\r\n", &limits), Ok(None));
+        assert_eq!(
+            scan(b"*2147483647\r\n", &limits),
+            Err(RespError::InvalidMultibulkLength)
+        );
+        assert_eq!(
+            scan(b"~-1\r\n", &limits),
+            Err(RespError::InvalidMultibulkLength)
+        );
+        assert_eq!(scan(b"*1\r\n*1\r\n:1\r\n", &limits), Ok(Some(12)));
+        assert_eq!(
+            scan(b"*1\r\n*1\r\n*0\r\n", &limits),
+            Err(RespError::TooDeep)
+        );
+        //超过限制的元素在后面也能发现
+        assert_eq!(
+            scan(b"*2\r\n$1\r\na\r\n$100\r\n", &limits),
+            Err(RespError::InvalidBulkLength)
+        );
+        assert_eq!(
+            scan(&[b'$'; MAX_HEADER_LEN + 1], &limits),
+            Err(RespError::HeaderTooLong)
+        );
+        assert!(matches!(
+            scan(b"?\r\n", &limits),
+            Err(RespError::InvalidFrameType(_))
+        ));
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let limits = RespLimits::default();
+        let depth = limits.max_depth;
+        let mut frame = b"*1\r\n".repeat(depth - 1);
+        frame.extend_from_slice(b"*0\r\n");
+        assert_eq!(scan(&frame, &limits), Ok(Some(frame.len())));
+        let mut buf = BytesMut::from(&frame[..]);
+        assert!(RespFrame::decode(&mut buf).is_ok());
+        assert!(buf.is_empty());
+
+        //一百万层的嵌套只需要扫描到超过限制的那一层
+        let frame = b"*1\r\n".repeat(1_000_000);
+        assert_eq!(scan(&frame, &limits), Err(RespError::TooDeep));
+    }
+
+    /// 随机字节和随机修改过的合法frame：扫描和解码都不能panic，
+    /// 扫描认为完整的frame，解码时要么出错，要么正好消耗这么多字节
+    #[test]
+    fn test_fuzz_adversarial_inputs() {
+        let limits = RespLimits {
+            max_bulk_len: 64,
+            max_multibulk_len: 8,
+            max_depth: 4,
+            ..RespLimits::default()
+        };
+        let seeds: &[&[u8]] = &[
+            b"*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\nb\r\n",
+            b"%2\r\n+a\r\n:1\r\n+b\r\n*2\r\n#f\r\n_\r\n",
+            b"|1\r\n+ttl\r\n:3600\r\n=7\r\ntxt:abc\r\n",
+            b">2\r\n!5\r\nerror\r\n(12345678901234567890\r\n",
+            b"~2\r\n,-1.5e10\r\n$-1\r\n",
+        ];
+        let alphabet = b"*$%|~>!=+-:_#,(0123456789-\r\n";
+        let mut rng = StdRng::seed_from_u64(20241019);
+        for _ in 0..20000 {
+            let mut input = match rng.gen_bool(0.2) {
+                true => (0..rng.gen_range(0..32))
+                    .map(|_| alphabet[rng.gen_range(0..alphabet.len())])
+                    .collect::<Vec<_>>(),
+                false => seeds[rng.gen_range(0..seeds.len())].to_vec(),
+            };
+            for _ in 0..rng.gen_range(0..4) {
+                if input.is_empty() {
+                    break;
+                }
+                let i = rng.gen_range(0..input.len());
+                match rng.gen_range(0..3) {
+                    0 => input[i] = alphabet[rng.gen_range(0..alphabet.len())],
+                    1 => {
+                        input.remove(i);
+                    }
+                    _ => input.insert(i, rng.gen()),
+                }
+            }
+
+            if let Ok(Some(len)) = scan(&input, &limits) {
+                let mut buf = BytesMut::from(&input[..]);
+                if RespFrame::decode(&mut buf).is_ok() {
+                    assert_eq!(input.len() - buf.len(), len, "{input:?}");
+                }
+            }
+            let mut buf = BytesMut::from(&input[..]);
+            let _ = RespFrame::decode(&mut buf);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 mod decode;
 mod encode;
+mod limits;
+mod protocol;
 
 /*
 Simple strings: +OK\r\n
@@ -24,22 +26,30 @@ Doubles: ,[<+|->]<integral>[.<fractional>][<E|e>[sign]<exponent>]\r\n
 Bulk errors: !<length>\r\n<error>\r\n
 Maps: %<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n>
 Sets: ~<number-of-elements>\r\n<element-1>...<element-n>
+Big numbers: ([+|-]<number>\r\n
+Verbatim strings: =<length>\r\n<encoding>:<data>\r\n
+Attributes: |<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>
+Pushes: ><number-of-elements>\r\n<element-1>...<element-n>
 
 */
 use crate::resp::decode::extract_simple_frame_data;
 use bytes::BytesMut;
 use derive_more::{AsRef, Constructor, Deref, From};
 use enum_dispatch::enum_dispatch;
+use indexmap::{IndexMap, IndexSet};
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
 use std::num::{ParseFloatError, ParseIntError};
 use std::ops::DerefMut;
 use thiserror::Error;
 
+pub use limits::{FrameScanner, RespLimits, DEFAULT_MAX_BULK_LEN};
+pub use protocol::RespProtocol;
+
 pub const CRLF: &[u8] = b"\r\n";
 pub const POSITIVE_SIGN: u8 = b'+';
 pub const NEGATIVE_SIGN: u8 = b'-';
-pub const ERROR: &[u8] = b"Error";
 pub const COLON: u8 = b':';
 pub const COMMA: u8 = b',';
 pub const DOLLAR: u8 = b'$';
@@ -52,6 +62,11 @@ pub const TILDE_SIGN: u8 = b'~';
 pub const TRUE: u8 = b't';
 pub const FALSE: u8 = b'f';
 pub const EXCLAMATION_MARK: u8 = b'!';
+pub const LEFT_PARENTHESIS: u8 = b'(';
+pub const EQUAL_SIGN: u8 = b'=';
+pub const VERTICAL_BAR: u8 = b'|';
+pub const GREATER_THAN_SIGN: u8 = b'>';
+pub const VERBATIM_ENCODING_LEN: usize = 3;
 pub const MAX_BUF_SIZE: usize = 4096;
 pub const WHITE_SPACE: u8 = b' ';
 pub const INFINITY: &[u8] = b"inf";
@@ -93,10 +108,25 @@ pub enum RespError {
 
     #[error("Parse float error:{0}")]
     ParseFloatError(#[from] ParseFloatError),
+
+    #[error("Protocol error: invalid bulk length")]
+    InvalidBulkLength,
+
+    #[error("Protocol error: invalid multibulk length")]
+    InvalidMultibulkLength,
+
+    #[error("Protocol error: too many nested levels")]
+    TooDeep,
+
+    #[error("Protocol error: too big count string")]
+    HeaderTooLong,
+
+    #[error("query buffer length {0} exceeds client-query-buffer-limit")]
+    QueryBufferLimit(usize),
 }
 
 #[enum_dispatch(EncodeResp)]
-#[derive(Debug, PartialEq, PartialOrd, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub enum RespFrame {
     SimpleString(SimpleString),
     SimpleError(SimpleError),
@@ -111,52 +141,259 @@ pub enum RespFrame {
     BulkErrors(RespBulkErrors),
     Maps(RespMaps),
     Sets(RespSets),
+    BigNumber(RespBigNumber),
+    VerbatimString(RespVerbatimString),
+    Attributes(RespAttributes),
+    Push(RespPush),
 }
 
 ///Simple strings: +OK\r\n
-#[derive(Debug, From, Deref, PartialEq, PartialOrd, Clone)]
+#[derive(Debug, From, Deref, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 #[from(Cow<'_, str>, String, &'static str)]
 pub struct SimpleString(pub(crate) String);
 
-#[derive(Debug, From, Deref, PartialEq, PartialOrd, Clone)]
+#[derive(Debug, From, Deref, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 #[from(Cow<'_, str>, String, &'static str)]
 pub struct SimpleError(pub(crate) String);
 
-#[derive(Debug, From, Deref, PartialEq, PartialOrd, Clone)]
+#[derive(Debug, From, Deref, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 #[from(i32, i64)]
 pub struct RespInteger(pub(crate) i64);
 
-#[derive(Debug, From, Deref, PartialEq, PartialOrd, Constructor, Clone)]
+#[derive(Debug, From, Deref, PartialEq, Eq, PartialOrd, Ord, Hash, Constructor, Clone)]
 #[from(&'static str, &[u8], Vec<u8>, String)]
 pub struct RespBulkString(pub(crate) Vec<u8>);
 
-#[derive(Debug, PartialEq, PartialOrd, Constructor, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Constructor, Clone)]
 pub struct RespNullBulkString;
 
-#[derive(Debug, From, PartialEq, PartialOrd, Constructor, Clone)]
+#[derive(Debug, From, PartialEq, Eq, PartialOrd, Ord, Hash, Constructor, Clone)]
 pub struct RespArray(pub(crate) Vec<RespFrame>);
 
-#[derive(Debug, PartialEq, PartialOrd, Constructor, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Constructor, Clone)]
 pub struct RespNullArray;
 
-#[derive(Debug, PartialEq, PartialOrd, Constructor, Clone)]
+///Pushes: ><number-of-elements>\r\n<element-1>...<element-n>
+///服务端主动推送的消息，比如CLIENT TRACKING的invalidate，第一个元素是消息类型
+#[derive(Debug, From, PartialEq, Eq, PartialOrd, Ord, Hash, Constructor, Clone)]
+pub struct RespPush(pub(crate) Vec<RespFrame>);
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Constructor, Clone)]
 pub struct RespNull;
 
-#[derive(Debug, From, Deref, PartialEq, PartialOrd, Constructor, Clone)]
+#[derive(Debug, From, Deref, PartialEq, Eq, PartialOrd, Ord, Hash, Constructor, Clone)]
 pub struct RespBooleans(pub(crate) bool);
 
-#[derive(Debug, From, Deref, PartialEq, PartialOrd, Constructor, Clone)]
+///Eq/Ord/Hash都基于f64::total_cmp，nan和-0.0也有确定的位置，可以作为map的key和set的元素
+#[derive(Debug, From, Deref, Constructor, Clone)]
 pub struct RespDoubles(pub(crate) f64);
 
-#[derive(Debug, From, PartialEq, PartialOrd, Clone)]
+#[derive(Debug, From, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 #[from(&'static str, &[u8], Vec<u8>)]
 pub struct RespBulkErrors(pub(crate) Vec<u8>);
 
-#[derive(Debug, From, PartialEq, PartialOrd, Default, Constructor, Clone)]
-pub struct RespMaps(pub(crate) BTreeMap<String, RespFrame>);
+///key可以是任意frame，编码时保持插入顺序；比较和哈希与顺序无关
+#[derive(Debug, From, Default, Constructor, Clone)]
+pub struct RespMaps(pub(crate) IndexMap<RespFrame, RespFrame>);
+
+///元素唯一，编码时保持插入顺序；比较和哈希与顺序无关
+#[derive(Debug, Default, Clone)]
+pub struct RespSets(pub(crate) IndexSet<RespFrame>);
+
+///Big numbers: ([+|-]<number>\r\n
+///只能通过new构造，保证编码出去的一定是合法的数字
+#[derive(Debug, Deref, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct RespBigNumber(pub(crate) String);
+
+///Verbatim strings: =<length>\r\n<encoding>:<data>\r\n
+///encoding固定为三个字节，比如txt和mkd
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct RespVerbatimString {
+    pub(crate) encoding: String,
+    pub(crate) data: Vec<u8>,
+}
+
+///Attributes: |<number-of-entries>\r\n<key-1><value-1>...<key-n><value-n><reply>
+///属性是附加在紧随其后的那个reply上的，所以和被修饰的frame保存在一起
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct RespAttributes {
+    pub(crate) attributes: RespMaps,
+    pub(crate) data: Box<RespFrame>,
+}
+
+impl RespBigNumber {
+    pub fn new(value: impl Into<String>) -> Result<Self, RespError> {
+        let value = value.into();
+        let digits = value.strip_prefix(['+', '-']).unwrap_or(&value);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(RespError::InvalidFrameType(format!(
+                "invalid big number: {value:?}"
+            )));
+        }
+        Ok(RespBigNumber(value))
+    }
+}
+
+impl RespVerbatimString {
+    pub fn new(encoding: impl Into<String>, data: impl Into<Vec<u8>>) -> Result<Self, RespError> {
+        let encoding = encoding.into();
+        if encoding.len() != VERBATIM_ENCODING_LEN {
+            return Err(RespError::InvalidFrameType(format!(
+                "verbatim string encoding should be {VERBATIM_ENCODING_LEN} bytes, got {encoding:?}"
+            )));
+        }
+        Ok(RespVerbatimString {
+            encoding,
+            data: data.into(),
+        })
+    }
+
+    ///纯文本
+    pub fn text(data: impl Into<Vec<u8>>) -> Self {
+        RespVerbatimString {
+            encoding: "txt".into(),
+            data: data.into(),
+        }
+    }
+
+    ///markdown
+    pub fn markdown(data: impl Into<Vec<u8>>) -> Self {
+        RespVerbatimString {
+            encoding: "mkd".into(),
+            data: data.into(),
+        }
+    }
+
+    pub fn encoding(&self) -> &str {
+        &self.encoding
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl RespAttributes {
+    pub fn new(attributes: RespMaps, data: impl Into<RespFrame>) -> Self {
+        RespAttributes {
+            attributes,
+            data: Box::new(data.into()),
+        }
+    }
+
+    pub fn attributes(&self) -> &RespMaps {
+        &self.attributes
+    }
+
+    pub fn data(&self) -> &RespFrame {
+        &self.data
+    }
+}
+
+impl PartialEq for RespDoubles {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RespDoubles {}
+
+impl PartialOrd for RespDoubles {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RespDoubles {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for RespDoubles {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        //total_cmp相等当且仅当二进制表示相同，所以按bits哈希和Eq是一致的
+        self.0.to_bits().hash(state);
+    }
+}
+
+impl RespMaps {
+    fn sorted_entries(&self) -> Vec<(&RespFrame, &RespFrame)> {
+        let mut entries = self.0.iter().collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
+}
+
+impl PartialEq for RespMaps {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RespMaps {}
+
+impl PartialOrd for RespMaps {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RespMaps {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sorted_entries().cmp(&other.sorted_entries())
+    }
+}
 
-#[derive(Debug, PartialEq, PartialOrd, From, Constructor, Clone)]
-pub struct RespSets(pub(crate) Vec<RespFrame>);
+impl Hash for RespMaps {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sorted_entries().hash(state);
+    }
+}
+
+impl RespSets {
+    pub fn new(elements: impl IntoIterator<Item = RespFrame>) -> Self {
+        RespSets(elements.into_iter().collect())
+    }
+
+    fn sorted_elements(&self) -> Vec<&RespFrame> {
+        let mut elements = self.0.iter().collect::<Vec<_>>();
+        elements.sort();
+        elements
+    }
+}
+
+impl From<Vec<RespFrame>> for RespSets {
+    fn from(value: Vec<RespFrame>) -> Self {
+        RespSets::new(value)
+    }
+}
+
+impl PartialEq for RespSets {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RespSets {}
+
+impl PartialOrd for RespSets {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RespSets {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sorted_elements().cmp(&other.sorted_elements())
+    }
+}
+
+impl Hash for RespSets {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sorted_elements().hash(state);
+    }
+}
 
 impl From<Cow<'_, str>> for RespBooleans {
     fn from(value: Cow<'_, str>) -> Self {
@@ -255,6 +492,14 @@ impl Deref for RespArray {
     }
 }
 
+impl Deref for RespPush {
+    type Target = Vec<RespFrame>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 // impl Deref for RespBooleans {
 //     type Target = bool;
 //
@@ -280,7 +525,7 @@ impl Deref for RespBulkErrors {
 }
 
 impl Deref for RespMaps {
-    type Target = BTreeMap<String, RespFrame>;
+    type Target = IndexMap<RespFrame, RespFrame>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -295,14 +540,81 @@ impl DerefMut for RespMaps {
 
 // impl RespMaps {
 //     pub fn new() -> Self {
-//         Self(BTreeMap::new())
+//         Self(IndexMap::new())
 //     }
 // }
 
 impl Deref for RespSets {
-    type Target = Vec<RespFrame>;
+    type Target = IndexSet<RespFrame>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
+
+impl DerefMut for RespSets {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::BTreeSet;
+
+    fn hash_of(frame: &RespFrame) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        frame.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_doubles_total_order() {
+        let nan = RespDoubles::new(f64::NAN);
+        assert_eq!(nan, nan.clone());
+        assert!(RespDoubles::new(f64::INFINITY) < nan);
+        assert!(RespDoubles::new(-0.0) < RespDoubles::new(0.0));
+        assert!(RespDoubles::new(f64::NEG_INFINITY) < RespDoubles::new(-1.0e300));
+
+        let frames = [1.5, f64::NAN, -2.0, 1.5]
+            .into_iter()
+            .map(|d| RespFrame::from(RespDoubles::new(d)))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(frames.len(), 3);
+    }
+
+    #[test]
+    fn test_maps_and_sets_ignore_order() {
+        let mut map1 = RespMaps::default();
+        map1.insert(RespInteger::from(1).into(), RespNull.into());
+        map1.insert(
+            RespBulkString::from("a").into(),
+            RespBooleans::new(true).into(),
+        );
+        let mut map2 = RespMaps::default();
+        map2.insert(
+            RespBulkString::from("a").into(),
+            RespBooleans::new(true).into(),
+        );
+        map2.insert(RespInteger::from(1).into(), RespNull.into());
+
+        let frame1: RespFrame = map1.into();
+        let frame2: RespFrame = map2.into();
+        assert_eq!(frame1, frame2);
+        assert_eq!(frame1.cmp(&frame2), Ordering::Equal);
+        assert_eq!(hash_of(&frame1), hash_of(&frame2));
+
+        let set1: RespFrame =
+            RespSets::new(vec![RespInteger::from(1).into(), RespNull.into()]).into();
+        let set2: RespFrame =
+            RespSets::new(vec![RespNull.into(), RespInteger::from(1).into()]).into();
+        assert_eq!(set1, set2);
+        assert_eq!(hash_of(&set1), hash_of(&set2));
+
+        //set本身也可以作为set的元素
+        let nested = RespSets::new(vec![set1, set2]);
+        assert_eq!(nested.len(), 1);
+    }
+}
@@ -0,0 +1,184 @@
+use super::*;
+
+/// 客户端通过HELLO协商出的协议版本，新连接默认使用RESP2
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RespProtocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+impl RespProtocol {
+    pub fn version(&self) -> i64 {
+        match self {
+            RespProtocol::Resp2 => 2,
+            RespProtocol::Resp3 => 3,
+        }
+    }
+}
+
+impl TryFrom<i64> for RespProtocol {
+    type Error = RespError;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        match value {
+            2 => Ok(RespProtocol::Resp2),
+            3 => Ok(RespProtocol::Resp3),
+            _ => Err(RespError::InvalidFrameType(format!(
+                "unsupported protocol version {value}"
+            ))),
+        }
+    }
+}
+
+impl RespFrame {
+    /// 按照连接协商的协议转换frame
+    pub fn with_protocol(self, protocol: RespProtocol) -> RespFrame {
+        match protocol {
+            RespProtocol::Resp2 => self.downgrade(),
+            RespProtocol::Resp3 => self.upgrade(),
+        }
+    }
+
+    /// RESP3只有一种null，把RESP2的$-1和*-1递归地统一为_
+    pub fn upgrade(self) -> RespFrame {
+        match self {
+            RespFrame::NullBulkString(_) | RespFrame::NullArray(_) => RespNull.into(),
+            RespFrame::Arrays(array) => {
+                RespArray::new(array.0.into_iter().map(|f| f.upgrade()).collect()).into()
+            }
+            RespFrame::Maps(map) => RespMaps::new(
+                map.0
+                    .into_iter()
+                    .map(|(k, v)| (k.upgrade(), v.upgrade()))
+                    .collect(),
+            )
+            .into(),
+            RespFrame::Sets(set) => RespSets::new(set.0.into_iter().map(|f| f.upgrade())).into(),
+            RespFrame::Attributes(a) => RespAttributes::new(a.attributes, a.data.upgrade()).into(),
+            RespFrame::Push(push) => {
+                RespPush::new(push.0.into_iter().map(|f| f.upgrade()).collect()).into()
+            }
+            frame => frame,
+        }
+    }
+
+    /// 把RESP3独有的类型递归地转换为RESP2中对应的表示:
+    /// Maps -> 扁平数组, Sets -> 数组, Doubles -> BulkString,
+    /// Null -> $-1, Booleans -> Integer, BulkErrors -> SimpleError,
+    /// BigNumber/VerbatimString -> BulkString, Attributes -> 丢弃属性只保留reply,
+    /// Push -> 数组
+    pub fn downgrade(self) -> RespFrame {
+        match self {
+            RespFrame::Arrays(array) => {
+                RespArray::new(array.0.into_iter().map(|f| f.downgrade()).collect()).into()
+            }
+            RespFrame::Null(_) => RespNullBulkString.into(),
+            RespFrame::Booleans(b) => RespInteger::from(if b.0 { 1 } else { 0 }).into(),
+            RespFrame::Doubles(d) => RespBulkString::from(d.0.to_string()).into(),
+            RespFrame::BulkErrors(e) => {
+                SimpleError::from(String::from_utf8_lossy(&e.0).into_owned()).into()
+            }
+            RespFrame::Maps(map) => {
+                let mut vec = Vec::with_capacity(map.len() * 2);
+                for (key, value) in map.0 {
+                    vec.push(key.downgrade());
+                    vec.push(value.downgrade());
+                }
+                RespArray::new(vec).into()
+            }
+            RespFrame::Sets(set) => {
+                RespArray::new(set.0.into_iter().map(|f| f.downgrade()).collect()).into()
+            }
+            RespFrame::BigNumber(n) => RespBulkString::from(n.0).into(),
+            RespFrame::VerbatimString(s) => RespBulkString::from(s.data).into(),
+            RespFrame::Attributes(a) => a.data.downgrade(),
+            RespFrame::Push(push) => {
+                RespArray::new(push.0.into_iter().map(|f| f.downgrade()).collect()).into()
+            }
+            frame => frame,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protocol_from_version() {
+        assert_eq!(RespProtocol::try_from(2), Ok(RespProtocol::Resp2));
+        assert_eq!(RespProtocol::try_from(3), Ok(RespProtocol::Resp3));
+        assert!(RespProtocol::try_from(4).is_err());
+    }
+
+    #[test]
+    fn test_downgrade_scalar_frames() {
+        let frame: RespFrame = RespNull.into();
+        assert_eq!(frame.downgrade().encode(), b"$-1\r\n");
+
+        let frame: RespFrame = RespBooleans::new(true).into();
+        assert_eq!(frame.downgrade().encode(), b":+1\r\n");
+
+        let frame: RespFrame = RespDoubles::new(1.5).into();
+        assert_eq!(frame.downgrade().encode(), b"$3\r\n1.5\r\n");
+
+        let frame: RespFrame = RespBulkErrors::from("SYNTAX bad").into();
+        assert_eq!(frame.downgrade().encode(), b"-SYNTAX bad\r\n");
+
+        let frame: RespFrame = RespBigNumber::new("12345678901234567890").unwrap().into();
+        assert_eq!(
+            frame.downgrade().encode(),
+            b"$20\r\n12345678901234567890\r\n"
+        );
+
+        let frame: RespFrame = RespVerbatimString::text("a:1").into();
+        assert_eq!(frame.downgrade().encode(), b"$3\r\na:1\r\n");
+
+        let mut attributes = RespMaps::default();
+        attributes.insert(
+            RespBulkString::from("ttl").into(),
+            RespInteger::from(1).into(),
+        );
+        let frame: RespFrame = RespAttributes::new(attributes, RespNull).into();
+        assert_eq!(frame.downgrade().encode(), b"$-1\r\n");
+    }
+
+    #[test]
+    fn test_downgrade_nested_frames() {
+        let mut map = RespMaps::default();
+        map.insert(
+            RespBulkString::from("proto").into(),
+            RespInteger::from(2).into(),
+        );
+        map.insert(
+            RespBulkString::from("set").into(),
+            RespSets::new(vec![RespBooleans::new(false).into()]).into(),
+        );
+        let frame: RespFrame = map.into();
+
+        assert_eq!(
+            String::from_utf8_lossy(&frame.downgrade().encode()),
+            "*4\r\n$5\r\nproto\r\n:+2\r\n$3\r\nset\r\n*1\r\n:+0\r\n"
+        );
+    }
+
+    #[test]
+    fn test_resp3_keeps_frames() {
+        let frame: RespFrame = RespNull.into();
+        assert_eq!(frame.clone().with_protocol(RespProtocol::Resp3), frame);
+
+        let frame: RespFrame = RespMaps::default().into();
+        assert_eq!(frame.clone().with_protocol(RespProtocol::Resp3), frame);
+    }
+
+    #[test]
+    fn test_upgrade_nulls() {
+        let frame: RespFrame =
+            RespArray::new(vec![RespNullBulkString.into(), RespNullArray.into()]).into();
+        assert_eq!(
+            frame.with_protocol(RespProtocol::Resp3).encode(),
+            b"*2\r\n_\r\n_\r\n"
+        );
+    }
+}
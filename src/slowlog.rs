@@ -0,0 +1,237 @@
+/*
+SLOWLOG：
+    执行时间超过slowlog-log-slower-than微秒的命令记进一个有界队列，最新的在前面，
+    超过slowlog-max-len时丢掉最旧的。和Redis一样只计执行时间，不包括读写网络和排队等锁的时间
+参数在执行之前截取：最多保留32个参数，每个参数最多128字节，多出来的用一个说明代替，
+    和MONITOR一样带密码的参数替换成(redacted)
+*/
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use crate::{backend::now_ms, monitor, network::ConnAddr, RespArray};
+
+/// 每条记录最多保留的参数个数
+const SLOWLOG_ENTRY_MAX_ARGC: usize = 32;
+/// 每个参数最多保留的字节数
+const SLOWLOG_ENTRY_MAX_STRING: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct SlowlogConfig {
+    /// 微秒，负数表示关闭，0表示记录所有命令
+    pub slower_than: i64,
+    pub max_len: usize,
+}
+
+impl Default for SlowlogConfig {
+    fn default() -> Self {
+        Self {
+            slower_than: 10000,
+            max_len: 128,
+        }
+    }
+}
+
+/// SLOWLOG GET返回的一条记录
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowlogEntry {
+    pub id: u64,
+    /// unix秒
+    pub time: u64,
+    /// 微秒
+    pub duration: u64,
+    pub args: Vec<Vec<u8>>,
+    /// 客户端的ip:port
+    pub addr: String,
+    /// CLIENT SETNAME设置的名字，没有时为空
+    pub name: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SlowlogState {
+    config: RwLock<SlowlogConfig>,
+    /// 最新的记录在前面
+    entries: Mutex<VecDeque<SlowlogEntry>>,
+    next_id: AtomicU64,
+}
+
+impl SlowlogState {
+    pub fn config(&self) -> SlowlogConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_config(&self, config: SlowlogConfig) {
+        let max_len = config.max_len;
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+        self.entries().truncate(max_len);
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, VecDeque<SlowlogEntry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 执行之前截取命令的参数，密码替换成(redacted)，关闭时返回None，不做任何拷贝
+    pub fn capture(&self, array: &RespArray) -> Option<Vec<Vec<u8>>> {
+        if self.config().slower_than < 0 {
+            return None;
+        }
+        let argc = array.len();
+        let mut args = Vec::with_capacity(argc.min(SLOWLOG_ENTRY_MAX_ARGC));
+        for (i, bytes) in monitor::redact(array).into_iter().enumerate() {
+            //最后一个位置留给被省略的参数个数
+            if argc > SLOWLOG_ENTRY_MAX_ARGC && i == SLOWLOG_ENTRY_MAX_ARGC - 1 {
+                let more = argc - SLOWLOG_ENTRY_MAX_ARGC + 1;
+                args.push(format!("... ({more} more arguments)").into_bytes());
+                break;
+            }
+            let mut arg = bytes[..bytes.len().min(SLOWLOG_ENTRY_MAX_STRING)].to_vec();
+            if bytes.len() > SLOWLOG_ENTRY_MAX_STRING {
+                let more = bytes.len() - SLOWLOG_ENTRY_MAX_STRING;
+                arg.extend_from_slice(format!("... ({more} more bytes)").as_bytes());
+            }
+            args.push(arg);
+        }
+        Some(args)
+    }
+
+    /// 执行时间不小于阈值时记一条
+    pub fn record(
+        &self,
+        args: Vec<Vec<u8>>,
+        elapsed: Duration,
+        addr: Option<ConnAddr>,
+        name: Option<&str>,
+    ) {
+        let config = self.config();
+        let duration = elapsed.as_micros() as u64;
+        if config.slower_than < 0 || duration < config.slower_than as u64 || config.max_len == 0 {
+            return;
+        }
+        let entry = SlowlogEntry {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            time: now_ms() / 1000,
+            duration,
+            args,
+            addr: addr.map(|addr| addr.to_string()).unwrap_or_default(),
+            name: name.unwrap_or_default().to_string(),
+        };
+        let mut entries = self.entries();
+        entries.push_front(entry);
+        entries.truncate(config.max_len);
+    }
+
+    /// SLOWLOG GET：最新的count条，None表示全部
+    pub fn get(&self, count: Option<usize>) -> Vec<SlowlogEntry> {
+        let entries = self.entries();
+        let count = count.unwrap_or(entries.len());
+        entries.iter().take(count).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    /// SLOWLOG RESET，id不会重新从0开始
+    pub fn reset(&self) {
+        self.entries().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aof::command_array;
+
+    #[test]
+    fn test_slowlog_record() {
+        let slowlog = SlowlogState::default();
+        slowlog.set_config(SlowlogConfig {
+            slower_than: 1000,
+            max_len: 2,
+        });
+        let args = slowlog.capture(&command_array(["SET", "a", "1"])).unwrap();
+        slowlog.record(args.clone(), Duration::from_micros(999), None, None);
+        assert!(slowlog.is_empty());
+
+        for i in 0..3 {
+            let addr = format!("127.0.0.1:{i}").parse().ok();
+            slowlog.record(args.clone(), Duration::from_millis(2), addr, Some("worker"));
+        }
+        //只保留最新的两条，最新的在前面
+        let entries = slowlog.get(None);
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].id, entries[1].id), (2, 1));
+        assert_eq!(entries[0].addr, "127.0.0.1:2");
+        assert_eq!(entries[0].name, "worker");
+        assert_eq!(entries[0].duration, 2000);
+        assert_eq!(
+            entries[0].args,
+            vec![b"SET".to_vec(), b"a".to_vec(), b"1".to_vec()]
+        );
+        assert_eq!(slowlog.get(Some(1)).len(), 1);
+
+        slowlog.reset();
+        assert_eq!(slowlog.len(), 0);
+        slowlog.set_config(SlowlogConfig {
+            slower_than: -1,
+            max_len: 2,
+        });
+        assert!(slowlog.capture(&command_array(["GET", "a"])).is_none());
+    }
+
+    #[test]
+    fn test_slowlog_truncate_args() {
+        let slowlog = SlowlogState::default();
+        let long = "x".repeat(SLOWLOG_ENTRY_MAX_STRING + 10);
+        let args = slowlog
+            .capture(&command_array(["SET", "key", long.as_str()]))
+            .unwrap();
+        let expected = format!("{}... (10 more bytes)", &long[..SLOWLOG_ENTRY_MAX_STRING]);
+        assert_eq!(args[2], expected.into_bytes());
+
+        let members = (0..40).map(|i| i.to_string()).collect::<Vec<_>>();
+        let array = command_array(
+            ["SADD", "set"]
+                .into_iter()
+                .chain(members.iter().map(String::as_str)),
+        );
+        let args = slowlog.capture(&array).unwrap();
+        assert_eq!(args.len(), SLOWLOG_ENTRY_MAX_ARGC);
+        assert_eq!(args[30], b"28".to_vec());
+        assert_eq!(args[31], b"... (11 more arguments)".to_vec());
+    }
+
+    #[test]
+    fn test_slowlog_redact() {
+        let slowlog = SlowlogState::default();
+        let args = slowlog
+            .capture(&command_array(["AUTH", "user", "secret"]))
+            .unwrap();
+        assert_eq!(
+            args,
+            vec![
+                b"AUTH".to_vec(),
+                b"(redacted)".to_vec(),
+                b"(redacted)".to_vec()
+            ]
+        );
+        let args = slowlog
+            .capture(&command_array([
+                "MIGRATE", "h", "1", "", "0", "10", "AUTH2", "u", "pw", "KEYS", "a",
+            ]))
+            .unwrap();
+        assert_eq!(args[7..9], [b"(redacted)".to_vec(), b"(redacted)".to_vec()]);
+        let args = slowlog
+            .capture(&command_array(["CONFIG", "SET", "requirepass", "secret"]))
+            .unwrap();
+        assert_eq!(args[3], b"(redacted)".to_vec());
+    }
+}
@@ -0,0 +1,465 @@
+/*
+INFO用到的运行时统计：
+    连接数、命令数和keyspace命中率这些计数器由连接循环和Backend在运行时累加，
+    每个命令的调用次数、耗时和错误数按命令名分别记录，耗时同时记进一个直方图用来算百分位
+ops/sec和Redis一样是最近一段时间的采样平均，不需要单独的定时任务：每条命令执行时顺便采样
+*/
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+use crate::backend::now_ms;
+
+/// 默认的最大连接数
+pub const DEFAULT_MAX_CLIENTS: usize = 10000;
+/// 每隔这么久采样一次命令总数
+const OPS_SAMPLE_INTERVAL_MS: u64 = 100;
+/// 保留的采样个数，ops/sec是最近1.6秒的平均
+const OPS_SAMPLES: usize = 16;
+/// 直方图每个2的幂区间再细分的个数，相对误差不超过1/8
+const HISTOGRAM_SUB_BUCKETS: u64 = 8;
+const HISTOGRAM_BUCKETS: usize = 64 * HISTOGRAM_SUB_BUCKETS as usize;
+
+#[derive(Debug)]
+pub struct ServerStats {
+    started: Instant,
+    max_clients: AtomicUsize,
+    connected_clients: AtomicUsize,
+    blocked_clients: AtomicUsize,
+    total_connections: AtomicU64,
+    rejected_connections: AtomicU64,
+    total_commands: AtomicU64,
+    keyspace_hits: AtomicU64,
+    keyspace_misses: AtomicU64,
+    expired_keys: AtomicU64,
+    total_error_replies: AtomicU64,
+    /// (unix毫秒, 当时的命令总数)
+    ops_samples: Mutex<VecDeque<(u64, u64)>>,
+    commands: DashMap<String, CommandStats>,
+    /// 错误前缀 -> 次数，比如ERR、WRONGTYPE
+    errors: DashMap<String, u64>,
+}
+
+/// 一个命令的累计统计，对应INFO commandstats里的一行
+#[derive(Debug, Clone, Default)]
+pub struct CommandStats {
+    pub calls: u64,
+    pub usec: u64,
+    /// 执行之前就被拒绝，比如参数错误、OOM、READONLY
+    pub rejected_calls: u64,
+    /// 执行了但回复的是错误
+    pub failed_calls: u64,
+    pub latency: LatencyHistogram,
+}
+
+/// 命令执行的结果，决定记到哪个计数器上
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallOutcome {
+    Ok,
+    Rejected,
+    Failed,
+}
+
+/// 微秒耗时的对数直方图，和Redis的hdr_histogram一样只保证几位有效数字
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; HISTOGRAM_BUCKETS],
+            total: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, usec: u64) {
+        self.counts[bucket_of(usec)] += 1;
+        self.total += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// 第p百分位的耗时(微秒)，返回所在区间的上界
+    pub fn percentile(&self, p: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        let target = ((p / 100.0) * self.total as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return bucket_upper(bucket);
+            }
+        }
+        u64::MAX
+    }
+
+    /// 非空的区间：(区间上界, 小于等于上界的累计次数)，LATENCY HISTOGRAM用它输出
+    pub fn cumulative(&self) -> Vec<(u64, u64)> {
+        let mut seen = 0;
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bucket, count)| {
+                seen += count;
+                (bucket_upper(bucket), seen)
+            })
+            .collect()
+    }
+}
+
+/// 小于SUB_BUCKETS的值每个数一个区间，更大的值按最高位所在的2的幂再均分
+fn bucket_of(value: u64) -> usize {
+    if value < HISTOGRAM_SUB_BUCKETS {
+        return value as usize;
+    }
+    let shift = 63 - value.leading_zeros() as u64 - HISTOGRAM_SUB_BUCKETS.trailing_zeros() as u64;
+    let sub = (value >> shift) - HISTOGRAM_SUB_BUCKETS;
+    ((shift + 1) * HISTOGRAM_SUB_BUCKETS + sub) as usize
+}
+
+fn bucket_upper(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < HISTOGRAM_SUB_BUCKETS {
+        return bucket;
+    }
+    let shift = bucket / HISTOGRAM_SUB_BUCKETS - 1;
+    let sub = bucket % HISTOGRAM_SUB_BUCKETS + HISTOGRAM_SUB_BUCKETS;
+    //最高的几个区间超出u64，只会出现在不可能的耗时上
+    ((((sub + 1) as u128) << shift) - 1).min(u64::MAX as u128) as u64
+}
+
+impl Default for ServerStats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            max_clients: AtomicUsize::new(DEFAULT_MAX_CLIENTS),
+            connected_clients: AtomicUsize::new(0),
+            blocked_clients: AtomicUsize::new(0),
+            total_connections: AtomicU64::new(0),
+            rejected_connections: AtomicU64::new(0),
+            total_commands: AtomicU64::new(0),
+            keyspace_hits: AtomicU64::new(0),
+            keyspace_misses: AtomicU64::new(0),
+            expired_keys: AtomicU64::new(0),
+            total_error_replies: AtomicU64::new(0),
+            ops_samples: Mutex::new(VecDeque::new()),
+            commands: DashMap::new(),
+            errors: DashMap::new(),
+        }
+    }
+}
+
+impl ServerStats {
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn max_clients(&self) -> usize {
+        self.max_clients.load(Ordering::SeqCst)
+    }
+
+    pub fn set_max_clients(&self, max_clients: usize) {
+        self.max_clients.store(max_clients, Ordering::SeqCst);
+    }
+
+    /// 新连接到来，超过maxclients时返回false，连接应该被拒绝
+    pub fn client_connected(&self) -> bool {
+        self.total_connections.fetch_add(1, Ordering::SeqCst);
+        let max = self.max_clients();
+        let admitted = self
+            .connected_clients
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .is_ok();
+        if !admitted {
+            self.rejected_connections.fetch_add(1, Ordering::SeqCst);
+        }
+        admitted
+    }
+
+    pub fn client_disconnected(&self) {
+        self.connected_clients.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub fn connected_clients(&self) -> usize {
+        self.connected_clients.load(Ordering::SeqCst)
+    }
+
+    pub fn total_connections(&self) -> u64 {
+        self.total_connections.load(Ordering::SeqCst)
+    }
+
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected_connections.load(Ordering::SeqCst)
+    }
+
+    /// 连接开始(true)或者结束(false)阻塞等待WAIT/WAITAOF/MIGRATE
+    pub fn set_blocked(&self, blocked: bool) {
+        if blocked {
+            self.blocked_clients.fetch_add(1, Ordering::SeqCst);
+        } else {
+            self.blocked_clients.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    pub fn blocked_clients(&self) -> usize {
+        self.blocked_clients.load(Ordering::SeqCst)
+    }
+
+    pub fn keyspace_hit(&self, hit: bool) {
+        match hit {
+            true => self.keyspace_hits.fetch_add(1, Ordering::SeqCst),
+            false => self.keyspace_misses.fetch_add(1, Ordering::SeqCst),
+        };
+    }
+
+    pub fn keyspace_hits(&self) -> u64 {
+        self.keyspace_hits.load(Ordering::SeqCst)
+    }
+
+    pub fn keyspace_misses(&self) -> u64 {
+        self.keyspace_misses.load(Ordering::SeqCst)
+    }
+
+    pub fn key_expired(&self) {
+        self.expired_keys.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn expired_keys(&self) -> u64 {
+        self.expired_keys.load(Ordering::SeqCst)
+    }
+
+    /// 记录一条命令。name是小写的命令名，未知命令传None，只计入总数和错误统计
+    pub fn record_call(
+        &self,
+        name: Option<&str>,
+        elapsed: Duration,
+        outcome: CallOutcome,
+        error: Option<&str>,
+    ) {
+        let total = self.total_commands.fetch_add(1, Ordering::SeqCst) + 1;
+        self.sample_ops(total);
+        if let Some(name) = name {
+            let usec = elapsed.as_micros() as u64;
+            let mut stats = self.commands.entry(name.to_string()).or_default();
+            match outcome {
+                //被拒绝的命令没有执行，不计入调用次数和耗时
+                CallOutcome::Rejected => stats.rejected_calls += 1,
+                CallOutcome::Failed | CallOutcome::Ok => {
+                    stats.calls += 1;
+                    stats.usec += usec;
+                    stats.latency.record(usec);
+                    if outcome == CallOutcome::Failed {
+                        stats.failed_calls += 1;
+                    }
+                }
+            }
+        }
+        if let Some(error) = error {
+            self.total_error_replies.fetch_add(1, Ordering::SeqCst);
+            *self.errors.entry(error_prefix(error)).or_default() += 1;
+        }
+    }
+
+    fn sample_ops(&self, total: u64) {
+        let now = now_ms();
+        let mut samples = self.ops_samples.lock().unwrap_or_else(|e| e.into_inner());
+        if samples
+            .back()
+            .is_none_or(|(at, _)| now >= at + OPS_SAMPLE_INTERVAL_MS)
+        {
+            samples.push_back((now, total));
+            if samples.len() > OPS_SAMPLES {
+                samples.pop_front();
+            }
+        }
+    }
+
+    pub fn total_commands(&self) -> u64 {
+        self.total_commands.load(Ordering::SeqCst)
+    }
+
+    /// 最近一段时间每秒执行的命令数
+    pub fn instantaneous_ops_per_sec(&self) -> u64 {
+        let now = now_ms();
+        let total = self.total_commands();
+        let samples = self.ops_samples.lock().unwrap_or_else(|e| e.into_inner());
+        let window = OPS_SAMPLE_INTERVAL_MS * OPS_SAMPLES as u64;
+        match samples.iter().find(|(at, _)| now - at <= window) {
+            Some((at, count)) if now > *at => (total - count) * 1000 / (now - at),
+            _ => 0,
+        }
+    }
+
+    pub fn total_error_replies(&self) -> u64 {
+        self.total_error_replies.load(Ordering::SeqCst)
+    }
+
+    /// 按命令名排序
+    pub fn commands(&self) -> Vec<(String, CommandStats)> {
+        let mut commands = self
+            .commands
+            .iter()
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect::<Vec<_>>();
+        commands.sort_by(|a, b| a.0.cmp(&b.0));
+        commands
+    }
+
+    pub fn command(&self, name: &str) -> Option<CommandStats> {
+        self.commands.get(name).map(|stats| stats.clone())
+    }
+
+    /// 按错误前缀排序
+    pub fn errors(&self) -> Vec<(String, u64)> {
+        let mut errors = self
+            .errors
+            .iter()
+            .map(|e| (e.key().clone(), *e.value()))
+            .collect::<Vec<_>>();
+        errors.sort();
+        errors
+    }
+
+    /// CONFIG RESETSTAT
+    pub fn reset(&self) {
+        for counter in [
+            &self.total_connections,
+            &self.rejected_connections,
+            &self.total_commands,
+            &self.keyspace_hits,
+            &self.keyspace_misses,
+            &self.expired_keys,
+            &self.total_error_replies,
+        ] {
+            counter.store(0, Ordering::SeqCst);
+        }
+        self.ops_samples
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        self.commands.clear();
+        self.errors.clear();
+    }
+}
+
+/// 错误回复的第一个单词是全大写的才算作错误码，否则统一归为ERR
+fn error_prefix(error: &str) -> String {
+    match error.split_whitespace().next() {
+        Some(word) if word.chars().all(|c| c.is_ascii_uppercase()) => word.to_string(),
+        _ => "ERR".into(),
+    }
+}
+
+/// 进程消耗的(用户态, 内核态)CPU秒数，只支持Linux，其他平台返回0
+pub fn cpu_usage() -> (f64, f64) {
+    //utime和stime是/proc/self/stat的第14、15个字段，单位是clock tick，几乎所有Linux都是100Hz
+    const CLOCK_TICKS: f64 = 100.0;
+    let Ok(stat) = std::fs::read_to_string("/proc/self/stat") else {
+        return (0.0, 0.0);
+    };
+    //进程名可能包含空格，从最后一个')'之后开始数
+    let fields = stat
+        .rsplit_once(')')
+        .map(|(_, rest)| rest.split_whitespace().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let field = |i: usize| {
+        fields
+            .get(i)
+            .and_then(|v| v.parse::<f64>().ok())
+            .unwrap_or(0.0)
+            / CLOCK_TICKS
+    };
+    (field(11), field(12))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        for value in [0, 1, 7, 8, 9, 15, 16, 100, 1000, 123_456, u64::MAX >> 1] {
+            let bucket = bucket_of(value);
+            assert!(bucket_upper(bucket) >= value, "{value}");
+            if bucket > 0 {
+                assert!(bucket_upper(bucket - 1) < value, "{value}");
+            }
+        }
+        let mut histogram = LatencyHistogram::default();
+        for usec in 1..=100 {
+            histogram.record(usec);
+        }
+        assert_eq!(histogram.count(), 100);
+        let p50 = histogram.percentile(50.0);
+        assert!((50..=56).contains(&p50), "{p50}");
+        assert!(histogram.percentile(100.0) >= 100);
+        assert_eq!(histogram.cumulative().last().unwrap().1, 100);
+    }
+
+    #[test]
+    fn test_record_call() {
+        let stats = ServerStats::default();
+        let elapsed = Duration::from_micros(10);
+        stats.record_call(Some("get"), elapsed, CallOutcome::Ok, None);
+        stats.record_call(
+            Some("get"),
+            elapsed,
+            CallOutcome::Rejected,
+            Some("invalid argument"),
+        );
+        stats.record_call(
+            Some("set"),
+            elapsed,
+            CallOutcome::Failed,
+            Some("WRONGTYPE Operation against a key"),
+        );
+        stats.record_call(
+            None,
+            elapsed,
+            CallOutcome::Rejected,
+            Some("ERR unknown command"),
+        );
+
+        assert_eq!(stats.total_commands(), 4);
+        assert_eq!(stats.total_error_replies(), 3);
+        let get = stats.command("get").unwrap();
+        assert_eq!((get.calls, get.usec, get.rejected_calls), (1, 10, 1));
+        assert_eq!(stats.command("set").unwrap().failed_calls, 1);
+        assert_eq!(stats.commands().len(), 2);
+        assert_eq!(
+            stats.errors(),
+            vec![("ERR".to_string(), 2), ("WRONGTYPE".to_string(), 1)]
+        );
+        stats.reset();
+        assert_eq!(stats.total_commands(), 0);
+        assert!(stats.commands().is_empty());
+    }
+
+    #[test]
+    fn test_max_clients() {
+        let stats = ServerStats::default();
+        stats.set_max_clients(1);
+        assert!(stats.client_connected());
+        assert!(!stats.client_connected());
+        stats.client_disconnected();
+        assert!(stats.client_connected());
+        assert_eq!(stats.connected_clients(), 1);
+        assert_eq!(stats.total_connections(), 3);
+        assert_eq!(stats.rejected_connections(), 1);
+    }
+}
@@ -0,0 +1,60 @@
+/*
+需要真实TCP连接的测试共用的工具：在当前runtime上启动服务端，通过连接发送命令
+*/
+use std::time::Duration;
+
+use futures::SinkExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_stream::StreamExt;
+use tokio_util::codec::Framed;
+
+use crate::{
+    aof::command_array,
+    network::{self, RespFrameCodec},
+    replication::ReplConfig,
+    Backend, RespFrame,
+};
+
+/// 在随机端口上启动一个服务端，返回它的Backend和端口
+pub async fn start_server() -> (Backend, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let backend = Backend::new();
+    //replica通过REPLCONF listening-port告诉master的是这个端口
+    backend.repl.set_config(ReplConfig {
+        port,
+        ..Default::default()
+    });
+    tokio::spawn(network::serve(listener, backend.clone()));
+    (backend, port)
+}
+
+pub async fn call(port: u16, args: &[&str]) -> RespFrame {
+    calls(port, &[args]).await.pop().unwrap()
+}
+
+/// 在同一个连接上依次执行
+pub async fn calls(port: u16, commands: &[&[&str]]) -> Vec<RespFrame> {
+    let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let mut framed = Framed::new(stream, RespFrameCodec::default());
+    let mut replies = Vec::new();
+    for args in commands {
+        framed
+            .send(command_array(args.iter().copied()).into())
+            .await
+            .unwrap();
+        replies.push(framed.next().await.unwrap().unwrap());
+    }
+    replies
+}
+
+/// 每50ms检查一次，10秒内条件不满足时panic
+pub async fn wait_for(mut cond: impl FnMut() -> bool) {
+    for _ in 0..200 {
+        if cond() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("condition not met in time");
+}
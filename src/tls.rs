@@ -0,0 +1,483 @@
+/*
+TLS：
+    tls-port不为0时在这个端口上额外监听TLS连接，握手之后和普通连接走同样的处理流程
+    证书和私钥从tls-cert-file、tls-key-file加载，客户端证书用tls-ca-cert-file里的CA验证，
+    tls-auth-clients决定客户端是否必须提供证书(yes)、可以不提供(optional)或者不验证(no)
+    tls-auth-clients-user CN：客户端证书的CN和某个启用的ACL用户同名时，连接直接以这个用户认证
+    CONFIG SET修改tls-*之后重新加载证书，加载失败时修改不生效。已经建立的连接不受影响，
+    新连接使用新的证书
+*/
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{
+    self,
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig, ServerConnection,
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+/// 握手必须在这个时间内完成，否则卡住的客户端会一直占着maxclients的名额
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("Failed to load {path}: {reason}")]
+    Load { path: String, reason: String },
+    #[error("No certificate found in {0}")]
+    NoCertificate(String),
+    #[error("No private key found in {0}")]
+    NoPrivateKey(String),
+    #[error("tls-cert-file and tls-key-file must be specified")]
+    NoCertFile,
+    #[error("tls-ca-cert-file must be specified when tls-auth-clients is enabled")]
+    NoCaCertFile,
+    #[error("{0}")]
+    Rustls(#[from] rustls::Error),
+    #[error("{0}")]
+    Verifier(#[from] rustls::server::VerifierBuilderError),
+    #[error("TLS is not configured")]
+    NotConfigured,
+    #[error("TLS handshake failed: {0}")]
+    Handshake(std::io::Error),
+    #[error("TLS handshake timed out")]
+    HandshakeTimeout,
+}
+
+/// tls-auth-clients
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsAuthClients {
+    No,
+    Yes,
+    Optional,
+}
+
+impl TlsAuthClients {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TlsAuthClients::No => "no",
+            TlsAuthClients::Yes => "yes",
+            TlsAuthClients::Optional => "optional",
+        }
+    }
+}
+
+impl FromStr for TlsAuthClients {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "no" => Ok(TlsAuthClients::No),
+            "yes" => Ok(TlsAuthClients::Yes),
+            "optional" => Ok(TlsAuthClients::Optional),
+            _ => Err("argument must be 'yes', 'no' or 'optional'".into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// 0表示不监听TLS端口
+    pub port: u16,
+    pub cert_file: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+    pub ca_cert_file: Option<PathBuf>,
+    pub auth_clients: TlsAuthClients,
+    /// tls-auth-clients-user CN：用客户端证书的CN作为ACL用户名
+    pub auth_clients_user: bool,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            port: 0,
+            cert_file: None,
+            key_file: None,
+            ca_cert_file: None,
+            auth_clients: TlsAuthClients::Yes,
+            auth_clients_user: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TlsState {
+    config: RwLock<TlsConfig>,
+    /// 最近一次成功加载的证书，没有加载过时为None
+    server_config: RwLock<Option<Arc<ServerConfig>>>,
+}
+
+impl TlsState {
+    pub fn config(&self) -> TlsConfig {
+        self.config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_config(&self, config: TlsConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    /// 证书已经加载，可以接受TLS连接
+    pub fn is_enabled(&self) -> bool {
+        self.server_config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+
+    pub fn acceptor(&self) -> Option<TlsAcceptor> {
+        let config = self.server_config.read().unwrap_or_else(|e| e.into_inner());
+        config.clone().map(TlsAcceptor::from)
+    }
+
+    /// 用当前的证书完成握手
+    pub async fn accept(&self, stream: TcpStream) -> Result<TlsStream<TcpStream>, TlsError> {
+        self.accept_within(stream, HANDSHAKE_TIMEOUT).await
+    }
+
+    async fn accept_within(
+        &self,
+        stream: TcpStream,
+        timeout: Duration,
+    ) -> Result<TlsStream<TcpStream>, TlsError> {
+        let acceptor = self.acceptor().ok_or(TlsError::NotConfigured)?;
+        match tokio::time::timeout(timeout, acceptor.accept(stream)).await {
+            Ok(ret) => ret.map_err(TlsError::Handshake),
+            Err(_) => Err(TlsError::HandshakeTimeout),
+        }
+    }
+
+    /// 按当前配置重新加载证书，失败时继续使用原来的证书
+    pub fn reload(&self) -> Result<(), TlsError> {
+        let config = server_config(&self.config())?;
+        *self
+            .server_config
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(config));
+        Ok(())
+    }
+
+    /// 打开了tls-auth-clients-user时，客户端证书的CN就是连接的用户名
+    pub fn client_user(&self, connection: &ServerConnection) -> Option<String> {
+        if !self.config().auth_clients_user {
+            return None;
+        }
+        let cert = connection.peer_certificates()?.first()?;
+        common_name(cert)
+    }
+}
+
+fn server_config(config: &TlsConfig) -> Result<ServerConfig, TlsError> {
+    let (Some(cert_file), Some(key_file)) = (&config.cert_file, &config.key_file) else {
+        return Err(TlsError::NoCertFile);
+    };
+    let provider = Arc::new(ring::default_provider());
+    let certs = load_certs(cert_file)?;
+    let key = load_key(key_file)?;
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let builder = match (config.auth_clients, &config.ca_cert_file) {
+        (TlsAuthClients::No, _) => builder.with_no_client_auth(),
+        (_, None) => return Err(TlsError::NoCaCertFile),
+        (auth_clients, Some(ca_cert_file)) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_cert_file)? {
+                roots.add(cert)?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone());
+            let verifier = match auth_clients {
+                TlsAuthClients::Optional => verifier.allow_unauthenticated(),
+                _ => verifier,
+            };
+            builder.with_client_cert_verifier(verifier.build()?)
+        }
+    };
+    Ok(builder.with_single_cert(certs, key)?)
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, TlsError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| TlsError::Load {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        })
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certs = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| TlsError::Load {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        })?;
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificate(path.display().to_string()));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsError> {
+    rustls_pemfile::private_key(&mut open(path)?)
+        .map_err(|e| TlsError::Load {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        })?
+        .ok_or_else(|| TlsError::NoPrivateKey(path.display().to_string()))
+}
+
+/// 证书subject里的CN
+fn common_name(cert: &CertificateDer<'_>) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let cn = cert.subject().iter_common_name().next()?;
+    cn.as_str().ok().map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config, network, Backend, RespArray, RespBulkString, RespFrame};
+    use futures::SinkExt;
+    use rcgen::{
+        BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use tokio::net::TcpListener;
+    use tokio_rustls::{rustls::ClientConfig, TlsConnector};
+    use tokio_stream::StreamExt;
+    use tokio_util::codec::Framed;
+
+    /// 一个CA和它签发的证书，都写成PEM文件
+    struct TestCa {
+        cert: rcgen::Certificate,
+        key: KeyPair,
+        dir: PathBuf,
+    }
+
+    impl TestCa {
+        fn new(dir: &Path, name: &str) -> Self {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec![]).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            params.distinguished_name.push(DnType::CommonName, name);
+            let cert = params.self_signed(&key).unwrap();
+            std::fs::write(dir.join(format!("{name}.crt")), cert.pem()).unwrap();
+            Self {
+                cert,
+                key,
+                dir: dir.to_path_buf(),
+            }
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            self.dir.join(name)
+        }
+
+        /// 签发一个证书，返回(证书文件, 私钥文件)
+        fn issue(&self, name: &str, client: bool) -> (PathBuf, PathBuf) {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec!["localhost".into()]).unwrap();
+            params.distinguished_name.push(DnType::CommonName, name);
+            params.extended_key_usages = vec![match client {
+                true => ExtendedKeyUsagePurpose::ClientAuth,
+                false => ExtendedKeyUsagePurpose::ServerAuth,
+            }];
+            let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+            let (cert_file, key_file) = (
+                self.path(&format!("{name}.crt")),
+                self.path(&format!("{name}.key")),
+            );
+            std::fs::write(&cert_file, cert.pem()).unwrap();
+            std::fs::write(&key_file, key.serialize_pem()).unwrap();
+            (cert_file, key_file)
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("simple-redis-tls-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn connector(ca_file: &Path, client: Option<(&Path, &Path)>) -> TlsConnector {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(ca_file).unwrap() {
+            roots.add(cert).unwrap();
+        }
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = match client {
+            Some((cert, key)) => builder
+                .with_client_auth_cert(load_certs(cert).unwrap(), load_key(key).unwrap())
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+        TlsConnector::from(Arc::new(config))
+    }
+
+    /// 连接并执行一条命令，握手或者读写失败时返回None
+    async fn request(
+        connector: &TlsConnector,
+        port: u16,
+        args: &[&'static str],
+    ) -> Option<RespFrame> {
+        let stream = TcpStream::connect(("127.0.0.1", port)).await.ok()?;
+        let name = "localhost".try_into().unwrap();
+        let stream = connector.connect(name, stream).await.ok()?;
+        let mut framed = Framed::new(stream, network::RespFrameCodec::default());
+        let cmd = RespArray::new(
+            args.iter()
+                .map(|arg| RespBulkString::from(*arg).into())
+                .collect(),
+        );
+        framed.send(cmd.into()).await.ok()?;
+        framed.next().await?.ok()
+    }
+
+    #[test]
+    fn test_load_certificates() {
+        let dir = temp_dir("load");
+        let ca = TestCa::new(&dir, "ca");
+        let (cert, key) = ca.issue("server", false);
+        let tls = TlsState::default();
+        assert!(matches!(tls.reload(), Err(TlsError::NoCertFile)));
+
+        tls.set_config(TlsConfig {
+            cert_file: Some(cert.clone()),
+            key_file: Some(key.clone()),
+            ..Default::default()
+        });
+        //默认要验证客户端证书，必须有CA
+        assert!(matches!(tls.reload(), Err(TlsError::NoCaCertFile)));
+        assert!(!tls.is_enabled());
+        tls.set_config(TlsConfig {
+            auth_clients: TlsAuthClients::No,
+            ..tls.config()
+        });
+        tls.reload().unwrap();
+        assert!(tls.is_enabled());
+
+        //证书和私钥不匹配、文件不存在时加载失败
+        let (_, other_key) = ca.issue("other", false);
+        tls.set_config(TlsConfig {
+            key_file: Some(other_key),
+            ..tls.config()
+        });
+        assert!(tls.reload().is_err());
+        tls.set_config(TlsConfig {
+            key_file: Some(dir.join("missing.key")),
+            ..tls.config()
+        });
+        assert!(matches!(tls.reload(), Err(TlsError::Load { .. })));
+        tls.set_config(TlsConfig {
+            key_file: Some(cert.clone()),
+            ..tls.config()
+        });
+        assert!(matches!(tls.reload(), Err(TlsError::NoPrivateKey(_))));
+        //失败时继续使用原来的证书
+        assert!(tls.is_enabled());
+
+        let der = load_certs(&cert).unwrap().remove(0);
+        assert_eq!(common_name(&der).as_deref(), Some("server"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_timeout() {
+        let dir = temp_dir("timeout");
+        let ca = TestCa::new(&dir, "ca");
+        let (cert, key) = ca.issue("server", false);
+        let tls = TlsState::default();
+        tls.set_config(TlsConfig {
+            cert_file: Some(cert),
+            key_file: Some(key),
+            auth_clients: TlsAuthClients::No,
+            ..Default::default()
+        });
+        tls.reload().unwrap();
+
+        //连上之后一直不发ClientHello
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let ret = tls.accept_within(stream, Duration::from_millis(100)).await;
+        assert!(matches!(ret, Err(TlsError::HandshakeTimeout)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_tls_connections() {
+        let dir = temp_dir("serve");
+        let ca = TestCa::new(&dir, "ca");
+        let (server_cert, server_key) = ca.issue("server", false);
+        let (alice_cert, alice_key) = ca.issue("alice", true);
+        let backend = Backend::new();
+        let ca_file = ca.path("ca.crt");
+        backend.tls.set_config(TlsConfig {
+            cert_file: Some(server_cert),
+            key_file: Some(server_key),
+            ca_cert_file: Some(ca_file.clone()),
+            auth_clients_user: true,
+            ..Default::default()
+        });
+        backend.tls.reload().unwrap();
+        backend.acl.set_requirepass("secret");
+        let rules = ["on", "nopass", "~*", "+@all"].map(String::from);
+        backend.acl.set_user("alice", &rules).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(network::serve_tls(listener, backend.clone()));
+
+        //客户端证书的CN是alice，连接直接以alice认证
+        let alice = connector(&ca_file, Some((&alice_cert, &alice_key)));
+        let reply = request(&alice, port, &["acl", "whoami"]).await;
+        assert_eq!(reply, Some(RespBulkString::from("alice").into()));
+
+        //tls-auth-clients yes时没有客户端证书的连接握手失败
+        let anonymous = connector(&ca_file, None);
+        assert_eq!(request(&anonymous, port, &["ping"]).await, None);
+        let params = [("tls-auth-clients".into(), "optional".into())];
+        config::set(&backend, &params).unwrap();
+        let reply = request(&anonymous, port, &["get", "a"]).await;
+        assert!(matches!(reply, Some(RespFrame::SimpleError(e)) if e.contains("NOAUTH")));
+
+        //CONFIG SET换成另一个CA签发的证书，新连接使用新证书
+        let other_dir = dir.join("other");
+        std::fs::create_dir_all(&other_dir).unwrap();
+        let other_ca = TestCa::new(&other_dir, "ca");
+        let (cert, key) = other_ca.issue("server", false);
+        let trusts_other = connector(&other_ca.path("ca.crt"), None);
+        assert_eq!(request(&trusts_other, port, &["ping"]).await, None);
+
+        //只换证书不换私钥时加载失败，配置不变
+        let params = [("tls-cert-file".to_string(), cert.display().to_string())];
+        assert!(config::set(&backend, &params).is_err());
+        assert_ne!(backend.tls.config().cert_file, Some(cert.clone()));
+        let params = [
+            ("tls-cert-file".to_string(), cert.display().to_string()),
+            ("tls-key-file".to_string(), key.display().to_string()),
+        ];
+        config::set(&backend, &params).unwrap();
+        let reply = request(&trusts_other, port, &["auth", "secret"]).await;
+        assert_eq!(reply, Some(RespFrame::SimpleString("OK".into())));
+        assert_eq!(request(&anonymous, port, &["auth", "secret"]).await, None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
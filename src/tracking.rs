@@ -0,0 +1,420 @@
+/*
+客户端缓存(CLIENT TRACKING)：
+    默认模式：打开TRACKING的连接通过GET/HGET读过的key记在表里，key被修改、删除或者过期时
+        给读过它的连接发一次invalidate，之后这个key从表里移除，连接要再读一次才会继续收到
+    BCAST模式：不记录读过的key，任何匹配PREFIX(没有PREFIX时匹配所有key)的key被修改都会通知
+    OPTIN/OPTOUT：只有CLIENT CACHING YES之后的下一条命令读的key才记录 / CLIENT CACHING NO之后的不记录
+    NOLOOP：连接自己修改的key不通知自己
+    REDIRECT：通知发给另一个连接，RESP2的连接只能这样接收。接收方是RESP3时收到invalidate推送，
+        RESP2时收到__redis__:invalidate频道的message
+    命令同步执行，执行命令的连接通过线程局部变量传给Backend，和NO-TOUCH一样
+*/
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use dashmap::DashMap;
+use thiserror::Error;
+
+use crate::{clients::ClientRegistry, RespArray, RespBulkString, RespFrame, RespInteger, RespPush};
+
+/// RESP2的连接通过这个频道接收invalidate
+pub const TRACKING_CHANNEL: &str = "__redis__:invalidate";
+
+#[derive(Error, Debug, PartialEq)]
+pub enum TrackingError {
+    #[error("PREFIX option requires BCAST mode to be enabled")]
+    PrefixWithoutBcast,
+    #[error("You can't use OPTIN and OPTOUT at the same time")]
+    OptInAndOptOut,
+    #[error("You can't switch BCAST mode on/off before disabling tracking for this client, and then re-enabling it with a different mode.")]
+    SwitchBcast,
+    #[error("You can't switch OPTIN/OPTOUT mode before disabling tracking for this client, and then re-enabling it with a different mode.")]
+    SwitchOpt,
+    #[error("Prefix '{0}' overlaps with an existing prefix '{1}'. Prefixes for a single client must not overlap.")]
+    PrefixOverlap(String, String),
+    #[error("The client ID you want redirect to does not exist")]
+    NoRedirectTarget,
+    #[error("CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled")]
+    CachingNotEnabled,
+    #[error("CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode.")]
+    CachingYesWithoutOptIn,
+    #[error("CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode.")]
+    CachingNoWithoutOptOut,
+}
+
+/// CLIENT TRACKING ON的选项
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackingOptions {
+    pub redirect: Option<u64>,
+    pub bcast: bool,
+    pub prefixes: Vec<String>,
+    pub optin: bool,
+    pub optout: bool,
+    pub noloop: bool,
+}
+
+impl TrackingOptions {
+    /// 只检查选项本身，和连接当前状态有关的检查在TrackingState::enable里
+    pub fn validate(&self) -> Result<(), TrackingError> {
+        if !self.prefixes.is_empty() && !self.bcast {
+            return Err(TrackingError::PrefixWithoutBcast);
+        }
+        if self.optin && self.optout {
+            return Err(TrackingError::OptInAndOptOut);
+        }
+        Ok(())
+    }
+
+    /// BCAST模式下key是否匹配PREFIX，没有PREFIX时匹配所有key
+    fn matches(&self, key: &str) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|p| key.starts_with(p.as_str()))
+    }
+}
+
+/// 正在执行命令的连接
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Caller {
+    pub id: u64,
+    /// 这条命令读的key要不要记录
+    pub track_reads: bool,
+}
+
+thread_local! {
+    static CALLER: Cell<Option<Caller>> = const { Cell::new(None) };
+}
+
+/// 以caller的身份执行f，f里读写的key按caller记录和通知
+pub fn with_caller<T>(caller: Option<Caller>, f: impl FnOnce() -> T) -> T {
+    let Some(caller) = caller else {
+        return f();
+    };
+    let previous = CALLER.replace(Some(caller));
+    let ret = f();
+    CALLER.set(previous);
+    ret
+}
+
+#[derive(Debug, Default)]
+pub struct TrackingState {
+    /// 打开了TRACKING的连接
+    clients: DashMap<u64, TrackingOptions>,
+    /// 默认模式下 key -> 读过这个key的连接
+    keys: DashMap<String, HashSet<u64>>,
+    /// 没有连接打开TRACKING时读写key不需要做任何事
+    count: AtomicUsize,
+}
+
+impl TrackingState {
+    /// CLIENT TRACKING ON，已经打开时只能追加PREFIX和修改REDIRECT、NOLOOP
+    pub fn enable(&self, id: u64, options: TrackingOptions) -> Result<(), TrackingError> {
+        options.validate()?;
+        let mut options = options;
+        if let Some(old) = self.clients.get(&id) {
+            if old.bcast != options.bcast {
+                return Err(TrackingError::SwitchBcast);
+            }
+            if old.optin != options.optin || old.optout != options.optout {
+                return Err(TrackingError::SwitchOpt);
+            }
+            let mut prefixes = old.prefixes.clone();
+            prefixes.extend(options.prefixes);
+            options.prefixes = prefixes;
+        }
+        check_overlap(&options.prefixes)?;
+        options.prefixes.sort();
+        options.prefixes.dedup();
+        self.clients.insert(id, options);
+        self.count.store(self.clients.len(), Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// CLIENT TRACKING OFF或者连接断开，表里这个连接的key不主动清理，发送时跳过
+    pub fn disable(&self, id: u64) {
+        self.clients.remove(&id);
+        self.count.store(self.clients.len(), Ordering::SeqCst);
+    }
+
+    pub fn options(&self, id: u64) -> Option<TrackingOptions> {
+        self.clients.get(&id).map(|options| options.clone())
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.count.load(Ordering::SeqCst) > 0
+    }
+
+    /// 打开了TRACKING的连接数
+    pub fn len(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 默认模式下记录的key个数
+    pub fn total_keys(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// 默认模式下记录的(key, 连接)个数
+    pub fn total_items(&self) -> usize {
+        self.keys.iter().map(|ids| ids.len()).sum()
+    }
+
+    /// BCAST模式的PREFIX个数
+    pub fn total_prefixes(&self) -> usize {
+        self.clients
+            .iter()
+            .map(|options| options.prefixes.len())
+            .sum()
+    }
+
+    /// 连接执行一条命令时的身份，caching是CLIENT CACHING YES/NO的设置
+    pub fn caller(&self, id: u64, caching: Option<bool>) -> Option<Caller> {
+        if !self.is_active() {
+            return None;
+        }
+        let options = self.clients.get(&id)?;
+        let track_reads = !options.bcast
+            && match (options.optin, options.optout) {
+                (true, _) => caching == Some(true),
+                (_, true) => caching != Some(false),
+                _ => true,
+            };
+        Some(Caller { id, track_reads })
+    }
+
+    /// CLIENT CACHING YES|NO只在对应的模式下有效
+    pub fn check_caching(&self, id: u64, yes: bool) -> Result<(), TrackingError> {
+        let options = self
+            .clients
+            .get(&id)
+            .ok_or(TrackingError::CachingNotEnabled)?;
+        match (yes, options.optin, options.optout) {
+            (_, false, false) => Err(TrackingError::CachingNotEnabled),
+            (true, false, _) => Err(TrackingError::CachingYesWithoutOptIn),
+            (false, _, false) => Err(TrackingError::CachingNoWithoutOptOut),
+            _ => Ok(()),
+        }
+    }
+
+    /// 当前连接读了key，需要的话记下来
+    pub fn remember(&self, key: &str) {
+        if !self.is_active() {
+            return;
+        }
+        if let Some(caller) = CALLER.get().filter(|caller| caller.track_reads) {
+            self.keys
+                .entry(key.to_string())
+                .or_default()
+                .insert(caller.id);
+        }
+    }
+
+    /// key被修改了，通知读过它的连接和匹配的BCAST连接
+    pub fn invalidate(&self, key: &str, clients: &ClientRegistry) {
+        if !self.is_active() {
+            return;
+        }
+        let mut targets = self
+            .keys
+            .remove(key)
+            .map(|(_, ids)| ids.into_iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        targets.extend(
+            self.clients
+                .iter()
+                .filter(|options| options.bcast && options.matches(key))
+                .map(|options| *options.key()),
+        );
+        let caller = CALLER.get().map(|caller| caller.id);
+        for id in targets {
+            let Some(options) = self.options(id) else {
+                continue;
+            };
+            if options.noloop && caller == Some(id) {
+                continue;
+            }
+            send_invalidate(id, &options, key, clients);
+        }
+    }
+}
+
+/// 同一个连接的PREFIX不能互为前缀，否则一次修改会通知两次
+fn check_overlap(prefixes: &[String]) -> Result<(), TrackingError> {
+    for (i, a) in prefixes.iter().enumerate() {
+        for b in &prefixes[i + 1..] {
+            if a != b && (a.starts_with(b.as_str()) || b.starts_with(a.as_str())) {
+                return Err(TrackingError::PrefixOverlap(b.clone(), a.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 按接收方的协议生成invalidate消息
+pub fn invalidate_message(resp: i64, key: &str) -> RespFrame {
+    let keys = RespArray::new(vec![RespBulkString::from(key.to_string()).into()]);
+    match resp {
+        3 => RespPush::new(vec![RespBulkString::from("invalidate").into(), keys.into()]).into(),
+        _ => RespArray::new(vec![
+            RespBulkString::from("message").into(),
+            RespBulkString::from(TRACKING_CHANNEL).into(),
+            keys.into(),
+        ])
+        .into(),
+    }
+}
+
+fn send_invalidate(id: u64, options: &TrackingOptions, key: &str, clients: &ClientRegistry) {
+    let target = options.redirect.unwrap_or(id);
+    //RESP2的连接没有REDIRECT时收不到通知
+    let sent = clients.push(target, |resp| {
+        (resp == 3 || options.redirect.is_some()).then(|| invalidate_message(resp, key))
+    });
+    //REDIRECT的连接已经断开，RESP3的连接会收到tracking-redir-broken
+    if !sent && options.redirect.is_some() {
+        clients.push(id, |resp| {
+            (resp == 3).then(|| {
+                RespPush::new(vec![
+                    RespBulkString::from("tracking-redir-broken").into(),
+                    RespInteger::from(target as i64).into(),
+                ])
+                .into()
+            })
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Session;
+    use crate::RespProtocol;
+
+    fn register(clients: &ClientRegistry, protocol: RespProtocol) -> Session {
+        let mut session = Session::new();
+        session.protocol = protocol;
+        clients.register(&session);
+        session
+    }
+
+    #[test]
+    fn test_tracking_options() {
+        let state = TrackingState::default();
+        let prefix = TrackingOptions {
+            prefixes: vec!["a".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            state.enable(1, prefix),
+            Err(TrackingError::PrefixWithoutBcast)
+        );
+        let opt = TrackingOptions {
+            optin: true,
+            optout: true,
+            ..Default::default()
+        };
+        assert_eq!(state.enable(1, opt), Err(TrackingError::OptInAndOptOut));
+
+        let bcast = |prefixes: &[&str]| TrackingOptions {
+            bcast: true,
+            prefixes: prefixes.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        };
+        state.enable(1, bcast(&["user:"])).unwrap();
+        state.enable(1, bcast(&["order:"])).unwrap();
+        assert_eq!(state.total_prefixes(), 2);
+        assert!(matches!(
+            state.enable(1, bcast(&["user:1"])),
+            Err(TrackingError::PrefixOverlap(..))
+        ));
+        assert_eq!(
+            state.enable(1, TrackingOptions::default()),
+            Err(TrackingError::SwitchBcast)
+        );
+        assert_eq!(
+            state.check_caching(1, true),
+            Err(TrackingError::CachingNotEnabled)
+        );
+        state.disable(1);
+        assert!(state.is_empty());
+    }
+
+    #[test]
+    fn test_caller() {
+        let state = TrackingState::default();
+        assert_eq!(state.caller(1, None), None);
+        let optin = TrackingOptions {
+            optin: true,
+            ..Default::default()
+        };
+        state.enable(1, optin).unwrap();
+        assert_eq!(state.caller(1, None).map(|c| c.track_reads), Some(false));
+        assert_eq!(
+            state.caller(1, Some(true)).map(|c| c.track_reads),
+            Some(true)
+        );
+        assert_eq!(state.caller(2, None), None);
+        assert!(state.check_caching(1, true).is_ok());
+        assert_eq!(
+            state.check_caching(1, false),
+            Err(TrackingError::CachingNoWithoutOptOut)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalidate() {
+        let state = TrackingState::default();
+        let clients = ClientRegistry::default();
+        let mut reader = register(&clients, RespProtocol::Resp3);
+        let mut writer = register(&clients, RespProtocol::Resp3);
+        state.enable(reader.id, TrackingOptions::default()).unwrap();
+        let noloop = TrackingOptions {
+            noloop: true,
+            bcast: true,
+            ..Default::default()
+        };
+        state.enable(writer.id, noloop).unwrap();
+
+        with_caller(state.caller(reader.id, None), || state.remember("k"));
+        assert_eq!(state.total_keys(), 1);
+        with_caller(state.caller(writer.id, None), || {
+            state.invalidate("k", &clients)
+        });
+        let expected = invalidate_message(3, "k");
+        assert_eq!(reader.pushes.recv().await, Some(expected.clone()));
+        //只通知一次，之后要重新读才会再通知
+        assert_eq!(state.total_keys(), 0);
+        state.invalidate("k", &clients);
+        assert_eq!(writer.pushes.recv().await, Some(expected));
+        assert!(reader.pushes.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_redirect() {
+        let state = TrackingState::default();
+        let clients = ClientRegistry::default();
+        let reader = register(&clients, RespProtocol::Resp2);
+        let mut target = register(&clients, RespProtocol::Resp2);
+        let redirect = TrackingOptions {
+            redirect: Some(target.id),
+            bcast: true,
+            prefixes: vec!["user:".into()],
+            ..Default::default()
+        };
+        state.enable(reader.id, redirect).unwrap();
+        state.invalidate("order:1", &clients);
+        state.invalidate("user:1", &clients);
+        let RespFrame::Arrays(message) = target.pushes.recv().await.unwrap() else {
+            panic!("RESP2 target should receive a pubsub message");
+        };
+        assert_eq!(message[1], RespBulkString::from(TRACKING_CHANNEL).into());
+        assert_eq!(
+            message[2],
+            RespArray::new(vec![RespBulkString::from("user:1").into()]).into()
+        );
+        assert!(target.pushes.try_recv().is_err());
+    }
+}